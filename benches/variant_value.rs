@@ -0,0 +1,66 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![feature(test)]
+
+extern crate rucene;
+extern crate test;
+
+use rucene::core::util::VariantValue;
+use test::{black_box, Bencher};
+
+const NUM_VALUES: i64 = 10_000;
+
+#[bench]
+fn bench_long_constructor(b: &mut Bencher) {
+    b.iter(|| {
+        let values: Vec<VariantValue> = (0..NUM_VALUES)
+            .map(|i| VariantValue::long(black_box(i)))
+            .collect();
+        black_box(values)
+    });
+}
+
+#[bench]
+fn bench_long_from(b: &mut Bencher) {
+    b.iter(|| {
+        let values: Vec<VariantValue> = (0..NUM_VALUES)
+            .map(|i| VariantValue::from(black_box(i)))
+            .collect();
+        black_box(values)
+    });
+}
+
+#[bench]
+fn bench_vstring_constructor(b: &mut Bencher) {
+    let strings: Vec<String> = (0..NUM_VALUES).map(|i| format!("value_{}", i)).collect();
+    b.iter(|| {
+        let values: Vec<VariantValue> = strings
+            .iter()
+            .map(|s| VariantValue::vstring(black_box(s.clone())))
+            .collect();
+        black_box(values)
+    });
+}
+
+#[bench]
+fn bench_vstring_from_str(b: &mut Bencher) {
+    let strings: Vec<String> = (0..NUM_VALUES).map(|i| format!("value_{}", i)).collect();
+    b.iter(|| {
+        let values: Vec<VariantValue> = strings
+            .iter()
+            .map(|s| VariantValue::from(black_box(s.as_str())))
+            .collect();
+        black_box(values)
+    });
+}
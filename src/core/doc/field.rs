@@ -36,7 +36,7 @@ impl Field {
         token_stream: Option<Box<dyn TokenStream>>,
     ) -> Field {
         let field_data = if let Some(data) = field_data {
-            match data {
+            match data.unshare() {
                 VariantValue::Binary(b) => {
                     let bytes = b[..(ByteBlockPool::BYTE_BLOCK_SIZE - 2).min(b.len())].to_vec();
                     Some(VariantValue::Binary(bytes))
@@ -49,7 +49,7 @@ impl Field {
                     let s = (&vs[..index]).to_string();
                     Some(VariantValue::VString(s))
                 }
-                _ => Some(data),
+                data => Some(data),
             }
         } else {
             None
@@ -80,7 +80,7 @@ impl Field {
     }
 
     pub fn set_field_data(&mut self, data: Option<VariantValue>) {
-        self.field_data = data;
+        self.field_data = data.map(VariantValue::unshare);
     }
 }
 
@@ -32,6 +32,7 @@ error_chain! {
 pub mod tests {
     use std::collections::HashMap;
 
+    use core::codec::doc_values::lucene54::DocValuesTermIterator;
     use core::codec::doc_values::{
         BinaryDocValues, DocValuesProducer, NumericDocValues, SortedDocValues,
         SortedNumericDocValues, SortedSetDocValues,
@@ -98,6 +99,8 @@ pub mod tests {
         max_doc: DocId,
         live_docs: BitsRef,
         field_infos: FieldInfos,
+        numeric_values: Option<Arc<Vec<i64>>>,
+        sorted_values: Option<Arc<(Vec<i32>, Vec<Vec<u8>>)>>,
     }
 
     impl MockLeafReader {
@@ -139,8 +142,86 @@ pub mod tests {
                 max_doc,
                 live_docs: Arc::new(MatchAllBits::new(0usize)),
                 field_infos: FieldInfos::new(infos).unwrap(),
+                numeric_values: None,
+                sorted_values: None,
             }
         }
+
+        /// Creates a reader whose numeric doc values are `values`, indexed by
+        /// doc, for all the fields.
+        pub fn with_numeric_values(values: Vec<i64>) -> MockLeafReader {
+            let mut reader = MockLeafReader::new(values.len() as DocId);
+            reader.numeric_values = Some(Arc::new(values));
+            reader
+        }
+
+        /// Creates a reader whose sorted doc values are `values`, indexed by
+        /// doc, for all the fields, `None` for the docs without a value.
+        pub fn with_sorted_values(values: Vec<Option<&str>>) -> MockLeafReader {
+            MockLeafReader::with_sorted_bytes(
+                values.into_iter().map(|v| v.map(str::as_bytes)).collect(),
+            )
+        }
+
+        /// Same as `with_sorted_values`, for values which may not be UTF-8.
+        pub fn with_sorted_bytes(values: Vec<Option<&[u8]>>) -> MockLeafReader {
+            let mut terms: Vec<Vec<u8>> = values
+                .iter()
+                .filter_map(|v| v.map(<[u8]>::to_vec))
+                .collect();
+            terms.sort();
+            terms.dedup();
+            let ords = values
+                .iter()
+                .map(|v| match v {
+                    Some(b) => terms.binary_search(&b.to_vec()).unwrap() as i32,
+                    None => -1,
+                })
+                .collect();
+            let mut reader = MockLeafReader::new(values.len() as DocId);
+            reader.sorted_values = Some(Arc::new((ords, terms)));
+            reader
+        }
+    }
+
+    pub struct MockLongValues(Arc<Vec<i64>>);
+
+    impl NumericDocValues for MockLongValues {
+        fn get(&self, doc_id: DocId) -> Result<i64> {
+            Ok(self.0[doc_id as usize])
+        }
+    }
+
+    /// the ords of the docs and the sorted terms
+    pub struct MockSortedValues(Arc<(Vec<i32>, Vec<Vec<u8>>)>);
+
+    impl BinaryDocValues for MockSortedValues {
+        fn get(&mut self, doc_id: DocId) -> Result<Vec<u8>> {
+            let ord = self.get_ord(doc_id)?;
+            if ord < 0 {
+                Ok(Vec::new())
+            } else {
+                self.lookup_ord(ord)
+            }
+        }
+    }
+
+    impl SortedDocValues for MockSortedValues {
+        fn get_ord(&mut self, doc_id: DocId) -> Result<i32> {
+            Ok((self.0).0[doc_id as usize])
+        }
+
+        fn lookup_ord(&mut self, ord: i32) -> Result<Vec<u8>> {
+            Ok((self.0).1[ord as usize].clone())
+        }
+
+        fn value_count(&self) -> usize {
+            (self.0).1.len()
+        }
+
+        fn term_iterator(&self) -> Result<DocValuesTermIterator> {
+            Ok(DocValuesTermIterator::empty())
+        }
     }
 
     impl LeafReader for MockLeafReader {
@@ -197,6 +278,9 @@ pub mod tests {
         }
 
         fn get_numeric_doc_values(&self, _field: &str) -> Result<Box<dyn NumericDocValues>> {
+            if let Some(ref values) = self.numeric_values {
+                return Ok(Box::new(MockLongValues(Arc::clone(values))));
+            }
             Ok(Box::new(MockNumericValues::default()))
         }
 
@@ -205,6 +289,9 @@ pub mod tests {
         }
 
         fn get_sorted_doc_values(&self, _field: &str) -> Result<Box<dyn SortedDocValues>> {
+            if let Some(ref values) = self.sorted_values {
+                return Ok(Box::new(MockSortedValues(Arc::clone(values))));
+            }
             unimplemented!()
         }
 
@@ -0,0 +1,486 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Ordering;
+use std::collections::binary_heap::BinaryHeap;
+use std::collections::HashMap;
+use std::f32;
+use std::mem;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use core::codec::doc_values::{BinaryDocValues, NumericDocValues, SortedDocValues};
+use core::codec::Codec;
+use core::doc::DocValuesType;
+use core::index::reader::LeafReaderContext;
+use core::search::collector::{Collector, ParallelLeafCollector, SearchCollector};
+use core::search::scorer::Scorer;
+use core::search::sort_field::ScoreDoc;
+use core::util::{BitsMut, DocId, VariantValue};
+use error::{
+    ErrorKind::{IllegalArgument, IllegalState},
+    Result, ResultExt,
+};
+
+/// The top hits of a group collected by a `GroupingCollector`.
+#[derive(Debug, Clone)]
+pub struct GroupDocs {
+    /// The value of the group field, `None` for the docs without a value.
+    pub group_value: Option<VariantValue>,
+    /// The number of docs of the group, including the ones not in
+    /// `score_docs`.
+    pub total_hits: usize,
+    pub max_score: f32,
+    /// The top hits of the group, best first.
+    pub score_docs: Vec<ScoreDoc>,
+}
+
+/// The doc values of the group field of the current leaf.
+enum GroupValues {
+    Numeric(Box<dyn NumericDocValues>, Box<dyn BitsMut>),
+    Binary(Box<dyn BinaryDocValues>, Box<dyn BitsMut>),
+    Sorted(Box<dyn SortedDocValues>),
+}
+
+impl GroupValues {
+    fn get(&mut self, doc: DocId) -> Result<Option<VariantValue>> {
+        let value = match self {
+            GroupValues::Numeric(values, docs_with_field) => {
+                if !docs_with_field.get(doc as usize)? {
+                    return Ok(None);
+                }
+                VariantValue::Long(values.get_mut(doc)?)
+            }
+            GroupValues::Binary(values, docs_with_field) => {
+                if !docs_with_field.get(doc as usize)? {
+                    return Ok(None);
+                }
+                VariantValue::Binary(values.get(doc)?)
+            }
+            GroupValues::Sorted(values) => {
+                let ord = values.get_ord(doc)?;
+                if ord < 0 {
+                    return Ok(None);
+                }
+                match String::from_utf8(values.lookup_ord(ord)?) {
+                    Ok(s) => VariantValue::VString(s),
+                    Err(e) => VariantValue::Binary(e.into_bytes()),
+                }
+            }
+        };
+        Ok(Some(value))
+    }
+}
+
+struct GroupBucket {
+    /// the lowest score on top, like `TopDocsCollector`
+    pq: BinaryHeap<ScoreDoc>,
+    total_hits: usize,
+    max_score: f32,
+}
+
+impl GroupBucket {
+    fn new() -> Self {
+        GroupBucket {
+            pq: BinaryHeap::new(),
+            total_hits: 0,
+            max_score: f32::NEG_INFINITY,
+        }
+    }
+
+    fn add_doc(&mut self, doc: DocId, score: f32, top_n: usize) {
+        if self.pq.len() < top_n {
+            self.pq.push(ScoreDoc::new(doc, score));
+        } else if let Some(mut bottom) = self.pq.peek_mut() {
+            if bottom.score < score {
+                bottom.reset(doc, score);
+            }
+        }
+    }
+
+    fn merge(&mut self, other: GroupBucket, top_n: usize) {
+        self.total_hits += other.total_hits;
+        self.max_score = self.max_score.max(other.max_score);
+        for doc in other.pq {
+            self.add_doc(doc.doc, doc.score, top_n);
+        }
+    }
+
+    fn into_group_docs(self, group_value: Option<VariantValue>) -> GroupDocs {
+        let mut score_docs = self.pq.into_vec();
+        score_docs.sort_by(|d1, d2| {
+            d2.score
+                .partial_cmp(&d1.score)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| d1.doc.cmp(&d2.doc))
+        });
+        GroupDocs {
+            group_value,
+            total_hits: self.total_hits,
+            max_score: self.max_score,
+            score_docs,
+        }
+    }
+}
+
+struct GroupingBaseCollector {
+    field: String,
+    doc_values_type: DocValuesType,
+    top_n: usize,
+    max_groups: usize,
+
+    values: Option<GroupValues>,
+    groups: HashMap<Option<VariantValue>, GroupBucket>,
+    total_hits: usize,
+    cur_doc_base: DocId,
+}
+
+impl GroupingBaseCollector {
+    fn new(field: String, doc_values_type: DocValuesType, top_n: usize, max_groups: usize) -> Self {
+        GroupingBaseCollector {
+            field,
+            doc_values_type,
+            top_n,
+            max_groups,
+            values: None,
+            groups: HashMap::new(),
+            total_hits: 0,
+            cur_doc_base: 0,
+        }
+    }
+
+    fn set_next_reader<C: Codec>(&mut self, reader: &LeafReaderContext<'_, C>) -> Result<()> {
+        self.cur_doc_base = reader.doc_base;
+        let leaf = reader.reader;
+        let values = match self.doc_values_type {
+            DocValuesType::Numeric => GroupValues::Numeric(
+                leaf.get_numeric_doc_values(&self.field)?,
+                leaf.get_docs_with_field(&self.field)?,
+            ),
+            DocValuesType::Binary => GroupValues::Binary(
+                leaf.get_binary_doc_values(&self.field)?,
+                leaf.get_docs_with_field(&self.field)?,
+            ),
+            DocValuesType::Sorted => GroupValues::Sorted(leaf.get_sorted_doc_values(&self.field)?),
+            _ => unreachable!(),
+        };
+        self.values = Some(values);
+        Ok(())
+    }
+
+    /// Returns the bucket of `group_value`, or `None` if it is a new group
+    /// but `max_groups` groups are already collected.
+    fn bucket(&mut self, group_value: Option<VariantValue>) -> Option<&mut GroupBucket> {
+        if !self.groups.contains_key(&group_value) && self.groups.len() >= self.max_groups {
+            return None;
+        }
+        Some(
+            self.groups
+                .entry(group_value)
+                .or_insert_with(GroupBucket::new),
+        )
+    }
+
+    fn merge(&mut self, groups: HashMap<Option<VariantValue>, GroupBucket>, total_hits: usize) {
+        self.total_hits += total_hits;
+        let top_n = self.top_n;
+        for (group_value, other) in groups {
+            if let Some(bucket) = self.bucket(group_value) {
+                bucket.merge(other, top_n);
+            }
+        }
+    }
+
+    fn top_groups(&mut self) -> Vec<GroupDocs> {
+        let mut groups: Vec<GroupDocs> = mem::take(&mut self.groups)
+            .into_iter()
+            .map(|(group_value, bucket)| bucket.into_group_docs(group_value))
+            .collect();
+        groups.sort_by(|g1, g2| {
+            g2.max_score
+                .partial_cmp(&g1.max_score)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| match (&g1.group_value, &g2.group_value) {
+                    // sorted values may be a mix of `VString` and `Binary`
+                    (Some(v1), Some(v2)) => v1.compare_as_bytes(v2).unwrap_or_else(|| v1.cmp(v2)),
+                    (v1, v2) => v1.is_some().cmp(&v2.is_some()),
+                })
+        });
+        groups
+    }
+}
+
+impl Collector for GroupingBaseCollector {
+    fn needs_scores(&self) -> bool {
+        true
+    }
+
+    fn collect<S: Scorer + ?Sized>(&mut self, doc: DocId, scorer: &mut S) -> Result<()> {
+        self.total_hits += 1;
+        let group_value = match self.values {
+            Some(ref mut values) => values.get(doc)?,
+            None => bail!(IllegalState("collect called before set_next_reader".into())),
+        };
+        let doc_id = doc + self.cur_doc_base;
+        let top_n = self.top_n;
+        if let Some(bucket) = self.bucket(group_value) {
+            let score = scorer.score()?;
+            bucket.total_hits += 1;
+            bucket.max_score = bucket.max_score.max(score);
+            bucket.add_doc(doc_id, score, top_n);
+        }
+        Ok(())
+    }
+}
+
+/// A `Collector` which groups the hits by the doc value of a field, eg. for
+/// faceted search, and keeps the top `top_n` hits by score of each group.
+///
+/// The group values are `VariantValue::Long` for numeric doc values,
+/// `VariantValue::Binary` for binary ones and `VariantValue::VString` for
+/// sorted ones, unless their bytes are not valid UTF-8. Docs without a value
+/// are grouped under `None`.
+///
+/// At most `max_groups` groups are collected, the hits of other groups are
+/// only counted in `total_hits`. Groups are created in collection order, so
+/// which groups are kept depends on the order of the docs.
+pub struct GroupingCollector {
+    base: GroupingBaseCollector,
+
+    channel: Option<(Sender<LeafGroups>, Receiver<LeafGroups>)>,
+}
+
+impl GroupingCollector {
+    /// Returns an error if `doc_values_type` is not `Numeric`, `Binary` or
+    /// `Sorted`.
+    pub fn new(
+        field: &str,
+        doc_values_type: DocValuesType,
+        top_n: usize,
+        max_groups: usize,
+    ) -> Result<Self> {
+        match doc_values_type {
+            DocValuesType::Numeric | DocValuesType::Binary | DocValuesType::Sorted => {}
+            t => bail!(IllegalArgument(format!(
+                "can't group by doc values of type {:?}",
+                t
+            ))),
+        }
+        Ok(GroupingCollector {
+            base: GroupingBaseCollector::new(field.to_string(), doc_values_type, top_n, max_groups),
+            channel: None,
+        })
+    }
+
+    /// Returns the number of docs collected, including the ones of the
+    /// groups over `max_groups`.
+    pub fn total_hits(&self) -> usize {
+        self.base.total_hits
+    }
+
+    /// Returns the collected groups, the group with the best hit first.
+    /// Groups with equal max scores are ordered by group value, the bytes of
+    /// strings and binaries alike, after the docs without a value.
+    pub fn top_groups(&mut self) -> Vec<GroupDocs> {
+        self.base.top_groups()
+    }
+}
+
+impl SearchCollector for GroupingCollector {
+    type LC = GroupingLeafCollector;
+
+    fn set_next_reader<C: Codec>(&mut self, reader: &LeafReaderContext<'_, C>) -> Result<()> {
+        self.base.set_next_reader(reader)
+    }
+
+    fn support_parallel(&self) -> bool {
+        true
+    }
+
+    fn init_parallel(&mut self) {
+        if self.channel.is_none() {
+            self.channel = Some(channel());
+        }
+    }
+
+    fn leaf_collector<C: Codec>(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+    ) -> Result<GroupingLeafCollector> {
+        let mut collector = GroupingBaseCollector::new(
+            self.base.field.clone(),
+            self.base.doc_values_type,
+            self.base.top_n,
+            self.base.max_groups,
+        );
+        collector.set_next_reader(reader)?;
+        Ok(GroupingLeafCollector {
+            collector,
+            channel: self.channel.as_ref().unwrap().0.clone(),
+        })
+    }
+
+    fn finish_parallel(&mut self) -> Result<()> {
+        let channel = self.channel.take();
+        if let Some((sender, receiver)) = channel {
+            drop(sender);
+            while let Ok(leaf) = receiver.recv() {
+                self.base.merge(leaf.groups, leaf.total_hits);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Collector for GroupingCollector {
+    fn needs_scores(&self) -> bool {
+        self.base.needs_scores()
+    }
+
+    fn collect<S: Scorer + ?Sized>(&mut self, doc: DocId, scorer: &mut S) -> Result<()> {
+        self.base.collect(doc, scorer)
+    }
+}
+
+struct LeafGroups {
+    groups: HashMap<Option<VariantValue>, GroupBucket>,
+    total_hits: usize,
+}
+
+pub struct GroupingLeafCollector {
+    collector: GroupingBaseCollector,
+    channel: Sender<LeafGroups>,
+}
+
+impl ParallelLeafCollector for GroupingLeafCollector {
+    fn finish_leaf(&mut self) -> Result<()> {
+        let groups = LeafGroups {
+            groups: mem::take(&mut self.collector.groups),
+            total_hits: self.collector.total_hits,
+        };
+        self.channel
+            .send(groups)
+            .chain_err(|| IllegalState("channel unexpected closed before search complete".into()))
+    }
+}
+
+impl Collector for GroupingLeafCollector {
+    fn needs_scores(&self) -> bool {
+        self.collector.needs_scores()
+    }
+
+    fn collect<S: Scorer + ?Sized>(&mut self, doc: DocId, scorer: &mut S) -> Result<()> {
+        self.collector.collect(doc, scorer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::index::tests::*;
+    use core::search::tests::*;
+
+    fn group_docs(group: &GroupDocs) -> Vec<DocId> {
+        group.score_docs.iter().map(|d| d.doc).collect()
+    }
+
+    #[test]
+    fn test_group_by_string_field() {
+        // the mock score is the doc id of the leaf
+        let leaves = vec![
+            MockLeafReader::with_sorted_values(vec![
+                Some("red"),
+                Some("blue"),
+                Some("red"),
+                Some("red"),
+            ]),
+            MockLeafReader::with_sorted_values(vec![Some("blue"), Some("red"), Some("blue")]),
+        ];
+        let mut collector = GroupingCollector::new("color", DocValuesType::Sorted, 2, 10).unwrap();
+        collect_all_docs(&mut collector, leaves);
+        assert_eq!(collector.total_hits(), 7);
+
+        let groups = collector.top_groups();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].group_value, Some(VariantValue::from("red")));
+        assert_eq!(groups[0].total_hits, 4);
+        assert!((groups[0].max_score - 3.0).abs() < ::std::f32::EPSILON);
+        assert_eq!(group_docs(&groups[0]), vec![3, 2]);
+        assert_eq!(groups[1].group_value, Some(VariantValue::from("blue")));
+        assert_eq!(groups[1].total_hits, 3);
+        assert!((groups[1].max_score - 2.0).abs() < ::std::f32::EPSILON);
+        assert_eq!(group_docs(&groups[1]), vec![6, 1]);
+    }
+
+    #[test]
+    fn test_max_groups_and_missing_values() {
+        let leaves = vec![MockLeafReader::with_sorted_values(vec![
+            Some("a"),
+            None,
+            Some("b"),
+            Some("c"),
+            None,
+            Some("a"),
+        ])];
+        let mut collector = GroupingCollector::new("tag", DocValuesType::Sorted, 5, 2).unwrap();
+        collect_all_docs(&mut collector, leaves);
+        // the docs of "b" and "c" are over the cap of 2 groups
+        assert_eq!(collector.total_hits(), 6);
+        let groups = collector.top_groups();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].group_value, Some(VariantValue::from("a")));
+        assert_eq!(group_docs(&groups[0]), vec![5, 0]);
+        assert_eq!(groups[1].group_value, None);
+        assert_eq!(group_docs(&groups[1]), vec![4, 1]);
+
+        let mut collector = GroupingCollector::new("price", DocValuesType::Numeric, 1, 10).unwrap();
+        collect_all_docs(
+            &mut collector,
+            vec![MockLeafReader::with_numeric_values(vec![10, 20, 10])],
+        );
+        let groups = collector.top_groups();
+        assert_eq!(groups[0].group_value, Some(VariantValue::Long(10)));
+        assert_eq!(groups[0].total_hits, 2);
+        assert_eq!(group_docs(&groups[0]), vec![2]);
+        assert_eq!(groups[1].group_value, Some(VariantValue::Long(20)));
+
+        assert!(GroupingCollector::new("tags", DocValuesType::SortedSet, 1, 1).is_err());
+    }
+
+    #[test]
+    fn test_tie_break_on_mixed_group_values() {
+        // all the groups have the max score 0 of their first doc of a leaf
+        let leaves = vec![
+            MockLeafReader::with_sorted_bytes(vec![Some(b"\xff\x00")]),
+            MockLeafReader::with_sorted_bytes(vec![Some(b"b")]),
+            MockLeafReader::with_sorted_bytes(vec![None]),
+            MockLeafReader::with_sorted_bytes(vec![Some(b"a")]),
+        ];
+        let mut collector = GroupingCollector::new("tag", DocValuesType::Sorted, 1, 10).unwrap();
+        collect_all_docs(&mut collector, leaves);
+        let values: Vec<Option<VariantValue>> = collector
+            .top_groups()
+            .into_iter()
+            .map(|g| g.group_value)
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                None,
+                Some(VariantValue::from("a")),
+                Some(VariantValue::from("b")),
+                Some(VariantValue::Binary(vec![0xff, 0])),
+            ]
+        );
+    }
+}
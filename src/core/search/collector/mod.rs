@@ -15,6 +15,14 @@ mod top_docs;
 
 pub use self::top_docs::*;
 
+mod top_field;
+
+pub use self::top_field::*;
+
+mod grouping;
+
+pub use self::grouping::*;
+
 mod early_terminating;
 
 pub use self::early_terminating::*;
@@ -27,6 +35,10 @@ mod chain;
 
 pub use self::chain::*;
 
+mod total_hit_count;
+
+pub use self::total_hit_count::*;
+
 use error::Result;
 
 use core::codec::Codec;
@@ -63,7 +75,7 @@ error_chain! {
 /// the score computation is skipped entirely if it's not
 /// needed. If your collector may request the
 /// score for a single hit multiple times, you should use
-/// `ScoreCachingWrappingScorer`.
+/// `CachingScorer`.
 ///
 /// *NOTE:* The doc that is passed to the collect
 /// method is relative to the current reader. If your
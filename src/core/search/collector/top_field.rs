@@ -0,0 +1,420 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Ordering;
+use std::collections::binary_heap::BinaryHeap;
+use std::f32;
+use std::mem;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use core::codec::Codec;
+use core::index::reader::LeafReaderContext;
+use core::search::collector::{Collector, ParallelLeafCollector, SearchCollector};
+use core::search::scorer::Scorer;
+use core::search::sort_field::{
+    ComparatorValue, FieldComparator, FieldComparatorEnum, FieldDoc, ScoreDocHit, Sort, SortField,
+    SortFieldType, TopDocs, TopFieldDocs,
+};
+use core::util::{DocId, VariantValue};
+use error::{ErrorKind::IllegalState, Result, ResultExt};
+
+/// A sort value with the direction of its `SortField`, so that hits compare
+/// by `VariantValue`'s `Ord` without looking up the sort.
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum SortKey {
+    Asc(VariantValue),
+    Desc(VariantValue),
+}
+
+impl SortKey {
+    fn into_value(self) -> VariantValue {
+        match self {
+            SortKey::Asc(v) | SortKey::Desc(v) => v,
+        }
+    }
+}
+
+impl Ord for SortKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (SortKey::Asc(v1), SortKey::Asc(v2)) => v1.cmp(v2),
+            (SortKey::Desc(v1), SortKey::Desc(v2)) => v2.cmp(v1),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl PartialOrd for SortKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// A collected hit, the greater the less competitive, so that the least
+/// competitive hit is on top of the `BinaryHeap`. Ties are broken by doc id.
+#[derive(Debug)]
+struct FieldHit {
+    keys: Vec<SortKey>,
+    doc: DocId,
+    score: f32,
+}
+
+impl Eq for FieldHit {}
+
+impl PartialEq for FieldHit {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Ord for FieldHit {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.keys
+            .cmp(&other.keys)
+            .then_with(|| self.doc.cmp(&other.doc))
+    }
+}
+
+impl PartialOrd for FieldHit {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+struct TopFieldBaseCollector {
+    sort_fields: Vec<SortField>,
+    /// one comparator of a single slot per sort field, used to read the sort
+    /// values of the collected docs
+    comparators: Vec<FieldComparatorEnum>,
+    /// whether the values of each comparator sort in descending order
+    descending: Vec<bool>,
+    needs_scores: bool,
+
+    pq: BinaryHeap<FieldHit>,
+    num_hits: usize,
+    total_hits: usize,
+    max_score: f32,
+    cur_doc_base: DocId,
+}
+
+impl TopFieldBaseCollector {
+    fn new(sort_fields: Vec<SortField>, num_hits: usize) -> Self {
+        let comparators: Vec<FieldComparatorEnum> = sort_fields
+            .iter()
+            .map(|f| f.get_comparator(1, f.missing_value()))
+            .collect();
+        // relevance sorts by descending score unless reversed
+        let descending = sort_fields
+            .iter()
+            .zip(&comparators)
+            .map(|(f, c)| f.is_reverse() != (c.get_type() == SortFieldType::Score))
+            .collect();
+        let needs_scores = sort_fields.iter().any(|f| f.needs_scores());
+        Self {
+            sort_fields,
+            comparators,
+            descending,
+            needs_scores,
+            pq: BinaryHeap::with_capacity(num_hits),
+            num_hits,
+            total_hits: 0,
+            max_score: f32::NEG_INFINITY,
+            cur_doc_base: 0,
+        }
+    }
+
+    fn set_next_reader<C: Codec>(&mut self, reader: &LeafReaderContext<'_, C>) -> Result<()> {
+        self.cur_doc_base = reader.doc_base;
+        for comparator in &mut self.comparators {
+            comparator.get_information_from_reader(reader)?;
+        }
+        Ok(())
+    }
+
+    fn add_hit(&mut self, hit: FieldHit) {
+        if self.pq.len() < self.num_hits {
+            self.pq.push(hit);
+        } else if let Some(mut bottom) = self.pq.peek_mut() {
+            if hit < *bottom {
+                *bottom = hit;
+            }
+        }
+    }
+
+    /// Returns the top docs collected, best first.
+    fn top_docs(&mut self) -> TopDocs {
+        let hits = mem::take(&mut self.pq).into_sorted_vec();
+        let score_docs = hits
+            .into_iter()
+            .map(|hit| {
+                let fields = hit.keys.into_iter().map(SortKey::into_value).collect();
+                ScoreDocHit::Field(FieldDoc::new(hit.doc, hit.score, fields))
+            })
+            .collect();
+        let max_score = if self.needs_scores && self.total_hits > 0 {
+            self.max_score
+        } else {
+            f32::NAN
+        };
+        TopDocs::Field(TopFieldDocs {
+            total_hits: self.total_hits,
+            score_docs,
+            max_score,
+            fields: self.sort_fields.clone(),
+        })
+    }
+}
+
+impl Collector for TopFieldBaseCollector {
+    fn needs_scores(&self) -> bool {
+        self.needs_scores
+    }
+
+    fn collect<S: Scorer + ?Sized>(&mut self, doc: DocId, scorer: &mut S) -> Result<()> {
+        self.total_hits += 1;
+        let score = if self.needs_scores {
+            let score = scorer.score()?;
+            self.max_score = self.max_score.max(score);
+            score
+        } else {
+            f32::NAN
+        };
+
+        let mut keys = Vec::with_capacity(self.comparators.len());
+        for (comparator, &desc) in self.comparators.iter_mut().zip(&self.descending) {
+            let value = if comparator.get_type() == SortFieldType::Score {
+                ComparatorValue::Score(score)
+            } else {
+                ComparatorValue::Doc(doc)
+            };
+            comparator.copy(0, value)?;
+            let value = comparator.value(0);
+            keys.push(if desc {
+                SortKey::Desc(value)
+            } else {
+                SortKey::Asc(value)
+            });
+        }
+        self.add_hit(FieldHit {
+            keys,
+            doc: doc + self.cur_doc_base,
+            score,
+        });
+        Ok(())
+    }
+}
+
+/// A `Collector` which keeps the top `num_hits` docs by the fields of a
+/// `Sort` rather than by score, like Lucene's `TopFieldCollector`.
+///
+/// The sort values of each doc are read by the `FieldComparator` of its
+/// `SortField`, eg. from numeric doc values, and compared by `VariantValue`'s
+/// `Ord`, a reversed field sorting in descending order. Docs with equal sort
+/// values are ordered by doc id. The sort values are returned in the
+/// `fields` of each `FieldDoc`.
+pub struct TopFieldCollector {
+    base: TopFieldBaseCollector,
+
+    channel: Option<(Sender<LeafTopFieldDocs>, Receiver<LeafTopFieldDocs>)>,
+}
+
+impl TopFieldCollector {
+    pub fn new(sort: &Sort, num_hits: usize) -> Self {
+        let base = TopFieldBaseCollector::new(sort.get_sort().to_vec(), num_hits);
+        Self {
+            base,
+            channel: None,
+        }
+    }
+
+    /// Returns the top docs that were collected by this collector.
+    pub fn top_docs(&mut self) -> TopDocs {
+        self.base.top_docs()
+    }
+}
+
+impl SearchCollector for TopFieldCollector {
+    type LC = TopFieldLeafCollector;
+
+    fn set_next_reader<C: Codec>(&mut self, reader: &LeafReaderContext<'_, C>) -> Result<()> {
+        self.base.set_next_reader(reader)
+    }
+
+    fn support_parallel(&self) -> bool {
+        true
+    }
+
+    fn init_parallel(&mut self) {
+        if self.channel.is_none() {
+            self.channel = Some(channel());
+        }
+    }
+
+    fn leaf_collector<C: Codec>(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+    ) -> Result<TopFieldLeafCollector> {
+        let mut collector =
+            TopFieldBaseCollector::new(self.base.sort_fields.clone(), self.base.num_hits);
+        collector.set_next_reader(reader)?;
+        Ok(TopFieldLeafCollector {
+            collector,
+            channel: self.channel.as_ref().unwrap().0.clone(),
+        })
+    }
+
+    fn finish_parallel(&mut self) -> Result<()> {
+        let channel = self.channel.take();
+        if let Some((sender, receiver)) = channel {
+            drop(sender);
+            while let Ok(docs) = receiver.recv() {
+                self.base.total_hits += docs.total_hits;
+                self.base.max_score = self.base.max_score.max(docs.max_score);
+                for hit in docs.hits {
+                    self.base.add_hit(hit);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Collector for TopFieldCollector {
+    fn needs_scores(&self) -> bool {
+        self.base.needs_scores()
+    }
+
+    fn collect<S: Scorer + ?Sized>(&mut self, doc: DocId, scorer: &mut S) -> Result<()> {
+        self.base.collect(doc, scorer)
+    }
+}
+
+struct LeafTopFieldDocs {
+    hits: Vec<FieldHit>,
+    total_hits: usize,
+    max_score: f32,
+}
+
+pub struct TopFieldLeafCollector {
+    collector: TopFieldBaseCollector,
+    channel: Sender<LeafTopFieldDocs>,
+}
+
+impl ParallelLeafCollector for TopFieldLeafCollector {
+    fn finish_leaf(&mut self) -> Result<()> {
+        let hits = mem::take(&mut self.collector.pq);
+        let top_docs = LeafTopFieldDocs {
+            hits: hits.into_vec(),
+            total_hits: self.collector.total_hits,
+            max_score: self.collector.max_score,
+        };
+        self.channel
+            .send(top_docs)
+            .chain_err(|| IllegalState("channel unexpected closed before search complete".into()))
+    }
+}
+
+impl Collector for TopFieldLeafCollector {
+    fn needs_scores(&self) -> bool {
+        self.collector.needs_scores()
+    }
+
+    fn collect<S: Scorer + ?Sized>(&mut self, doc: DocId, scorer: &mut S) -> Result<()> {
+        self.collector.collect(doc, scorer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::index::tests::*;
+    use core::search::sort_field::SimpleSortField;
+    use core::search::tests::*;
+
+    fn sort_by_price(reverse: bool) -> Sort {
+        let field = SimpleSortField::new("price".into(), SortFieldType::Long, reverse);
+        Sort::new(vec![SortField::Simple(field)])
+    }
+
+    /// collects all the docs of each leaf, returns the `(doc, fields)` of the
+    /// top docs
+    fn collect_top(
+        collector: &mut TopFieldCollector,
+        leaves: Vec<Vec<i64>>,
+    ) -> Vec<(DocId, Vec<VariantValue>)> {
+        let leaves: Vec<_> = leaves
+            .into_iter()
+            .map(MockLeafReader::with_numeric_values)
+            .collect();
+        collect_all_docs(collector, leaves);
+        let top_docs = collector.top_docs();
+        top_docs
+            .score_docs()
+            .iter()
+            .map(|hit| match hit {
+                ScoreDocHit::Field(f) => (f.doc, f.fields.clone()),
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sort_by_numeric_field() {
+        let prices = vec![vec![30, 10, 50, 10], vec![40, 5, 20]];
+
+        let mut collector = TopFieldCollector::new(&sort_by_price(false), 4);
+        assert!(!collector.needs_scores());
+        let top = collect_top(&mut collector, prices.clone());
+        // docs 1 and 3 tie, the lower doc id comes first
+        assert_eq!(
+            top,
+            vec![
+                (5, vec![VariantValue::Long(5)]),
+                (1, vec![VariantValue::Long(10)]),
+                (3, vec![VariantValue::Long(10)]),
+                (6, vec![VariantValue::Long(20)]),
+            ]
+        );
+
+        let mut collector = TopFieldCollector::new(&sort_by_price(true), 3);
+        let top = collect_top(&mut collector, prices);
+        assert_eq!(
+            top,
+            vec![
+                (2, vec![VariantValue::Long(50)]),
+                (4, vec![VariantValue::Long(40)]),
+                (0, vec![VariantValue::Long(30)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sort_by_field_then_score() {
+        let sort = Sort::new(vec![
+            sort_by_price(false).get_sort()[0].clone(),
+            SortField::new_score(),
+        ]);
+        let mut collector = TopFieldCollector::new(&sort, 2);
+        assert!(collector.needs_scores());
+        // the mock score is the doc id of the leaf
+        let top = collect_top(&mut collector, vec![vec![7, 3, 3, 9]]);
+        assert_eq!(
+            top,
+            vec![
+                (2, vec![VariantValue::Long(3), VariantValue::Float(2.0)]),
+                (1, vec![VariantValue::Long(3), VariantValue::Float(1.0)]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,206 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::codec::Codec;
+use core::index::reader::LeafReaderContext;
+use core::search::collector::{Collector, ParallelLeafCollector, SearchCollector};
+use core::search::scorer::{ScoreMode, Scorer};
+use core::search::NO_MORE_DOCS;
+use error::{ErrorKind, Result};
+
+use crossbeam::channel::{unbounded, Receiver, Sender};
+
+/// A collector which only counts the matched docs, eg. the total hits of a
+/// paginated search, it never needs scores.
+pub struct TotalHitCountCollector {
+    total_hits: i32,
+    channel: Option<(Sender<i32>, Receiver<i32>)>,
+}
+
+impl TotalHitCountCollector {
+    pub fn new() -> Self {
+        TotalHitCountCollector {
+            total_hits: 0,
+            channel: None,
+        }
+    }
+
+    pub fn total_hits(&self) -> i32 {
+        self.total_hits
+    }
+
+    /// Counts the docs of `scorer` after its current one, eg. all of them if
+    /// it is not positioned yet, and adds them to the total hits. The score
+    /// mode of `scorer` is set to `ScoreMode::CountOnly` first so that it can
+    /// skip the scoring work. Returns the number of docs counted.
+    pub fn count<S: Scorer + ?Sized>(&mut self, scorer: &mut S) -> Result<i32> {
+        scorer.set_score_mode(ScoreMode::CountOnly);
+        let mut count = 0;
+        while scorer.next()? != NO_MORE_DOCS {
+            count += 1;
+        }
+        self.total_hits += count;
+        Ok(count)
+    }
+}
+
+impl Default for TotalHitCountCollector {
+    fn default() -> Self {
+        TotalHitCountCollector::new()
+    }
+}
+
+impl SearchCollector for TotalHitCountCollector {
+    type LC = TotalHitsCountLeafCollector;
+    fn set_next_reader<C: Codec>(&mut self, _reader: &LeafReaderContext<'_, C>) -> Result<()> {
+        Ok(())
+    }
+
+    fn support_parallel(&self) -> bool {
+        true
+    }
+
+    fn init_parallel(&mut self) {
+        if self.channel.is_none() {
+            self.channel = Some(unbounded());
+        }
+    }
+
+    fn leaf_collector<C: Codec>(
+        &self,
+        _reader: &LeafReaderContext<'_, C>,
+    ) -> Result<TotalHitsCountLeafCollector> {
+        Ok(TotalHitsCountLeafCollector {
+            count: 0,
+            sender: self.channel.as_ref().unwrap().0.clone(),
+        })
+    }
+
+    fn finish_parallel(&mut self) -> Result<()> {
+        let channel = self.channel.take();
+        // iff all the `weight.create_scorer(leaf_reader)` return None, the channel won't
+        // inited and thus stay None
+        if let Some((sender, receiver)) = channel {
+            drop(sender);
+            while let Ok(v) = receiver.recv() {
+                self.total_hits += v;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Collector for TotalHitCountCollector {
+    fn needs_scores(&self) -> bool {
+        false
+    }
+
+    fn collect<S: Scorer + ?Sized>(&mut self, _doc: i32, _scorer: &mut S) -> Result<()> {
+        self.total_hits += 1;
+        Ok(())
+    }
+}
+
+pub struct TotalHitsCountLeafCollector {
+    count: i32,
+    sender: Sender<i32>,
+}
+
+impl Collector for TotalHitsCountLeafCollector {
+    fn needs_scores(&self) -> bool {
+        false
+    }
+
+    fn collect<S: Scorer + ?Sized>(&mut self, _doc: i32, _scorer: &mut S) -> Result<()> {
+        self.count += 1;
+        Ok(())
+    }
+}
+
+impl ParallelLeafCollector for TotalHitsCountLeafCollector {
+    fn finish_leaf(&mut self) -> Result<()> {
+        self.sender.send(self.count).map_err(|e| {
+            ErrorKind::IllegalState(format!(
+                "channel unexpected closed before search complete with err: {:?}",
+                e
+            ))
+            .into()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::tests::*;
+    use core::search::DocIterator;
+    use core::util::DocId;
+    use std::sync::atomic::Ordering;
+
+    /// records the score mode the collector sets
+    struct ScoreModeScorer {
+        scorer: MockCountingScorer,
+        mode: ScoreMode,
+    }
+
+    impl Scorer for ScoreModeScorer {
+        fn score(&mut self) -> Result<f32> {
+            self.scorer.score()
+        }
+
+        fn set_score_mode(&mut self, mode: ScoreMode) {
+            self.mode = mode;
+        }
+    }
+
+    impl DocIterator for ScoreModeScorer {
+        fn doc_id(&self) -> DocId {
+            self.scorer.doc_id()
+        }
+
+        fn next(&mut self) -> Result<DocId> {
+            self.scorer.next()
+        }
+
+        fn advance(&mut self, target: DocId) -> Result<DocId> {
+            self.scorer.advance(target)
+        }
+
+        fn cost(&self) -> usize {
+            self.scorer.cost()
+        }
+    }
+
+    #[test]
+    fn test_count_without_scoring() {
+        let scorer = MockCountingScorer::new(vec![1, 3, 4, 8, 10]);
+        let score_calls = scorer.score_calls.clone();
+        let mut scorer = ScoreModeScorer {
+            scorer,
+            mode: ScoreMode::Complete,
+        };
+
+        let mut collector = TotalHitCountCollector::default();
+        assert!(!collector.needs_scores());
+        assert_eq!(collector.count(&mut scorer).unwrap(), 5);
+        assert_eq!(scorer.mode, ScoreMode::CountOnly);
+        assert_eq!(score_calls.load(Ordering::SeqCst), 0);
+
+        // counts add up, the current doc of the scorer is not counted
+        let mut scorer = MockCountingScorer::new(vec![2, 5, 7]);
+        scorer.next().unwrap();
+        assert_eq!(collector.count(&mut scorer).unwrap(), 2);
+        assert_eq!(collector.total_hits(), 7);
+    }
+}
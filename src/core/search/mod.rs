@@ -49,6 +49,9 @@ error_chain! {
         SearchFailed {
             description("Search failed")
         }
+        TimeExceeded {
+            description("Time exceeded")
+        }
     }
 }
 
@@ -153,6 +156,113 @@ pub trait DocIterator: Send {
     fn approximate_advance(&mut self, target: DocId) -> Result<DocId> {
         self.advance(target)
     }
+
+    /// Returns a two phase view of this iterator if it supports two phase
+    /// iteration, so that collectors can iterate over approximations and
+    /// only confirm matches lazily. Default to `None`.
+    fn as_two_phase(&mut self) -> Option<&mut dyn TwoPhaseIterator> {
+        None
+    }
+}
+
+impl DocIterator for Box<dyn DocIterator> {
+    fn doc_id(&self) -> DocId {
+        (**self).doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        (**self).next()
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        (**self).advance(target)
+    }
+
+    fn slow_advance(&mut self, target: DocId) -> Result<DocId> {
+        (**self).slow_advance(target)
+    }
+
+    fn cost(&self) -> usize {
+        (**self).cost()
+    }
+
+    fn matches(&mut self) -> Result<bool> {
+        (**self).matches()
+    }
+
+    fn match_cost(&self) -> f32 {
+        (**self).match_cost()
+    }
+
+    fn support_two_phase(&self) -> bool {
+        (**self).support_two_phase()
+    }
+
+    fn approximate_next(&mut self) -> Result<DocId> {
+        (**self).approximate_next()
+    }
+
+    fn approximate_advance(&mut self, target: DocId) -> Result<DocId> {
+        (**self).approximate_advance(target)
+    }
+
+    fn as_two_phase(&mut self) -> Option<&mut dyn TwoPhaseIterator> {
+        (**self).as_two_phase()
+    }
+}
+
+mod two_phase {
+    pub trait Sealed {}
+
+    impl<T: super::DocIterator + ?Sized> Sealed for T {}
+}
+
+/// The two phase view of a `DocIterator`, obtained by `DocIterator::as_two_phase`.
+///
+/// The approximation is iterated with `next_approximation`/`advance_approximation`
+/// and each approximate doc must be confirmed with `confirm_match` before it's
+/// considered a real match. This trait is sealed and implemented for all
+/// `DocIterator`s by forwarding to their approximate methods.
+pub trait TwoPhaseIterator: two_phase::Sealed {
+    /// Returns the doc id the approximation is currently on.
+    fn approximation_doc_id(&self) -> DocId;
+
+    /// Advances the approximation to its next doc, works the same as
+    /// `DocIterator::approximate_next`.
+    fn next_approximation(&mut self) -> Result<DocId>;
+
+    /// Advances the approximation to the first doc on or after `target`, works
+    /// the same as `DocIterator::approximate_advance`.
+    fn advance_approximation(&mut self, target: DocId) -> Result<DocId>;
+
+    /// Returns whether the current approximate doc is a real match, see
+    /// `DocIterator::matches`.
+    fn confirm_match(&mut self) -> Result<bool>;
+
+    /// Expected cost of a single `confirm_match` call, see `DocIterator::match_cost`.
+    fn confirm_match_cost(&self) -> f32;
+}
+
+impl<T: DocIterator + ?Sized> TwoPhaseIterator for T {
+    fn approximation_doc_id(&self) -> DocId {
+        self.doc_id()
+    }
+
+    fn next_approximation(&mut self) -> Result<DocId> {
+        self.approximate_next()
+    }
+
+    fn advance_approximation(&mut self, target: DocId) -> Result<DocId> {
+        self.approximate_advance(target)
+    }
+
+    fn confirm_match(&mut self) -> Result<bool> {
+        self.matches()
+    }
+
+    fn confirm_match_cost(&self) -> f32 {
+        self.match_cost()
+    }
 }
 
 impl Eq for dyn DocIterator {}
@@ -208,11 +318,16 @@ pub trait DocIdSet: Send + Sync {
 #[cfg(test)]
 pub mod tests {
     use super::query::Weight;
-    use super::scorer::Scorer;
+    use super::scorer::{two_phase_next, Scorer};
+    use super::similarity::SimScorer;
     use super::*;
-    use core::codec::Codec;
-    use core::index::reader::LeafReaderContext;
+    use core::codec::{Codec, PostingIterator, SeekStatus, TermIterator};
+    use core::index::reader::{IndexReader, LeafReaderContext};
+    use core::index::tests::{MockIndexReader, MockLeafReader};
+    use core::search::collector::SearchCollector;
     use std::fmt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
 
     pub struct MockDocIterator {
         doc_ids: Vec<DocId>,
@@ -310,6 +425,374 @@ pub mod tests {
         }
     }
 
+    /// A mock scorer which records how many times `score()` and `advance()`
+    /// are called, used to verify that scorers skip unnecessary work.
+    pub struct MockCountingScorer {
+        scorer: MockSimpleScorer<MockDocIterator>,
+        pub score_calls: Arc<AtomicUsize>,
+        pub advance_calls: Arc<AtomicUsize>,
+    }
+
+    impl MockCountingScorer {
+        pub fn new(docs: Vec<DocId>) -> MockCountingScorer {
+            MockCountingScorer {
+                scorer: create_mock_scorer(docs),
+                score_calls: Arc::new(AtomicUsize::new(0)),
+                advance_calls: Arc::new(AtomicUsize::new(0)),
+            }
+        }
+    }
+
+    impl Scorer for MockCountingScorer {
+        fn score(&mut self) -> Result<f32> {
+            self.score_calls.fetch_add(1, Ordering::SeqCst);
+            self.scorer.score()
+        }
+    }
+
+    impl DocIterator for MockCountingScorer {
+        fn doc_id(&self) -> DocId {
+            self.scorer.doc_id()
+        }
+
+        fn next(&mut self) -> Result<DocId> {
+            self.scorer.next()
+        }
+
+        fn advance(&mut self, target: DocId) -> Result<DocId> {
+            self.advance_calls.fetch_add(1, Ordering::SeqCst);
+            self.scorer.advance(target)
+        }
+
+        fn cost(&self) -> usize {
+            self.scorer.cost()
+        }
+    }
+
+    /// A mock `PostingIterator` built from `(doc, positions)` pairs, the freq
+    /// of each doc is the number of its positions.
+    pub struct MockPostingIterator {
+        postings: Vec<(DocId, Vec<i32>)>,
+        offset: usize,
+        doc_id: DocId,
+        position_idx: usize,
+    }
+
+    impl MockPostingIterator {
+        pub fn new(postings: Vec<(DocId, Vec<i32>)>) -> MockPostingIterator {
+            MockPostingIterator {
+                postings,
+                offset: 0,
+                doc_id: -1,
+                position_idx: 0,
+            }
+        }
+    }
+
+    impl DocIterator for MockPostingIterator {
+        fn doc_id(&self) -> DocId {
+            self.doc_id
+        }
+
+        fn next(&mut self) -> Result<DocId> {
+            let target = self.doc_id + 1;
+            self.advance(target)
+        }
+
+        fn advance(&mut self, target: DocId) -> Result<DocId> {
+            while self.offset < self.postings.len() && self.postings[self.offset].0 < target {
+                self.offset += 1;
+            }
+            self.position_idx = 0;
+            self.doc_id = if self.offset < self.postings.len() {
+                self.postings[self.offset].0
+            } else {
+                NO_MORE_DOCS
+            };
+            Ok(self.doc_id)
+        }
+
+        fn cost(&self) -> usize {
+            self.postings.len()
+        }
+    }
+
+    impl PostingIterator for MockPostingIterator {
+        fn freq(&self) -> Result<i32> {
+            Ok(self.postings[self.offset].1.len() as i32)
+        }
+
+        fn next_position(&mut self) -> Result<i32> {
+            let pos = self.postings[self.offset].1[self.position_idx];
+            self.position_idx += 1;
+            Ok(pos)
+        }
+
+        fn start_offset(&self) -> Result<i32> {
+            Ok(-1)
+        }
+
+        fn end_offset(&self) -> Result<i32> {
+            Ok(-1)
+        }
+
+        fn payload(&self) -> Result<Payload> {
+            Ok(Payload::new())
+        }
+    }
+
+    /// An in memory `Scorer` and `PostingIterator` built from `(doc, freq,
+    /// positions)` triples sorted by doc, so that scorers consuming either can
+    /// be tested without an index. The postings are a `MockPostingIterator`.
+    ///
+    /// The score and `Scorer::freq` of a doc are its `freq`, which may differ
+    /// from the number of its positions, eg. a sloppy freq, while
+    /// `PostingIterator::freq` is the number of positions to read.
+    pub struct MemoryPostingsScorer {
+        postings: MockPostingIterator,
+        freqs: Vec<f32>,
+    }
+
+    impl MemoryPostingsScorer {
+        pub fn new(postings: Vec<(DocId, f32, Vec<u32>)>) -> MemoryPostingsScorer {
+            debug_assert!(postings.windows(2).all(|w| w[0].0 < w[1].0));
+            let freqs = postings.iter().map(|p| p.1).collect();
+            let postings = postings
+                .into_iter()
+                .map(|(doc, _, positions)| (doc, positions.into_iter().map(|p| p as i32).collect()))
+                .collect();
+            MemoryPostingsScorer {
+                postings: MockPostingIterator::new(postings),
+                freqs,
+            }
+        }
+    }
+
+    impl Scorer for MemoryPostingsScorer {
+        fn score(&mut self) -> Result<f32> {
+            Ok(self.freqs[self.postings.offset])
+        }
+
+        fn freq(&self) -> Result<f32> {
+            Ok(self.freqs[self.postings.offset])
+        }
+    }
+
+    impl DocIterator for MemoryPostingsScorer {
+        fn doc_id(&self) -> DocId {
+            self.postings.doc_id()
+        }
+
+        fn next(&mut self) -> Result<DocId> {
+            self.postings.next()
+        }
+
+        fn advance(&mut self, target: DocId) -> Result<DocId> {
+            self.postings.advance(target)
+        }
+
+        fn cost(&self) -> usize {
+            self.postings.cost()
+        }
+    }
+
+    impl PostingIterator for MemoryPostingsScorer {
+        fn freq(&self) -> Result<i32> {
+            PostingIterator::freq(&self.postings)
+        }
+
+        fn next_position(&mut self) -> Result<i32> {
+            self.postings.next_position()
+        }
+
+        fn start_offset(&self) -> Result<i32> {
+            self.postings.start_offset()
+        }
+
+        fn end_offset(&self) -> Result<i32> {
+            self.postings.end_offset()
+        }
+
+        fn payload(&self) -> Result<Payload> {
+            self.postings.payload()
+        }
+    }
+
+    /// A mock `TermIterator` over `(term, postings)` pairs sorted by term, the
+    /// postings are the same as `MockPostingIterator::new`.
+    pub struct MockTermIterator {
+        terms: Vec<(Vec<u8>, Vec<(DocId, Vec<i32>)>)>,
+        // `terms.len()` means unpositioned, eg. before the first `next`
+        idx: usize,
+        /// the number of calls to `next`
+        pub next_calls: usize,
+    }
+
+    impl MockTermIterator {
+        pub fn new(terms: Vec<(&str, Vec<(DocId, Vec<i32>)>)>) -> MockTermIterator {
+            let mut terms: Vec<_> = terms
+                .into_iter()
+                .map(|(t, postings)| (t.as_bytes().to_vec(), postings))
+                .collect();
+            terms.sort_by(|a, b| a.0.cmp(&b.0));
+            let idx = terms.len();
+            MockTermIterator {
+                terms,
+                idx,
+                next_calls: 0,
+            }
+        }
+    }
+
+    impl TermIterator for MockTermIterator {
+        type Postings = MockPostingIterator;
+        type TermState = ();
+
+        fn next(&mut self) -> Result<Option<Vec<u8>>> {
+            self.next_calls += 1;
+            self.idx = if self.idx == self.terms.len() {
+                0
+            } else {
+                self.idx + 1
+            };
+            if self.idx < self.terms.len() {
+                Ok(Some(self.terms[self.idx].0.clone()))
+            } else {
+                // stay exhausted
+                self.idx = self.terms.len() + 1;
+                Ok(None)
+            }
+        }
+
+        fn seek_ceil(&mut self, text: &[u8]) -> Result<SeekStatus> {
+            match self.terms.iter().position(|t| t.0.as_slice() >= text) {
+                Some(idx) => {
+                    self.idx = idx;
+                    if self.terms[idx].0.as_slice() == text {
+                        Ok(SeekStatus::Found)
+                    } else {
+                        Ok(SeekStatus::NotFound)
+                    }
+                }
+                None => {
+                    self.idx = self.terms.len();
+                    Ok(SeekStatus::End)
+                }
+            }
+        }
+
+        fn seek_exact_ord(&mut self, ord: i64) -> Result<()> {
+            self.idx = ord as usize;
+            Ok(())
+        }
+
+        fn term(&self) -> Result<&[u8]> {
+            Ok(&self.terms[self.idx].0)
+        }
+
+        fn ord(&self) -> Result<i64> {
+            Ok(self.idx as i64)
+        }
+
+        fn doc_freq(&mut self) -> Result<i32> {
+            Ok(self.terms[self.idx].1.len() as i32)
+        }
+
+        fn total_term_freq(&mut self) -> Result<i64> {
+            Ok(self.terms[self.idx]
+                .1
+                .iter()
+                .map(|p| p.1.len() as i64)
+                .sum())
+        }
+
+        fn postings_with_flags(&mut self, _flags: u16) -> Result<MockPostingIterator> {
+            Ok(MockPostingIterator::new(self.terms[self.idx].1.clone()))
+        }
+    }
+
+    /// A mock `SimScorer` which scores a doc by its freq.
+    pub struct MockSimScorer;
+
+    impl SimScorer for MockSimScorer {
+        fn score(&mut self, _doc: DocId, freq: f32) -> Result<f32> {
+            Ok(freq)
+        }
+
+        fn compute_slop_factor(&self, distance: i32) -> f32 {
+            1.0 / (distance as f32 + 1.0)
+        }
+    }
+
+    /// A mock two phase scorer, iterates `approximation` docs but only the
+    /// `matching` ones are confirmed by `matches()`. Scores by doc id.
+    pub struct MockTwoPhaseScorer {
+        approximation: MockDocIterator,
+        matching: Vec<DocId>,
+        pub matches_calls: Arc<AtomicUsize>,
+    }
+
+    impl MockTwoPhaseScorer {
+        pub fn new(approximation: Vec<DocId>, matching: Vec<DocId>) -> MockTwoPhaseScorer {
+            MockTwoPhaseScorer {
+                approximation: MockDocIterator::new(approximation),
+                matching,
+                matches_calls: Arc::new(AtomicUsize::new(0)),
+            }
+        }
+    }
+
+    impl Scorer for MockTwoPhaseScorer {
+        fn score(&mut self) -> Result<f32> {
+            Ok(self.doc_id() as f32)
+        }
+    }
+
+    impl DocIterator for MockTwoPhaseScorer {
+        fn doc_id(&self) -> DocId {
+            self.approximation.doc_id()
+        }
+
+        fn next(&mut self) -> Result<DocId> {
+            self.approximate_next()?;
+            two_phase_next(self)
+        }
+
+        fn advance(&mut self, target: DocId) -> Result<DocId> {
+            self.approximate_advance(target)?;
+            two_phase_next(self)
+        }
+
+        fn cost(&self) -> usize {
+            self.approximation.cost()
+        }
+
+        fn matches(&mut self) -> Result<bool> {
+            self.matches_calls.fetch_add(1, Ordering::SeqCst);
+            Ok(self.matching.contains(&self.doc_id()))
+        }
+
+        fn match_cost(&self) -> f32 {
+            1.0
+        }
+
+        fn support_two_phase(&self) -> bool {
+            true
+        }
+
+        fn approximate_next(&mut self) -> Result<DocId> {
+            self.approximation.next()
+        }
+
+        fn approximate_advance(&mut self, target: DocId) -> Result<DocId> {
+            self.approximation.advance(target)
+        }
+
+        fn as_two_phase(&mut self) -> Option<&mut dyn TwoPhaseIterator> {
+            Some(self)
+        }
+    }
+
     pub struct MockSimpleWeight {
         docs: Vec<DocId>,
     }
@@ -364,4 +847,21 @@ pub mod tests {
     pub fn create_mock_doc_iterator(docs: Vec<DocId>) -> MockDocIterator {
         MockDocIterator::new(docs)
     }
+
+    /// Collects all the docs of each of `leaves` with `collector`, scored by
+    /// their doc id in the leaf as by `create_mock_scorer`.
+    pub fn collect_all_docs<T: SearchCollector>(collector: &mut T, leaves: Vec<MockLeafReader>) {
+        let index_reader = MockIndexReader::new(leaves);
+        for leaf in index_reader.leaves() {
+            let mut scorer = create_mock_scorer((0..leaf.reader.max_doc()).collect());
+            collector.set_next_reader(&leaf).unwrap();
+            loop {
+                let doc = scorer.next().unwrap();
+                if doc == NO_MORE_DOCS {
+                    break;
+                }
+                collector.collect(doc, &mut scorer).unwrap();
+            }
+        }
+    }
 }
@@ -19,7 +19,7 @@ use core::index::reader::LeafReaderContext;
 use core::search::explanation::Explanation;
 use core::search::query::{ConstantScoreQuery, MatchAllDocsQuery, Query, TermQuery, Weight};
 use core::search::scorer::{
-    ConjunctionScorer, DisjunctionSumScorer, ReqNotScorer, ReqOptScorer, Scorer,
+    build_conjunction, BooleanScorer, DisjunctionSumScorer, ReqNotScorer, ReqOptScorer, Scorer,
 };
 use core::search::searcher::SearchPlanBuilder;
 use core::util::DocId;
@@ -198,29 +198,48 @@ impl<C: Codec> Weight<C> for BooleanWeight<C> {
         leaf_reader: &LeafReaderContext<'_, C>,
     ) -> Result<Option<Box<dyn Scorer>>> {
         let must_scorer: Option<Box<dyn Scorer>> = if !self.must_weights.is_empty() {
-            let mut scorers = vec![];
+            // the suppliers are built in cost order, cheapest clause leads
+            let mut suppliers = vec![];
             for weight in &self.must_weights {
-                if let Some(scorer) = weight.create_scorer(leaf_reader)? {
-                    scorers.push(scorer);
+                if let Some(supplier) = weight.scorer_supplier(leaf_reader)? {
+                    suppliers.push(supplier);
                 } else {
                     return Ok(None);
                 }
             }
-            if scorers.len() > 1 {
-                Some(Box::new(ConjunctionScorer::new(scorers)))
-            } else {
-                Some(scorers.remove(0))
-            }
+            Some(build_conjunction(suppliers)?)
         } else {
             None
         };
-        let should_scorer: Option<Box<dyn Scorer>> = {
-            let mut scorers = vec![];
-            for weight in &self.should_weights {
-                if let Some(scorer) = weight.create_scorer(leaf_reader)? {
-                    scorers.push(scorer);
-                }
+        let mut should_scorers = vec![];
+        for weight in &self.should_weights {
+            if let Some(scorer) = weight.create_scorer(leaf_reader)? {
+                should_scorers.push(scorer);
             }
+        }
+        let mut must_not_scorers = vec![];
+        for weight in &self.must_not_weights {
+            if let Some(scorer) = weight.create_scorer(leaf_reader)? {
+                must_not_scorers.push(scorer);
+            }
+        }
+
+        if self.min_should_match > 0 {
+            // the should clauses are required along with the must clauses,
+            // which `ReqOptScorer` can't express
+            if let Some(must) = must_scorer {
+                let scorer = BooleanScorer::new(
+                    vec![must],
+                    should_scorers,
+                    must_not_scorers,
+                    self.min_should_match,
+                )?;
+                return Ok(scorer.map(|s| Box::new(s) as Box<dyn Scorer>));
+            }
+        }
+
+        let should_scorer: Option<Box<dyn Scorer>> = {
+            let scorers = should_scorers;
             match scorers.len() {
                 0 => None,
                 // min_should_match
@@ -233,12 +252,7 @@ impl<C: Codec> Weight<C> for BooleanWeight<C> {
             }
         };
         let must_not_scorer: Option<Box<dyn Scorer>> = {
-            let mut scorers = vec![];
-            for weight in &self.must_not_weights {
-                if let Some(scorer) = weight.create_scorer(leaf_reader)? {
-                    scorers.push(scorer);
-                }
-            }
+            let mut scorers = must_not_scorers;
             match scorers.len() {
                 0 => None,
                 1 => Some(scorers.remove(0)),
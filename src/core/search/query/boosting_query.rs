@@ -18,8 +18,7 @@ use core::codec::Codec;
 use core::index::reader::LeafReaderContext;
 use core::search::explanation::Explanation;
 use core::search::query::{Query, TermQuery, Weight};
-use core::search::scorer::BoostingScorer;
-use core::search::scorer::Scorer;
+use core::search::scorer::{BoostingScorer, BoostingScorerSupplier, Scorer, ScorerSupplier};
 use core::search::searcher::SearchPlanBuilder;
 use core::util::DocId;
 use error::Result;
@@ -117,6 +116,24 @@ impl<C: Codec> Weight<C> for BoostingWeight<C> {
         }
     }
 
+    fn scorer_supplier(
+        &self,
+        leaf_reader: &LeafReaderContext<'_, C>,
+    ) -> Result<Option<Box<dyn ScorerSupplier>>> {
+        if let (Some(positive), Some(negative)) = (
+            self.positive_weight.scorer_supplier(leaf_reader)?,
+            self.negative_weight.scorer_supplier(leaf_reader)?,
+        ) {
+            Ok(Some(Box::new(BoostingScorerSupplier::new(
+                positive,
+                negative,
+                self.negative_boost,
+            ))))
+        } else {
+            Ok(None)
+        }
+    }
+
     fn query_type(&self) -> &'static str {
         BOOSTING_QUERY
     }
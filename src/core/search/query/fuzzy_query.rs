@@ -0,0 +1,193 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::any::Any;
+use std::fmt;
+
+use core::codec::{Codec, Terms};
+use core::doc::Term;
+use core::index::reader::LeafReaderContext;
+use core::search::explanation::Explanation;
+use core::search::query::{Query, TermQuery, Weight, DEFAULT_MAX_EXPANSIONS};
+use core::search::scorer::{FuzzyScorer, Scorer, MAX_SUPPORTED_EDITS};
+use core::search::searcher::SearchPlanBuilder;
+use core::search::DocIterator;
+use core::util::DocId;
+use error::{ErrorKind::IllegalArgument, Result};
+
+pub const FUZZY: &str = "fuzzy";
+
+/// A query that matches documents containing terms similar to `term`, eg.
+/// within `max_edits` Levenshtein distance of it, see `FuzzyScorer`.
+///
+/// By default no prefix is required to match exactly, transpositions count
+/// as a single edit and at most `DEFAULT_MAX_EXPANSIONS` terms are used.
+pub struct FuzzyQuery {
+    term: Term,
+    max_edits: usize,
+    prefix_length: usize,
+    max_expansions: usize,
+    transpositions: bool,
+    boost: f32,
+}
+
+impl FuzzyQuery {
+    pub fn new(term: Term, max_edits: usize, boost: f32) -> Result<FuzzyQuery> {
+        FuzzyQuery::with_params(term, max_edits, 0, DEFAULT_MAX_EXPANSIONS, true, boost)
+    }
+
+    pub fn with_params(
+        term: Term,
+        max_edits: usize,
+        prefix_length: usize,
+        max_expansions: usize,
+        transpositions: bool,
+        boost: f32,
+    ) -> Result<FuzzyQuery> {
+        if max_edits > MAX_SUPPORTED_EDITS {
+            bail!(IllegalArgument(format!(
+                "max_edits must be between 0 and {}, got {}",
+                MAX_SUPPORTED_EDITS, max_edits
+            )));
+        }
+        Ok(FuzzyQuery {
+            term,
+            max_edits,
+            prefix_length,
+            max_expansions,
+            transpositions,
+            boost,
+        })
+    }
+
+    pub fn term(&self) -> &Term {
+        &self.term
+    }
+}
+
+impl<C: Codec> Query<C> for FuzzyQuery {
+    fn create_weight(
+        &self,
+        _searcher: &dyn SearchPlanBuilder<C>,
+        _needs_scores: bool,
+    ) -> Result<Box<dyn Weight<C>>> {
+        Ok(Box::new(FuzzyWeight {
+            field: self.term.field.clone(),
+            text: self.term.text()?,
+            max_edits: self.max_edits,
+            prefix_length: self.prefix_length,
+            max_expansions: self.max_expansions,
+            transpositions: self.transpositions,
+            boost: self.boost,
+            weight: self.boost,
+        }))
+    }
+
+    fn extract_terms(&self) -> Vec<TermQuery> {
+        vec![]
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl fmt::Display for FuzzyQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "FuzzyQuery(field: {}, term: {}, max_edits: {}, prefix_length: {}, boost: {})",
+            &self.term.field(),
+            String::from_utf8_lossy(&self.term.bytes),
+            self.max_edits,
+            self.prefix_length,
+            self.boost
+        )
+    }
+}
+
+struct FuzzyWeight {
+    field: String,
+    text: String,
+    max_edits: usize,
+    prefix_length: usize,
+    max_expansions: usize,
+    transpositions: bool,
+    boost: f32,
+    weight: f32,
+}
+
+impl<C: Codec> Weight<C> for FuzzyWeight {
+    fn create_scorer(&self, reader: &LeafReaderContext<'_, C>) -> Result<Option<Box<dyn Scorer>>> {
+        if let Some(terms) = reader.reader.terms(&self.field)? {
+            let mut terms_iter = terms.iterator()?;
+            let scorer = FuzzyScorer::new(
+                &mut terms_iter,
+                &self.text,
+                self.max_edits,
+                self.prefix_length,
+                self.transpositions,
+                self.max_expansions,
+                self.weight,
+            )?;
+            Ok(scorer.map(|s| -> Box<dyn Scorer> { Box::new(s) }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn query_type(&self) -> &'static str {
+        FUZZY
+    }
+
+    fn normalize(&mut self, norm: f32, boost: f32) {
+        self.weight = norm * boost * self.boost;
+    }
+
+    fn value_for_normalization(&self) -> f32 {
+        self.weight * self.weight
+    }
+
+    fn needs_scores(&self) -> bool {
+        true
+    }
+
+    fn explain(&self, reader: &LeafReaderContext<'_, C>, doc: DocId) -> Result<Explanation> {
+        if let Some(mut scorer) = self.create_scorer(reader)? {
+            if scorer.advance(doc)? == doc {
+                return Ok(Explanation::new(
+                    true,
+                    scorer.score()?,
+                    format!("{}, weight of the best matching term", self),
+                    vec![],
+                ));
+            }
+        }
+        Ok(Explanation::new(
+            false,
+            0f32,
+            "no matching term".to_string(),
+            vec![],
+        ))
+    }
+}
+
+impl fmt::Display for FuzzyWeight {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "FuzzyWeight(field: {}, term: {}, max_edits: {}, weight: {})",
+            &self.field, &self.text, self.max_edits, self.weight
+        )
+    }
+}
@@ -114,22 +114,36 @@ impl fmt::Display for MatchAllDocsWeight {
     }
 }
 
-/// a `DocIterator` for all docs
+/// a `DocIterator` for all docs, scoring every doc with the same `score`,
+/// 1 by default
 pub struct AllDocsIterator {
     doc: DocId,
     max_doc: DocId,
+    score: f32,
 }
 
 impl AllDocsIterator {
     pub fn new(max_doc: DocId) -> AllDocsIterator {
+        Self::with_score(max_doc, 1f32)
+    }
+
+    pub fn with_score(max_doc: DocId, score: f32) -> AllDocsIterator {
         assert!(max_doc >= 0);
-        AllDocsIterator { doc: -1, max_doc }
+        AllDocsIterator {
+            doc: -1,
+            max_doc,
+            score,
+        }
     }
 }
 
 impl Scorer for AllDocsIterator {
     fn score(&mut self) -> Result<f32> {
-        Ok(1f32)
+        Ok(self.score)
+    }
+
+    fn get_max_score(&mut self, _up_to: DocId) -> Result<f32> {
+        Ok(self.score)
     }
 }
 
@@ -139,6 +153,9 @@ impl DocIterator for AllDocsIterator {
     }
 
     fn next(&mut self) -> Result<DocId> {
+        if self.doc == NO_MORE_DOCS {
+            return Ok(NO_MORE_DOCS);
+        }
         let target = self.doc + 1;
         self.advance(target)
     }
@@ -38,6 +38,14 @@ mod point_range_query;
 
 pub use self::point_range_query::*;
 
+mod fuzzy_query;
+
+pub use self::fuzzy_query::*;
+
+mod prefix_query;
+
+pub use self::prefix_query::*;
+
 mod query_string;
 
 pub use self::query_string::*;
@@ -61,7 +69,7 @@ pub use self::exists_query::*;
 use core::codec::Codec;
 use core::index::reader::LeafReaderContext;
 use core::search::explanation::Explanation;
-use core::search::scorer::{BatchScorer, Scorer};
+use core::search::scorer::{BatchScorer, Scorer, ScorerSupplier};
 use core::search::searcher::SearchPlanBuilder;
 use core::util::DocId;
 
@@ -138,6 +146,17 @@ pub trait Query<C: Codec>: Display {
 pub trait Weight<C: Codec>: Display {
     fn create_scorer(&self, reader: &LeafReaderContext<'_, C>) -> Result<Option<Box<dyn Scorer>>>;
 
+    /// Returns a `ScorerSupplier` which estimates the cost of the scorer before
+    /// building it, by default the scorer is built eagerly by `create_scorer()`.
+    fn scorer_supplier(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+    ) -> Result<Option<Box<dyn ScorerSupplier>>> {
+        Ok(self
+            .create_scorer(reader)?
+            .map(|s| -> Box<dyn ScorerSupplier> { Box::new(s) }))
+    }
+
     fn hash_code(&self) -> u32 {
         let key = format!("{}", self);
         let mut hasher = DefaultHasher::new();
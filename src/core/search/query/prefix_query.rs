@@ -0,0 +1,190 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::any::Any;
+use std::fmt;
+
+use core::codec::{Codec, Terms};
+use core::index::reader::LeafReaderContext;
+use core::search::explanation::Explanation;
+use core::search::query::{Query, TermQuery, Weight};
+use core::search::scorer::{PrefixScorer, Scorer};
+use core::search::searcher::SearchPlanBuilder;
+use core::search::DocIterator;
+use core::util::DocId;
+use error::Result;
+
+pub const PREFIX: &str = "prefix";
+
+/// Max number of terms a `PrefixQuery` expands to by default.
+pub const DEFAULT_MAX_EXPANSIONS: usize = 50;
+
+/// A query that matches documents containing a term starting with `prefix`,
+/// all the matching docs get the same score, eg. the boost of the query.
+///
+/// Only the first `max_expansions` matching terms in term order are used, an
+/// empty prefix matches every term of the field.
+pub struct PrefixQuery {
+    field: String,
+    prefix: Vec<u8>,
+    max_expansions: usize,
+    boost: f32,
+}
+
+impl PrefixQuery {
+    pub fn new(field: String, prefix: Vec<u8>, boost: f32) -> PrefixQuery {
+        PrefixQuery::with_max_expansions(field, prefix, DEFAULT_MAX_EXPANSIONS, boost)
+    }
+
+    pub fn with_max_expansions(
+        field: String,
+        prefix: Vec<u8>,
+        max_expansions: usize,
+        boost: f32,
+    ) -> PrefixQuery {
+        PrefixQuery {
+            field,
+            prefix,
+            max_expansions,
+            boost,
+        }
+    }
+
+    pub fn field(&self) -> &str {
+        &self.field
+    }
+
+    pub fn prefix(&self) -> &[u8] {
+        &self.prefix
+    }
+}
+
+impl<C: Codec> Query<C> for PrefixQuery {
+    fn create_weight(
+        &self,
+        _searcher: &dyn SearchPlanBuilder<C>,
+        _needs_scores: bool,
+    ) -> Result<Box<dyn Weight<C>>> {
+        Ok(Box::new(PrefixWeight {
+            field: self.field.clone(),
+            prefix: self.prefix.clone(),
+            max_expansions: self.max_expansions,
+            boost: self.boost,
+            weight: self.boost,
+        }))
+    }
+
+    fn extract_terms(&self) -> Vec<TermQuery> {
+        vec![]
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl fmt::Display for PrefixQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "PrefixQuery(field: {}, prefix: {}, max_expansions: {}, boost: {})",
+            &self.field,
+            String::from_utf8_lossy(&self.prefix),
+            self.max_expansions,
+            self.boost
+        )
+    }
+}
+
+struct PrefixWeight {
+    field: String,
+    prefix: Vec<u8>,
+    max_expansions: usize,
+    boost: f32,
+    weight: f32,
+}
+
+impl PrefixWeight {
+    fn prefix_scorer<C: Codec>(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+    ) -> Result<Option<Box<dyn Scorer>>> {
+        if let Some(terms) = reader.reader.terms(&self.field)? {
+            let mut terms_iter = terms.iterator()?;
+            let scorer = PrefixScorer::new(
+                &mut terms_iter,
+                &self.prefix,
+                self.max_expansions,
+                reader.reader.max_doc(),
+                self.weight,
+            )?;
+            Ok(scorer.map(|s| -> Box<dyn Scorer> { Box::new(s) }))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl<C: Codec> Weight<C> for PrefixWeight {
+    fn create_scorer(&self, reader: &LeafReaderContext<'_, C>) -> Result<Option<Box<dyn Scorer>>> {
+        self.prefix_scorer(reader)
+    }
+
+    fn query_type(&self) -> &'static str {
+        PREFIX
+    }
+
+    fn normalize(&mut self, norm: f32, boost: f32) {
+        self.weight = norm * boost * self.boost;
+    }
+
+    fn value_for_normalization(&self) -> f32 {
+        self.weight * self.weight
+    }
+
+    fn needs_scores(&self) -> bool {
+        false
+    }
+
+    fn explain(&self, reader: &LeafReaderContext<'_, C>, doc: DocId) -> Result<Explanation> {
+        if let Some(mut scorer) = self.prefix_scorer(reader)? {
+            if scorer.advance(doc)? == doc {
+                return Ok(Explanation::new(
+                    true,
+                    self.weight,
+                    format!("{}, constant score of matching prefix", self),
+                    vec![],
+                ));
+            }
+        }
+        Ok(Explanation::new(
+            false,
+            0f32,
+            "no matching term".to_string(),
+            vec![],
+        ))
+    }
+}
+
+impl fmt::Display for PrefixWeight {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "PrefixWeight(field: {}, prefix: {}, max_expansions: {}, weight: {})",
+            &self.field,
+            String::from_utf8_lossy(&self.prefix),
+            self.max_expansions,
+            self.weight
+        )
+    }
+}
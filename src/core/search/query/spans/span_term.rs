@@ -132,6 +132,11 @@ impl<T: PostingIterator> TermSpans<T> {
         }
     }
 
+    /// The number of positions of the term in the current doc.
+    pub fn freq(&self) -> i32 {
+        self.freq
+    }
+
     fn set_doc(&mut self) -> Result<()> {
         if self.doc != NO_MORE_DOCS {
             self.freq = self.postings.freq()?;
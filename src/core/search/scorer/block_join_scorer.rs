@@ -0,0 +1,409 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::search::scorer::Scorer;
+use core::search::{DocIterator, NO_MORE_DOCS};
+use core::util::{Bits, BitsRef, DocId};
+use error::{ErrorKind::IllegalState, Result};
+
+/// How the scores of the matching children of a parent are combined into
+/// the score of the parent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinScoreMode {
+    /// The average of the child scores.
+    Avg,
+    /// The highest child score.
+    Max,
+    /// The sum of the child scores.
+    Sum,
+    /// The number of matching children, the child scores are ignored.
+    Total,
+}
+
+/// Returns the last parent before `doc`, or -1 if there is none.
+fn prev_parent(parents: &dyn Bits, doc: DocId) -> Result<DocId> {
+    for parent in (0..doc.min(parents.len() as DocId)).rev() {
+        if parents.get(parent as usize)? {
+            return Ok(parent);
+        }
+    }
+    Ok(-1)
+}
+
+/// A `Scorer` which joins the docs matched by a child scorer to their
+/// parent docs, eg. for nested docs indexed as blocks.
+///
+/// Each block is made of the child docs immediately followed by their
+/// parent doc, the parent docs are the bits set in `parents`. For each of
+/// the matched children the scorer moves to the parent following it, and
+/// scores the parent by combining the scores of all its matching children
+/// according to `score_mode`.
+///
+/// The child scorer must not match parent docs.
+pub struct ToParentBlockJoinScorer<S: Scorer> {
+    child: S,
+    parents: BitsRef,
+    score_mode: JoinScoreMode,
+    doc: DocId,
+    score: f32,
+    // number of matching children of the current parent
+    child_count: usize,
+}
+
+impl<S: Scorer> ToParentBlockJoinScorer<S> {
+    pub fn new(child: S, parents: BitsRef, score_mode: JoinScoreMode) -> Self {
+        ToParentBlockJoinScorer {
+            child,
+            parents,
+            score_mode,
+            doc: -1,
+            score: 0.0,
+            child_count: 0,
+        }
+    }
+
+    /// Returns the number of matching children of the current parent.
+    pub fn child_count(&self) -> usize {
+        self.child_count
+    }
+
+    /// Returns the first parent at or after `doc`, or `NO_MORE_DOCS` if
+    /// there is none, eg. for trailing children without a parent.
+    fn next_parent(&self, doc: DocId) -> Result<DocId> {
+        for parent in doc as usize..self.parents.len() {
+            if self.parents.get(parent)? {
+                return Ok(parent as DocId);
+            }
+        }
+        Ok(NO_MORE_DOCS)
+    }
+
+    /// Collects the children of the parent of the current child doc, which
+    /// are left positioned on the first child of the following block.
+    fn collect_block(&mut self) -> Result<DocId> {
+        let mut child_doc = self.child.doc_id();
+        if child_doc == NO_MORE_DOCS {
+            self.doc = NO_MORE_DOCS;
+            return Ok(NO_MORE_DOCS);
+        }
+        let parent = self.next_parent(child_doc)?;
+        if parent == child_doc {
+            bail!(IllegalState(format!(
+                "child scorer matched the parent doc {}",
+                parent
+            )));
+        }
+
+        let mut sum = 0f32;
+        let mut max = ::std::f32::NEG_INFINITY;
+        let mut count = 0;
+        while child_doc < parent {
+            if self.score_mode != JoinScoreMode::Total {
+                let score = self.child.score()?;
+                sum += score;
+                max = max.max(score);
+            }
+            count += 1;
+            child_doc = self.child.next()?;
+        }
+
+        self.doc = parent;
+        self.child_count = count;
+        self.score = match self.score_mode {
+            JoinScoreMode::Avg => sum / count as f32,
+            JoinScoreMode::Max => max,
+            JoinScoreMode::Sum => sum,
+            JoinScoreMode::Total => count as f32,
+        };
+        Ok(parent)
+    }
+}
+
+impl<S: Scorer> Scorer for ToParentBlockJoinScorer<S> {
+    fn score(&mut self) -> Result<f32> {
+        Ok(self.score)
+    }
+
+    fn freq(&self) -> Result<f32> {
+        Ok(self.child_count as f32)
+    }
+}
+
+impl<S: Scorer> DocIterator for ToParentBlockJoinScorer<S> {
+    fn doc_id(&self) -> DocId {
+        self.doc
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        if self.child.doc_id() == -1 {
+            self.child.next()?;
+        }
+        self.collect_block()
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        if target == NO_MORE_DOCS {
+            self.doc = NO_MORE_DOCS;
+            return Ok(NO_MORE_DOCS);
+        }
+        // the children of a parent at or after `target` come after the
+        // parent before `target`
+        let first_child = prev_parent(&*self.parents, target)? + 1;
+        if self.child.doc_id() < first_child {
+            self.child.advance(first_child)?;
+        }
+        self.collect_block()
+    }
+
+    fn cost(&self) -> usize {
+        self.child.cost()
+    }
+}
+
+/// A `Scorer` which joins the parent docs matched by a parent scorer to
+/// their child docs, the inverse of `ToParentBlockJoinScorer`.
+///
+/// With the same block layout, eg. each parent doc immediately follows its
+/// children, the scorer enumerates all the children of each matched parent
+/// and scores them with the score of their parent. Matched parents without
+/// children are skipped.
+///
+/// The parent scorer must only match parent docs.
+pub struct ToChildBlockJoinScorer<S: Scorer> {
+    parent: S,
+    parents: BitsRef,
+    doc: DocId,
+    // the matched parent of the current child
+    parent_doc: DocId,
+    parent_score: f32,
+}
+
+impl<S: Scorer> ToChildBlockJoinScorer<S> {
+    pub fn new(parent: S, parents: BitsRef) -> Self {
+        ToChildBlockJoinScorer {
+            parent,
+            parents,
+            doc: -1,
+            parent_doc: -1,
+            parent_score: 0.0,
+        }
+    }
+
+    /// Returns the parent of the current child doc.
+    pub fn parent_doc(&self) -> DocId {
+        self.parent_doc
+    }
+
+    /// Moves to the first child at or after `target` of the current parent
+    /// doc or of the following matched parents.
+    fn next_block(&mut self, target: DocId) -> Result<DocId> {
+        let mut parent = self.parent.doc_id();
+        while parent != NO_MORE_DOCS {
+            if !self.parents.get(parent as usize)? {
+                bail!(IllegalState(format!(
+                    "parent scorer matched the non parent doc {}",
+                    parent
+                )));
+            }
+            let first_child = prev_parent(&*self.parents, parent)? + 1;
+            let child = first_child.max(target);
+            if child < parent {
+                self.doc = child;
+                self.parent_doc = parent;
+                self.parent_score = self.parent.score()?;
+                return Ok(child);
+            }
+            // no children, or all of them are before `target`
+            parent = self.parent.next()?;
+        }
+        self.doc = NO_MORE_DOCS;
+        self.parent_doc = NO_MORE_DOCS;
+        Ok(NO_MORE_DOCS)
+    }
+}
+
+impl<S: Scorer> Scorer for ToChildBlockJoinScorer<S> {
+    fn score(&mut self) -> Result<f32> {
+        Ok(self.parent_score)
+    }
+}
+
+impl<S: Scorer> DocIterator for ToChildBlockJoinScorer<S> {
+    fn doc_id(&self) -> DocId {
+        self.doc
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        if self.doc == NO_MORE_DOCS {
+            return Ok(NO_MORE_DOCS);
+        }
+        if self.doc != -1 && self.doc + 1 < self.parent_doc {
+            self.doc += 1;
+            return Ok(self.doc);
+        }
+        self.parent.next()?;
+        self.next_block(0)
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        if target == NO_MORE_DOCS {
+            self.doc = NO_MORE_DOCS;
+            return Ok(NO_MORE_DOCS);
+        }
+        if self.doc != -1 && target < self.parent_doc {
+            self.doc = target;
+            return Ok(target);
+        }
+        // the parent of `target` is the first parent after it
+        self.parent.advance(target + 1)?;
+        self.next_block(target)
+    }
+
+    fn cost(&self) -> usize {
+        self.parent.cost()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::tests::*;
+    use core::util::{BitSet, FixedBitSet};
+    use std::sync::Arc;
+
+    /// blocks of two children and a parent: parents are 2, 5, 8 and 11
+    fn parents() -> BitsRef {
+        let mut bits = FixedBitSet::new(12);
+        for parent in &[2, 5, 8, 11] {
+            bits.set(*parent);
+        }
+        Arc::new(bits)
+    }
+
+    fn collect<S: Scorer>(scorer: &mut ToParentBlockJoinScorer<S>) -> Vec<(DocId, f32, usize)> {
+        let mut result = vec![];
+        loop {
+            let doc = scorer.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            result.push((doc, scorer.score().unwrap(), scorer.child_count()));
+        }
+        result
+    }
+
+    #[test]
+    fn test_score_modes() {
+        // the mock scores are the child doc ids, no child of parent 8 matches
+        let children = vec![0, 1, 4, 9, 10];
+        let expected = vec![
+            (JoinScoreMode::Avg, vec![0.5, 4.0, 9.5]),
+            (JoinScoreMode::Max, vec![1.0, 4.0, 10.0]),
+            (JoinScoreMode::Sum, vec![1.0, 4.0, 19.0]),
+            (JoinScoreMode::Total, vec![2.0, 1.0, 2.0]),
+        ];
+        for (mode, scores) in expected {
+            let child = create_mock_scorer(children.clone());
+            let mut scorer = ToParentBlockJoinScorer::new(child, parents(), mode);
+            let result = collect(&mut scorer);
+            let docs: Vec<DocId> = result.iter().map(|r| r.0).collect();
+            assert_eq!(docs, vec![2, 5, 11]);
+            let counts: Vec<usize> = result.iter().map(|r| r.2).collect();
+            assert_eq!(counts, vec![2, 1, 2]);
+            for (r, score) in result.iter().zip(scores) {
+                assert!((r.1 - score).abs() < ::std::f32::EPSILON, "{:?}", mode);
+            }
+        }
+    }
+
+    #[test]
+    fn test_advance() {
+        let child = create_mock_scorer(vec![0, 1, 3, 4, 6, 7, 9, 10]);
+        let mut scorer = ToParentBlockJoinScorer::new(child, parents(), JoinScoreMode::Sum);
+        assert_eq!(scorer.advance(4).unwrap(), 5);
+        assert!((scorer.score().unwrap() - 7.0).abs() < ::std::f32::EPSILON);
+        // the block of parent 8 starts before the target
+        assert_eq!(scorer.advance(8).unwrap(), 8);
+        assert!((scorer.score().unwrap() - 13.0).abs() < ::std::f32::EPSILON);
+        assert_eq!(scorer.advance(9).unwrap(), 11);
+        assert_eq!(scorer.child_count(), 2);
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+    }
+
+    #[test]
+    fn test_child_matching_parent() {
+        let child = create_mock_scorer(vec![0, 2]);
+        let mut scorer = ToParentBlockJoinScorer::new(child, parents(), JoinScoreMode::Max);
+        assert_eq!(scorer.next().unwrap(), 2);
+        assert!(scorer.next().is_err());
+    }
+
+    /// parents 2, 3 and 7, parent 3 has no children
+    fn two_block_parents() -> BitsRef {
+        let mut bits = FixedBitSet::new(8);
+        for parent in &[2, 3, 7] {
+            bits.set(*parent);
+        }
+        Arc::new(bits)
+    }
+
+    #[test]
+    fn test_to_child() {
+        // the mock scores are the parent doc ids
+        let parent = create_mock_scorer(vec![2, 3, 7]);
+        let mut scorer = ToChildBlockJoinScorer::new(parent, two_block_parents());
+        let mut result = vec![];
+        loop {
+            let doc = scorer.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            result.push((doc, scorer.score().unwrap(), scorer.parent_doc()));
+        }
+        assert_eq!(
+            result,
+            vec![
+                (0, 2.0, 2),
+                (1, 2.0, 2),
+                (4, 7.0, 7),
+                (5, 7.0, 7),
+                (6, 7.0, 7),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_to_child_advance() {
+        let parents = two_block_parents();
+        let mut scorer =
+            ToChildBlockJoinScorer::new(create_mock_scorer(vec![2, 7]), parents.clone());
+        assert_eq!(scorer.advance(1).unwrap(), 1);
+        assert_eq!(scorer.parent_doc(), 2);
+        // 2 and 3 are parents, the next child is in the block of 7
+        assert_eq!(scorer.advance(2).unwrap(), 4);
+        assert_eq!(scorer.advance(6).unwrap(), 6);
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+
+        // only the parent without children matches
+        let mut scorer = ToChildBlockJoinScorer::new(create_mock_scorer(vec![3]), parents.clone());
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+
+        // a target within the children of an unmatched parent
+        let mut scorer = ToChildBlockJoinScorer::new(create_mock_scorer(vec![7]), parents.clone());
+        assert_eq!(scorer.advance(1).unwrap(), 4);
+        assert!((scorer.score().unwrap() - 7.0).abs() < ::std::f32::EPSILON);
+
+        let mut scorer = ToChildBlockJoinScorer::new(create_mock_scorer(vec![5]), parents);
+        assert!(scorer.next().is_err());
+    }
+}
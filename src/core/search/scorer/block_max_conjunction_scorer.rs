@@ -0,0 +1,344 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::search::scorer::Scorer;
+use core::search::{DocIterator, NO_MORE_DOCS};
+use core::util::DocId;
+use error::Result;
+
+/// Scorer for conjunctions that checks the maximum scores of each clause in
+/// order to skip over blocks of documents that can't produce competitive
+/// scores, eg. whose sum of max scores is less than `min_competitive_score`.
+///
+/// The produced docs are the same as `ConjunctionScorer` except for skipped
+/// blocks, and the score is the sum of all the sub scores.
+pub struct BlockMaxConjunctionScorer<T: Scorer> {
+    // sorted by cost, the first one leads the iteration
+    scorers: Vec<T>,
+    min_competitive_score: f32,
+    // last doc (inclusive) of the current block
+    up_to: DocId,
+    // sum of the max scores of all the scorers in the current block
+    max_score: f32,
+}
+
+impl<T: Scorer> BlockMaxConjunctionScorer<T> {
+    pub fn new(mut children: Vec<T>) -> BlockMaxConjunctionScorer<T> {
+        assert!(children.len() >= 2);
+
+        children.sort_by_key(|s| s.cost());
+
+        BlockMaxConjunctionScorer {
+            scorers: children,
+            min_competitive_score: 0f32,
+            up_to: -1,
+            max_score: 0f32,
+        }
+    }
+
+    fn move_to_next_block(&mut self, target: DocId) -> Result<()> {
+        let mut up_to = NO_MORE_DOCS;
+        for scorer in &mut self.scorers {
+            up_to = up_to.min(scorer.advance_shallow(target)?);
+        }
+
+        let mut max_score = 0f32;
+        for scorer in &mut self.scorers {
+            max_score += scorer.get_max_score(up_to)?;
+        }
+
+        self.up_to = up_to;
+        self.max_score = max_score;
+        Ok(())
+    }
+
+    /// Returns the first doc on or after `target` that belongs to a competitive block.
+    fn advance_target(&mut self, mut target: DocId) -> Result<DocId> {
+        if target > self.up_to {
+            self.move_to_next_block(target)?;
+        }
+
+        loop {
+            if self.max_score >= self.min_competitive_score {
+                return Ok(target);
+            }
+            if self.up_to == NO_MORE_DOCS {
+                return Ok(NO_MORE_DOCS);
+            }
+            target = self.up_to + 1;
+            self.move_to_next_block(target)?;
+        }
+    }
+
+    fn do_next(&mut self, mut doc: DocId) -> Result<DocId> {
+        'advanceHead: loop {
+            debug_assert_eq!(doc, self.scorers[0].doc_id());
+            if doc == NO_MORE_DOCS {
+                return Ok(NO_MORE_DOCS);
+            }
+
+            if doc > self.up_to {
+                // the lead landed on a block we didn't check yet
+                let next_target = self.advance_target(doc)?;
+                if next_target != doc {
+                    doc = self.scorers[0].advance(next_target)?;
+                    continue;
+                }
+            }
+
+            // then find agreement with other iterators
+            for i in 1..self.scorers.len() {
+                if self.scorers[i].doc_id() < doc {
+                    let next = self.scorers[i].advance(doc)?;
+                    if next > doc {
+                        let target = self.advance_target(next)?;
+                        doc = self.scorers[0].advance(target)?;
+                        continue 'advanceHead;
+                    }
+                }
+            }
+
+            return Ok(doc);
+        }
+    }
+}
+
+impl<T: Scorer> Scorer for BlockMaxConjunctionScorer<T> {
+    fn score(&mut self) -> Result<f32> {
+        let mut score = 0f32;
+        for scorer in &mut self.scorers {
+            score += scorer.score()?;
+        }
+        Ok(score)
+    }
+
+    fn advance_shallow(&mut self, target: DocId) -> Result<DocId> {
+        let mut up_to = NO_MORE_DOCS;
+        for scorer in &mut self.scorers {
+            up_to = up_to.min(scorer.advance_shallow(target)?);
+        }
+        Ok(up_to)
+    }
+
+    fn get_max_score(&mut self, up_to: DocId) -> Result<f32> {
+        let mut max_score = 0f32;
+        for scorer in &mut self.scorers {
+            max_score += scorer.get_max_score(up_to)?;
+        }
+        Ok(max_score)
+    }
+
+    /// Docs in blocks whose max score is less than `min_score` will be skipped.
+    fn set_min_competitive_score(&mut self, min_score: f32) {
+        self.min_competitive_score = min_score;
+    }
+}
+
+impl<T: Scorer> DocIterator for BlockMaxConjunctionScorer<T> {
+    fn doc_id(&self) -> DocId {
+        self.scorers[0].doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        let target = self.doc_id() + 1;
+        self.advance(target)
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        let target = self.advance_target(target)?;
+        let doc = self.scorers[0].advance(target)?;
+        self.do_next(doc)
+    }
+
+    fn cost(&self) -> usize {
+        self.scorers[0].cost()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::scorer::{BoostingScorer, ConjunctionScorer};
+    use core::search::tests::create_mock_scorer;
+    use std::f32;
+
+    /// mock scorer with block level max scores, each block holds `block_size` docs.
+    struct MockBlockScorer {
+        docs: Vec<DocId>,
+        scores: Vec<f32>,
+        block_size: usize,
+        offset: usize,
+        doc: DocId,
+    }
+
+    impl MockBlockScorer {
+        fn new(docs: Vec<DocId>, scores: Vec<f32>, block_size: usize) -> Self {
+            assert_eq!(docs.len(), scores.len());
+            MockBlockScorer {
+                docs,
+                scores,
+                block_size,
+                offset: 0,
+                doc: -1,
+            }
+        }
+    }
+
+    impl Scorer for MockBlockScorer {
+        fn score(&mut self) -> Result<f32> {
+            Ok(self.scores[self.offset])
+        }
+
+        fn advance_shallow(&mut self, target: DocId) -> Result<DocId> {
+            match self.docs.iter().position(|&d| d >= target) {
+                Some(idx) => {
+                    let last = (idx / self.block_size + 1) * self.block_size - 1;
+                    if last + 1 >= self.docs.len() {
+                        Ok(NO_MORE_DOCS)
+                    } else {
+                        Ok(self.docs[last])
+                    }
+                }
+                None => Ok(NO_MORE_DOCS),
+            }
+        }
+
+        fn get_max_score(&mut self, up_to: DocId) -> Result<f32> {
+            let from = self.doc.max(0);
+            Ok(self
+                .docs
+                .iter()
+                .zip(self.scores.iter())
+                .filter(|&(&d, _)| d >= from && d <= up_to)
+                .fold(0f32, |acc, (_, &s)| acc.max(s)))
+        }
+    }
+
+    impl DocIterator for MockBlockScorer {
+        fn doc_id(&self) -> DocId {
+            self.doc
+        }
+
+        fn next(&mut self) -> Result<DocId> {
+            let target = self.doc + 1;
+            self.advance(target)
+        }
+
+        fn advance(&mut self, target: DocId) -> Result<DocId> {
+            match self.docs.iter().position(|&d| d >= target) {
+                Some(idx) => {
+                    self.offset = idx;
+                    self.doc = self.docs[idx];
+                }
+                None => self.doc = NO_MORE_DOCS,
+            }
+            Ok(self.doc)
+        }
+
+        fn cost(&self) -> usize {
+            self.docs.len()
+        }
+    }
+
+    fn create_scorers() -> Vec<MockBlockScorer> {
+        // clause 1: every doc
+        let docs1: Vec<DocId> = (0..200).collect();
+        let scores1 = docs1.iter().map(|&d| (d % 7) as f32 * 0.2).collect();
+        // clause 2: multiples of 3
+        let docs2: Vec<DocId> = (0..200).filter(|d| d % 3 == 0).collect();
+        let scores2 = docs2.iter().map(|&d| (d % 11) as f32 * 0.1).collect();
+        // clause 3: even docs, high scores only in [100, 120)
+        let docs3: Vec<DocId> = (0..200).filter(|d| d % 2 == 0).collect();
+        let scores3 = docs3
+            .iter()
+            .map(|&d| if d >= 100 && d < 120 { 5.0 } else { 0.1 })
+            .collect();
+
+        vec![
+            MockBlockScorer::new(docs1, scores1, 16),
+            MockBlockScorer::new(docs2, scores2, 4),
+            MockBlockScorer::new(docs3, scores3, 8),
+        ]
+    }
+
+    fn collect<T: Scorer>(scorer: &mut T) -> Vec<(DocId, f32)> {
+        let mut result = vec![];
+        loop {
+            let doc = scorer.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            result.push((doc, scorer.score().unwrap()));
+        }
+        result
+    }
+
+    #[test]
+    fn test_same_as_naive_conjunction() {
+        let expected = collect(&mut ConjunctionScorer::new(create_scorers()));
+        let actual = collect(&mut BlockMaxConjunctionScorer::new(create_scorers()));
+
+        assert!(!expected.is_empty());
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected.iter()) {
+            assert_eq!(a.0, e.0);
+            assert!((a.1 - e.1).abs() < f32::EPSILON);
+        }
+    }
+
+    #[test]
+    fn test_skip_non_competitive_blocks() {
+        let min_score = 5.0;
+        let expected = collect(&mut ConjunctionScorer::new(create_scorers()));
+
+        let mut scorer = BlockMaxConjunctionScorer::new(create_scorers());
+        scorer.set_min_competitive_score(min_score);
+        let actual = collect(&mut scorer);
+
+        // some blocks must have been skipped
+        assert!(actual.len() < expected.len());
+        // every returned doc is a real match with the right score
+        for a in &actual {
+            let e = expected.iter().find(|e| e.0 == a.0).unwrap();
+            assert!((a.1 - e.1).abs() < f32::EPSILON);
+        }
+        // no competitive doc is skipped
+        for e in expected.iter().filter(|e| e.1 >= min_score) {
+            assert!(actual.iter().any(|a| a.0 == e.0));
+        }
+    }
+
+    #[test]
+    fn test_boosting_forwards_min_competitive_score() {
+        let min_score = 5.0;
+        let negative_docs: Vec<DocId> = (0..200).filter(|d| d % 4 == 0).collect();
+        let boosting = || {
+            BoostingScorer::new(
+                Box::new(BlockMaxConjunctionScorer::new(create_scorers())),
+                Box::new(create_mock_scorer(negative_docs.clone())),
+                0.5,
+            )
+        };
+        let expected = collect(&mut boosting());
+
+        let mut scorer = boosting();
+        scorer.set_min_competitive_score(min_score);
+        let actual = collect(&mut scorer);
+
+        // the positive clause skipped the docs below the threshold
+        assert!(actual.len() < expected.len());
+        for e in expected.iter().filter(|e| e.1 >= min_score) {
+            assert!(actual.iter().any(|a| a.0 == e.0));
+        }
+    }
+}
@@ -0,0 +1,306 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::search::scorer::{ConjunctionScorer, Scorer};
+use core::search::{DocIterator, NO_MORE_DOCS};
+use core::util::{DisiPriorityQueue, DocId};
+use error::{ErrorKind::IllegalArgument, Result};
+
+/// A `Scorer` combining the `must`, `should` and `must_not` clauses of a
+/// boolean query in one place, instead of nesting `ReqOptScorer`,
+/// `ReqNotScorer` and disjunctions by hand.
+///
+/// A doc matches if it matches all the `must` clauses, at least
+/// `min_should_match` of the `should` clauses, and none of the `must_not`
+/// clauses. Without `must` clauses at least one `should` clause has to
+/// match, otherwise the `should` clauses are optional unless
+/// `min_should_match` is set. The score is the sum of the scores of the
+/// `must` and `should` clauses that match.
+///
+/// The `should` clauses are kept in a `DisiPriorityQueue` so only the ones
+/// behind the current doc are advanced. This scorer doesn't support two phase
+/// iteration, `BooleanWeight` uses it when `must` clauses are combined with a
+/// positive `min_should_match`, the other combinations are still built from
+/// `ReqOptScorer` and `ReqNotScorer`.
+pub struct BooleanScorer {
+    must: Option<Box<dyn Scorer>>,
+    should: Option<DisiPriorityQueue<Box<dyn Scorer>>>,
+    must_not: Vec<Box<dyn Scorer>>,
+    min_should_match: usize,
+    doc: DocId,
+    cost: usize,
+}
+
+impl BooleanScorer {
+    /// Returns `None` if no doc can match, eg. without `must` nor `should`
+    /// clauses or with fewer `should` clauses than `min_should_match`.
+    pub fn new(
+        mut must: Vec<Box<dyn Scorer>>,
+        should: Vec<Box<dyn Scorer>>,
+        must_not: Vec<Box<dyn Scorer>>,
+        min_should_match: i32,
+    ) -> Result<Option<BooleanScorer>> {
+        if min_should_match < 0 {
+            bail!(IllegalArgument(format!(
+                "min_should_match must be >= 0, got {}",
+                min_should_match
+            )));
+        }
+        let mut min_should_match = min_should_match as usize;
+        if must.is_empty() {
+            min_should_match = min_should_match.max(1);
+        }
+        if (must.is_empty() && should.is_empty()) || min_should_match > should.len() {
+            return Ok(None);
+        }
+
+        let must: Option<Box<dyn Scorer>> = match must.len() {
+            0 => None,
+            1 => Some(must.remove(0)),
+            _ => Some(Box::new(ConjunctionScorer::new(must))),
+        };
+        let cost = match must {
+            Some(ref must) => must.cost(),
+            None => should.iter().map(|s| s.cost()).sum(),
+        };
+        let should = if should.is_empty() {
+            None
+        } else {
+            Some(DisiPriorityQueue::new(should))
+        };
+        Ok(Some(BooleanScorer {
+            must,
+            should,
+            must_not,
+            min_should_match,
+            doc: -1,
+            cost,
+        }))
+    }
+
+    /// Returns the first doc from `target` on that matches all the clauses.
+    fn next_match(&mut self, mut target: DocId) -> Result<DocId> {
+        loop {
+            let candidate = self.next_candidate(target)?;
+            if candidate == NO_MORE_DOCS {
+                self.doc = NO_MORE_DOCS;
+                return Ok(NO_MORE_DOCS);
+            }
+            if self.should_matches(candidate)? >= self.min_should_match
+                && !self.is_excluded(candidate)?
+            {
+                self.doc = candidate;
+                return Ok(candidate);
+            }
+            target = candidate + 1;
+        }
+    }
+
+    /// Returns the next doc from `target` on matched by the lead clauses,
+    /// the `must` clauses if any, else the disjunction of the `should` ones.
+    fn next_candidate(&mut self, target: DocId) -> Result<DocId> {
+        if let Some(ref mut must) = self.must {
+            let doc = must.doc_id();
+            return if doc < target {
+                must.advance(target)
+            } else {
+                Ok(doc)
+            };
+        }
+        self.advance_should(target)
+    }
+
+    /// Advances the `should` clauses which are behind `target` and returns
+    /// the first doc any of them is on.
+    fn advance_should(&mut self, target: DocId) -> Result<DocId> {
+        if let Some(ref mut should) = self.should {
+            loop {
+                let mut top = should.peek_mut();
+                if top.doc() >= target {
+                    return Ok(top.doc());
+                }
+                top.advance(target)?;
+            }
+        }
+        Ok(NO_MORE_DOCS)
+    }
+
+    fn should_matches(&mut self, doc: DocId) -> Result<usize> {
+        if self.advance_should(doc)? != doc {
+            return Ok(0);
+        }
+        let mut matches = 0;
+        if let Some(ref mut should) = self.should {
+            let mut disi = should.top_list();
+            loop {
+                matches += 1;
+                if disi.next.is_null() {
+                    break;
+                }
+                unsafe { disi = &mut *disi.next };
+            }
+        }
+        Ok(matches)
+    }
+
+    fn is_excluded(&mut self, doc: DocId) -> Result<bool> {
+        for must_not in &mut self.must_not {
+            if must_not.doc_id() < doc {
+                must_not.advance(doc)?;
+            }
+            if must_not.doc_id() == doc {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl Scorer for BooleanScorer {
+    fn score(&mut self) -> Result<f32> {
+        let mut score = match self.must {
+            Some(ref mut must) => must.score()?,
+            None => 0.0,
+        };
+        if let Some(ref mut should) = self.should {
+            if should.peek().doc() == self.doc {
+                let mut disi = should.top_list();
+                loop {
+                    score += disi.inner_mut().score()?;
+                    if disi.next.is_null() {
+                        break;
+                    }
+                    unsafe { disi = &mut *disi.next };
+                }
+            }
+        }
+        Ok(score)
+    }
+}
+
+impl DocIterator for BooleanScorer {
+    fn doc_id(&self) -> DocId {
+        self.doc
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        if self.doc == NO_MORE_DOCS {
+            return Ok(NO_MORE_DOCS);
+        }
+        let target = self.doc + 1;
+        self.next_match(target)
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        if self.doc == NO_MORE_DOCS {
+            return Ok(NO_MORE_DOCS);
+        }
+        self.next_match(target.max(self.doc + 1))
+    }
+
+    fn cost(&self) -> usize {
+        self.cost
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::tests::*;
+
+    fn scorers(clauses: Vec<Vec<DocId>>) -> Vec<Box<dyn Scorer>> {
+        clauses
+            .into_iter()
+            .map(|docs| Box::new(create_mock_scorer(docs)) as Box<dyn Scorer>)
+            .collect()
+    }
+
+    fn collect(
+        must: Vec<Vec<DocId>>,
+        should: Vec<Vec<DocId>>,
+        must_not: Vec<Vec<DocId>>,
+        min_should_match: i32,
+    ) -> Vec<(DocId, f32)> {
+        let mut scorer = BooleanScorer::new(
+            scorers(must),
+            scorers(should),
+            scorers(must_not),
+            min_should_match,
+        )
+        .unwrap()
+        .unwrap();
+        scorer.iter().map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn test_should_only() {
+        // the mock scores are the doc ids, summed over the matching clauses
+        let hits = collect(vec![], vec![vec![1, 3, 5], vec![3, 4]], vec![], 0);
+        assert_eq!(hits, vec![(1, 1.0), (3, 6.0), (4, 4.0), (5, 5.0)]);
+
+        let hits = collect(vec![], vec![vec![1, 3, 5], vec![3, 4]], vec![vec![3, 5]], 0);
+        assert_eq!(hits, vec![(1, 1.0), (4, 4.0)]);
+    }
+
+    #[test]
+    fn test_must_and_must_not() {
+        let hits = collect(
+            vec![vec![1, 2, 3, 4, 6], vec![2, 3, 4, 6]],
+            vec![],
+            vec![],
+            0,
+        );
+        assert_eq!(hits, vec![(2, 4.0), (3, 6.0), (4, 8.0), (6, 12.0)]);
+
+        let must = vec![vec![1, 2, 3, 4, 6], vec![2, 3, 4, 6]];
+        let hits = collect(must, vec![], vec![vec![3], vec![0, 6]], 0);
+        assert_eq!(hits, vec![(2, 4.0), (4, 8.0)]);
+
+        // optional should clauses only add to the score
+        let hits = collect(vec![vec![1, 2, 3]], vec![vec![2, 7]], vec![vec![3]], 0);
+        assert_eq!(hits, vec![(1, 1.0), (2, 4.0)]);
+    }
+
+    #[test]
+    fn test_min_should_match() {
+        let should = vec![vec![1, 2, 3, 5], vec![2, 3, 6], vec![3, 5, 6]];
+        let hits = collect(vec![], should.clone(), vec![], 2);
+        assert_eq!(hits, vec![(2, 4.0), (3, 9.0), (5, 10.0), (6, 12.0)]);
+        let hits = collect(vec![], should.clone(), vec![], 3);
+        assert_eq!(hits, vec![(3, 9.0)]);
+
+        // with must clauses the should clauses become required
+        let hits = collect(vec![vec![1, 2, 5, 6]], should.clone(), vec![vec![6]], 2);
+        assert_eq!(hits, vec![(2, 6.0), (5, 15.0)]);
+
+        let mut scorer = BooleanScorer::new(vec![], scorers(should.clone()), vec![], 2)
+            .unwrap()
+            .unwrap();
+        assert_eq!(scorer.advance(4).unwrap(), 5);
+        assert_eq!(scorer.advance(5).unwrap(), 6);
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+        assert_eq!(scorer.advance(7).unwrap(), NO_MORE_DOCS);
+
+        assert!(
+            BooleanScorer::new(vec![], scorers(should.clone()), vec![], 4)
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            BooleanScorer::new(vec![], vec![], scorers(should.clone()), 0)
+                .unwrap()
+                .is_none()
+        );
+        assert!(BooleanScorer::new(vec![], scorers(should), vec![], -1).is_err());
+    }
+}
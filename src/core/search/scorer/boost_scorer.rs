@@ -0,0 +1,205 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::search::scorer::{FeatureResult, ScoreMode, Scorer};
+use core::search::{DocIterator, Explanation, TwoPhaseIterator};
+use core::util::DocId;
+use error::Result;
+
+/// A `Scorer` which multiplies the score of `child` by a static `boost`,
+/// eg. for query time field boosts. The matched docs are those of `child`.
+///
+/// Unlike `BoostingScorer` the boost applies to every doc, the max scores of
+/// `child` are multiplied too so that block max skipping keeps working.
+pub struct BoostScorer<S: Scorer> {
+    child: S,
+    boost: f32,
+}
+
+impl<S: Scorer> BoostScorer<S> {
+    pub fn new(child: S, boost: f32) -> Self {
+        debug_assert!(boost >= 0.0 && boost.is_finite());
+        BoostScorer { child, boost }
+    }
+
+    pub fn boost(&self) -> f32 {
+        self.boost
+    }
+}
+
+impl<S: Scorer> Scorer for BoostScorer<S> {
+    fn score(&mut self) -> Result<f32> {
+        Ok(self.child.score()? * self.boost)
+    }
+
+    fn score_feature(&mut self) -> Result<Vec<FeatureResult>> {
+        self.child.score_feature()
+    }
+
+    fn freq(&self) -> Result<f32> {
+        self.child.freq()
+    }
+
+    fn set_score_mode(&mut self, mode: ScoreMode) {
+        self.child.set_score_mode(mode)
+    }
+
+    fn advance_shallow(&mut self, target: DocId) -> Result<DocId> {
+        self.child.advance_shallow(target)
+    }
+
+    fn get_max_score(&mut self, up_to: DocId) -> Result<f32> {
+        Ok(self.child.get_max_score(up_to)? * self.boost)
+    }
+
+    fn set_min_competitive_score(&mut self, min_score: f32) {
+        // with a zero boost there is no child threshold to derive
+        if self.boost > 0.0 {
+            self.child.set_min_competitive_score(min_score / self.boost);
+        }
+    }
+
+    fn explain(&mut self, doc: DocId) -> Result<Explanation> {
+        let child = self.child.explain(doc)?;
+        if !child.is_match() {
+            return Ok(child);
+        }
+        let boost = Explanation::new(true, self.boost, "boost".to_string(), vec![]);
+        Ok(Explanation::new(
+            true,
+            child.value() * self.boost,
+            "product of:".to_string(),
+            vec![child, boost],
+        ))
+    }
+}
+
+impl<S: Scorer> DocIterator for BoostScorer<S> {
+    fn doc_id(&self) -> DocId {
+        self.child.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        self.child.next()
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        self.child.advance(target)
+    }
+
+    fn cost(&self) -> usize {
+        self.child.cost()
+    }
+
+    fn matches(&mut self) -> Result<bool> {
+        self.child.matches()
+    }
+
+    fn match_cost(&self) -> f32 {
+        self.child.match_cost()
+    }
+
+    fn support_two_phase(&self) -> bool {
+        self.child.support_two_phase()
+    }
+
+    fn approximate_next(&mut self) -> Result<DocId> {
+        self.child.approximate_next()
+    }
+
+    fn approximate_advance(&mut self, target: DocId) -> Result<DocId> {
+        self.child.approximate_advance(target)
+    }
+
+    fn as_two_phase(&mut self) -> Option<&mut dyn TwoPhaseIterator> {
+        if self.child.support_two_phase() {
+            Some(self)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::tests::*;
+    use core::search::NO_MORE_DOCS;
+
+    /// mock scorer whose max score is the largest doc id up to `up_to`
+    struct MaxScoreScorer {
+        docs: Vec<DocId>,
+        inner: MockSimpleScorer<MockDocIterator>,
+    }
+
+    impl Scorer for MaxScoreScorer {
+        fn score(&mut self) -> Result<f32> {
+            self.inner.score()
+        }
+
+        fn get_max_score(&mut self, up_to: DocId) -> Result<f32> {
+            let max = self.docs.iter().filter(|&&d| d <= up_to).max();
+            Ok(max.map_or(0f32, |&d| d as f32))
+        }
+    }
+
+    impl DocIterator for MaxScoreScorer {
+        fn doc_id(&self) -> DocId {
+            self.inner.doc_id()
+        }
+
+        fn next(&mut self) -> Result<DocId> {
+            self.inner.next()
+        }
+
+        fn advance(&mut self, target: DocId) -> Result<DocId> {
+            self.inner.advance(target)
+        }
+
+        fn cost(&self) -> usize {
+            self.inner.cost()
+        }
+    }
+
+    #[test]
+    fn test_score() {
+        let mut scorer = BoostScorer::new(create_mock_scorer(vec![1, 2, 5]), 1.5);
+
+        assert_eq!(scorer.next().unwrap(), 1);
+        assert!((scorer.score().unwrap() - 1.5).abs() < ::std::f32::EPSILON);
+        assert_eq!(scorer.next().unwrap(), 2);
+        assert!((scorer.score().unwrap() - 3.0).abs() < ::std::f32::EPSILON);
+        assert_eq!(scorer.advance(3).unwrap(), 5);
+        assert!((scorer.score().unwrap() - 7.5).abs() < ::std::f32::EPSILON);
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+    }
+
+    #[test]
+    fn test_max_score() {
+        let docs = vec![1, 4, 9];
+        let child = MaxScoreScorer {
+            docs: docs.clone(),
+            inner: create_mock_scorer(docs),
+        };
+        let mut scorer = BoostScorer::new(child, 2.0);
+        assert!((scorer.get_max_score(5).unwrap() - 8.0).abs() < ::std::f32::EPSILON);
+        assert!((scorer.get_max_score(NO_MORE_DOCS).unwrap() - 18.0).abs() < ::std::f32::EPSILON);
+
+        // unknown max scores stay unbounded
+        let mut scorer = BoostScorer::new(create_mock_scorer(vec![1]), 2.0);
+        assert_eq!(
+            scorer.get_max_score(NO_MORE_DOCS).unwrap(),
+            ::std::f32::INFINITY
+        );
+    }
+}
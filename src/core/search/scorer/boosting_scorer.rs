@@ -11,8 +11,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use core::search::scorer::Scorer;
-use core::search::DocIterator;
+use core::search::scorer::{ScoreMode, Scorer};
+use core::search::{DocIterator, Explanation, TwoPhaseIterator};
 use core::util::DocId;
 use error::Result;
 
@@ -20,6 +20,7 @@ pub struct BoostingScorer {
     positive: Box<dyn Scorer>,
     negative: Box<dyn Scorer>,
     negative_boost: f32,
+    score_mode: ScoreMode,
 }
 
 impl BoostingScorer {
@@ -33,8 +34,17 @@ impl BoostingScorer {
             positive,
             negative,
             negative_boost,
+            score_mode: ScoreMode::default(),
         }
     }
+
+    fn matches_negative(&mut self, doc: DocId) -> Result<bool> {
+        let mut negative_doc = self.negative.doc_id();
+        if negative_doc < doc {
+            negative_doc = self.negative.advance(doc)?;
+        }
+        Ok(negative_doc == doc)
+    }
 }
 
 impl Scorer for BoostingScorer {
@@ -42,12 +52,73 @@ impl Scorer for BoostingScorer {
         let current_doc = self.positive.doc_id();
         let mut score = self.positive.score()?;
 
-        if current_doc == self.negative.advance(current_doc)? {
+        // the negative clause only affects scoring, don't bother advancing it
+        // if no one cares about the scores.
+        if !self.score_mode.needs_scores() {
+            return Ok(score);
+        }
+
+        if self.matches_negative(current_doc)? {
             score *= self.negative_boost;
         }
 
         Ok(score)
     }
+
+    /// Advances only the positive clause to `doc`, the negative clause is
+    /// checked on `doc` alone.
+    fn score_at(&mut self, doc: DocId) -> Result<Option<f32>> {
+        let score = match self.positive.score_at(doc)? {
+            Some(score) => score,
+            None => return Ok(None),
+        };
+        if self.score_mode.needs_scores() && self.matches_negative(doc)? {
+            Ok(Some(score * self.negative_boost))
+        } else {
+            Ok(Some(score))
+        }
+    }
+
+    fn freq(&self) -> Result<f32> {
+        self.positive.freq()
+    }
+
+    fn set_score_mode(&mut self, mode: ScoreMode) {
+        self.score_mode = mode;
+        self.positive.set_score_mode(mode);
+    }
+
+    /// The boost of the negative clause only lowers scores, so the positive
+    /// clause is told the looser `min_score * negative_boost`, which never
+    /// prunes a competitive doc.
+    fn set_min_competitive_score(&mut self, min_score: f32) {
+        self.positive
+            .set_min_competitive_score(min_score * self.negative_boost);
+    }
+
+    fn explain(&mut self, doc: DocId) -> Result<Explanation> {
+        let positive = self.positive.explain(doc)?;
+        if !positive.is_match() {
+            return Ok(positive);
+        }
+
+        if !self.matches_negative(doc)? {
+            return Ok(positive);
+        }
+
+        let boost = Explanation::new(
+            true,
+            self.negative_boost,
+            "negative_boost, matched the negative clause".to_string(),
+            vec![],
+        );
+        Ok(Explanation::new(
+            true,
+            positive.value() * self.negative_boost,
+            "product of:".to_string(),
+            vec![positive, boost],
+        ))
+    }
 }
 
 impl DocIterator for BoostingScorer {
@@ -71,6 +142,14 @@ impl DocIterator for BoostingScorer {
         self.positive.matches()
     }
 
+    fn match_cost(&self) -> f32 {
+        self.positive.match_cost()
+    }
+
+    fn support_two_phase(&self) -> bool {
+        self.positive.support_two_phase()
+    }
+
     fn approximate_next(&mut self) -> Result<DocId> {
         self.positive.approximate_next()
     }
@@ -78,4 +157,174 @@ impl DocIterator for BoostingScorer {
     fn approximate_advance(&mut self, target: DocId) -> Result<DocId> {
         self.positive.approximate_advance(target)
     }
+
+    /// The negative clause only affects scoring, so the two phase view is the one
+    /// of the positive clause, and the negative check is applied by `score()`
+    /// once a doc is confirmed.
+    fn as_two_phase(&mut self) -> Option<&mut dyn TwoPhaseIterator> {
+        if self.positive.support_two_phase() {
+            Some(self)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::tests::*;
+    use core::search::NO_MORE_DOCS;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_score() {
+        let positive = Box::new(create_mock_scorer(vec![1, 2, 3, 4]));
+        let negative = Box::new(create_mock_scorer(vec![2, 4]));
+        let mut scorer = BoostingScorer::new(positive, negative, 0.5);
+
+        assert_eq!(scorer.next().unwrap(), 1);
+        assert!((scorer.score().unwrap() - 1.0).abs() < ::std::f32::EPSILON);
+        assert_eq!(scorer.next().unwrap(), 2);
+        assert!((scorer.score().unwrap() - 1.0).abs() < ::std::f32::EPSILON);
+        assert_eq!(scorer.next().unwrap(), 3);
+        assert!((scorer.score().unwrap() - 3.0).abs() < ::std::f32::EPSILON);
+        assert_eq!(scorer.next().unwrap(), 4);
+        assert!((scorer.score().unwrap() - 2.0).abs() < ::std::f32::EPSILON);
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+    }
+
+    #[test]
+    fn test_score_memory_postings() {
+        // the score of a memory postings doc is its freq
+        let positive = Box::new(MemoryPostingsScorer::new(vec![
+            (1, 2.0, vec![]),
+            (3, 4.0, vec![]),
+            (5, 1.5, vec![]),
+        ]));
+        let negative = Box::new(MemoryPostingsScorer::new(vec![
+            (3, 1.0, vec![]),
+            (4, 1.0, vec![]),
+        ]));
+        let mut scorer = BoostingScorer::new(positive, negative, 0.5);
+
+        let mut scores = vec![];
+        while scorer.next().unwrap() != NO_MORE_DOCS {
+            scores.push((scorer.doc_id(), scorer.score().unwrap()));
+        }
+        assert_eq!(scores, vec![(1, 2.0), (3, 2.0), (5, 1.5)]);
+        assert_eq!(scorer.cost(), 3);
+    }
+
+    #[test]
+    fn test_explain() {
+        let positive = Box::new(create_mock_scorer(vec![1, 2, 3, 4]));
+        let negative = Box::new(create_mock_scorer(vec![2, 4]));
+        let mut scorer = BoostingScorer::new(positive, negative, 0.5);
+
+        // not matched by the negative clause, only the positive score
+        let expl = scorer.explain(1).unwrap();
+        assert!(expl.is_match());
+        assert!((expl.value() - 1.0).abs() < ::std::f32::EPSILON);
+        assert!(expl.details().is_empty());
+
+        let expl = scorer.explain(4).unwrap();
+        assert!(expl.is_match());
+        assert!((expl.value() - 2.0).abs() < ::std::f32::EPSILON);
+        assert_eq!(expl.details().len(), 2);
+        assert!((expl.details()[0].value() - 4.0).abs() < ::std::f32::EPSILON);
+        assert!((expl.details()[1].value() - 0.5).abs() < ::std::f32::EPSILON);
+        assert!(expl.details()[1].description().contains("negative_boost"));
+        // the explained score is the same as the real one
+        assert!((scorer.score().unwrap() - expl.value()).abs() < ::std::f32::EPSILON);
+
+        assert!(!scorer.explain(10).unwrap().is_match());
+    }
+
+    #[test]
+    fn test_score_at() {
+        let positive = Box::new(create_mock_scorer(vec![1, 3, 4, 8, 9]));
+        let negative = Box::new(create_mock_scorer(vec![2, 4, 9]));
+        let mut scorer = BoostingScorer::new(positive, negative, 0.5);
+
+        assert_eq!(scorer.score_at(4).unwrap(), Some(2.0));
+        // the same doc again
+        assert_eq!(scorer.score_at(4).unwrap(), Some(2.0));
+        // not matched by the positive clause
+        assert_eq!(scorer.score_at(6).unwrap(), None);
+        assert_eq!(scorer.doc_id(), 8);
+        assert_eq!(scorer.score_at(8).unwrap(), Some(8.0));
+        assert_eq!(scorer.score_at(9).unwrap(), Some(4.5));
+        // docs before the current one can't be scored
+        assert_eq!(scorer.score_at(3).unwrap(), None);
+        assert_eq!(scorer.score_at(20).unwrap(), None);
+        assert_eq!(scorer.doc_id(), NO_MORE_DOCS);
+
+        // the default of `Scorer`
+        let mut scorer = create_mock_scorer(vec![2, 5]);
+        assert_eq!(scorer.score_at(5).unwrap(), Some(5.0));
+    }
+
+    #[test]
+    fn test_count_only_skips_negative() {
+        let negative = MockCountingScorer::new(vec![2, 4]);
+        let advance_calls = Arc::clone(&negative.advance_calls);
+
+        let positive = Box::new(create_mock_scorer(vec![1, 2, 3, 4]));
+        let mut scorer = BoostingScorer::new(positive, Box::new(negative), 0.5);
+        scorer.set_score_mode(ScoreMode::CountOnly);
+
+        while scorer.next().unwrap() != NO_MORE_DOCS {
+            scorer.score().unwrap();
+        }
+        assert_eq!(advance_calls.load(Ordering::SeqCst), 0);
+
+        let negative = MockCountingScorer::new(vec![2, 4]);
+        let advance_calls = Arc::clone(&negative.advance_calls);
+
+        let positive = Box::new(create_mock_scorer(vec![1, 2, 3, 4]));
+        let mut scorer = BoostingScorer::new(positive, Box::new(negative), 0.5);
+        scorer.set_score_mode(ScoreMode::Complete);
+
+        while scorer.next().unwrap() != NO_MORE_DOCS {
+            scorer.score().unwrap();
+        }
+        assert_eq!(advance_calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_two_phase() {
+        let positive = Box::new(MockTwoPhaseScorer::new(
+            vec![1, 2, 3, 4, 5, 6],
+            vec![2, 3, 6],
+        ));
+        let negative = Box::new(create_mock_scorer(vec![3, 5]));
+        let mut scorer = BoostingScorer::new(positive, negative, 0.5);
+
+        assert!(scorer.as_two_phase().is_some());
+
+        let mut collected = vec![];
+        loop {
+            let doc = {
+                let two_phase = scorer.as_two_phase().unwrap();
+                let mut doc = two_phase.next_approximation().unwrap();
+                while doc != NO_MORE_DOCS && !two_phase.confirm_match().unwrap() {
+                    doc = two_phase.next_approximation().unwrap();
+                }
+                doc
+            };
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            collected.push((doc, scorer.score().unwrap()));
+        }
+
+        assert_eq!(collected, vec![(2, 2.0), (3, 1.5), (6, 6.0)]);
+
+        let positive = Box::new(create_mock_scorer(vec![1, 2]));
+        let negative = Box::new(create_mock_scorer(vec![2]));
+        let mut scorer = BoostingScorer::new(positive, negative, 0.5);
+        assert!(scorer.as_two_phase().is_none());
+    }
 }
@@ -12,11 +12,29 @@
 // limitations under the License.
 
 use core::search::collector::Collector;
-use core::search::scorer::Scorer;
-use core::search::NO_MORE_DOCS;
+use core::search::scorer::{ConstantScoreScorer, Scorer};
+use core::search::{DocIterator, NO_MORE_DOCS};
 use core::util::Bits;
 use core::util::DocId;
-use error::Result;
+use error::{ErrorKind::UnsupportedOperation, Result};
+
+/// Scores the documents of a leaf segment range by range.
+///
+/// Driving a `Scorer` by hand costs a `next` and a `score` call per doc,
+/// implementations can do the whole loop at once, and may use a cheaper way
+/// to iterate and score the docs if they know how.
+pub trait LeafBulkScorer {
+    /// Collects the matching documents in `[min_doc, max_doc)` and returns an
+    /// estimation of the next matching document which is on or after `max_doc`,
+    /// eg. `NO_MORE_DOCS` once all docs are collected. Ranges must be given in
+    /// increasing order, see `BulkScorer::score` for details.
+    fn score_range<C: Collector>(
+        &mut self,
+        collector: &mut C,
+        min_doc: DocId,
+        max_doc: DocId,
+    ) -> Result<DocId>;
+}
 
 /// used to score a range of documents at once.
 /// Only queries that have a more optimized means of scoring
@@ -68,10 +86,10 @@ impl<'a, S: Scorer + ?Sized + 'a> BulkScorer<'a, S> {
             self.scorer.approximate_advance(min)?
         };
 
-        self.score_range(collector, accept_docs, current_doc, max, next_limit)
+        self.collect_range(collector, accept_docs, current_doc, max, next_limit)
     }
 
-    fn score_range<T: Collector, B: Bits + ?Sized>(
+    fn collect_range<T: Collector, B: Bits + ?Sized>(
         &mut self,
         collector: &mut T,
         accept_docs: Option<&B>,
@@ -154,6 +172,79 @@ impl<'a, S: Scorer + ?Sized + 'a> BulkScorer<'a, S> {
     }
 }
 
+/// The default adapter, drives any `Scorer` one doc after another.
+impl<'a, S: Scorer + ?Sized + 'a> LeafBulkScorer for BulkScorer<'a, S> {
+    fn score_range<C: Collector>(
+        &mut self,
+        collector: &mut C,
+        min_doc: DocId,
+        max_doc: DocId,
+    ) -> Result<DocId> {
+        let mut current_doc = self.scorer.doc_id();
+        if current_doc < min_doc {
+            current_doc = self.scorer.approximate_advance(min_doc)?;
+        }
+        self.score_range_all(collector, current_doc, max_doc, NO_MORE_DOCS as usize)
+    }
+}
+
+/// The score is the same for every doc, so this iterates the wrapped
+/// `DocIterator` directly and hands a plain doc and score holder to the collector.
+impl<T: DocIterator> LeafBulkScorer for ConstantScoreScorer<T> {
+    fn score_range<C: Collector>(
+        &mut self,
+        collector: &mut C,
+        min_doc: DocId,
+        max_doc: DocId,
+    ) -> Result<DocId> {
+        let mut current_doc = self.iterator.doc_id();
+        if current_doc < min_doc {
+            current_doc = self.iterator.advance(min_doc)?;
+        }
+        let mut scorer = ScoreAndDoc {
+            doc: current_doc,
+            score: self.score,
+        };
+        while current_doc < max_doc {
+            scorer.doc = current_doc;
+            collector.collect(current_doc, &mut scorer)?;
+            current_doc = self.iterator.next()?;
+        }
+        Ok(current_doc)
+    }
+}
+
+/// A `Scorer` only holding the current doc and its score, passed to the
+/// collector by bulk scorers which do the iteration themselves.
+struct ScoreAndDoc {
+    doc: DocId,
+    score: f32,
+}
+
+impl Scorer for ScoreAndDoc {
+    fn score(&mut self) -> Result<f32> {
+        Ok(self.score)
+    }
+}
+
+impl DocIterator for ScoreAndDoc {
+    fn doc_id(&self) -> DocId {
+        self.doc
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        bail!(UnsupportedOperation("ScoreAndDoc can't be iterated".into()))
+    }
+
+    fn advance(&mut self, _target: DocId) -> Result<DocId> {
+        bail!(UnsupportedOperation("ScoreAndDoc can't be iterated".into()))
+    }
+
+    fn cost(&self) -> usize {
+        1
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -198,4 +289,71 @@ mod tests {
         assert_eq!(score_docs[1].doc_id(), 4);
         assert_eq!(score_docs[2].doc_id(), 3);
     }
+
+    /// records every collected doc with its score
+    #[derive(Default)]
+    struct RecordingCollector {
+        hits: Vec<(DocId, f32)>,
+    }
+
+    impl Collector for RecordingCollector {
+        fn needs_scores(&self) -> bool {
+            true
+        }
+
+        fn collect<S: Scorer + ?Sized>(&mut self, doc: DocId, scorer: &mut S) -> Result<()> {
+            assert_eq!(scorer.doc_id(), doc);
+            let score = scorer.score()?;
+            self.hits.push((doc, score));
+            Ok(())
+        }
+    }
+
+    fn collect_per_doc<S: Scorer>(scorer: &mut S) -> Vec<(DocId, f32)> {
+        let mut hits = vec![];
+        loop {
+            let doc = scorer.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            hits.push((doc, scorer.score().unwrap()));
+        }
+        hits
+    }
+
+    fn collect_bulk<S: LeafBulkScorer>(
+        scorer: &mut S,
+        ranges: &[(DocId, DocId)],
+    ) -> Vec<(DocId, f32)> {
+        let mut collector = RecordingCollector::default();
+        let mut next = 0;
+        for &(min, max) in ranges {
+            next = scorer.score_range(&mut collector, min, max).unwrap();
+            assert!(next >= max);
+        }
+        assert_eq!(next, NO_MORE_DOCS);
+        collector.hits
+    }
+
+    #[test]
+    fn test_bulk_same_as_per_doc() {
+        let docs = vec![0, 2, 3, 5, 8, 13, 21];
+        let ranges = [(0, 3), (3, 9), (9, 10), (10, NO_MORE_DOCS)];
+
+        let expected = collect_per_doc(&mut create_mock_scorer(docs.clone()));
+        let mut scorer = create_mock_scorer(docs.clone());
+        let actual = collect_bulk(&mut BulkScorer::new(&mut scorer), &ranges);
+        assert_eq!(actual, expected);
+
+        let constant_scorer =
+            || ConstantScoreScorer::new(0.5, create_mock_doc_iterator(docs.clone()), 7);
+        let expected = collect_per_doc(&mut constant_scorer());
+        let actual = collect_bulk(&mut constant_scorer(), &ranges);
+        assert_eq!(actual.len(), docs.len());
+        assert_eq!(actual, expected);
+
+        // skips the docs before the first range
+        let actual = collect_bulk(&mut constant_scorer(), &[(4, NO_MORE_DOCS)]);
+        assert_eq!(actual, expected[3..].to_vec());
+    }
 }
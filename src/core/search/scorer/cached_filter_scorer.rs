@@ -0,0 +1,237 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::search::cache::LRUCache;
+use core::search::scorer::Scorer;
+use core::search::{DocIterator, NO_MORE_DOCS};
+use core::util::{BitSet, BitSetDocIterator, DocId, FixedBitSet, ImmutableBitSet};
+use error::Result;
+
+use std::sync::{Arc, Mutex};
+
+/// A cache of the docs matched by filters, materialized as one `FixedBitSet`
+/// per filter and segment.
+///
+/// Entries are keyed by the identity of the filter, eg. its string form, and
+/// the name of the segment. Once `max_entries` bit sets are cached, the
+/// least recently used one is evicted to make room for another.
+pub struct FilterBitSetCache {
+    cache: Mutex<LRUCache<(String, String), Arc<FixedBitSet>>>,
+}
+
+impl FilterBitSetCache {
+    pub fn new(max_entries: usize) -> FilterBitSetCache {
+        debug_assert!(max_entries > 0);
+        FilterBitSetCache {
+            cache: Mutex::new(LRUCache::with_capacity(max_entries)),
+        }
+    }
+
+    /// Returns the cached bit set of `filter` on `segment`, if any.
+    pub fn get(&self, filter: &str, segment: &str) -> Result<Option<Arc<FixedBitSet>>> {
+        let key = (filter.to_string(), segment.to_string());
+        Ok(self.cache.lock()?.get(&key).cloned())
+    }
+
+    /// Returns the cached bit set of `filter` on `segment`, or on a miss
+    /// collects all the docs of the scorer built by `load` into a new bit set
+    /// of `max_doc` bits and caches it.
+    ///
+    /// The lock is not held while loading, so concurrent misses on the same
+    /// key may load it more than once.
+    pub fn get_or_load<F>(
+        &self,
+        filter: &str,
+        segment: &str,
+        max_doc: DocId,
+        load: F,
+    ) -> Result<Arc<FixedBitSet>>
+    where
+        F: FnOnce() -> Result<Box<dyn Scorer>>,
+    {
+        if let Some(bits) = self.get(filter, segment)? {
+            return Ok(bits);
+        }
+
+        let mut scorer = load()?;
+        let mut bits = FixedBitSet::new(max_doc as usize);
+        loop {
+            let doc = scorer.next()?;
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            bits.set(doc as usize);
+        }
+        let bits = Arc::new(bits);
+        self.cache
+            .lock()?
+            .insert((filter.to_string(), segment.to_string()), Arc::clone(&bits));
+        Ok(bits)
+    }
+
+    /// Evicts the bit set of `filter` on `segment`, eg. when the segment is
+    /// dropped, returns whether it was cached.
+    pub fn remove(&self, filter: &str, segment: &str) -> Result<bool> {
+        let key = (filter.to_string(), segment.to_string());
+        Ok(self.cache.lock()?.remove(&key).is_some())
+    }
+
+    /// Evicts all the cached bit sets.
+    pub fn clear(&self) -> Result<()> {
+        let mut cache = self.cache.lock()?;
+        while cache.remove_last().is_some() {}
+        Ok(())
+    }
+
+    pub fn len(&self) -> Result<usize> {
+        Ok(self.cache.lock()?.len())
+    }
+
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.cache.lock()?.is_empty())
+    }
+}
+
+/// A constant score `Scorer` over the docs of a filter, which are collected
+/// from the filter scorer into a `FilterBitSetCache` on first use and then
+/// iterated from the cached bit set, eg. for frequently reused filters.
+pub struct CachedFilterScorer {
+    iterator: BitSetDocIterator<FixedBitSet>,
+    score: f32,
+}
+
+impl CachedFilterScorer {
+    /// Creates a scorer over the cached docs of `filter` on `segment`, the
+    /// filter scorer is only built by `load` on a cache miss.
+    pub fn new<F>(
+        cache: &FilterBitSetCache,
+        filter: &str,
+        segment: &str,
+        max_doc: DocId,
+        score: f32,
+        load: F,
+    ) -> Result<CachedFilterScorer>
+    where
+        F: FnOnce() -> Result<Box<dyn Scorer>>,
+    {
+        let bits = cache.get_or_load(filter, segment, max_doc, load)?;
+        let cost = bits.cardinality();
+        Ok(CachedFilterScorer {
+            iterator: BitSetDocIterator::new(bits, cost)?,
+            score,
+        })
+    }
+}
+
+impl Scorer for CachedFilterScorer {
+    fn score(&mut self) -> Result<f32> {
+        Ok(self.score)
+    }
+
+    fn get_max_score(&mut self, _up_to: DocId) -> Result<f32> {
+        Ok(self.score)
+    }
+}
+
+impl DocIterator for CachedFilterScorer {
+    fn doc_id(&self) -> DocId {
+        self.iterator.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        self.iterator.next()
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        self.iterator.advance(target)
+    }
+
+    fn cost(&self) -> usize {
+        self.iterator.cost()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::tests::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn create_scorer(
+        cache: &FilterBitSetCache,
+        segment: &str,
+        loads: &Arc<AtomicUsize>,
+    ) -> CachedFilterScorer {
+        let loads = Arc::clone(loads);
+        CachedFilterScorer::new(cache, "status:active", segment, 16, 1.0, move || {
+            loads.fetch_add(1, Ordering::SeqCst);
+            Ok(Box::new(create_mock_scorer(vec![1, 4, 9, 15])) as Box<dyn Scorer>)
+        })
+        .unwrap()
+    }
+
+    fn collect(scorer: &mut CachedFilterScorer) -> Vec<DocId> {
+        let mut docs = vec![];
+        loop {
+            let doc = scorer.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            docs.push(doc);
+        }
+        docs
+    }
+
+    #[test]
+    fn test_filter_runs_once() {
+        let cache = FilterBitSetCache::new(4);
+        let loads = Arc::new(AtomicUsize::new(0));
+        for _ in 0..3 {
+            let mut scorer = create_scorer(&cache, "_0", &loads);
+            assert_eq!(scorer.cost(), 4);
+            assert_eq!(collect(&mut scorer), vec![1, 4, 9, 15]);
+        }
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+
+        let mut scorer = create_scorer(&cache, "_0", &loads);
+        assert_eq!(scorer.advance(5).unwrap(), 9);
+        assert!((scorer.score().unwrap() - 1.0).abs() < ::std::f32::EPSILON);
+
+        // each segment has its own entry
+        create_scorer(&cache, "_1", &loads);
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+        assert_eq!(cache.len().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_eviction() {
+        let cache = FilterBitSetCache::new(2);
+        let loads = Arc::new(AtomicUsize::new(0));
+        create_scorer(&cache, "_0", &loads);
+        create_scorer(&cache, "_1", &loads);
+        // `_0` is used again so `_1` is the least recently used
+        create_scorer(&cache, "_0", &loads);
+        create_scorer(&cache, "_2", &loads);
+        assert_eq!(loads.load(Ordering::SeqCst), 3);
+        assert!(cache.get("status:active", "_0").unwrap().is_some());
+        assert!(cache.get("status:active", "_1").unwrap().is_none());
+
+        assert!(cache.remove("status:active", "_0").unwrap());
+        assert!(!cache.remove("status:active", "_0").unwrap());
+        create_scorer(&cache, "_0", &loads);
+        assert_eq!(loads.load(Ordering::SeqCst), 4);
+
+        cache.clear().unwrap();
+        assert!(cache.is_empty().unwrap());
+    }
+}
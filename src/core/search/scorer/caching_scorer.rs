@@ -0,0 +1,176 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::search::scorer::{FeatureResult, ScoreMode, Scorer};
+use core::search::{DocIterator, Explanation, TwoPhaseIterator};
+use core::util::{DocId, IndexedContext};
+use error::Result;
+
+/// A `Scorer` which wraps another scorer and caches the score of the
+/// current document, this is the counterpart of Lucene's `ScoreCachingWrappingScorer`.
+///
+/// This is useful when the score of a single hit may be requested
+/// multiple times, eg. by both collector and highlighter.
+pub struct CachingScorer<S: Scorer> {
+    scorer: S,
+    cur_doc: DocId,
+    cur_score: f32,
+}
+
+impl<S: Scorer> CachingScorer<S> {
+    pub fn new(scorer: S) -> Self {
+        CachingScorer {
+            scorer,
+            cur_doc: -1,
+            cur_score: 0f32,
+        }
+    }
+}
+
+impl<S: Scorer> Scorer for CachingScorer<S> {
+    fn score(&mut self) -> Result<f32> {
+        let doc = self.scorer.doc_id();
+        if doc != self.cur_doc {
+            self.cur_score = self.scorer.score()?;
+            self.cur_doc = doc;
+        }
+        Ok(self.cur_score)
+    }
+
+    fn score_context(&mut self) -> Result<IndexedContext> {
+        self.scorer.score_context()
+    }
+
+    fn score_feature(&mut self) -> Result<Vec<FeatureResult>> {
+        self.scorer.score_feature()
+    }
+
+    fn freq(&self) -> Result<f32> {
+        self.scorer.freq()
+    }
+
+    fn set_score_mode(&mut self, mode: ScoreMode) {
+        self.scorer.set_score_mode(mode)
+    }
+
+    fn advance_shallow(&mut self, target: DocId) -> Result<DocId> {
+        self.scorer.advance_shallow(target)
+    }
+
+    fn get_max_score(&mut self, up_to: DocId) -> Result<f32> {
+        self.scorer.get_max_score(up_to)
+    }
+
+    fn set_min_competitive_score(&mut self, min_score: f32) {
+        self.scorer.set_min_competitive_score(min_score)
+    }
+
+    fn explain(&mut self, doc: DocId) -> Result<Explanation> {
+        self.scorer.explain(doc)
+    }
+}
+
+impl<S: Scorer> DocIterator for CachingScorer<S> {
+    fn doc_id(&self) -> DocId {
+        self.scorer.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        self.scorer.next()
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        self.scorer.advance(target)
+    }
+
+    fn cost(&self) -> usize {
+        self.scorer.cost()
+    }
+
+    fn matches(&mut self) -> Result<bool> {
+        self.scorer.matches()
+    }
+
+    fn match_cost(&self) -> f32 {
+        self.scorer.match_cost()
+    }
+
+    fn support_two_phase(&self) -> bool {
+        self.scorer.support_two_phase()
+    }
+
+    fn approximate_next(&mut self) -> Result<DocId> {
+        self.scorer.approximate_next()
+    }
+
+    fn approximate_advance(&mut self, target: DocId) -> Result<DocId> {
+        self.scorer.approximate_advance(target)
+    }
+
+    fn as_two_phase(&mut self) -> Option<&mut dyn TwoPhaseIterator> {
+        if self.scorer.support_two_phase() {
+            Some(self)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::tests::*;
+    use core::search::NO_MORE_DOCS;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_score_cached_per_doc() {
+        let inner = MockCountingScorer::new(vec![1, 3, 5, 7]);
+        let score_calls = Arc::clone(&inner.score_calls);
+        let inner: Box<dyn Scorer> = Box::new(inner);
+        let mut scorer = CachingScorer::new(inner);
+
+        assert_eq!(scorer.next().unwrap(), 1);
+        assert!((scorer.score().unwrap() - 1.0).abs() < ::std::f32::EPSILON);
+        assert!((scorer.score().unwrap() - 1.0).abs() < ::std::f32::EPSILON);
+        assert_eq!(score_calls.load(Ordering::SeqCst), 1);
+
+        assert_eq!(scorer.advance(4).unwrap(), 5);
+        assert!((scorer.score().unwrap() - 5.0).abs() < ::std::f32::EPSILON);
+        assert!((scorer.score().unwrap() - 5.0).abs() < ::std::f32::EPSILON);
+        assert_eq!(score_calls.load(Ordering::SeqCst), 2);
+
+        assert_eq!(scorer.next().unwrap(), 7);
+        assert!((scorer.score().unwrap() - 7.0).abs() < ::std::f32::EPSILON);
+        assert_eq!(score_calls.load(Ordering::SeqCst), 3);
+
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+    }
+
+    #[test]
+    fn test_forward_two_phase() {
+        let mut scorer = CachingScorer::new(MockTwoPhaseScorer::new(vec![1, 3, 5], vec![3]));
+        {
+            let two_phase = scorer.as_two_phase().unwrap();
+            assert_eq!(two_phase.next_approximation().unwrap(), 1);
+            assert!(!two_phase.confirm_match().unwrap());
+            assert_eq!(two_phase.next_approximation().unwrap(), 3);
+            assert!(two_phase.confirm_match().unwrap());
+        }
+
+        assert!(CachingScorer::new(create_mock_scorer(vec![1]))
+            .as_two_phase()
+            .is_none());
+    }
+}
@@ -0,0 +1,163 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::search::scorer::{Scorer, SynonymScorer};
+use core::search::similarity::SimScorer;
+use core::search::DocIterator;
+use core::util::DocId;
+use error::{ErrorKind::IllegalArgument, Result};
+
+/// A `Scorer` which scores a term over several fields as if the fields were
+/// a single one, eg. BM25F as done by Lucene's `CombinedFieldQuery`.
+///
+/// Each sub scorer matches the term in one field and comes with the weight
+/// of that field. The matched docs are the union of the docs of all the sub
+/// scorers, the frequency of a doc is the weighted sum of the frequencies of
+/// the sub scorers on it, and the score is computed by `sim_scorer` from that
+/// combined frequency, so the saturation of the similarity applies across the
+/// fields instead of to each field separately.
+///
+/// Weights must be at least 1, like in Lucene, a lower weight could make a
+/// combined frequency lower than the frequency of a single field.
+///
+/// This is a `SynonymScorer` whose sub scorers are weighted.
+pub struct CombinedFieldsScorer {
+    scorer: SynonymScorer,
+}
+
+impl CombinedFieldsScorer {
+    pub fn new(
+        fields: Vec<(Box<dyn Scorer>, f32)>,
+        sim_scorer: Box<dyn SimScorer>,
+    ) -> Result<CombinedFieldsScorer> {
+        let mut scorers = Vec::with_capacity(fields.len());
+        let mut weights = Vec::with_capacity(fields.len());
+        for (scorer, weight) in fields {
+            if !(weight >= 1.0 && weight.is_finite()) {
+                bail!(IllegalArgument(format!(
+                    "field weight must be finite and at least 1, got {}",
+                    weight
+                )));
+            }
+            scorers.push(scorer);
+            weights.push(weight);
+        }
+        Ok(CombinedFieldsScorer {
+            scorer: SynonymScorer::with_weights(scorers, weights, sim_scorer),
+        })
+    }
+}
+
+impl Scorer for CombinedFieldsScorer {
+    fn score(&mut self) -> Result<f32> {
+        self.scorer.score()
+    }
+
+    fn freq(&self) -> Result<f32> {
+        self.scorer.freq()
+    }
+}
+
+impl DocIterator for CombinedFieldsScorer {
+    fn doc_id(&self) -> DocId {
+        self.scorer.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        self.scorer.next()
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        self.scorer.advance(target)
+    }
+
+    fn cost(&self) -> usize {
+        self.scorer.cost()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::scorer::TermScorer;
+    use core::search::tests::*;
+    use core::search::NO_MORE_DOCS;
+
+    /// a saturating similarity, eg. `freq / (freq + 1)`
+    struct SaturatingSimScorer;
+
+    impl SimScorer for SaturatingSimScorer {
+        fn score(&mut self, _doc: DocId, freq: f32) -> Result<f32> {
+            Ok(freq / (freq + 1.0))
+        }
+
+        fn compute_slop_factor(&self, _distance: i32) -> f32 {
+            1.0
+        }
+    }
+
+    fn term_scorer(postings: Vec<(DocId, Vec<i32>)>) -> Box<dyn Scorer> {
+        Box::new(TermScorer::new(
+            Box::new(SaturatingSimScorer),
+            MockPostingIterator::new(postings),
+        ))
+    }
+
+    fn create_scorer() -> CombinedFieldsScorer {
+        // the term in "title" (weight 2): docs 1 and 4, in "body": docs 1 (freq 2) and 2
+        let title = term_scorer(vec![(1, vec![0]), (4, vec![1])]);
+        let body = term_scorer(vec![(1, vec![3, 8]), (2, vec![5])]);
+        CombinedFieldsScorer::new(
+            vec![(title, 2.0), (body, 1.0)],
+            Box::new(SaturatingSimScorer),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_combined_freq() {
+        let mut scorer = create_scorer();
+        let mut hits = vec![];
+        loop {
+            let doc = scorer.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            hits.push((doc, scorer.freq().unwrap(), scorer.score().unwrap()));
+        }
+
+        assert_eq!(
+            hits.iter().map(|h| (h.0, h.1)).collect::<Vec<_>>(),
+            vec![(1, 4.0), (2, 1.0), (4, 2.0)]
+        );
+        // doc 1 is scored once from the combined freq 2 * 1 + 2
+        assert!((hits[0].2 - 0.8).abs() < 1e-6);
+        // the naive weighted sum of the per field scores is 2 * 0.5 + 2 / 3
+        let naive = 2.0 * 0.5 + 2.0 / 3.0;
+        assert!((hits[0].2 - naive).abs() > 0.5);
+        assert!((hits[1].2 - 0.5).abs() < 1e-6);
+        assert!((hits[2].2 - 2.0 / 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_advance_and_weights() {
+        let mut scorer = create_scorer();
+        assert_eq!(scorer.advance(2).unwrap(), 2);
+        assert_eq!(scorer.advance(3).unwrap(), 4);
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+
+        let title = term_scorer(vec![(1, vec![0])]);
+        let result = CombinedFieldsScorer::new(vec![(title, 0.5)], Box::new(SaturatingSimScorer));
+        assert!(result.is_err());
+    }
+}
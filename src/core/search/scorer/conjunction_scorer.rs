@@ -17,78 +17,29 @@ use core::util::DocId;
 use error::Result;
 
 /// Scorer for conjunctions, sets of queries, all of which are required.
+///
+/// The children are intersected by their approximations with a
+/// `ConjunctionDocIterator`, and the score is the sum of their scores.
 pub struct ConjunctionScorer<T: Scorer> {
-    lead1: T,
-    lead2: T,
-    others: Vec<T>,
+    conjunction: ConjunctionDocIterator<Approximation<T>>,
 }
 
 impl<T: Scorer> ConjunctionScorer<T> {
-    pub fn new(mut children: Vec<T>) -> ConjunctionScorer<T> {
+    pub fn new(children: Vec<T>) -> ConjunctionScorer<T> {
         assert!(children.len() >= 2);
 
-        children.sort_by(|a, b| a.cost().cmp(&b.cost()));
-
-        let others = children.drain(2..).collect();
-
-        let lead2 = children.remove(1);
-        let lead1 = children.remove(0);
-
+        let children = children.into_iter().map(Approximation).collect();
         ConjunctionScorer {
-            lead1,
-            lead2,
-            others,
-        }
-    }
-
-    fn skip_to_approx(&mut self, target: DocId) -> Result<DocId> {
-        let mut doc = target;
-
-        'advanceHead: loop {
-            debug_assert_eq!(self.lead1.doc_id(), doc);
-
-            // find agreement between the two iterators with the lower costs
-            // we special case them because they do not need the
-            // 'other.doc_id() < target' check that the 'others' iterators need
-            let next2 = self.lead2.approximate_advance(doc)?;
-
-            if next2 != doc {
-                doc = self.lead1.approximate_advance(next2)?;
-                if next2 != doc {
-                    continue;
-                }
-            }
-
-            if doc == NO_MORE_DOCS {
-                return Ok(doc);
-            }
-
-            // then find agreement with other iterators
-            for other in &mut self.others {
-                // other.doc may already be equal to doc if we "continued advanceHead"
-                // on the previous iteration and the advance on the lead scorer exactly matched.
-                if other.doc_id() < doc {
-                    let next = other.approximate_advance(doc)?;
-
-                    if next > doc {
-                        // iterator beyond the current doc - advance lead
-                        // and continue to the new highest doc.
-                        doc = self.lead1.approximate_advance(next)?;
-                        continue 'advanceHead;
-                    }
-                }
-            }
-            return Ok(doc);
+            conjunction: ConjunctionDocIterator::new(children),
         }
     }
 }
 
 impl<T: Scorer> Scorer for ConjunctionScorer<T> {
     fn score(&mut self) -> Result<f32> {
-        let mut score: f32 = self.lead1.score()?;
-        score += self.lead2.score()?;
-        for scorer in &mut self.others {
-            score += scorer.score()?;
+        let mut score: f32 = 0.0;
+        for scorer in self.conjunction.iterators() {
+            score += scorer.0.score()?;
         }
 
         Ok(score)
@@ -97,7 +48,7 @@ impl<T: Scorer> Scorer for ConjunctionScorer<T> {
 
 impl<T: Scorer> DocIterator for ConjunctionScorer<T> {
     fn doc_id(&self) -> DocId {
-        self.lead1.doc_id()
+        self.conjunction.doc_id()
     }
 
     fn next(&mut self) -> Result<DocId> {
@@ -109,7 +60,7 @@ impl<T: Scorer> DocIterator for ConjunctionScorer<T> {
     }
 
     fn cost(&self) -> usize {
-        self.lead1.cost()
+        self.conjunction.cost()
     }
 
     fn matches(&mut self) -> Result<bool> {
@@ -117,13 +68,99 @@ impl<T: Scorer> DocIterator for ConjunctionScorer<T> {
     }
 
     fn approximate_next(&mut self) -> Result<DocId> {
-        let doc = self.lead1.approximate_next()?;
-        self.skip_to_approx(doc)
+        self.conjunction.next()
     }
 
     fn approximate_advance(&mut self, target: DocId) -> Result<DocId> {
-        let doc = self.lead1.approximate_advance(target)?;
-        self.skip_to_approx(doc)
+        self.conjunction.advance(target)
+    }
+}
+
+// iterates over the approximation of a scorer
+struct Approximation<T: Scorer>(T);
+
+impl<T: Scorer> DocIterator for Approximation<T> {
+    fn doc_id(&self) -> DocId {
+        self.0.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        self.0.approximate_next()
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        self.0.approximate_advance(target)
+    }
+
+    fn cost(&self) -> usize {
+        self.0.cost()
+    }
+}
+
+/// A `DocIterator` over the intersection of several doc iterators,
+/// independent of scoring, eg. for the phrase scorers which align the
+/// postings of their terms by doc id before checking the positions.
+///
+/// The cheapest iterator leads, the others are advanced to its doc and the
+/// lead is advanced again whenever one of them moves beyond it, so each doc
+/// returned is matched by all the iterators, which are all positioned on it.
+pub struct ConjunctionDocIterator<T: DocIterator> {
+    lead: T,
+    others: Vec<T>,
+}
+
+impl<T: DocIterator> ConjunctionDocIterator<T> {
+    pub fn new(mut iterators: Vec<T>) -> ConjunctionDocIterator<T> {
+        assert!(!iterators.is_empty());
+        iterators.sort_by_key(|it| it.cost());
+        let lead = iterators.remove(0);
+        ConjunctionDocIterator {
+            lead,
+            others: iterators,
+        }
+    }
+
+    /// Returns the iterators, which are all positioned on the current doc,
+    /// the lead iterator comes first.
+    pub fn iterators(&mut self) -> impl Iterator<Item = &mut T> {
+        ::std::iter::once(&mut self.lead).chain(self.others.iter_mut())
+    }
+
+    fn do_next(&mut self, mut doc: DocId) -> Result<DocId> {
+        'advance_lead: while doc != NO_MORE_DOCS {
+            for other in &mut self.others {
+                let mut other_doc = other.doc_id();
+                if other_doc < doc {
+                    other_doc = other.advance(doc)?;
+                }
+                if other_doc > doc {
+                    doc = self.lead.advance(other_doc)?;
+                    continue 'advance_lead;
+                }
+            }
+            break;
+        }
+        Ok(doc)
+    }
+}
+
+impl<T: DocIterator> DocIterator for ConjunctionDocIterator<T> {
+    fn doc_id(&self) -> DocId {
+        self.lead.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        let doc = self.lead.next()?;
+        self.do_next(doc)
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        let doc = self.lead.advance(target)?;
+        self.do_next(doc)
+    }
+
+    fn cost(&self) -> usize {
+        self.lead.cost()
     }
 }
 
@@ -220,4 +257,48 @@ mod tests {
 
         ConjunctionScorer::new(vec![s1, s2, s3])
     }
+
+    #[test]
+    fn test_conjunction_doc_iterator() {
+        let iterators = vec![
+            create_mock_doc_iterator(vec![1, 2, 3, 4, 5, 7, 9, 10]),
+            create_mock_doc_iterator(vec![2, 5, 7, 10]),
+            create_mock_doc_iterator(vec![0, 2, 3, 5, 8, 10, 11]),
+        ];
+        let mut iterator = ConjunctionDocIterator::new(iterators);
+        // led by the cheapest one
+        assert_eq!(iterator.cost(), 4);
+        assert_eq!(iterator.doc_id(), -1);
+
+        let mut docs = vec![];
+        loop {
+            let doc = iterator.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            assert!(iterator.iterators().all(|it| it.doc_id() == doc));
+            docs.push(doc);
+        }
+        assert_eq!(docs, vec![2, 5, 10]);
+        assert_eq!(iterator.doc_id(), NO_MORE_DOCS);
+    }
+
+    #[test]
+    fn test_conjunction_doc_iterator_advance() {
+        // boxed iterators of different types can be intersected too
+        let iterators: Vec<Box<dyn DocIterator>> = vec![
+            Box::new(create_mock_doc_iterator(vec![1, 3, 6, 8, 12])),
+            Box::new(create_mock_scorer(vec![3, 6, 12])),
+            Box::new(create_mock_doc_iterator(vec![3, 4, 5, 6, 7, 8, 12])),
+        ];
+        let mut iterator = ConjunctionDocIterator::new(iterators);
+        assert_eq!(iterator.advance(4).unwrap(), 6);
+        assert_eq!(iterator.advance(7).unwrap(), 12);
+        assert_eq!(iterator.next().unwrap(), NO_MORE_DOCS);
+
+        // the intersection of a single iterator is the iterator itself
+        let mut iterator = ConjunctionDocIterator::new(vec![create_mock_doc_iterator(vec![4])]);
+        assert_eq!(iterator.next().unwrap(), 4);
+        assert_eq!(iterator.next().unwrap(), NO_MORE_DOCS);
+    }
 }
@@ -11,21 +11,31 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use core::search::scorer::Scorer;
-use core::search::{DocIterator, NO_MORE_DOCS};
-use core::util::{DisiPriorityQueue, DocId};
+use core::search::scorer::{two_phase_next, Scorer};
+use core::search::{DocIterator, TwoPhaseIterator, NO_MORE_DOCS};
+use core::util::{DisiPriorityQueue, DisiWrapper, DocId};
 
 use error::Result;
+use std::cmp::Ordering;
 use std::f32;
 
 pub const DEFAULT_MIN_SHOULD_MATCH: i32 = 1;
 
 /// A Scorer for OR like queries, counterpart of `ConjunctionScorer`.
+///
+/// If any of the children supports two phase iteration so does this scorer,
+/// it iterates over the union of the approximations of the children and a
+/// doc only matches once enough of the children on it are confirmed. The
+/// children which don't support two phase iteration are counted first, so
+/// the two phase ones are only confirmed if they are needed, and only the
+/// ones on the current doc.
 pub struct DisjunctionSumScorer<T: Scorer> {
     sub_scorers: SubScorers<T>,
     needs_scores: bool,
     cost: usize,
     min_should_match: i32,
+    two_phase: bool,
+    match_cost: f32,
 }
 
 impl<T: Scorer> DisjunctionSumScorer<T> {
@@ -34,9 +44,11 @@ impl<T: Scorer> DisjunctionSumScorer<T> {
         needs_scores: bool,
         min_should_match: i32,
     ) -> DisjunctionSumScorer<T> {
-        debug_assert!(children.len() > 0);
+        debug_assert!(!children.is_empty());
 
         let cost = children.iter().map(|w| w.cost()).sum();
+        let two_phase = children.iter().any(|c| c.support_two_phase());
+        let match_cost = children.iter().map(|c| c.match_cost()).sum();
 
         let sub_scorers = if children.len() < 10 || min_should_match > DEFAULT_MIN_SHOULD_MATCH {
             SubScorers::SQ(SimpleQueue::new(children))
@@ -49,6 +61,8 @@ impl<T: Scorer> DisjunctionSumScorer<T> {
             needs_scores,
             cost,
             min_should_match,
+            two_phase,
+            match_cost,
         }
     }
 }
@@ -69,11 +83,21 @@ impl<T: Scorer> DocIterator for DisjunctionSumScorer<T> {
     }
 
     fn next(&mut self) -> Result<DocId> {
-        self.approximate_next()
+        let doc = self.approximate_next()?;
+        if self.two_phase {
+            two_phase_next(self)
+        } else {
+            Ok(doc)
+        }
     }
 
     fn advance(&mut self, target: DocId) -> Result<DocId> {
-        self.approximate_advance(target)
+        let doc = self.approximate_advance(target)?;
+        if self.two_phase {
+            two_phase_next(self)
+        } else {
+            Ok(doc)
+        }
     }
 
     fn cost(&self) -> usize {
@@ -81,11 +105,19 @@ impl<T: Scorer> DocIterator for DisjunctionSumScorer<T> {
     }
 
     fn matches(&mut self) -> Result<bool> {
-        Ok(true)
+        if !self.two_phase {
+            return Ok(true);
+        }
+        self.sub_scorers
+            .matches(self.min_should_match.max(1) as usize)
     }
 
     fn match_cost(&self) -> f32 {
-        0f32
+        self.match_cost
+    }
+
+    fn support_two_phase(&self) -> bool {
+        self.two_phase
     }
 
     fn approximate_next(&mut self) -> Result<DocId> {
@@ -101,6 +133,14 @@ impl<T: Scorer> DocIterator for DisjunctionSumScorer<T> {
     fn approximate_advance(&mut self, target: DocId) -> Result<DocId> {
         self.sub_scorers.approximate_advance(target)
     }
+
+    fn as_two_phase(&mut self) -> Option<&mut dyn TwoPhaseIterator> {
+        if self.two_phase {
+            Some(self)
+        } else {
+            None
+        }
+    }
 }
 
 /// The Scorer for DisjunctionMaxQuery.  The union of all documents generated by the the subquery
@@ -108,11 +148,15 @@ impl<T: Scorer> DocIterator for DisjunctionSumScorer<T> {
 /// the scores computed by the subquery scorers that generate that document, plus
 /// tieBreakerMultiplier times the sum of the scores for the other subqueries that generate the
 /// document.
+///
+/// Two phase children are handled like in `DisjunctionSumScorer`.
 pub struct DisjunctionMaxScorer<T: Scorer> {
     sub_scorers: SubScorers<T>,
     needs_scores: bool,
     cost: usize,
     tie_breaker_multiplier: f32,
+    two_phase: bool,
+    match_cost: f32,
 }
 
 impl<T: Scorer> DisjunctionMaxScorer<T> {
@@ -121,9 +165,11 @@ impl<T: Scorer> DisjunctionMaxScorer<T> {
         tie_breaker_multiplier: f32,
         needs_scores: bool,
     ) -> DisjunctionMaxScorer<T> {
-        debug_assert!(children.len() > 0);
+        debug_assert!(!children.is_empty());
 
         let cost = children.iter().map(|w| w.cost()).sum();
+        let two_phase = children.iter().any(|c| c.support_two_phase());
+        let match_cost = children.iter().map(|c| c.match_cost()).sum();
 
         let sub_scorers = if children.len() < 10 {
             SubScorers::SQ(SimpleQueue::new(children))
@@ -136,6 +182,8 @@ impl<T: Scorer> DisjunctionMaxScorer<T> {
             needs_scores,
             cost,
             tie_breaker_multiplier,
+            two_phase,
+            match_cost,
         }
     }
 }
@@ -156,11 +204,21 @@ impl<T: Scorer> DocIterator for DisjunctionMaxScorer<T> {
     }
 
     fn next(&mut self) -> Result<DocId> {
-        self.approximate_next()
+        let doc = self.approximate_next()?;
+        if self.two_phase {
+            two_phase_next(self)
+        } else {
+            Ok(doc)
+        }
     }
 
     fn advance(&mut self, target: DocId) -> Result<DocId> {
-        self.approximate_advance(target)
+        let doc = self.approximate_advance(target)?;
+        if self.two_phase {
+            two_phase_next(self)
+        } else {
+            Ok(doc)
+        }
     }
 
     fn cost(&self) -> usize {
@@ -168,11 +226,18 @@ impl<T: Scorer> DocIterator for DisjunctionMaxScorer<T> {
     }
 
     fn matches(&mut self) -> Result<bool> {
-        Ok(true)
+        if !self.two_phase {
+            return Ok(true);
+        }
+        self.sub_scorers.matches(1)
     }
 
     fn match_cost(&self) -> f32 {
-        0f32
+        self.match_cost
+    }
+
+    fn support_two_phase(&self) -> bool {
+        self.two_phase
     }
 
     fn approximate_next(&mut self) -> Result<DocId> {
@@ -182,11 +247,22 @@ impl<T: Scorer> DocIterator for DisjunctionMaxScorer<T> {
     fn approximate_advance(&mut self, target: DocId) -> Result<DocId> {
         self.sub_scorers.approximate_advance(target)
     }
+
+    fn as_two_phase(&mut self) -> Option<&mut dyn TwoPhaseIterator> {
+        if self.two_phase {
+            Some(self)
+        } else {
+            None
+        }
+    }
 }
 
 pub struct SimpleQueue<T: Scorer> {
     scorers: Vec<T>,
     curr_doc: DocId,
+    /// whether the two phase scorers on `curr_doc` matched, `None` until
+    /// they are confirmed
+    matches: Vec<Option<bool>>,
 }
 
 impl<T: Scorer> SimpleQueue<T> {
@@ -195,11 +271,42 @@ impl<T: Scorer> SimpleQueue<T> {
         for s in children.iter() {
             curr_doc = curr_doc.min(s.doc_id());
         }
+        let matches = vec![None; children.len()];
         SimpleQueue {
             scorers: children,
             curr_doc,
+            matches,
         }
     }
+
+    fn set_curr_doc(&mut self, doc: DocId) {
+        if doc != self.curr_doc {
+            for m in &mut self.matches {
+                *m = None;
+            }
+        }
+        self.curr_doc = doc;
+    }
+
+    /// Returns whether the `i`th scorer, which must be on `curr_doc`, matches.
+    fn sub_matches(&mut self, i: usize) -> Result<bool> {
+        debug_assert_eq!(self.scorers[i].doc_id(), self.curr_doc);
+        if !self.scorers[i].support_two_phase() {
+            return Ok(true);
+        }
+        if self.matches[i].is_none() {
+            self.matches[i] = Some(self.scorers[i].matches()?);
+        }
+        Ok(self.matches[i].unwrap())
+    }
+}
+
+fn disi_matches<T: Scorer>(disi: &mut DisiWrapper<T>) -> Result<bool> {
+    if disi.inner().support_two_phase() {
+        disi.matches()
+    } else {
+        Ok(true)
+    }
 }
 
 pub enum SubScorers<T: Scorer> {
@@ -214,9 +321,9 @@ impl<T: Scorer> SubScorers<T> {
                 let mut score: f32 = 0.0f32;
 
                 let doc_id = sq.curr_doc;
-                for s in sq.scorers.iter_mut() {
-                    if s.doc_id() == doc_id {
-                        let sub_score = s.score()?;
+                for i in 0..sq.scorers.len() {
+                    if sq.scorers[i].doc_id() == doc_id && sq.sub_matches(i)? {
+                        let sub_score = sq.scorers[i].score()?;
                         score += sub_score;
                     }
                 }
@@ -228,8 +335,10 @@ impl<T: Scorer> SubScorers<T> {
                 let mut disi = dpq.top_list();
 
                 loop {
-                    let sub_score = disi.inner_mut().score()?;
-                    score += sub_score;
+                    if disi_matches(disi)? {
+                        let sub_score = disi.inner_mut().score()?;
+                        score += sub_score;
+                    }
 
                     if disi.next.is_null() {
                         break;
@@ -250,9 +359,9 @@ impl<T: Scorer> SubScorers<T> {
                 let mut score_max = f32::NEG_INFINITY;
 
                 let doc_id = sq.curr_doc;
-                for s in sq.scorers.iter_mut() {
-                    if s.doc_id() == doc_id {
-                        let sub_score = s.score()?;
+                for i in 0..sq.scorers.len() {
+                    if sq.scorers[i].doc_id() == doc_id && sq.sub_matches(i)? {
+                        let sub_score = sq.scorers[i].score()?;
 
                         score_sum += sub_score;
                         score_max = score_max.max(sub_score);
@@ -267,10 +376,12 @@ impl<T: Scorer> SubScorers<T> {
                 let mut disi = dbq.top_list();
 
                 loop {
-                    let sub_score = disi.inner_mut().score()?;
-                    score_sum += sub_score;
-                    if sub_score > score_max {
-                        score_max = sub_score;
+                    if disi_matches(disi)? {
+                        let sub_score = disi.inner_mut().score()?;
+                        score_sum += sub_score;
+                        if sub_score > score_max {
+                            score_max = sub_score;
+                        }
                     }
 
                     if disi.next.is_null() {
@@ -292,6 +403,75 @@ impl<T: Scorer> SubScorers<T> {
         }
     }
 
+    /// Returns whether at least `min_should_match` of the scorers on the
+    /// current doc match. The scorers which don't support two phase iteration
+    /// match without being confirmed, the two phase ones are confirmed by
+    /// increasing match cost until enough of them matched.
+    fn matches(&mut self, min_should_match: usize) -> Result<bool> {
+        match self {
+            SubScorers::SQ(sq) => {
+                let doc_id = sq.curr_doc;
+                let mut count = 0;
+                let mut two_phase = vec![];
+                for (i, s) in sq.scorers.iter().enumerate() {
+                    if s.doc_id() == doc_id {
+                        if s.support_two_phase() {
+                            two_phase.push(i);
+                        } else {
+                            count += 1;
+                        }
+                    }
+                }
+                two_phase.sort_by(|&i, &j| {
+                    let c1 = sq.scorers[i].match_cost();
+                    c1.partial_cmp(&sq.scorers[j].match_cost())
+                        .unwrap_or(Ordering::Equal)
+                });
+
+                for i in two_phase {
+                    if count >= min_should_match {
+                        break;
+                    }
+                    if sq.sub_matches(i)? {
+                        count += 1;
+                    }
+                }
+                Ok(count >= min_should_match)
+            }
+            SubScorers::DPQ(dbq) => {
+                let mut count = 0;
+                let mut two_phase: Vec<*mut DisiWrapper<T>> = vec![];
+                let mut disi = dbq.top_list() as *mut DisiWrapper<T>;
+                while !disi.is_null() {
+                    unsafe {
+                        if (*disi).inner().support_two_phase() {
+                            two_phase.push(disi);
+                        } else {
+                            count += 1;
+                        }
+                        disi = (*disi).next;
+                    }
+                }
+                two_phase.sort_by(|&d1, &d2| unsafe {
+                    (*d1)
+                        .match_cost()
+                        .partial_cmp(&(*d2).match_cost())
+                        .unwrap_or(Ordering::Equal)
+                });
+
+                for disi in two_phase {
+                    if count >= min_should_match {
+                        break;
+                    }
+                    if unsafe { (*disi).matches()? } {
+                        count += 1;
+                    }
+                }
+                Ok(count >= min_should_match)
+            }
+        }
+    }
+
     fn approximate_next(&mut self, min_should_match: Option<i32>) -> Result<DocId> {
         match self {
             SubScorers::SQ(sq) => {
@@ -312,7 +492,7 @@ impl<T: Scorer> SubScorers<T> {
 
                         min_doc = min_doc.min(s.doc_id());
                     }
-                    sq.curr_doc = min_doc;
+                    sq.set_curr_doc(min_doc);
 
                     if min_should_match > DEFAULT_MIN_SHOULD_MATCH {
                         let mut should_count = 0;
@@ -359,7 +539,7 @@ impl<T: Scorer> SubScorers<T> {
                     min_doc = min_doc.min(s.doc_id());
                 }
 
-                sq.curr_doc = min_doc;
+                sq.set_curr_doc(min_doc);
                 Ok(sq.curr_doc)
             }
             SubScorers::DPQ(dbq) => {
@@ -375,3 +555,132 @@ impl<T: Scorer> SubScorers<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::tests::*;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    fn collect<T: Scorer>(scorer: &mut T) -> Vec<(DocId, f32)> {
+        let mut result = vec![];
+        loop {
+            let doc = scorer.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            result.push((doc, scorer.score().unwrap()));
+        }
+        result
+    }
+
+    #[test]
+    fn test_sum_of_matching_clauses() {
+        // the mock scorers score each doc by its id
+        let clauses: Vec<Box<dyn Scorer>> = vec![
+            Box::new(create_mock_scorer(vec![1, 4, 6])),
+            Box::new(create_mock_scorer(vec![2, 4])),
+            Box::new(create_mock_scorer(vec![3, 7])),
+        ];
+        let mut scorer = DisjunctionSumScorer::new(clauses, true, DEFAULT_MIN_SHOULD_MATCH);
+        assert_eq!(scorer.cost(), 7);
+
+        // doc 4 is matched by two of the three clauses
+        assert_eq!(
+            collect(&mut scorer),
+            vec![(1, 1.0), (2, 2.0), (3, 3.0), (4, 8.0), (6, 6.0), (7, 7.0)]
+        );
+
+        let clauses = vec![
+            create_mock_scorer(vec![1, 4, 6]),
+            create_mock_scorer(vec![2, 4]),
+        ];
+        let mut scorer = DisjunctionSumScorer::new(clauses, false, DEFAULT_MIN_SHOULD_MATCH);
+        assert_eq!(scorer.advance(3).unwrap(), 4);
+        assert!(scorer.score().unwrap().abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn test_sum_with_priority_queue() {
+        // enough clauses to use a `DisiPriorityQueue`, doc 20 matches all
+        let clauses: Vec<Box<dyn Scorer>> = (0..12)
+            .map(|i| -> Box<dyn Scorer> { Box::new(create_mock_scorer(vec![i, 20])) })
+            .collect();
+        let mut scorer = DisjunctionSumScorer::new(clauses, true, DEFAULT_MIN_SHOULD_MATCH);
+        assert_eq!(scorer.cost(), 24);
+
+        let result = collect(&mut scorer);
+        let docs: Vec<DocId> = result.iter().map(|r| r.0).collect();
+        assert_eq!(docs, (0..12).chain(vec![20]).collect::<Vec<_>>());
+        assert!((result[11].1 - 11.0).abs() < f32::EPSILON);
+        assert!((result[12].1 - 240.0).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn test_two_phase_clauses() {
+        // the two phase clause is only confirmed on docs 2 and 5
+        let two_phase = MockTwoPhaseScorer::new(vec![1, 2, 3, 5], vec![2, 5]);
+        let matches_calls = Arc::clone(&two_phase.matches_calls);
+        let clauses: Vec<Box<dyn Scorer>> = vec![
+            Box::new(create_mock_scorer(vec![1, 5])),
+            Box::new(two_phase),
+        ];
+        let mut scorer = DisjunctionSumScorer::new(clauses, false, DEFAULT_MIN_SHOULD_MATCH);
+        assert!(scorer.support_two_phase());
+        let mut docs = vec![];
+        {
+            let two_phase = scorer.as_two_phase().unwrap();
+            while two_phase.next_approximation().unwrap() != NO_MORE_DOCS {
+                if two_phase.confirm_match().unwrap() {
+                    docs.push(two_phase.approximation_doc_id());
+                }
+            }
+        }
+        assert_eq!(docs, vec![1, 2, 5]);
+        // docs 1 and 5 are matched by the one phase clause
+        assert_eq!(matches_calls.load(Ordering::SeqCst), 2);
+
+        // scoring confirms the two phase clause, once per doc
+        let two_phase = MockTwoPhaseScorer::new(vec![1, 2, 3, 5], vec![2, 5]);
+        let matches_calls = Arc::clone(&two_phase.matches_calls);
+        let clauses: Vec<Box<dyn Scorer>> = vec![
+            Box::new(create_mock_scorer(vec![1, 5])),
+            Box::new(two_phase),
+        ];
+        let mut scorer = DisjunctionSumScorer::new(clauses, true, DEFAULT_MIN_SHOULD_MATCH);
+        assert_eq!(collect(&mut scorer), vec![(1, 1.0), (2, 2.0), (5, 10.0)]);
+        assert_eq!(matches_calls.load(Ordering::SeqCst), 4);
+
+        let clauses = vec![create_mock_scorer(vec![1, 2])];
+        let mut scorer = DisjunctionMaxScorer::new(clauses, 0.0, true);
+        assert!(scorer.as_two_phase().is_none());
+    }
+
+    #[test]
+    fn test_two_phase_min_should_match() {
+        let two_phase = MockTwoPhaseScorer::new(vec![1, 3, 4, 5], vec![3, 5]);
+        let matches_calls = Arc::clone(&two_phase.matches_calls);
+        let clauses: Vec<Box<dyn Scorer>> = vec![
+            Box::new(create_mock_scorer(vec![1, 3, 5])),
+            Box::new(two_phase),
+            Box::new(create_mock_scorer(vec![6])),
+        ];
+        let mut scorer = DisjunctionSumScorer::new(clauses, true, 2);
+        // doc 1 is rejected by the two phase clause, docs 4 and 6 only have one
+        // approximate clause so they are never confirmed
+        assert_eq!(collect(&mut scorer), vec![(3, 6.0), (5, 10.0)]);
+        assert_eq!(matches_calls.load(Ordering::SeqCst), 3);
+
+        // enough clauses to use a `DisiPriorityQueue`
+        let mut clauses: Vec<Box<dyn Scorer>> = (0..11)
+            .map(|i| -> Box<dyn Scorer> { Box::new(create_mock_scorer(vec![i])) })
+            .collect();
+        clauses.push(Box::new(MockTwoPhaseScorer::new(vec![3, 20, 30], vec![20])));
+        let mut scorer = DisjunctionMaxScorer::new(clauses, 0.5, true);
+        assert_eq!(scorer.advance(3).unwrap(), 3);
+        assert!((scorer.score().unwrap() - 3.0).abs() < f32::EPSILON);
+        assert_eq!(scorer.advance(11).unwrap(), 20);
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+    }
+}
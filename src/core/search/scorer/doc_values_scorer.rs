@@ -0,0 +1,221 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::codec::doc_values::{BinaryDocValues, NumericDocValues};
+use core::search::scorer::{FeatureResult, ScoreMode, Scorer};
+use core::search::{DocIterator, Explanation, NO_MORE_DOCS};
+use core::util::{BitsMut, DocId, VariantValue};
+use error::Result;
+
+/// The doc values of a field read by a `DocValuesScorer`.
+pub enum DocValuesReader {
+    /// Read as `VariantValue::Long`.
+    Numeric(Box<dyn NumericDocValues>),
+    /// Read as `VariantValue::Binary`.
+    Binary(Box<dyn BinaryDocValues>),
+}
+
+/// A `Scorer` which forwards to `child` and exposes the doc value of a field
+/// at the current doc, eg. so that a collector can read the field values of
+/// the matched docs without looking them up again afterwards.
+///
+/// Docs not set in `docs_with_field` have no value, without it every doc has
+/// one, eg. 0 or the empty bytes for docs missing the field.
+pub struct DocValuesScorer<S: Scorer> {
+    child: S,
+    values: DocValuesReader,
+    docs_with_field: Option<Box<dyn BitsMut>>,
+}
+
+impl<S: Scorer> DocValuesScorer<S> {
+    pub fn new(
+        child: S,
+        values: DocValuesReader,
+        docs_with_field: Option<Box<dyn BitsMut>>,
+    ) -> Self {
+        DocValuesScorer {
+            child,
+            values,
+            docs_with_field,
+        }
+    }
+
+    /// Returns the value of the current doc, `None` if the doc has no value or
+    /// the scorer is not positioned on a doc.
+    pub fn current_value(&mut self) -> Result<Option<VariantValue>> {
+        let doc = self.child.doc_id();
+        if doc < 0 || doc == NO_MORE_DOCS {
+            return Ok(None);
+        }
+        if let Some(ref mut bits) = self.docs_with_field {
+            if !bits.get(doc as usize)? {
+                return Ok(None);
+            }
+        }
+        let value = match self.values {
+            DocValuesReader::Numeric(ref mut values) => VariantValue::Long(values.get_mut(doc)?),
+            DocValuesReader::Binary(ref mut values) => VariantValue::Binary(values.get(doc)?),
+        };
+        Ok(Some(value))
+    }
+
+    pub fn child(&self) -> &S {
+        &self.child
+    }
+}
+
+impl<S: Scorer> Scorer for DocValuesScorer<S> {
+    fn score(&mut self) -> Result<f32> {
+        self.child.score()
+    }
+
+    fn score_feature(&mut self) -> Result<Vec<FeatureResult>> {
+        self.child.score_feature()
+    }
+
+    fn freq(&self) -> Result<f32> {
+        self.child.freq()
+    }
+
+    fn set_score_mode(&mut self, mode: ScoreMode) {
+        self.child.set_score_mode(mode)
+    }
+
+    fn advance_shallow(&mut self, target: DocId) -> Result<DocId> {
+        self.child.advance_shallow(target)
+    }
+
+    fn get_max_score(&mut self, up_to: DocId) -> Result<f32> {
+        self.child.get_max_score(up_to)
+    }
+
+    fn set_min_competitive_score(&mut self, min_score: f32) {
+        self.child.set_min_competitive_score(min_score)
+    }
+
+    fn explain(&mut self, doc: DocId) -> Result<Explanation> {
+        self.child.explain(doc)
+    }
+}
+
+impl<S: Scorer> DocIterator for DocValuesScorer<S> {
+    fn doc_id(&self) -> DocId {
+        self.child.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        self.child.next()
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        self.child.advance(target)
+    }
+
+    fn cost(&self) -> usize {
+        self.child.cost()
+    }
+
+    fn matches(&mut self) -> Result<bool> {
+        self.child.matches()
+    }
+
+    fn match_cost(&self) -> f32 {
+        self.child.match_cost()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::tests::*;
+
+    struct MockNumeric;
+
+    impl NumericDocValues for MockNumeric {
+        fn get(&self, doc_id: DocId) -> Result<i64> {
+            Ok(i64::from(doc_id) * 100)
+        }
+    }
+
+    struct MockBinary;
+
+    impl BinaryDocValues for MockBinary {
+        fn get(&mut self, doc_id: DocId) -> Result<Vec<u8>> {
+            Ok(format!("doc{}", doc_id).into_bytes())
+        }
+    }
+
+    /// only the even docs have the field
+    struct MockEvenBits;
+
+    impl BitsMut for MockEvenBits {
+        fn get(&mut self, index: usize) -> Result<bool> {
+            Ok(index % 2 == 0)
+        }
+
+        fn len(&self) -> usize {
+            16
+        }
+    }
+
+    fn collect_values<S: Scorer>(scorer: &mut DocValuesScorer<S>) -> Vec<(DocId, Option<i64>)> {
+        let mut result = vec![];
+        while scorer.next().unwrap() != NO_MORE_DOCS {
+            let value = scorer
+                .current_value()
+                .unwrap()
+                .map(|v| v.get_long().unwrap());
+            result.push((scorer.doc_id(), value));
+        }
+        result
+    }
+
+    #[test]
+    fn test_value_of_matched_docs() {
+        let child = create_mock_scorer(vec![1, 2, 5, 8]);
+        let mut scorer =
+            DocValuesScorer::new(child, DocValuesReader::Numeric(Box::new(MockNumeric)), None);
+        assert!(scorer.current_value().unwrap().is_none());
+        assert_eq!(
+            collect_values(&mut scorer),
+            vec![
+                (1, Some(100)),
+                (2, Some(200)),
+                (5, Some(500)),
+                (8, Some(800))
+            ]
+        );
+        assert!(scorer.current_value().unwrap().is_none());
+
+        let child = create_mock_scorer(vec![1, 2, 5, 8]);
+        let mut scorer = DocValuesScorer::new(
+            child,
+            DocValuesReader::Numeric(Box::new(MockNumeric)),
+            Some(Box::new(MockEvenBits)),
+        );
+        assert_eq!(
+            collect_values(&mut scorer),
+            vec![(1, None), (2, Some(200)), (5, None), (8, Some(800))]
+        );
+
+        let child = create_mock_scorer(vec![3, 7]);
+        let mut scorer =
+            DocValuesScorer::new(child, DocValuesReader::Binary(Box::new(MockBinary)), None);
+        assert_eq!(scorer.advance(4).unwrap(), 7);
+        assert!((scorer.score().unwrap() - 7.0).abs() < ::std::f32::EPSILON);
+        match scorer.current_value().unwrap() {
+            Some(VariantValue::Binary(bytes)) => assert_eq!(bytes, b"doc7".to_vec()),
+            _ => panic!("expected a binary value"),
+        }
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::search::scorer::Scorer;
+use core::search::DocIterator;
+use core::util::DocId;
+use error::Result;
+
+/// A `Scorer` which matches the docs of `iterator` and computes the score of
+/// each doc with `func`, instead of by term statistics.
+///
+/// `func` is typically used to score by a function of some numeric doc values
+/// of the doc, any error it returns is propagated by `score()`.
+pub struct FunctionScorer<T: DocIterator, F: FnMut(DocId) -> Result<f32> + Send> {
+    iterator: T,
+    func: F,
+}
+
+impl<T, F> FunctionScorer<T, F>
+where
+    T: DocIterator,
+    F: FnMut(DocId) -> Result<f32> + Send,
+{
+    pub fn new(iterator: T, func: F) -> Self {
+        FunctionScorer { iterator, func }
+    }
+}
+
+impl<T, F> Scorer for FunctionScorer<T, F>
+where
+    T: DocIterator,
+    F: FnMut(DocId) -> Result<f32> + Send,
+{
+    fn score(&mut self) -> Result<f32> {
+        let doc = self.iterator.doc_id();
+        (self.func)(doc)
+    }
+}
+
+impl<T, F> DocIterator for FunctionScorer<T, F>
+where
+    T: DocIterator,
+    F: FnMut(DocId) -> Result<f32> + Send,
+{
+    fn doc_id(&self) -> DocId {
+        self.iterator.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        self.iterator.next()
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        self.iterator.advance(target)
+    }
+
+    fn cost(&self) -> usize {
+        self.iterator.cost()
+    }
+
+    fn matches(&mut self) -> Result<bool> {
+        self.iterator.matches()
+    }
+
+    fn match_cost(&self) -> f32 {
+        self.iterator.match_cost()
+    }
+
+    fn support_two_phase(&self) -> bool {
+        self.iterator.support_two_phase()
+    }
+
+    fn approximate_next(&mut self) -> Result<DocId> {
+        self.iterator.approximate_next()
+    }
+
+    fn approximate_advance(&mut self, target: DocId) -> Result<DocId> {
+        self.iterator.approximate_advance(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::codec::doc_values::NumericDocValues;
+    use core::search::tests::*;
+    use core::search::NO_MORE_DOCS;
+    use error::ErrorKind::IllegalState;
+
+    /// numeric doc values of a single field, indexed by doc id
+    struct FieldValues(Vec<i64>);
+
+    impl NumericDocValues for FieldValues {
+        fn get(&self, doc_id: DocId) -> Result<i64> {
+            Ok(self.0[doc_id as usize])
+        }
+    }
+
+    #[test]
+    fn test_score_by_doc_values() {
+        let values = FieldValues(vec![10, 20, 30, 40, 50, 60]);
+        let iterator = create_mock_doc_iterator(vec![1, 3, 4]);
+        let mut scorer = FunctionScorer::new(iterator, |doc| Ok(values.get(doc)? as f32));
+
+        let mut hits = vec![];
+        loop {
+            let doc = scorer.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            hits.push((doc, scorer.score().unwrap()));
+        }
+        assert_eq!(hits, vec![(1, 20.0), (3, 40.0), (4, 50.0)]);
+    }
+
+    #[test]
+    fn test_score_error() {
+        let iterator = create_mock_doc_iterator(vec![1]);
+        let mut scorer = FunctionScorer::new(iterator, |doc| {
+            bail!(IllegalState(format!("no value for doc {}", doc)))
+        });
+        assert_eq!(scorer.next().unwrap(), 1);
+        assert!(scorer.score().is_err());
+    }
+}
@@ -0,0 +1,545 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::codec::{PostingIterator, PostingIteratorFlags, SeekStatus, TermIterator};
+use core::search::scorer::{ConstantScoreScorer, MultiTermScorer, RewriteMethod, Scorer};
+use core::search::DocIterator;
+use core::util::DocId;
+use error::{ErrorKind::IllegalArgument, Result};
+
+use std::cmp::{min, Ordering};
+use std::collections::HashMap;
+
+/// Max edit distance supported by `FuzzyScorer`, same as Lucene's.
+pub const MAX_SUPPORTED_EDITS: usize = 2;
+
+/// A `Scorer` which matches the docs of all the terms within `max_edits`
+/// Levenshtein distance of a query term.
+///
+/// The first `prefix_length` chars of the query term must match exactly, only
+/// the terms sharing that prefix are enumerated and run through a Levenshtein
+/// automaton of the rest of the query term, so a non zero prefix makes the
+/// expansion much cheaper. Once the leading chars of a term are too far from
+/// the query, the terms are sought to the next prefix the automaton accepts.
+/// If `transpositions` is true, swapping two adjacent chars counts as a
+/// single edit, eg. the optimal string alignment distance.
+///
+/// The best `max_expansions` terms are kept, each term is weighted by
+/// `1 - edits / min(len(query), len(term))` in chars, and a doc is scored by
+/// `boost` times the max weight of the terms it contains, see
+/// `RewriteMethod::ScoreMax`.
+pub struct FuzzyScorer<P: PostingIterator> {
+    scorer: MultiTermScorer<ConstantScoreScorer<P>>,
+    expanded_terms: Vec<(Vec<u8>, f32)>,
+}
+
+impl<P: PostingIterator> FuzzyScorer<P> {
+    /// Returns `None` if no term is within `max_edits` of `term`.
+    pub fn new<T: TermIterator<Postings = P>>(
+        terms: &mut T,
+        term: &str,
+        max_edits: usize,
+        prefix_length: usize,
+        transpositions: bool,
+        max_expansions: usize,
+        boost: f32,
+    ) -> Result<Option<FuzzyScorer<P>>> {
+        if max_edits > MAX_SUPPORTED_EDITS {
+            bail!(IllegalArgument(format!(
+                "max_edits must be between 0 and {}, got {}",
+                MAX_SUPPORTED_EDITS, max_edits
+            )));
+        }
+
+        let query_len = term.chars().count();
+        let prefix_length = min(prefix_length, query_len);
+        let prefix: String = term.chars().take(prefix_length).collect();
+        let prefix = prefix.as_bytes();
+        let mut automaton =
+            LevenshteinAutomaton::new(&term[prefix.len()..], max_edits, transpositions);
+
+        // (term, weight) of all the terms close enough
+        let mut candidates = vec![];
+        let mut status = terms.seek_ceil(prefix)?;
+        while status != SeekStatus::End {
+            let bytes = terms.term()?;
+            if !bytes.starts_with(prefix) {
+                break;
+            }
+            let mut skip_to = None;
+            // terms that are not valid UTF-8 never match
+            if let Ok(text) = ::std::str::from_utf8(&bytes[prefix.len()..]) {
+                match automaton.distance(text) {
+                    Distance::Within(edits) => {
+                        let len = min(query_len, prefix_length + text.chars().count()).max(1);
+                        let weight = 1.0 - edits as f32 / len as f32;
+                        candidates.push((bytes.to_vec(), weight));
+                    }
+                    Distance::PrefixTooFar(Some(next)) => {
+                        let mut target = prefix.to_vec();
+                        target.extend_from_slice(next.as_bytes());
+                        skip_to = Some(target);
+                    }
+                    Distance::PrefixTooFar(None) => break,
+                    Distance::TooFar => {}
+                }
+            }
+            status = match skip_to {
+                Some(target) => terms.seek_ceil(&target)?,
+                None if terms.next()?.is_some() => SeekStatus::Found,
+                None => SeekStatus::End,
+            };
+        }
+
+        // keep the best terms, ties are broken by term order
+        candidates.sort_by(|a, b| {
+            b.1.partial_cmp(&a.1)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| a.0.cmp(&b.0))
+        });
+        candidates.truncate(max_expansions);
+        if candidates.is_empty() {
+            return Ok(None);
+        }
+
+        let mut children = Vec::with_capacity(candidates.len());
+        for (bytes, weight) in &candidates {
+            if terms.seek_exact(bytes)? {
+                let postings = terms.postings_with_flags(PostingIteratorFlags::NONE)?;
+                let cost = postings.cost();
+                children.push(Ok(ConstantScoreScorer::new(*weight, postings, cost)));
+            }
+        }
+        // `max_doc` is only needed by `RewriteMethod::ConstantScore`
+        let scorer =
+            MultiTermScorer::new(children, RewriteMethod::ScoreMax, max_expansions, 0, boost)?;
+        Ok(scorer.map(|scorer| FuzzyScorer {
+            scorer,
+            expanded_terms: candidates,
+        }))
+    }
+
+    /// Returns the matched terms with their weights, best first.
+    pub fn expanded_terms(&self) -> &[(Vec<u8>, f32)] {
+        &self.expanded_terms
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Distance {
+    /// the number of edits from the query to the term
+    Within(usize),
+    /// the first chars of the term are too far from the query, so are all
+    /// the terms up to the given one, or all the following terms if `None`
+    PrefixTooFar(Option<String>),
+    /// the term is too far from the query, but longer terms may not be
+    TooFar,
+}
+
+/// A position of the Levenshtein NFA, eg. the number of query chars consumed
+/// and the edits spent so far. `transposed` means the next query char was
+/// just read and the current one is expected, to complete a transposition.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Position {
+    offset: usize,
+    transposed: bool,
+    edits: usize,
+}
+
+impl Position {
+    fn new(offset: usize, edits: usize, transposed: bool) -> Position {
+        Position {
+            offset,
+            transposed,
+            edits,
+        }
+    }
+}
+
+const DEAD_STATE: usize = 0;
+const START_STATE: usize = 1;
+
+/// A Levenshtein automaton accepting the strings within `max_edits` of a
+/// query, determinized lazily while the terms are matched against it.
+///
+/// The DFA states are sets of NFA positions. A transition only depends on
+/// which query char the next char equals, so each state has one transition
+/// per distinct query char plus one for all the other chars. The states
+/// reached by the last matched term are kept, a term only runs the chars
+/// after the prefix it shares with the previous one.
+struct LevenshteinAutomaton {
+    query: Vec<char>,
+    max_edits: usize,
+    transpositions: bool,
+    /// the distinct chars of the query, sorted
+    alphabet: Vec<char>,
+    states: Vec<Vec<Position>>,
+    state_ids: HashMap<Vec<Position>, usize>,
+    /// `alphabet.len() + 1` transitions per state, the last one is taken by
+    /// the chars not in the query
+    transitions: Vec<Option<usize>>,
+    /// the chars of the last term and the states before each of them
+    chars: Vec<char>,
+    path: Vec<usize>,
+}
+
+impl LevenshteinAutomaton {
+    fn new(query: &str, max_edits: usize, transpositions: bool) -> LevenshteinAutomaton {
+        let query: Vec<char> = query.chars().collect();
+        let mut alphabet = query.clone();
+        alphabet.sort();
+        alphabet.dedup();
+        let mut automaton = LevenshteinAutomaton {
+            query,
+            max_edits,
+            transpositions,
+            alphabet,
+            states: vec![],
+            state_ids: HashMap::new(),
+            transitions: vec![],
+            chars: vec![],
+            path: vec![],
+        };
+        automaton.add_state(vec![]);
+        let start = automaton.closure(vec![Position::new(0, 0, false)]);
+        automaton.add_state(start);
+        automaton
+    }
+
+    fn add_state(&mut self, positions: Vec<Position>) -> usize {
+        if let Some(&id) = self.state_ids.get(&positions) {
+            return id;
+        }
+        let id = self.states.len();
+        self.state_ids.insert(positions.clone(), id);
+        self.states.push(positions);
+        let transitions = self.alphabet.len() + 1;
+        self.transitions.extend((0..transitions).map(|_| None));
+        id
+    }
+
+    /// Adds the positions reached by deleting query chars, and only keeps the
+    /// fewest edits of each position.
+    fn closure(&self, mut positions: Vec<Position>) -> Vec<Position> {
+        let len = self.query.len();
+        for i in 0..positions.len() {
+            let p = positions[i];
+            if !p.transposed {
+                for d in 1..=min(self.max_edits - p.edits, len - p.offset) {
+                    positions.push(Position::new(p.offset + d, p.edits + d, false));
+                }
+            }
+        }
+        positions.sort();
+        positions.dedup_by_key(|p| (p.offset, p.transposed));
+        positions
+    }
+
+    fn step(&mut self, state: usize, c: char) -> usize {
+        let class = self
+            .alphabet
+            .binary_search(&c)
+            .unwrap_or(self.alphabet.len());
+        let idx = state * (self.alphabet.len() + 1) + class;
+        if let Some(next) = self.transitions[idx] {
+            return next;
+        }
+
+        let query = &self.query;
+        let mut positions = vec![];
+        for p in &self.states[state] {
+            let (i, e) = (p.offset, p.edits);
+            if p.transposed {
+                if query[i] == c {
+                    positions.push(Position::new(i + 2, e, false));
+                }
+                continue;
+            }
+            if i < query.len() && query[i] == c {
+                positions.push(Position::new(i + 1, e, false));
+            }
+            if e < self.max_edits {
+                // insertion and substitution
+                positions.push(Position::new(i, e + 1, false));
+                if i < query.len() {
+                    positions.push(Position::new(i + 1, e + 1, false));
+                }
+                if self.transpositions && i + 1 < query.len() && query[i + 1] == c && query[i] != c
+                {
+                    positions.push(Position::new(i, e + 1, true));
+                }
+            }
+        }
+        let positions = self.closure(positions);
+        let next = self.add_state(positions);
+        self.transitions[idx] = Some(next);
+        next
+    }
+
+    /// Returns the smallest char greater than `c` leading `state` to a live
+    /// state.
+    fn next_live_char(&mut self, state: usize, c: char) -> Option<char> {
+        // the smallest char after `c` not in the query
+        let mut other = next_char(c);
+        while let Some(o) = other {
+            if self.alphabet.binary_search(&o).is_err() {
+                break;
+            }
+            other = next_char(o);
+        }
+        let mut live = other.filter(|&o| self.step(state, o) != DEAD_STATE);
+        for i in 0..self.alphabet.len() {
+            let q = self.alphabet[i];
+            if q > c && live.map_or(true, |o| q < o) && self.step(state, q) != DEAD_STATE {
+                live = Some(q);
+                break;
+            }
+        }
+        live
+    }
+
+    fn distance(&mut self, term: &str) -> Distance {
+        let shared = term
+            .chars()
+            .zip(&self.chars)
+            .take_while(|&(a, &b)| a == b)
+            .count();
+        self.chars.truncate(shared);
+        self.path.truncate(shared);
+        let mut state = match self.path.last() {
+            Some(&last) => {
+                let c = self.chars[shared - 1];
+                self.step(last, c)
+            }
+            None => START_STATE,
+        };
+
+        for c in term.chars().skip(shared) {
+            let next = self.step(state, c);
+            self.chars.push(c);
+            self.path.push(state);
+            if next == DEAD_STATE {
+                let target = self.next_live_prefix();
+                self.chars.pop();
+                self.path.pop();
+                return Distance::PrefixTooFar(target);
+            }
+            state = next;
+        }
+
+        let len = self.query.len();
+        let edits = self.states[state]
+            .iter()
+            .filter(|p| p.offset == len && !p.transposed)
+            .map(|p| p.edits)
+            .min();
+        match edits {
+            Some(edits) => Distance::Within(edits),
+            None => Distance::TooFar,
+        }
+    }
+
+    /// Returns the smallest string greater than the chars of the last term
+    /// which is the prefix of a match, the last char leads to the dead state.
+    fn next_live_prefix(&mut self) -> Option<String> {
+        for i in (0..self.chars.len()).rev() {
+            if let Some(c) = self.next_live_char(self.path[i], self.chars[i]) {
+                let mut prefix: String = self.chars[..i].iter().collect();
+                prefix.push(c);
+                return Some(prefix);
+            }
+        }
+        None
+    }
+}
+
+fn next_char(c: char) -> Option<char> {
+    (c as u32 + 1..=::std::char::MAX as u32)
+        .filter_map(::std::char::from_u32)
+        .next()
+}
+
+impl<P: PostingIterator> Scorer for FuzzyScorer<P> {
+    fn score(&mut self) -> Result<f32> {
+        self.scorer.score()
+    }
+}
+
+impl<P: PostingIterator> DocIterator for FuzzyScorer<P> {
+    fn doc_id(&self) -> DocId {
+        self.scorer.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        self.scorer.next()
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        self.scorer.advance(target)
+    }
+
+    fn cost(&self) -> usize {
+        self.scorer.cost()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::tests::*;
+    use core::search::NO_MORE_DOCS;
+
+    fn create_terms() -> MockTermIterator {
+        MockTermIterator::new(vec![
+            ("collar", vec![(0, vec![0])]),
+            ("colour", vec![(1, vec![0]), (3, vec![0])]),
+            ("colr", vec![(2, vec![0]), (3, vec![1])]),
+            ("colro", vec![(4, vec![0])]),
+            ("dolor", vec![(5, vec![0])]),
+        ])
+    }
+
+    fn create_scorer(
+        term: &str,
+        max_edits: usize,
+        prefix_length: usize,
+        transpositions: bool,
+    ) -> Option<FuzzyScorer<MockPostingIterator>> {
+        let mut terms = create_terms();
+        FuzzyScorer::new(
+            &mut terms,
+            term,
+            max_edits,
+            prefix_length,
+            transpositions,
+            50,
+            1.0,
+        )
+        .unwrap()
+    }
+
+    fn expanded(scorer: &FuzzyScorer<MockPostingIterator>) -> Vec<&str> {
+        scorer
+            .expanded_terms()
+            .iter()
+            .map(|t| ::std::str::from_utf8(&t.0).unwrap())
+            .collect()
+    }
+
+    fn collect(scorer: &mut FuzzyScorer<MockPostingIterator>) -> Vec<(DocId, f32)> {
+        let mut result = vec![];
+        loop {
+            let doc = scorer.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            result.push((doc, scorer.score().unwrap()));
+        }
+        result
+    }
+
+    #[test]
+    fn test_levenshtein_automaton() {
+        let dist = |a, b, t| LevenshteinAutomaton::new(a, 2, t).distance(b);
+        assert_eq!(dist("color", "color", false), Distance::Within(0));
+        assert_eq!(dist("color", "colour", false), Distance::Within(1));
+        assert_eq!(dist("color", "colr", false), Distance::Within(1));
+        assert_eq!(dist("color", "collar", false), Distance::Within(2));
+        assert_eq!(dist("color", "colro", false), Distance::Within(2));
+        assert_eq!(dist("color", "colro", true), Distance::Within(1));
+        assert_eq!(dist("color", "cl", false), Distance::TooFar);
+        assert_eq!(dist("", "ab", false), Distance::Within(2));
+        assert_eq!(dist("héllo", "hello", false), Distance::Within(1));
+        assert_eq!(dist("ab", "ba", true), Distance::Within(1));
+        // "abxyz" is 3 edits away from "ab", the next live prefix is "abxz"
+        let next = |s: &str| Distance::PrefixTooFar(Some(s.to_string()));
+        assert_eq!(dist("ab", "abxyz", false), next("abxz"));
+        assert_eq!(dist("é", "xyzé", false), next("xyé"));
+        let mut exact = LevenshteinAutomaton::new("b", 0, false);
+        assert_eq!(exact.distance("c"), Distance::PrefixTooFar(None));
+
+        // the states of the shared prefix are reused from term to term
+        let mut automaton = LevenshteinAutomaton::new("color", 1, true);
+        assert_eq!(automaton.distance("xyz"), next("y"));
+        assert_eq!(automaton.distance("colro"), Distance::Within(1));
+        assert_eq!(automaton.distance("color"), Distance::Within(0));
+        assert_eq!(automaton.distance("colr"), Distance::Within(1));
+        assert_eq!(automaton.distance("colx"), Distance::TooFar);
+        assert_eq!(automaton.distance("colxy"), next("coly"));
+    }
+
+    #[test]
+    fn test_skip_too_far_prefixes() {
+        let mut entries: Vec<(String, Vec<(DocId, Vec<i32>)>)> = (0..1000)
+            .map(|i| (format!("cz{:03}", i), vec![(i, vec![0])]))
+            .collect();
+        entries.push(("color".to_string(), vec![(1000, vec![0])]));
+        entries.push(("czlor".to_string(), vec![(1001, vec![0])]));
+        entries.push(("dolor".to_string(), vec![(1002, vec![0])]));
+        let mut terms = MockTermIterator::new(
+            entries
+                .iter()
+                .map(|(t, p)| (t.as_str(), p.clone()))
+                .collect(),
+        );
+
+        let mut scorer = FuzzyScorer::new(&mut terms, "color", 1, 0, false, 50, 1.0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(expanded(&scorer), vec!["color", "czlor", "dolor"]);
+        assert_eq!(
+            collect(&mut scorer).iter().map(|r| r.0).collect::<Vec<_>>(),
+            vec![1000, 1001, 1002]
+        );
+        // the terms starting with "cz0" are skipped by a single seek to "czl"
+        assert!(terms.next_calls < 10, "{}", terms.next_calls);
+    }
+
+    #[test]
+    fn test_fuzzy() {
+        let mut scorer = create_scorer("color", 1, 0, true).unwrap();
+        // best weights first, eg. 1 - 1/5 before 1 - 1/4
+        assert_eq!(expanded(&scorer), vec!["colour", "colro", "dolor", "colr"]);
+
+        let mut scorer_without_transpositions = create_scorer("color", 1, 0, false).unwrap();
+        assert_eq!(
+            expanded(&scorer_without_transpositions),
+            vec!["colour", "dolor", "colr"]
+        );
+
+        // "colour" weights 1 - 1/5 and "colr" 1 - 1/4, doc 3 has both
+        let result = collect(&mut scorer_without_transpositions);
+        let docs: Vec<DocId> = result.iter().map(|r| r.0).collect();
+        assert_eq!(docs, vec![1, 2, 3, 5]);
+        assert!((result[0].1 - 0.8).abs() < 1e-6);
+        assert!((result[1].1 - 0.75).abs() < 1e-6);
+        assert!((result[2].1 - 0.8).abs() < 1e-6);
+
+        assert_eq!(collect(&mut scorer).len(), 5);
+    }
+
+    #[test]
+    fn test_prefix_length() {
+        // "dolor" differs in the first char
+        let scorer = create_scorer("color", 1, 1, false).unwrap();
+        assert_eq!(expanded(&scorer), vec!["colour", "colr"]);
+
+        // "collar" is 2 edits away
+        let scorer = create_scorer("color", 2, 3, false).unwrap();
+        assert_eq!(expanded(&scorer), vec!["colour", "colr", "collar", "colro"]);
+
+        assert!(create_scorer("xyz", 1, 0, true).is_none());
+        let mut terms = create_terms();
+        assert!(FuzzyScorer::new(&mut terms, "color", 3, 0, true, 50, 1.0).is_err());
+    }
+}
@@ -0,0 +1,286 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::codec::doc_values::NumericDocValues;
+use core::search::scorer::Scorer;
+use core::search::{DocIterator, NO_MORE_DOCS};
+use core::util::{BitsMut, DocId};
+use error::{ErrorKind::IllegalArgument, Result};
+
+/// Mean radius of the earth in meters, as used by Lucene's `GeoUtils`.
+pub const EARTH_MEAN_RADIUS_METERS: f64 = 6_371_008.771_4;
+
+/// Returns the great circle distance in meters between two points given in
+/// degrees, computed by the haversine formula.
+pub fn haversine_meters(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let d_lat = (lat2 - lat1).to_radians();
+    let d_lon = (lon2 - lon1).to_radians();
+    let h = (d_lat / 2.0).sin().powi(2)
+        + lat1.to_radians().cos() * lat2.to_radians().cos() * (d_lon / 2.0).sin().powi(2);
+    // rounding may push `h` slightly above 1 for antipodal points
+    2.0 * EARTH_MEAN_RADIUS_METERS * h.sqrt().min(1.0).asin()
+}
+
+/// A `Scorer` which matches the docs whose geo point is within
+/// `radius_meters` of a center point, and scores them by
+/// `1 / (1 + distance)`, eg. closer docs score higher.
+///
+/// The latitude and longitude of each doc are read from two `Double` doc
+/// values fields, eg. `f64` bits, docs not set in `docs_with_field` have no
+/// point and never match.
+pub struct GeoDistanceScorer {
+    lat: Box<dyn NumericDocValues>,
+    lon: Box<dyn NumericDocValues>,
+    docs_with_field: Box<dyn BitsMut>,
+    max_doc: DocId,
+    center_lat: f64,
+    center_lon: f64,
+    radius_meters: f64,
+    doc: DocId,
+    // distance of the current doc to the center
+    distance: f64,
+}
+
+impl GeoDistanceScorer {
+    pub fn new(
+        lat: Box<dyn NumericDocValues>,
+        lon: Box<dyn NumericDocValues>,
+        docs_with_field: Box<dyn BitsMut>,
+        max_doc: DocId,
+        center_lat: f64,
+        center_lon: f64,
+        radius_meters: f64,
+    ) -> Result<GeoDistanceScorer> {
+        if !(-90.0..=90.0).contains(&center_lat) {
+            bail!(IllegalArgument(format!(
+                "invalid latitude {}, must be in [-90, 90]",
+                center_lat
+            )));
+        }
+        if !(-180.0..=180.0).contains(&center_lon) {
+            bail!(IllegalArgument(format!(
+                "invalid longitude {}, must be in [-180, 180]",
+                center_lon
+            )));
+        }
+        if !(radius_meters >= 0.0 && radius_meters.is_finite()) {
+            bail!(IllegalArgument(format!(
+                "invalid radius {}, must be finite and non negative",
+                radius_meters
+            )));
+        }
+        Ok(GeoDistanceScorer {
+            lat,
+            lon,
+            docs_with_field,
+            max_doc,
+            center_lat,
+            center_lon,
+            radius_meters,
+            doc: -1,
+            distance: 0.0,
+        })
+    }
+
+    /// Returns the distance in meters of the current doc to the center.
+    pub fn distance(&self) -> f64 {
+        self.distance
+    }
+
+    fn doc_distance(&mut self, doc: DocId) -> Result<Option<f64>> {
+        if !self.docs_with_field.get(doc as usize)? {
+            return Ok(None);
+        }
+        let lat = f64::from_bits(self.lat.get_mut(doc)? as u64);
+        let lon = f64::from_bits(self.lon.get_mut(doc)? as u64);
+        Ok(Some(haversine_meters(
+            self.center_lat,
+            self.center_lon,
+            lat,
+            lon,
+        )))
+    }
+}
+
+impl Scorer for GeoDistanceScorer {
+    fn score(&mut self) -> Result<f32> {
+        Ok((1.0 / (1.0 + self.distance)) as f32)
+    }
+
+    fn get_max_score(&mut self, _up_to: DocId) -> Result<f32> {
+        Ok(1.0)
+    }
+}
+
+impl DocIterator for GeoDistanceScorer {
+    fn doc_id(&self) -> DocId {
+        self.doc
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        if self.doc == NO_MORE_DOCS {
+            return Ok(NO_MORE_DOCS);
+        }
+        let target = self.doc + 1;
+        self.advance(target)
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        for doc in target..self.max_doc {
+            if let Some(distance) = self.doc_distance(doc)? {
+                if distance <= self.radius_meters {
+                    self.doc = doc;
+                    self.distance = distance;
+                    return Ok(doc);
+                }
+            }
+        }
+        self.doc = NO_MORE_DOCS;
+        Ok(NO_MORE_DOCS)
+    }
+
+    fn cost(&self) -> usize {
+        self.max_doc as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MockDoubleValues(Vec<f64>);
+
+    impl NumericDocValues for MockDoubleValues {
+        fn get(&self, doc_id: DocId) -> Result<i64> {
+            Ok(self.0[doc_id as usize].to_bits() as i64)
+        }
+    }
+
+    struct MockDocsWithField(Vec<bool>);
+
+    impl BitsMut for MockDocsWithField {
+        fn get(&mut self, index: usize) -> Result<bool> {
+            Ok(self.0[index])
+        }
+
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    /// `None` points are docs without a value.
+    fn create_scorer(
+        points: &[Option<(f64, f64)>],
+        center_lat: f64,
+        center_lon: f64,
+        radius_meters: f64,
+    ) -> Result<GeoDistanceScorer> {
+        let values = |f: fn(&(f64, f64)) -> f64| {
+            Box::new(MockDoubleValues(
+                points.iter().map(|p| p.as_ref().map_or(0.0, f)).collect(),
+            ))
+        };
+        GeoDistanceScorer::new(
+            values(|p| p.0),
+            values(|p| p.1),
+            Box::new(MockDocsWithField(
+                points.iter().map(Option::is_some).collect(),
+            )),
+            points.len() as DocId,
+            center_lat,
+            center_lon,
+            radius_meters,
+        )
+    }
+
+    fn collect(scorer: &mut GeoDistanceScorer) -> Vec<(DocId, f32)> {
+        let mut result = vec![];
+        loop {
+            let doc = scorer.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            result.push((doc, scorer.score().unwrap()));
+        }
+        result
+    }
+
+    #[test]
+    fn test_haversine() {
+        assert!(haversine_meters(10.0, 20.0, 10.0, 20.0).abs() < 1e-9);
+        // one degree of latitude is about 111.2km
+        let d = haversine_meters(0.0, 0.0, 1.0, 0.0);
+        assert!((d - 111_195.08).abs() < 1.0);
+        // antipodal points are half the circumference apart
+        let d = haversine_meters(0.0, 0.0, 0.0, 180.0);
+        assert!((d - ::std::f64::consts::PI * EARTH_MEAN_RADIUS_METERS).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_near_poles() {
+        // close to the north pole longitudes are only a few meters apart
+        let points = vec![
+            Some((89.9999, 0.0)),
+            Some((89.9999, 180.0)),
+            Some((89.99, 90.0)),
+            None,
+            Some((-89.9999, 0.0)),
+        ];
+        let mut scorer = create_scorer(&points, 90.0, 0.0, 100.0).unwrap();
+        let result = collect(&mut scorer);
+        let docs: Vec<DocId> = result.iter().map(|r| r.0).collect();
+        assert_eq!(docs, vec![0, 1]);
+        // 0.0001 degree is about 11 meters
+        let expected = 1.0 / (1.0 + haversine_meters(90.0, 0.0, 89.9999, 0.0));
+        assert!((f64::from(result[0].1) - expected).abs() < 1e-6);
+
+        // the two points are across the pole from each other
+        let mut scorer = create_scorer(&points, 89.9999, 0.0, 30.0).unwrap();
+        let result = collect(&mut scorer);
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[1].0, 1);
+        assert!((result[0].1 - 1.0).abs() < ::std::f32::EPSILON);
+    }
+
+    #[test]
+    fn test_across_antimeridian() {
+        let points = vec![
+            Some((0.0, 179.999)),
+            Some((0.0, -179.999)),
+            Some((0.0, 179.9)),
+            Some((0.0, 0.0)),
+        ];
+        // 0.001 degree of longitude on the equator is about 111 meters
+        let mut scorer = create_scorer(&points, 0.0, 180.0, 500.0).unwrap();
+        let result = collect(&mut scorer);
+        let docs: Vec<DocId> = result.iter().map(|r| r.0).collect();
+        assert_eq!(docs, vec![0, 1]);
+        assert!((result[0].1 - result[1].1).abs() < 1e-6);
+
+        let mut scorer = create_scorer(&points, 0.0, -179.95, 20_000.0).unwrap();
+        assert_eq!(scorer.advance(1).unwrap(), 1);
+        assert!(scorer.distance() < 10_000.0);
+        assert_eq!(scorer.next().unwrap(), 2);
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+    }
+
+    #[test]
+    fn test_invalid_params() {
+        let points = vec![Some((0.0, 0.0))];
+        assert!(create_scorer(&points, 90.5, 0.0, 1.0).is_err());
+        assert!(create_scorer(&points, 0.0, -180.5, 1.0).is_err());
+        assert!(create_scorer(&points, 0.0, 0.0, -1.0).is_err());
+        assert!(create_scorer(&points, 0.0, 0.0, ::std::f64::NAN).is_err());
+    }
+}
@@ -0,0 +1,648 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::codec::PostingIterator;
+use core::search::scorer::Scorer;
+use core::search::similarity::SimScorer;
+use core::search::{DocIterator, NO_MORE_DOCS};
+use core::util::DocId;
+use error::Result;
+
+use std::i32;
+
+/// Returned by `IntervalIterator::next_interval` once the intervals of the
+/// current doc are exhausted.
+pub const NO_MORE_INTERVALS: i32 = i32::max_value();
+
+/// A `DocIterator` which can also iterate over the position intervals of the
+/// current doc, this is the base of Lucene style interval queries.
+///
+/// Intervals are returned in order of their start position, `start` and `end`
+/// are both inclusive. They are `-1` until `next_interval` is called on a doc,
+/// and `NO_MORE_INTERVALS` once the intervals of the doc are exhausted.
+pub trait IntervalIterator: DocIterator {
+    /// Returns the start position of the current interval.
+    fn start(&self) -> i32;
+
+    /// Returns the (inclusive) end position of the current interval.
+    fn end(&self) -> i32;
+
+    /// Returns the number of positions covered by the current interval.
+    fn width(&self) -> i32 {
+        self.end() - self.start() + 1
+    }
+
+    /// Advances to the next interval of the current doc and returns its start
+    /// position, or `NO_MORE_INTERVALS`.
+    fn next_interval(&mut self) -> Result<i32>;
+}
+
+impl IntervalIterator for Box<dyn IntervalIterator> {
+    fn start(&self) -> i32 {
+        (**self).start()
+    }
+
+    fn end(&self) -> i32 {
+        (**self).end()
+    }
+
+    fn width(&self) -> i32 {
+        (**self).width()
+    }
+
+    fn next_interval(&mut self) -> Result<i32> {
+        (**self).next_interval()
+    }
+}
+
+impl DocIterator for Box<dyn IntervalIterator> {
+    fn doc_id(&self) -> DocId {
+        (**self).doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        (**self).next()
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        (**self).advance(target)
+    }
+
+    fn cost(&self) -> usize {
+        (**self).cost()
+    }
+}
+
+/// Factory methods to build and combine `IntervalIterator`s.
+pub struct Intervals;
+
+impl Intervals {
+    /// Intervals of a single term, eg. one interval of width 1 per position.
+    pub fn term<P: PostingIterator + 'static>(postings: P) -> Box<dyn IntervalIterator> {
+        Box::new(TermIntervalIterator::new(postings))
+    }
+
+    /// Minimal intervals containing all the `subs` intervals in order, without
+    /// overlaps between them.
+    pub fn ordered(subs: Vec<Box<dyn IntervalIterator>>) -> Box<dyn IntervalIterator> {
+        debug_assert!(!subs.is_empty());
+        Box::new(ConjunctionIntervalIterator::new(
+            subs,
+            IntervalFunction::Ordered,
+        ))
+    }
+
+    /// Minimal intervals containing all the `subs` intervals in any order,
+    /// intervals of different subs may overlap.
+    pub fn unordered(subs: Vec<Box<dyn IntervalIterator>>) -> Box<dyn IntervalIterator> {
+        debug_assert!(!subs.is_empty());
+        let queue = Vec::with_capacity(subs.len());
+        Box::new(ConjunctionIntervalIterator::new(
+            subs,
+            IntervalFunction::Unordered {
+                queue,
+                queue_end: -1,
+            },
+        ))
+    }
+
+    /// Intervals of `big` which contain an interval of `small`.
+    pub fn containing(
+        big: Box<dyn IntervalIterator>,
+        small: Box<dyn IntervalIterator>,
+    ) -> Box<dyn IntervalIterator> {
+        Box::new(ConjunctionIntervalIterator::new(
+            vec![big, small],
+            IntervalFunction::Containing {
+                small_positioned: false,
+            },
+        ))
+    }
+}
+
+/// Intervals over the positions of a term.
+pub struct TermIntervalIterator<P: PostingIterator> {
+    postings: P,
+    freq: i32,
+    upto: i32,
+    pos: i32,
+}
+
+impl<P: PostingIterator> TermIntervalIterator<P> {
+    pub fn new(postings: P) -> Self {
+        TermIntervalIterator {
+            postings,
+            freq: 0,
+            upto: 0,
+            pos: -1,
+        }
+    }
+
+    fn reset(&mut self, doc: DocId) -> Result<DocId> {
+        self.freq = if doc == NO_MORE_DOCS {
+            0
+        } else {
+            self.postings.freq()?
+        };
+        self.upto = 0;
+        self.pos = -1;
+        Ok(doc)
+    }
+}
+
+impl<P: PostingIterator> IntervalIterator for TermIntervalIterator<P> {
+    fn start(&self) -> i32 {
+        self.pos
+    }
+
+    fn end(&self) -> i32 {
+        self.pos
+    }
+
+    fn next_interval(&mut self) -> Result<i32> {
+        if self.upto >= self.freq {
+            self.pos = NO_MORE_INTERVALS;
+        } else {
+            self.pos = self.postings.next_position()?;
+            self.upto += 1;
+        }
+        Ok(self.pos)
+    }
+}
+
+impl<P: PostingIterator> DocIterator for TermIntervalIterator<P> {
+    fn doc_id(&self) -> DocId {
+        self.postings.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        let doc = self.postings.next()?;
+        self.reset(doc)
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        let doc = self.postings.advance(target)?;
+        self.reset(doc)
+    }
+
+    fn cost(&self) -> usize {
+        self.postings.cost()
+    }
+}
+
+enum IntervalFunction {
+    Ordered,
+    // `queue` holds the indexes of the positioned subs, `queue_end` is
+    // the largest end of them
+    Unordered { queue: Vec<usize>, queue_end: i32 },
+    // subs[0] is the big one, subs[1] the small one
+    Containing { small_positioned: bool },
+}
+
+/// Combines the intervals of docs matching all the sub iterators.
+///
+/// A doc only matches if it has at least one combined interval, the first one
+/// is computed when the doc is positioned and returned by the following
+/// `next_interval` call.
+struct ConjunctionIntervalIterator {
+    subs: Vec<Box<dyn IntervalIterator>>,
+    function: IntervalFunction,
+    doc: DocId,
+    start: i32,
+    end: i32,
+    first_pending: bool,
+}
+
+impl ConjunctionIntervalIterator {
+    fn new(subs: Vec<Box<dyn IntervalIterator>>, function: IntervalFunction) -> Self {
+        ConjunctionIntervalIterator {
+            subs,
+            function,
+            doc: -1,
+            start: -1,
+            end: -1,
+            first_pending: false,
+        }
+    }
+
+    fn do_next(&mut self, mut doc: DocId) -> Result<DocId> {
+        'advanceHead: loop {
+            if doc == NO_MORE_DOCS {
+                self.doc = NO_MORE_DOCS;
+                self.start = NO_MORE_INTERVALS;
+                self.end = NO_MORE_INTERVALS;
+                return Ok(NO_MORE_DOCS);
+            }
+
+            for i in 1..self.subs.len() {
+                let mut sub_doc = self.subs[i].doc_id();
+                if sub_doc < doc {
+                    sub_doc = self.subs[i].advance(doc)?;
+                }
+                if sub_doc > doc {
+                    doc = self.subs[0].advance(sub_doc)?;
+                    continue 'advanceHead;
+                }
+            }
+
+            self.doc = doc;
+            self.reset()?;
+            if self.compute_next_interval()? != NO_MORE_INTERVALS {
+                self.first_pending = true;
+                return Ok(doc);
+            }
+            doc = self.subs[0].next()?;
+        }
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.start = -1;
+        self.end = -1;
+        self.first_pending = false;
+        match self.function {
+            IntervalFunction::Ordered => {
+                self.subs[0].next_interval()?;
+            }
+            IntervalFunction::Unordered {
+                ref mut queue,
+                ref mut queue_end,
+            } => {
+                queue.clear();
+                *queue_end = -1;
+                for (i, sub) in self.subs.iter_mut().enumerate() {
+                    if sub.next_interval()? == NO_MORE_INTERVALS {
+                        break;
+                    }
+                    queue.push(i);
+                    *queue_end = (*queue_end).max(sub.end());
+                }
+            }
+            IntervalFunction::Containing {
+                ref mut small_positioned,
+            } => {
+                *small_positioned = self.subs[1].next_interval()? != NO_MORE_INTERVALS;
+            }
+        }
+        Ok(())
+    }
+
+    fn compute_next_interval(&mut self) -> Result<i32> {
+        match self.function {
+            IntervalFunction::Ordered => self.next_ordered(),
+            IntervalFunction::Unordered { .. } => self.next_unordered(),
+            IntervalFunction::Containing { .. } => self.next_containing(),
+        }
+    }
+
+    /// Finds the next interval where each sub interval starts after the end
+    /// of the previous one, and minimizes it by moving the first sub forward
+    /// as long as the match doesn't get longer.
+    fn next_ordered(&mut self) -> Result<i32> {
+        let subs = &mut self.subs;
+        let n = subs.len();
+        self.start = NO_MORE_INTERVALS;
+        self.end = NO_MORE_INTERVALS;
+        let mut last_start = i32::max_value();
+        let mut minimizing = false;
+        let mut i = 1;
+        loop {
+            loop {
+                if subs[i - 1].end() >= last_start {
+                    return Ok(self.start);
+                }
+                if i == n || (minimizing && subs[i].start() > subs[i - 1].end()) {
+                    break;
+                }
+                loop {
+                    if subs[i].end() >= last_start || subs[i].next_interval()? == NO_MORE_INTERVALS
+                    {
+                        return Ok(self.start);
+                    }
+                    if subs[i].start() > subs[i - 1].end() {
+                        break;
+                    }
+                }
+                i += 1;
+            }
+            self.start = subs[0].start();
+            if self.start == NO_MORE_INTERVALS {
+                self.end = NO_MORE_INTERVALS;
+                return Ok(self.start);
+            }
+            self.end = subs[n - 1].end();
+            last_start = subs[n - 1].start();
+            i = 1;
+            if subs[0].next_interval()? == NO_MORE_INTERVALS {
+                return Ok(self.start);
+            }
+            minimizing = true;
+        }
+    }
+
+    /// Keeps one interval of each sub in a queue ordered by start position,
+    /// the candidate spans from the smallest start to the largest end, and is
+    /// minimized by moving the sub with the smallest start forward.
+    fn next_unordered(&mut self) -> Result<i32> {
+        let subs = &mut self.subs;
+        let (queue, queue_end) = match self.function {
+            IntervalFunction::Unordered {
+                ref mut queue,
+                ref mut queue_end,
+            } => (queue, queue_end),
+            _ => unreachable!(),
+        };
+
+        // first, find a matching interval
+        while queue.len() == subs.len() {
+            let top_idx = queue_top(queue, subs);
+            if subs[queue[top_idx]].start() != self.start {
+                break;
+            }
+            let idx = queue.swap_remove(top_idx);
+            if subs[idx].next_interval()? != NO_MORE_INTERVALS {
+                queue.push(idx);
+                *queue_end = (*queue_end).max(subs[idx].end());
+            }
+        }
+        if queue.len() < subs.len() {
+            self.start = NO_MORE_INTERVALS;
+            self.end = NO_MORE_INTERVALS;
+            return Ok(self.start);
+        }
+
+        // then, minimize it
+        loop {
+            let top_idx = queue_top(queue, subs);
+            self.start = subs[queue[top_idx]].start();
+            self.end = *queue_end;
+            if subs[queue[top_idx]].end() == self.end {
+                return Ok(self.start);
+            }
+            let idx = queue.swap_remove(top_idx);
+            if subs[idx].next_interval()? != NO_MORE_INTERVALS {
+                queue.push(idx);
+                *queue_end = (*queue_end).max(subs[idx].end());
+            }
+            if queue.len() < subs.len() || self.end != *queue_end {
+                return Ok(self.start);
+            }
+        }
+    }
+
+    /// Moves the small sub forward while it can't be contained by the current
+    /// big interval, returns the first big interval containing a small one.
+    fn next_containing(&mut self) -> Result<i32> {
+        let small_positioned = match self.function {
+            IntervalFunction::Containing {
+                ref mut small_positioned,
+            } => small_positioned,
+            _ => unreachable!(),
+        };
+        self.start = NO_MORE_INTERVALS;
+        self.end = NO_MORE_INTERVALS;
+        if !*small_positioned {
+            return Ok(NO_MORE_INTERVALS);
+        }
+        let (big, small) = self.subs.split_at_mut(1);
+        let (big, small) = (&mut big[0], &mut small[0]);
+        while big.next_interval()? != NO_MORE_INTERVALS {
+            while small.start() < big.start() && small.end() < big.end() {
+                if small.next_interval()? == NO_MORE_INTERVALS {
+                    *small_positioned = false;
+                    return Ok(NO_MORE_INTERVALS);
+                }
+            }
+            if big.start() <= small.start() && big.end() >= small.end() {
+                self.start = big.start();
+                self.end = big.end();
+                return Ok(self.start);
+            }
+        }
+        Ok(NO_MORE_INTERVALS)
+    }
+}
+
+/// Returns the index in `queue` of the sub with the smallest start, the one
+/// with the largest end first on ties.
+fn queue_top(queue: &[usize], subs: &[Box<dyn IntervalIterator>]) -> usize {
+    let mut top = 0;
+    for (idx, &sub) in queue.iter().enumerate().skip(1) {
+        let (s1, s2) = (&subs[sub], &subs[queue[top]]);
+        if s1.start() < s2.start() || (s1.start() == s2.start() && s1.end() > s2.end()) {
+            top = idx;
+        }
+    }
+    top
+}
+
+impl IntervalIterator for ConjunctionIntervalIterator {
+    fn start(&self) -> i32 {
+        self.start
+    }
+
+    fn end(&self) -> i32 {
+        self.end
+    }
+
+    fn next_interval(&mut self) -> Result<i32> {
+        if self.first_pending {
+            self.first_pending = false;
+            return Ok(self.start);
+        }
+        self.compute_next_interval()
+    }
+}
+
+impl DocIterator for ConjunctionIntervalIterator {
+    fn doc_id(&self) -> DocId {
+        self.doc
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        let doc = self.subs[0].next()?;
+        self.do_next(doc)
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        let doc = self.subs[0].advance(target)?;
+        self.do_next(doc)
+    }
+
+    fn cost(&self) -> usize {
+        self.subs.iter().map(|s| s.cost()).min().unwrap_or(0)
+    }
+}
+
+/// A `Scorer` over the docs of an `IntervalIterator`, the frequency of a doc
+/// is the number of its (minimized) intervals.
+pub struct IntervalScorer<I: IntervalIterator> {
+    intervals: I,
+    sim_scorer: Box<dyn SimScorer>,
+    freq: f32,
+}
+
+impl<I: IntervalIterator> IntervalScorer<I> {
+    pub fn new(intervals: I, sim_scorer: Box<dyn SimScorer>) -> Self {
+        IntervalScorer {
+            intervals,
+            sim_scorer,
+            freq: 0f32,
+        }
+    }
+
+    fn count_intervals(&mut self, doc: DocId) -> Result<DocId> {
+        self.freq = 0f32;
+        if doc != NO_MORE_DOCS {
+            while self.intervals.next_interval()? != NO_MORE_INTERVALS {
+                self.freq += 1f32;
+            }
+        }
+        Ok(doc)
+    }
+}
+
+impl<I: IntervalIterator> Scorer for IntervalScorer<I> {
+    fn score(&mut self) -> Result<f32> {
+        let doc = self.intervals.doc_id();
+        self.sim_scorer.score(doc, self.freq)
+    }
+
+    fn freq(&self) -> Result<f32> {
+        Ok(self.freq)
+    }
+}
+
+impl<I: IntervalIterator> DocIterator for IntervalScorer<I> {
+    fn doc_id(&self) -> DocId {
+        self.intervals.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        let doc = self.intervals.next()?;
+        self.count_intervals(doc)
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        let doc = self.intervals.advance(target)?;
+        self.count_intervals(doc)
+    }
+
+    fn cost(&self) -> usize {
+        self.intervals.cost()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::tests::*;
+
+    fn term(postings: Vec<(DocId, Vec<i32>)>) -> Box<dyn IntervalIterator> {
+        Intervals::term(MockPostingIterator::new(postings))
+    }
+
+    // doc 0: "a x b", doc 1: "b a", doc 2: "a a b b", doc 3: "a b c", doc 4: "a c b"
+    fn term_a() -> Box<dyn IntervalIterator> {
+        term(vec![
+            (0, vec![0]),
+            (1, vec![1]),
+            (2, vec![0, 1]),
+            (3, vec![0]),
+            (4, vec![0]),
+        ])
+    }
+
+    fn term_b() -> Box<dyn IntervalIterator> {
+        term(vec![
+            (0, vec![2]),
+            (1, vec![0]),
+            (2, vec![2, 3]),
+            (3, vec![1]),
+            (4, vec![2]),
+        ])
+    }
+
+    fn term_c() -> Box<dyn IntervalIterator> {
+        term(vec![(3, vec![2]), (4, vec![1])])
+    }
+
+    fn collect(intervals: &mut dyn IntervalIterator) -> Vec<(DocId, Vec<(i32, i32)>)> {
+        let mut result = vec![];
+        loop {
+            let doc = intervals.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            let mut doc_intervals = vec![];
+            while intervals.next_interval().unwrap() != NO_MORE_INTERVALS {
+                doc_intervals.push((intervals.start(), intervals.end()));
+            }
+            result.push((doc, doc_intervals));
+        }
+        result
+    }
+
+    #[test]
+    fn test_ordered() {
+        let mut intervals = Intervals::ordered(vec![term_a(), term_b()]);
+        assert_eq!(
+            collect(intervals.as_mut()),
+            vec![
+                (0, vec![(0, 2)]),
+                (2, vec![(1, 2)]),
+                (3, vec![(0, 1)]),
+                (4, vec![(0, 2)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unordered() {
+        let mut intervals = Intervals::unordered(vec![term_a(), term_b()]);
+        assert_eq!(
+            collect(intervals.as_mut()),
+            vec![
+                (0, vec![(0, 2)]),
+                (1, vec![(0, 1)]),
+                (2, vec![(1, 2)]),
+                (3, vec![(0, 1)]),
+                (4, vec![(0, 2)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_containing() {
+        // ORDERED(a, c) containing b, only "a b c" matches
+        let big = Intervals::ordered(vec![term_a(), term_c()]);
+        let mut intervals = Intervals::containing(big, term_b());
+        assert_eq!(collect(intervals.as_mut()), vec![(3, vec![(0, 2)])]);
+    }
+
+    #[test]
+    fn test_interval_scorer() {
+        // doc 0: "a b a b", doc 1: "a x b"
+        let a = term(vec![(0, vec![0, 2]), (1, vec![0])]);
+        let b = term(vec![(0, vec![1, 3]), (1, vec![2])]);
+        let intervals = Intervals::ordered(vec![a, b]);
+        let mut scorer = IntervalScorer::new(intervals, Box::new(MockSimScorer));
+
+        assert_eq!(scorer.next().unwrap(), 0);
+        // two minimal intervals: [0, 1] and [2, 3]
+        assert!((scorer.freq().unwrap() - 2.0).abs() < ::std::f32::EPSILON);
+        assert!((scorer.score().unwrap() - 2.0).abs() < ::std::f32::EPSILON);
+        assert_eq!(scorer.next().unwrap(), 1);
+        assert!((scorer.score().unwrap() - 1.0).abs() < ::std::f32::EPSILON);
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+    }
+}
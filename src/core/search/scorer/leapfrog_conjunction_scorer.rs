@@ -0,0 +1,198 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::search::scorer::Scorer;
+use core::search::{DocIterator, NO_MORE_DOCS};
+use core::util::DocId;
+use error::Result;
+
+/// Scorer for conjunctions which intersects its children with the leapfrog
+/// algorithm, eg. for clauses of very different costs.
+///
+/// Instead of always advancing a fixed lead and then the others, the children
+/// are visited in turn and each one lagging behind the highest doc seen so
+/// far is advanced to it, moving the highest doc forward whenever it lands
+/// beyond. A doc matches once all the children agree on it in a row, so a
+/// tiny clause bounds the number of advances on the huge ones.
+pub struct LeapfrogConjunctionScorer<T: Scorer> {
+    children: Vec<T>,
+    doc: DocId,
+}
+
+impl<T: Scorer> LeapfrogConjunctionScorer<T> {
+    pub fn new(mut children: Vec<T>) -> LeapfrogConjunctionScorer<T> {
+        assert!(children.len() >= 2);
+
+        // the cheapest child is advanced first
+        children.sort_by_key(|c| c.cost());
+        LeapfrogConjunctionScorer { children, doc: -1 }
+    }
+
+    fn leapfrog(&mut self, target: DocId) -> Result<DocId> {
+        let mut max = target;
+        let mut agreed = 0;
+        let mut idx = 0;
+        while max != NO_MORE_DOCS {
+            let child = &mut self.children[idx];
+            let mut doc = child.doc_id();
+            if doc < max {
+                doc = child.advance(max)?;
+            }
+            if doc > max {
+                max = doc;
+                agreed = 1;
+            } else {
+                agreed += 1;
+                if agreed == self.children.len() {
+                    break;
+                }
+            }
+            idx = (idx + 1) % self.children.len();
+        }
+        self.doc = max;
+        Ok(max)
+    }
+}
+
+impl<T: Scorer> Scorer for LeapfrogConjunctionScorer<T> {
+    fn score(&mut self) -> Result<f32> {
+        let mut score = 0.0f32;
+        for child in &mut self.children {
+            score += child.score()?;
+        }
+        Ok(score)
+    }
+}
+
+impl<T: Scorer> DocIterator for LeapfrogConjunctionScorer<T> {
+    fn doc_id(&self) -> DocId {
+        self.doc
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        if self.doc == NO_MORE_DOCS {
+            return Ok(NO_MORE_DOCS);
+        }
+        let target = self.doc + 1;
+        self.leapfrog(target)
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        if self.doc == NO_MORE_DOCS {
+            return Ok(NO_MORE_DOCS);
+        }
+        self.leapfrog(target.max(self.doc + 1))
+    }
+
+    fn cost(&self) -> usize {
+        self.children[0].cost()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::scorer::ConjunctionScorer;
+    use core::search::tests::*;
+    use std::collections::BTreeSet;
+    use std::sync::atomic::Ordering;
+
+    fn collect<S: Scorer>(scorer: &mut S) -> Vec<DocId> {
+        let mut docs = vec![];
+        loop {
+            let doc = scorer.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            docs.push(doc);
+        }
+        docs
+    }
+
+    /// pseudo random sorted docs in `[0, max_doc)`, each kept with a chance
+    /// of `1 / sparsity`
+    fn random_docs(seed: &mut u64, max_doc: DocId, sparsity: u64) -> Vec<DocId> {
+        (0..max_doc)
+            .filter(|_| {
+                *seed = seed
+                    .wrapping_mul(6_364_136_223_846_793_005)
+                    .wrapping_add(1_442_695_040_888_963_407);
+                (*seed >> 33) % sparsity == 0
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_same_as_naive_conjunction() {
+        let mut seed = 7u64;
+        for &sparsities in &[&[2u64, 3][..], &[1, 50, 2], &[500, 2, 3, 1], &[10, 10, 10]] {
+            let clauses: Vec<Vec<DocId>> = sparsities
+                .iter()
+                .map(|&s| random_docs(&mut seed, 20_000, s))
+                .collect();
+
+            let mut expected: BTreeSet<DocId> = clauses[0].iter().cloned().collect();
+            for clause in &clauses[1..] {
+                let docs: BTreeSet<DocId> = clause.iter().cloned().collect();
+                expected = expected.intersection(&docs).cloned().collect();
+            }
+            let expected: Vec<DocId> = expected.into_iter().collect();
+
+            let children = clauses.iter().map(|c| create_mock_scorer(c.clone()));
+            let mut leapfrog = LeapfrogConjunctionScorer::new(children.clone().collect());
+            assert_eq!(collect(&mut leapfrog), expected);
+            let mut conjunction = ConjunctionScorer::new(children.collect());
+            assert_eq!(collect(&mut conjunction), expected);
+        }
+
+        let children = vec![
+            create_mock_scorer(vec![1, 2, 3, 5, 8]),
+            create_mock_scorer(vec![2, 3, 5, 7, 9]),
+            create_mock_scorer(vec![3, 5, 8, 9]),
+        ];
+        let mut scorer = LeapfrogConjunctionScorer::new(children);
+        assert_eq!(scorer.advance(4).unwrap(), 5);
+        assert!((scorer.score().unwrap() - 15.0).abs() < ::std::f32::EPSILON);
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+        assert_eq!(scorer.doc_id(), NO_MORE_DOCS);
+        assert_eq!(scorer.advance(3).unwrap(), NO_MORE_DOCS);
+    }
+
+    #[test]
+    fn test_skewed_costs() {
+        // 10 rare docs against two clauses matching almost all of 1M docs
+        let rare: Vec<DocId> = (1..=10).map(|i| i * 99_991).collect();
+        let common: Vec<DocId> = (0..1_000_000).filter(|d| d % 7 != 3).collect();
+        let other: Vec<DocId> = (0..1_000_000).filter(|d| d % 11 != 5).collect();
+        let expected: Vec<DocId> = rare
+            .iter()
+            .cloned()
+            .filter(|d| d % 7 != 3 && d % 11 != 5)
+            .collect();
+
+        let children = vec![
+            MockCountingScorer::new(common),
+            MockCountingScorer::new(rare),
+            MockCountingScorer::new(other),
+        ];
+        let advance_calls: Vec<_> = children.iter().map(|c| c.advance_calls.clone()).collect();
+        let mut scorer = LeapfrogConjunctionScorer::new(children);
+        assert_eq!(scorer.cost(), 10);
+        assert_eq!(collect(&mut scorer), expected);
+
+        // the common clauses are only advanced about once per rare doc
+        for calls in &advance_calls {
+            assert!(calls.load(Ordering::SeqCst) <= 2 * 10 + 1);
+        }
+    }
+}
@@ -0,0 +1,201 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::search::scorer::{FeatureResult, ScoreMode, Scorer};
+use core::search::{DocIterator, Explanation, TwoPhaseIterator, NO_MORE_DOCS};
+use core::util::{BitsRef, DocId};
+use error::Result;
+
+/// A `Scorer` which skips the docs of `child` that are not set in
+/// `live_docs`, eg. the deleted docs of a segment, so that `child` doesn't
+/// need to check the live docs itself.
+///
+/// `next` and `advance` only stop on live docs. If `child` supports two
+/// phase iteration so does this scorer, its approximation is the one of
+/// `child` and deleted docs are rejected by `matches` before `child` is
+/// asked to confirm them. Scoring is forwarded to `child`.
+pub struct LiveDocsFilterScorer<S: Scorer> {
+    child: S,
+    live_docs: BitsRef,
+}
+
+impl<S: Scorer> LiveDocsFilterScorer<S> {
+    pub fn new(child: S, live_docs: BitsRef) -> Self {
+        LiveDocsFilterScorer { child, live_docs }
+    }
+
+    fn is_live(&self, doc: DocId) -> Result<bool> {
+        self.live_docs.get(doc as usize)
+    }
+
+    fn next_live(&mut self, mut doc: DocId) -> Result<DocId> {
+        while doc != NO_MORE_DOCS && !self.is_live(doc)? {
+            doc = self.child.next()?;
+        }
+        Ok(doc)
+    }
+}
+
+impl<S: Scorer> Scorer for LiveDocsFilterScorer<S> {
+    fn score(&mut self) -> Result<f32> {
+        self.child.score()
+    }
+
+    fn score_feature(&mut self) -> Result<Vec<FeatureResult>> {
+        self.child.score_feature()
+    }
+
+    fn freq(&self) -> Result<f32> {
+        self.child.freq()
+    }
+
+    fn set_score_mode(&mut self, mode: ScoreMode) {
+        self.child.set_score_mode(mode)
+    }
+
+    fn advance_shallow(&mut self, target: DocId) -> Result<DocId> {
+        self.child.advance_shallow(target)
+    }
+
+    fn get_max_score(&mut self, up_to: DocId) -> Result<f32> {
+        self.child.get_max_score(up_to)
+    }
+
+    fn set_min_competitive_score(&mut self, min_score: f32) {
+        self.child.set_min_competitive_score(min_score)
+    }
+
+    fn explain(&mut self, doc: DocId) -> Result<Explanation> {
+        self.child.explain(doc)
+    }
+}
+
+impl<S: Scorer> DocIterator for LiveDocsFilterScorer<S> {
+    fn doc_id(&self) -> DocId {
+        self.child.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        let doc = self.child.next()?;
+        self.next_live(doc)
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        let doc = self.child.advance(target)?;
+        self.next_live(doc)
+    }
+
+    fn cost(&self) -> usize {
+        self.child.cost()
+    }
+
+    fn matches(&mut self) -> Result<bool> {
+        let doc = self.child.doc_id();
+        Ok(self.is_live(doc)? && self.child.matches()?)
+    }
+
+    fn match_cost(&self) -> f32 {
+        self.child.match_cost()
+    }
+
+    fn support_two_phase(&self) -> bool {
+        self.child.support_two_phase()
+    }
+
+    fn approximate_next(&mut self) -> Result<DocId> {
+        self.child.approximate_next()
+    }
+
+    fn approximate_advance(&mut self, target: DocId) -> Result<DocId> {
+        self.child.approximate_advance(target)
+    }
+
+    fn as_two_phase(&mut self) -> Option<&mut dyn TwoPhaseIterator> {
+        if self.support_two_phase() {
+            Some(self)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::tests::*;
+    use core::util::{BitSet, FixedBitSet};
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    /// all the docs in `[0, 10)` but `deleted`
+    fn live_docs(deleted: &[usize]) -> BitsRef {
+        let mut bits = FixedBitSet::new(10);
+        for doc in 0..10 {
+            if !deleted.contains(&doc) {
+                bits.set(doc);
+            }
+        }
+        Arc::new(bits)
+    }
+
+    #[test]
+    fn test_skip_deleted_docs() {
+        let child = create_mock_scorer(vec![1, 2, 3, 5, 8, 9]);
+        let mut scorer = LiveDocsFilterScorer::new(child, live_docs(&[2, 3, 9]));
+        let mut docs = vec![];
+        while scorer.next().unwrap() != NO_MORE_DOCS {
+            let doc = scorer.doc_id();
+            assert!((scorer.score().unwrap() - doc as f32).abs() < ::std::f32::EPSILON);
+            docs.push(doc);
+        }
+        assert_eq!(docs, vec![1, 5, 8]);
+
+        // advancing to a deleted doc moves to the next live one
+        let child = create_mock_scorer(vec![1, 2, 3, 5, 8, 9]);
+        let mut scorer = LiveDocsFilterScorer::new(child, live_docs(&[2, 3, 9]));
+        assert_eq!(scorer.advance(2).unwrap(), 5);
+        assert_eq!(scorer.advance(6).unwrap(), 8);
+        assert_eq!(scorer.advance(9).unwrap(), NO_MORE_DOCS);
+        assert_eq!(scorer.cost(), 6);
+    }
+
+    #[test]
+    fn test_two_phase() {
+        let child = MockTwoPhaseScorer::new(vec![1, 2, 4, 6, 7], vec![2, 4, 7]);
+        let matches_calls = Arc::clone(&child.matches_calls);
+        let mut scorer = LiveDocsFilterScorer::new(child, live_docs(&[4]));
+        assert!(scorer.support_two_phase());
+
+        let mut docs = vec![];
+        {
+            let two_phase = scorer.as_two_phase().unwrap();
+            while two_phase.next_approximation().unwrap() != NO_MORE_DOCS {
+                if two_phase.confirm_match().unwrap() {
+                    docs.push(two_phase.approximation_doc_id());
+                }
+            }
+        }
+        assert_eq!(docs, vec![2, 7]);
+        // the deleted doc 4 is never confirmed by the child
+        assert_eq!(matches_calls.load(Ordering::SeqCst), 4);
+
+        let child = MockTwoPhaseScorer::new(vec![1, 2, 4, 6, 7], vec![2, 4, 7]);
+        let mut scorer = LiveDocsFilterScorer::new(child, live_docs(&[4]));
+        assert_eq!(scorer.advance(3).unwrap(), 7);
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+
+        let child = create_mock_scorer(vec![1]);
+        let mut scorer = LiveDocsFilterScorer::new(child, live_docs(&[]));
+        assert!(scorer.as_two_phase().is_none());
+    }
+}
@@ -0,0 +1,121 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::search::query::AllDocsIterator;
+use core::search::scorer::{LiveDocsFilterScorer, Scorer};
+use core::search::DocIterator;
+use core::util::{BitsRef, DocId, MatchAllBits};
+use error::Result;
+
+use std::sync::Arc;
+
+/// A `Scorer` which matches every doc in `[0, max_doc)` with a constant
+/// `score`, eg. for `*:*` queries and filter contexts.
+///
+/// Unlike `AllDocsIterator`, deleted docs are skipped if `live_docs` is set,
+/// so this can be used where the caller doesn't check the live docs itself.
+pub struct MatchAllScorer {
+    scorer: LiveDocsFilterScorer<AllDocsIterator>,
+}
+
+impl MatchAllScorer {
+    pub fn new(max_doc: DocId, score: f32) -> Self {
+        Self::with_live_docs(max_doc, score, None)
+    }
+
+    /// Docs not set in `live_docs` are treated as deleted and never matched.
+    pub fn with_live_docs(max_doc: DocId, score: f32, live_docs: Option<BitsRef>) -> Self {
+        let live_docs =
+            live_docs.unwrap_or_else(|| Arc::new(MatchAllBits::new(max_doc.max(0) as usize)));
+        MatchAllScorer {
+            scorer: LiveDocsFilterScorer::new(
+                AllDocsIterator::with_score(max_doc, score),
+                live_docs,
+            ),
+        }
+    }
+}
+
+impl Scorer for MatchAllScorer {
+    fn score(&mut self) -> Result<f32> {
+        self.scorer.score()
+    }
+
+    fn get_max_score(&mut self, up_to: DocId) -> Result<f32> {
+        self.scorer.get_max_score(up_to)
+    }
+}
+
+impl DocIterator for MatchAllScorer {
+    fn doc_id(&self) -> DocId {
+        self.scorer.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        self.scorer.next()
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        self.scorer.advance(target)
+    }
+
+    fn cost(&self) -> usize {
+        self.scorer.cost()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::NO_MORE_DOCS;
+    use core::util::{BitSet, FixedBitSet};
+
+    fn collect_docs<S: Scorer>(scorer: &mut S) -> Vec<DocId> {
+        let mut docs = vec![];
+        loop {
+            let doc = scorer.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            assert!((scorer.score().unwrap() - 0.5).abs() < ::std::f32::EPSILON);
+            docs.push(doc);
+        }
+        docs
+    }
+
+    #[test]
+    fn test_all_docs() {
+        let mut scorer = MatchAllScorer::new(4, 0.5);
+        assert_eq!(scorer.doc_id(), -1);
+        assert_eq!(collect_docs(&mut scorer), vec![0, 1, 2, 3]);
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+
+        assert!(collect_docs(&mut MatchAllScorer::new(0, 0.5)).is_empty());
+    }
+
+    #[test]
+    fn test_skip_deleted_docs() {
+        let mut live_docs = FixedBitSet::new(6);
+        for doc in &[1, 2, 4] {
+            live_docs.set(*doc);
+        }
+        let live_docs: BitsRef = Arc::new(live_docs);
+
+        let mut scorer = MatchAllScorer::with_live_docs(6, 0.5, Some(Arc::clone(&live_docs)));
+        assert_eq!(collect_docs(&mut scorer), vec![1, 2, 4]);
+
+        let mut scorer = MatchAllScorer::with_live_docs(6, 0.5, Some(live_docs));
+        assert_eq!(scorer.advance(3).unwrap(), 4);
+        assert_eq!(scorer.advance(5).unwrap(), NO_MORE_DOCS);
+    }
+}
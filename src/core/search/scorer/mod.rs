@@ -19,6 +19,14 @@ mod conjunction_scorer;
 
 pub use self::conjunction_scorer::*;
 
+mod leapfrog_conjunction_scorer;
+
+pub use self::leapfrog_conjunction_scorer::*;
+
+mod block_max_conjunction_scorer;
+
+pub use self::block_max_conjunction_scorer::*;
+
 mod disjunction_scorer;
 
 pub use self::disjunction_scorer::*;
@@ -27,6 +35,54 @@ mod min_scorer;
 
 pub use self::min_scorer::*;
 
+mod caching_scorer;
+
+pub use self::caching_scorer::*;
+
+mod tapped_scorer;
+
+pub use self::tapped_scorer::*;
+
+mod function_scorer;
+
+pub use self::function_scorer::*;
+
+mod synonym_scorer;
+
+pub use self::synonym_scorer::*;
+
+mod combined_fields_scorer;
+
+pub use self::combined_fields_scorer::*;
+
+mod interval_scorer;
+
+pub use self::interval_scorer::*;
+
+mod match_all_scorer;
+
+pub use self::match_all_scorer::*;
+
+mod multi_term_scorer;
+
+pub use self::multi_term_scorer::*;
+
+mod fuzzy_scorer;
+
+pub use self::fuzzy_scorer::*;
+
+mod prefix_scorer;
+
+pub use self::prefix_scorer::*;
+
+mod negation_scorer;
+
+pub use self::negation_scorer::*;
+
+mod boolean_scorer;
+
+pub use self::boolean_scorer::*;
+
 mod req_opt_scorer;
 
 pub use self::req_opt_scorer::*;
@@ -35,29 +91,102 @@ mod req_not_scorer;
 
 pub use self::req_not_scorer::*;
 
+mod req_excl_scorer;
+
+pub use self::req_excl_scorer::*;
+
 mod rescorer;
 
 pub use self::rescorer::*;
 
+mod rescore_scorer;
+
+pub use self::rescore_scorer::*;
+
 mod term_scorer;
 
 pub use self::term_scorer::*;
 
+mod payload_scorer;
+
+pub use self::payload_scorer::*;
+
+mod boost_scorer;
+
+pub use self::boost_scorer::*;
+
 mod boosting_scorer;
 
 pub use self::boosting_scorer::*;
 
+mod normalizing_scorer;
+
+pub use self::normalizing_scorer::*;
+
 mod phrase_scorer;
 
 pub use self::phrase_scorer::*;
 
+mod multi_phrase_scorer;
+
+pub use self::multi_phrase_scorer::*;
+
+mod span_near_scorer;
+
+pub use self::span_near_scorer::*;
+
+mod span_not_scorer;
+
+pub use self::span_not_scorer::*;
+
+mod span_term_scorer;
+
+pub use self::span_term_scorer::*;
+
+mod scorer_iter;
+
+pub use self::scorer_iter::*;
+
+mod scorer_supplier;
+
+pub use self::scorer_supplier::*;
+
+mod geo_distance_scorer;
+
+pub use self::geo_distance_scorer::*;
+
+mod point_range_scorer;
+
+pub use self::point_range_scorer::*;
+
+mod doc_values_scorer;
+
+pub use self::doc_values_scorer::*;
+
+mod live_docs_filter_scorer;
+
+pub use self::live_docs_filter_scorer::*;
+
+mod time_limiting_scorer;
+
+pub use self::time_limiting_scorer::*;
+
+mod block_join_scorer;
+
+pub use self::block_join_scorer::*;
+
+mod cached_filter_scorer;
+
+pub use self::cached_filter_scorer::*;
+
 use std::collections::HashMap;
+use std::f32;
 use std::i32;
 
 use core::util::{DocId, IndexedContext, VariantValue};
 
 use core::search::query::AllDocsIterator;
-use core::search::{DocIterator, NO_MORE_DOCS};
+use core::search::{DocIterator, Explanation, TwoPhaseIterator, NO_MORE_DOCS};
 use error::{ErrorKind::IllegalArgument, Result};
 
 pub struct FeatureResult {
@@ -72,6 +201,26 @@ impl FeatureResult {
     }
 }
 
+/// Indicates how the scores produced by a `Scorer` are going to be consumed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScoreMode {
+    /// Scores are needed for every matching document.
+    #[default]
+    Complete,
+    /// Only the top scoring documents matter, scores of non-competitive
+    /// documents may be skipped.
+    Top,
+    /// Scores are not needed at all, eg. when counting or filtering hits.
+    CountOnly,
+}
+
+impl ScoreMode {
+    /// Returns whether this mode requires scores to be computed.
+    pub fn needs_scores(self) -> bool {
+        self != ScoreMode::CountOnly
+    }
+}
+
 /// Expert: Common scoring functionality for different types of queries.
 ///
 /// A `Scorer` exposes an `iterator()` over documents matching a query in increasing order of doc
@@ -96,6 +245,114 @@ pub trait Scorer: DocIterator {
     fn score_feature(&mut self) -> Result<Vec<FeatureResult>> {
         Ok(vec![])
     }
+
+    /// Returns the frequency of the matched term(s) in the current document,
+    /// postings based scorers return the real term frequency, others
+    /// default to `1.0`.
+    fn freq(&self) -> Result<f32> {
+        Ok(1.0)
+    }
+
+    /// Tells this scorer how its scores are going to be consumed, scorers may
+    /// skip work that is only needed for scoring when scores are not required.
+    fn set_score_mode(&mut self, _mode: ScoreMode) {}
+
+    /// Advances to the block of documents that contains `target` in order to
+    /// get scoring information about this block, without moving the iterator.
+    /// Returns the last doc id (inclusive) of that block, which is used as the
+    /// `up_to` argument of `get_max_score`.
+    ///
+    /// Scorer that don't have block level score information will return
+    /// `NO_MORE_DOCS`, eg. treat all the docs as one block.
+    fn advance_shallow(&mut self, _target: DocId) -> Result<DocId> {
+        Ok(NO_MORE_DOCS)
+    }
+
+    /// Returns an upper bound of the score of all documents from the current
+    /// doc up to `up_to` (inclusive). Defaults to `f32::INFINITY`, which means
+    /// no documents can be skipped.
+    fn get_max_score(&mut self, _up_to: DocId) -> Result<f32> {
+        Ok(f32::INFINITY)
+    }
+
+    /// Tells the scorer that only docs scoring at least `min_score` are
+    /// competitive, eg. the score of the worst hit of a full top-k queue.
+    /// Scorers with block level score information may skip the docs which
+    /// can't reach it, the default ignores it.
+    fn set_min_competitive_score(&mut self, _min_score: f32) {}
+
+    /// Returns the score of `doc`, or `None` if it doesn't match, eg. for
+    /// reranking a given doc. The scorer is advanced to `doc` if it is
+    /// positioned before it, so like `explain`, `doc` must not be before the
+    /// current doc, such a doc is reported as not matching.
+    fn score_at(&mut self, doc: DocId) -> Result<Option<f32>> {
+        if self.doc_id() < doc {
+            self.advance(doc)?;
+        }
+        if self.doc_id() == doc {
+            Ok(Some(self.score()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns an `Iterator` over the `(doc, score)` of the docs after the
+    /// current one, see `ScorerIter`.
+    fn iter(&mut self) -> ScorerIter<'_, Self>
+    where
+        Self: Sized,
+    {
+        ScorerIter::new(self)
+    }
+
+    /// Explains how the score of `doc` is computed, the scorer is advanced to
+    /// `doc` if it is positioned before it, so `doc` must not be before the
+    /// current doc.
+    ///
+    /// The default is a leaf explanation holding the score, composite scorers
+    /// override this to break the score down by their sub scorers.
+    fn explain(&mut self, doc: DocId) -> Result<Explanation> {
+        if self.doc_id() < doc {
+            self.advance(doc)?;
+        }
+        if self.doc_id() == doc {
+            let score = self.score()?;
+            Ok(Explanation::new(true, score, "score".to_string(), vec![]))
+        } else {
+            Ok(Explanation::new(
+                false,
+                0.0,
+                format!("no matching doc {}", doc),
+                vec![],
+            ))
+        }
+    }
+}
+
+/// A `Scorer` over position based matches which exposes the start and end
+/// position of each match in the current document.
+///
+/// Positions follow the same convention as `Spans`: `-1` before the first
+/// call of `next_start_position` in the current doc, and `NO_MORE_POSITIONS`
+/// once all the matches are exhausted. The end position is exclusive.
+pub trait SpanScorer: Scorer {
+    /// Moves to the next match in the current doc and returns its start position,
+    /// or `NO_MORE_POSITIONS` if there are no more matches.
+    fn next_start_position(&mut self) -> Result<i32>;
+
+    /// Returns the start position of the current match.
+    fn start_position(&self) -> i32;
+
+    /// Returns the (exclusive) end position of the current match.
+    fn end_position(&self) -> i32;
+}
+
+/// A `Scorer` over a single term's postings which can return the positions
+/// of the term in the current document, eg. for highlighting.
+pub trait PositionsScorer: Scorer {
+    /// Returns the positions of the term in the current document in increasing
+    /// order. Fails if the postings were opened without positions.
+    fn positions(&mut self) -> Result<Vec<u32>>;
 }
 
 impl Scorer for Box<dyn Scorer> {
@@ -110,6 +367,34 @@ impl Scorer for Box<dyn Scorer> {
     fn score_feature(&mut self) -> Result<Vec<FeatureResult>> {
         (**self).score_feature()
     }
+
+    fn freq(&self) -> Result<f32> {
+        (**self).freq()
+    }
+
+    fn set_score_mode(&mut self, mode: ScoreMode) {
+        (**self).set_score_mode(mode)
+    }
+
+    fn advance_shallow(&mut self, target: DocId) -> Result<DocId> {
+        (**self).advance_shallow(target)
+    }
+
+    fn get_max_score(&mut self, up_to: DocId) -> Result<f32> {
+        (**self).get_max_score(up_to)
+    }
+
+    fn set_min_competitive_score(&mut self, min_score: f32) {
+        (**self).set_min_competitive_score(min_score)
+    }
+
+    fn score_at(&mut self, doc: DocId) -> Result<Option<f32>> {
+        (**self).score_at(doc)
+    }
+
+    fn explain(&mut self, doc: DocId) -> Result<Explanation> {
+        (**self).explain(doc)
+    }
 }
 
 impl DocIterator for Box<dyn Scorer> {
@@ -152,6 +437,10 @@ impl DocIterator for Box<dyn Scorer> {
     fn approximate_advance(&mut self, target: i32) -> Result<i32> {
         (**self).approximate_advance(target)
     }
+
+    fn as_two_phase(&mut self) -> Option<&mut dyn TwoPhaseIterator> {
+        (**self).as_two_phase()
+    }
 }
 
 /// helper function for doc iterator support two phase
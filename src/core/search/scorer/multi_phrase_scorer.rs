@@ -0,0 +1,261 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::codec::PostingIterator;
+use core::search::scorer::{ExactPhraseScorer, PostingsAndFreq, Scorer};
+use core::search::similarity::SimScorer;
+use core::search::{DocIterator, Payload, NO_MORE_DOCS};
+use core::util::DocId;
+use error::Result;
+
+/// A `PostingIterator` over the union of several postings, eg. the
+/// alternative terms of a multi phrase slot.
+///
+/// A doc matches if any of the postings matches it, and its positions are
+/// the sorted and deduplicated positions of all the postings on the doc.
+/// Offsets and payloads are not supported.
+pub struct UnionPostingIterator<T: PostingIterator> {
+    postings: Vec<T>,
+    doc: DocId,
+    positions: Vec<i32>,
+    position_idx: usize,
+}
+
+impl<T: PostingIterator> UnionPostingIterator<T> {
+    pub fn new(postings: Vec<T>) -> UnionPostingIterator<T> {
+        debug_assert!(!postings.is_empty());
+        UnionPostingIterator {
+            postings,
+            doc: -1,
+            positions: vec![],
+            position_idx: 0,
+        }
+    }
+
+    fn load_positions(&mut self) -> Result<()> {
+        self.positions.clear();
+        self.position_idx = 0;
+        for postings in &mut self.postings {
+            if postings.doc_id() == self.doc {
+                for _ in 0..postings.freq()? {
+                    self.positions.push(postings.next_position()?);
+                }
+            }
+        }
+        self.positions.sort_unstable();
+        self.positions.dedup();
+        Ok(())
+    }
+}
+
+impl<T: PostingIterator> DocIterator for UnionPostingIterator<T> {
+    fn doc_id(&self) -> DocId {
+        self.doc
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        if self.doc == NO_MORE_DOCS {
+            return Ok(NO_MORE_DOCS);
+        }
+        let target = self.doc + 1;
+        self.advance(target)
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        let mut doc = NO_MORE_DOCS;
+        for postings in &mut self.postings {
+            let mut current = postings.doc_id();
+            if current < target {
+                current = postings.advance(target)?;
+            }
+            doc = doc.min(current);
+        }
+        self.doc = doc;
+        if doc != NO_MORE_DOCS {
+            self.load_positions()?;
+        }
+        Ok(doc)
+    }
+
+    fn cost(&self) -> usize {
+        self.postings.iter().map(|p| p.cost()).sum()
+    }
+}
+
+impl<T: PostingIterator> PostingIterator for UnionPostingIterator<T> {
+    fn freq(&self) -> Result<i32> {
+        Ok(self.positions.len() as i32)
+    }
+
+    fn next_position(&mut self) -> Result<i32> {
+        let pos = self.positions[self.position_idx];
+        self.position_idx += 1;
+        Ok(pos)
+    }
+
+    fn start_offset(&self) -> Result<i32> {
+        Ok(-1)
+    }
+
+    fn end_offset(&self) -> Result<i32> {
+        Ok(-1)
+    }
+
+    fn payload(&self) -> Result<Payload> {
+        Ok(Payload::new())
+    }
+}
+
+/// A phrase `Scorer` where each slot of the phrase accepts any of several
+/// terms, eg. `(quick|fast) fox`, matching when one alternative of every slot
+/// is found at the slot's position relative to the others.
+///
+/// Each slot is given as its position in the phrase and the postings of its
+/// alternatives, which are merged by `UnionPostingIterator` and matched by an
+/// `ExactPhraseScorer`, so the phrase must have at least two slots.
+pub struct MultiPhraseScorer<T: PostingIterator> {
+    scorer: ExactPhraseScorer<UnionPostingIterator<T>>,
+    match_cost: f32,
+}
+
+impl<T: PostingIterator> MultiPhraseScorer<T> {
+    pub fn new(
+        slots: Vec<(i32, Vec<T>)>,
+        doc_scorer: Box<dyn SimScorer>,
+        needs_scores: bool,
+        match_cost: f32,
+    ) -> MultiPhraseScorer<T> {
+        let postings = slots
+            .into_iter()
+            .map(|(pos, alternatives)| PostingsAndFreq {
+                postings: UnionPostingIterator::new(alternatives),
+                pos,
+                terms: vec![],
+                nterms: 0,
+            })
+            .collect();
+        MultiPhraseScorer {
+            scorer: ExactPhraseScorer::new(postings, doc_scorer, needs_scores, match_cost),
+            match_cost,
+        }
+    }
+
+    /// Returns the number of phrase matches in the current doc.
+    pub fn freq(&self) -> i32 {
+        self.scorer.freq()
+    }
+}
+
+impl<T: PostingIterator> Scorer for MultiPhraseScorer<T> {
+    fn score(&mut self) -> Result<f32> {
+        self.scorer.score()
+    }
+
+    fn freq(&self) -> Result<f32> {
+        Ok(self.scorer.freq() as f32)
+    }
+}
+
+impl<T: PostingIterator> DocIterator for MultiPhraseScorer<T> {
+    fn doc_id(&self) -> DocId {
+        self.scorer.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        self.scorer.next()
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        self.scorer.advance(target)
+    }
+
+    fn cost(&self) -> usize {
+        self.scorer.cost()
+    }
+
+    fn matches(&mut self) -> Result<bool> {
+        self.scorer.matches()
+    }
+
+    fn match_cost(&self) -> f32 {
+        self.match_cost
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::tests::*;
+
+    fn postings(postings: Vec<(DocId, Vec<i32>)>) -> MockPostingIterator {
+        MockPostingIterator::new(postings)
+    }
+
+    /// `(quick|fast) fox`
+    fn create_scorer() -> MultiPhraseScorer<MockPostingIterator> {
+        let quick = postings(vec![(0, vec![0]), (1, vec![3]), (3, vec![0, 5])]);
+        let fast = postings(vec![(1, vec![0]), (2, vec![1]), (3, vec![2]), (4, vec![0])]);
+        let fox = postings(vec![
+            (0, vec![1]),
+            (1, vec![1]),
+            (2, vec![0]),
+            (3, vec![1, 3, 6]),
+            (5, vec![1]),
+        ]);
+        MultiPhraseScorer::new(
+            vec![(0, vec![quick, fast]), (1, vec![fox])],
+            Box::new(MockSimScorer),
+            true,
+            1.0,
+        )
+    }
+
+    #[test]
+    fn test_union_postings() {
+        let mut union = UnionPostingIterator::new(vec![
+            postings(vec![(1, vec![2, 7]), (4, vec![1])]),
+            postings(vec![(1, vec![2, 5]), (3, vec![0])]),
+        ]);
+        assert_eq!(union.cost(), 4);
+        assert_eq!(union.next().unwrap(), 1);
+        assert_eq!(union.freq().unwrap(), 3);
+        let positions: Vec<i32> = (0..3).map(|_| union.next_position().unwrap()).collect();
+        assert_eq!(positions, vec![2, 5, 7]);
+        assert_eq!(union.next().unwrap(), 3);
+        assert_eq!(union.advance(4).unwrap(), 4);
+        assert_eq!(union.next_position().unwrap(), 1);
+        assert_eq!(union.next().unwrap(), NO_MORE_DOCS);
+        assert_eq!(union.next().unwrap(), NO_MORE_DOCS);
+    }
+
+    #[test]
+    fn test_alternatives_at_first_slot() {
+        let mut scorer = create_scorer();
+        let mut result = vec![];
+        loop {
+            let doc = scorer.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            result.push((doc, scorer.freq()));
+        }
+        // doc 0: "quick fox", doc 1: "fast fox", doc 2 has "fox fast",
+        // doc 3: "quick fox" at 0 and "fast fox" at 2, "quick" at 5 and "fox"
+        // at 6 too, doc 4 and 5 miss a slot
+        assert_eq!(result, vec![(0, 1), (1, 1), (3, 3)]);
+
+        let mut scorer = create_scorer();
+        assert_eq!(scorer.advance(2).unwrap(), 3);
+        assert!((scorer.score().unwrap() - 3.0).abs() < ::std::f32::EPSILON);
+    }
+}
@@ -0,0 +1,215 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::search::scorer::{DisjunctionMaxScorer, DisjunctionSumScorer, Scorer};
+use core::search::{DocIdSet, DocIterator};
+use core::util::{DocId, DocIdSetBuilder, DocIdSetDocIterEnum};
+use error::Result;
+
+/// How the terms expanded by a multi term query are scored, like Lucene's
+/// `MultiTermQuery.RewriteMethod`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RewriteMethod {
+    /// A doc is scored by the sum of the scores of the terms it contains,
+    /// eg. a boolean query of `should` term clauses.
+    ScoreBoolean,
+    /// A doc is scored by the max of the scores of the terms it contains,
+    /// eg. a dis-max query of the term clauses, so that a doc matching many
+    /// similar terms doesn't outscore one matching the best term.
+    ScoreMax,
+    /// All the docs containing any of the terms get the boost as score, the
+    /// docs are collected upfront so the term scorers are not kept around.
+    ConstantScore,
+}
+
+enum MultiTermScorerEnum<S: Scorer> {
+    Boolean(DisjunctionSumScorer<S>),
+    Max(DisjunctionMaxScorer<S>),
+    Constant(DocIdSetDocIterEnum),
+}
+
+/// A `Scorer` over the union of the docs of the terms expanded by a term
+/// enumerating query, eg. regexp, prefix or fuzzy queries, which only have to
+/// supply their per term scorers.
+///
+/// At most `max_expansions` term scorers are taken from `terms`, the
+/// remaining ones are ignored. The scores are multiplied by `boost`, see
+/// `RewriteMethod`.
+pub struct MultiTermScorer<S: Scorer> {
+    scorer: MultiTermScorerEnum<S>,
+    boost: f32,
+    expanded_terms: usize,
+}
+
+impl<S: Scorer> MultiTermScorer<S> {
+    /// Returns `None` if no term is expanded. With `ConstantScore` each term
+    /// scorer is consumed as soon as it is taken from `terms`.
+    pub fn new<I>(
+        terms: I,
+        rewrite_method: RewriteMethod,
+        max_expansions: usize,
+        max_doc: DocId,
+        boost: f32,
+    ) -> Result<Option<MultiTermScorer<S>>>
+    where
+        I: IntoIterator<Item = Result<S>>,
+    {
+        let terms = terms.into_iter().take(max_expansions);
+        if rewrite_method == RewriteMethod::ConstantScore {
+            let mut builder = DocIdSetBuilder::with_max_doc(max_doc);
+            let mut expanded_terms = 0;
+            for term in terms {
+                builder.add(&mut term?)?;
+                expanded_terms += 1;
+            }
+            if expanded_terms == 0 {
+                return Ok(None);
+            }
+            return Ok(builder.build().iterator()?.map(|iterator| MultiTermScorer {
+                scorer: MultiTermScorerEnum::Constant(iterator),
+                boost,
+                expanded_terms,
+            }));
+        }
+
+        let children = terms.collect::<Result<Vec<S>>>()?;
+        let expanded_terms = children.len();
+        if expanded_terms == 0 {
+            return Ok(None);
+        }
+        let scorer = if rewrite_method == RewriteMethod::ScoreBoolean {
+            MultiTermScorerEnum::Boolean(DisjunctionSumScorer::new(children, true, 1))
+        } else {
+            MultiTermScorerEnum::Max(DisjunctionMaxScorer::new(children, 0.0, true))
+        };
+        Ok(Some(MultiTermScorer {
+            scorer,
+            boost,
+            expanded_terms,
+        }))
+    }
+
+    /// Returns the number of terms whose docs are matched.
+    pub fn expanded_terms(&self) -> usize {
+        self.expanded_terms
+    }
+}
+
+impl<S: Scorer> Scorer for MultiTermScorer<S> {
+    fn score(&mut self) -> Result<f32> {
+        match &mut self.scorer {
+            MultiTermScorerEnum::Boolean(s) => Ok(self.boost * s.score()?),
+            MultiTermScorerEnum::Max(s) => Ok(self.boost * s.score()?),
+            MultiTermScorerEnum::Constant(_) => Ok(self.boost),
+        }
+    }
+}
+
+impl<S: Scorer> DocIterator for MultiTermScorer<S> {
+    fn doc_id(&self) -> DocId {
+        match &self.scorer {
+            MultiTermScorerEnum::Boolean(s) => s.doc_id(),
+            MultiTermScorerEnum::Max(s) => s.doc_id(),
+            MultiTermScorerEnum::Constant(i) => i.doc_id(),
+        }
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        match &mut self.scorer {
+            MultiTermScorerEnum::Boolean(s) => s.next(),
+            MultiTermScorerEnum::Max(s) => s.next(),
+            MultiTermScorerEnum::Constant(i) => i.next(),
+        }
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        match &mut self.scorer {
+            MultiTermScorerEnum::Boolean(s) => s.advance(target),
+            MultiTermScorerEnum::Max(s) => s.advance(target),
+            MultiTermScorerEnum::Constant(i) => i.advance(target),
+        }
+    }
+
+    fn cost(&self) -> usize {
+        match &self.scorer {
+            MultiTermScorerEnum::Boolean(s) => s.cost(),
+            MultiTermScorerEnum::Max(s) => s.cost(),
+            MultiTermScorerEnum::Constant(i) => i.cost(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::tests::*;
+
+    /// the docs of the expanded terms, the mock scores are the doc ids
+    fn create_terms() -> Vec<Result<MockSimpleScorer<MockDocIterator>>> {
+        vec![
+            Ok(create_mock_scorer(vec![1, 3])),
+            Ok(create_mock_scorer(vec![3, 6])),
+            Ok(create_mock_scorer(vec![2])),
+        ]
+    }
+
+    fn create_scorer(
+        rewrite_method: RewriteMethod,
+        max_expansions: usize,
+    ) -> MultiTermScorer<MockSimpleScorer<MockDocIterator>> {
+        MultiTermScorer::new(create_terms(), rewrite_method, max_expansions, 8, 2.0)
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_score_boolean() {
+        let mut scorer = create_scorer(RewriteMethod::ScoreBoolean, 10);
+        assert_eq!(scorer.expanded_terms(), 3);
+        let hits: Vec<(DocId, f32)> = scorer.iter().map(Result::unwrap).collect();
+        // doc 3 is matched by the first two terms and sums both scores
+        assert_eq!(hits, vec![(1, 2.0), (2, 4.0), (3, 12.0), (6, 12.0)]);
+
+        let mut scorer = create_scorer(RewriteMethod::ScoreBoolean, 1);
+        assert_eq!(scorer.expanded_terms(), 1);
+        let hits: Vec<(DocId, f32)> = scorer.iter().map(Result::unwrap).collect();
+        assert_eq!(hits, vec![(1, 2.0), (3, 6.0)]);
+    }
+
+    #[test]
+    fn test_score_max() {
+        let mut scorer = create_scorer(RewriteMethod::ScoreMax, 10);
+        assert_eq!(scorer.expanded_terms(), 3);
+        let hits: Vec<(DocId, f32)> = scorer.iter().map(Result::unwrap).collect();
+        // doc 3 is matched by the first two terms and keeps the best score
+        assert_eq!(hits, vec![(1, 2.0), (2, 4.0), (3, 6.0), (6, 12.0)]);
+    }
+
+    #[test]
+    fn test_constant_score() {
+        let mut scorer = create_scorer(RewriteMethod::ConstantScore, 10);
+        assert_eq!(scorer.advance(2).unwrap(), 2);
+        let hits: Vec<(DocId, f32)> = scorer.iter().map(Result::unwrap).collect();
+        assert_eq!(hits, vec![(3, 2.0), (6, 2.0)]);
+
+        let mut scorer = create_scorer(RewriteMethod::ConstantScore, 2);
+        let docs: Vec<DocId> = scorer.iter().map(|h| h.unwrap().0).collect();
+        assert_eq!(docs, vec![1, 3, 6]);
+
+        let none = MultiTermScorer::new(create_terms(), RewriteMethod::ConstantScore, 0, 8, 1.0);
+        assert!(none.unwrap().is_none());
+        let empty: Vec<Result<MockSimpleScorer<MockDocIterator>>> = vec![];
+        let none = MultiTermScorer::new(empty, RewriteMethod::ScoreBoolean, 10, 8, 1.0);
+        assert!(none.unwrap().is_none());
+    }
+}
@@ -0,0 +1,144 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::search::scorer::Scorer;
+use core::search::{DocIterator, NO_MORE_DOCS};
+use core::util::DocId;
+use error::Result;
+
+/// A `Scorer` which matches all the docs in `[0, max_doc)` that are NOT
+/// matched by `child`, every matching doc gets the same constant `score`.
+///
+/// This is used for standalone negations, eg. a boolean query with only
+/// `MUST_NOT` clauses. Deleted docs are never matched by `child` so they
+/// are returned too, wrap this in a `LiveDocsFilterScorer` to skip them.
+pub struct NegationScorer<T: DocIterator> {
+    child: T,
+    max_doc: DocId,
+    score: f32,
+    doc: DocId,
+}
+
+impl<T: DocIterator> NegationScorer<T> {
+    pub fn new(child: T, max_doc: DocId, score: f32) -> Self {
+        NegationScorer {
+            child,
+            max_doc,
+            score,
+            doc: -1,
+        }
+    }
+}
+
+impl<T: DocIterator> Scorer for NegationScorer<T> {
+    fn score(&mut self) -> Result<f32> {
+        Ok(self.score)
+    }
+}
+
+impl<T: DocIterator> DocIterator for NegationScorer<T> {
+    fn doc_id(&self) -> DocId {
+        self.doc
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        if self.doc == NO_MORE_DOCS {
+            return Ok(NO_MORE_DOCS);
+        }
+        let target = self.doc + 1;
+        self.advance(target)
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        let mut target = target;
+        loop {
+            if target >= self.max_doc {
+                self.doc = NO_MORE_DOCS;
+                return Ok(NO_MORE_DOCS);
+            }
+            // once exhausted the child stays on NO_MORE_DOCS, which is
+            // greater than any target, so all remaining docs are gaps
+            if self.child.doc_id() < target {
+                self.child.advance(target)?;
+            }
+            if self.child.doc_id() != target {
+                self.doc = target;
+                return Ok(target);
+            }
+            target += 1;
+        }
+    }
+
+    fn cost(&self) -> usize {
+        self.max_doc as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::scorer::LiveDocsFilterScorer;
+    use core::search::tests::*;
+    use core::util::{BitSet, BitsRef, FixedBitSet};
+    use std::sync::Arc;
+
+    fn collect_docs<T: DocIterator>(scorer: &mut NegationScorer<T>) -> Vec<DocId> {
+        let mut docs = vec![];
+        loop {
+            let doc = scorer.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            assert!((scorer.score().unwrap() - 2.0).abs() < ::std::f32::EPSILON);
+            docs.push(doc);
+        }
+        docs
+    }
+
+    #[test]
+    fn test_complement() {
+        let child = create_mock_doc_iterator(vec![1, 3, 5]);
+        let mut scorer = NegationScorer::new(child, 6, 2.0);
+        assert_eq!(scorer.doc_id(), -1);
+        assert_eq!(collect_docs(&mut scorer), vec![0, 2, 4]);
+        assert_eq!(scorer.doc_id(), NO_MORE_DOCS);
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+    }
+
+    #[test]
+    fn test_child_exhausted() {
+        let child = create_mock_doc_iterator(vec![0, 1]);
+        let mut scorer = NegationScorer::new(child, 5, 2.0);
+        assert_eq!(collect_docs(&mut scorer), vec![2, 3, 4]);
+
+        let child = create_mock_doc_iterator(vec![]);
+        let mut scorer = NegationScorer::new(child, 3, 2.0);
+        assert_eq!(collect_docs(&mut scorer), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_advance_and_deleted_docs() {
+        let mut live_docs = FixedBitSet::new(8);
+        for doc in &[0, 1, 3, 4, 5, 7] {
+            live_docs.set(*doc);
+        }
+        let child = create_mock_doc_iterator(vec![1, 4]);
+        let live_docs: BitsRef = Arc::new(live_docs);
+        let mut scorer = LiveDocsFilterScorer::new(NegationScorer::new(child, 8, 2.0), live_docs);
+
+        assert_eq!(scorer.advance(1).unwrap(), 3);
+        assert_eq!(scorer.advance(4).unwrap(), 5);
+        assert_eq!(scorer.next().unwrap(), 7);
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+    }
+}
@@ -0,0 +1,220 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::search::scorer::{FeatureResult, ScoreMode, Scorer};
+use core::search::{DocIterator, Explanation, TwoPhaseIterator, NO_MORE_DOCS};
+use core::util::DocId;
+use error::{ErrorKind::IllegalArgument, Result};
+
+/// A `Scorer` which rescales the score of `child` into `[0, 1]` by dividing
+/// it by the expected `max_score`, eg. to blend the scores of heterogeneous
+/// queries. Scores above `max_score` are clamped to 1. The matched docs are
+/// those of `child`.
+pub struct NormalizingScorer<S: Scorer> {
+    child: S,
+    max_score: f32,
+}
+
+impl<S: Scorer> NormalizingScorer<S> {
+    /// Returns an error unless `max_score` is positive and finite.
+    pub fn new(child: S, max_score: f32) -> Result<Self> {
+        if !(max_score > 0.0 && max_score.is_finite()) {
+            bail!(IllegalArgument(format!(
+                "max_score must be positive and finite, got {}",
+                max_score
+            )));
+        }
+        Ok(NormalizingScorer { child, max_score })
+    }
+
+    /// Normalizes by the max score of all the docs of `child`, see
+    /// `Scorer::get_max_score`. Returns an error if `child` can't bound its
+    /// scores or they are all 0.
+    pub fn with_child_max_score(mut child: S) -> Result<Self> {
+        let max_score = child.get_max_score(NO_MORE_DOCS)?;
+        Self::new(child, max_score)
+    }
+
+    pub fn max_score(&self) -> f32 {
+        self.max_score
+    }
+
+    fn normalize(&self, score: f32) -> f32 {
+        (score / self.max_score).max(0.0).min(1.0)
+    }
+}
+
+impl<S: Scorer> Scorer for NormalizingScorer<S> {
+    fn score(&mut self) -> Result<f32> {
+        let score = self.child.score()?;
+        Ok(self.normalize(score))
+    }
+
+    fn score_feature(&mut self) -> Result<Vec<FeatureResult>> {
+        self.child.score_feature()
+    }
+
+    fn freq(&self) -> Result<f32> {
+        self.child.freq()
+    }
+
+    fn set_score_mode(&mut self, mode: ScoreMode) {
+        self.child.set_score_mode(mode)
+    }
+
+    fn advance_shallow(&mut self, target: DocId) -> Result<DocId> {
+        self.child.advance_shallow(target)
+    }
+
+    fn get_max_score(&mut self, up_to: DocId) -> Result<f32> {
+        let max_score = self.child.get_max_score(up_to)?;
+        Ok(self.normalize(max_score))
+    }
+
+    fn set_min_competitive_score(&mut self, min_score: f32) {
+        // the normalization is monotonic, up to the clamping at 1
+        self.child
+            .set_min_competitive_score(min_score.min(1.0) * self.max_score);
+    }
+
+    fn explain(&mut self, doc: DocId) -> Result<Explanation> {
+        let child = self.child.explain(doc)?;
+        if !child.is_match() {
+            return Ok(child);
+        }
+        let max_score = Explanation::new(true, self.max_score, "max_score".to_string(), vec![]);
+        Ok(Explanation::new(
+            true,
+            self.normalize(child.value()),
+            "min(1, score / max_score) of:".to_string(),
+            vec![child, max_score],
+        ))
+    }
+}
+
+impl<S: Scorer> DocIterator for NormalizingScorer<S> {
+    fn doc_id(&self) -> DocId {
+        self.child.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        self.child.next()
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        self.child.advance(target)
+    }
+
+    fn cost(&self) -> usize {
+        self.child.cost()
+    }
+
+    fn matches(&mut self) -> Result<bool> {
+        self.child.matches()
+    }
+
+    fn match_cost(&self) -> f32 {
+        self.child.match_cost()
+    }
+
+    fn support_two_phase(&self) -> bool {
+        self.child.support_two_phase()
+    }
+
+    fn approximate_next(&mut self) -> Result<DocId> {
+        self.child.approximate_next()
+    }
+
+    fn approximate_advance(&mut self, target: DocId) -> Result<DocId> {
+        self.child.approximate_advance(target)
+    }
+
+    fn as_two_phase(&mut self) -> Option<&mut dyn TwoPhaseIterator> {
+        if self.child.support_two_phase() {
+            Some(self)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::tests::*;
+
+    /// mock scorer scoring by doc id which knows its max score
+    struct BoundedScorer {
+        inner: MockSimpleScorer<MockDocIterator>,
+        max_score: f32,
+    }
+
+    impl Scorer for BoundedScorer {
+        fn score(&mut self) -> Result<f32> {
+            self.inner.score()
+        }
+
+        fn get_max_score(&mut self, _up_to: DocId) -> Result<f32> {
+            Ok(self.max_score)
+        }
+    }
+
+    impl DocIterator for BoundedScorer {
+        fn doc_id(&self) -> DocId {
+            self.inner.doc_id()
+        }
+
+        fn next(&mut self) -> Result<DocId> {
+            self.inner.next()
+        }
+
+        fn advance(&mut self, target: DocId) -> Result<DocId> {
+            self.inner.advance(target)
+        }
+
+        fn cost(&self) -> usize {
+            self.inner.cost()
+        }
+    }
+
+    #[test]
+    fn test_normalized_range() {
+        // the mock score is the doc id
+        let mut scorer = NormalizingScorer::new(create_mock_scorer(vec![0, 2, 5, 8]), 5.0).unwrap();
+        let hits: Vec<(DocId, f32)> = scorer.iter().map(|hit| hit.unwrap()).collect();
+        assert_eq!(hits, vec![(0, 0.0), (2, 0.4), (5, 1.0), (8, 1.0)]);
+        assert!(hits.iter().all(|h| h.1 >= 0.0 && h.1 <= 1.0));
+        assert_eq!(scorer.cost(), 4);
+
+        assert!(NormalizingScorer::new(create_mock_scorer(vec![1]), 0.0).is_err());
+        assert!(NormalizingScorer::new(create_mock_scorer(vec![1]), -1.0).is_err());
+        assert!(NormalizingScorer::new(create_mock_scorer(vec![1]), ::std::f32::NAN).is_err());
+    }
+
+    #[test]
+    fn test_with_child_max_score() {
+        let child = BoundedScorer {
+            inner: create_mock_scorer(vec![1, 3, 4]),
+            max_score: 4.0,
+        };
+        let mut scorer = NormalizingScorer::with_child_max_score(child).unwrap();
+        assert!((scorer.max_score() - 4.0).abs() < ::std::f32::EPSILON);
+        assert!((scorer.get_max_score(NO_MORE_DOCS).unwrap() - 1.0).abs() < ::std::f32::EPSILON);
+        assert_eq!(scorer.advance(2).unwrap(), 3);
+        assert!((scorer.score().unwrap() - 0.75).abs() < ::std::f32::EPSILON);
+
+        // the mock scorer has unbounded scores
+        let child = create_mock_scorer(vec![1]);
+        assert!(NormalizingScorer::with_child_max_score(child).is_err());
+    }
+}
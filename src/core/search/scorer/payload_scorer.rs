@@ -0,0 +1,276 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::codec::PostingIterator;
+use core::search::scorer::Scorer;
+use core::search::similarity::SimScorer;
+use core::search::DocIterator;
+use core::util::DocId;
+use error::Result;
+
+/// Decodes the payload bytes of a position into a weight, eg. a big endian
+/// `f32` written at index time.
+pub type PayloadDecoder = Box<dyn Fn(&[u8]) -> f32 + Send + Sync>;
+
+/// How the payload weights of the positions of a doc are folded into a
+/// single factor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PayloadFunction {
+    Min,
+    Max,
+    Average,
+}
+
+impl PayloadFunction {
+    fn fold(self, factors: &[f32]) -> f32 {
+        match self {
+            PayloadFunction::Min => factors.iter().cloned().fold(::std::f32::INFINITY, f32::min),
+            PayloadFunction::Max => factors
+                .iter()
+                .cloned()
+                .fold(::std::f32::NEG_INFINITY, f32::max),
+            PayloadFunction::Average => factors.iter().sum::<f32>() / factors.len() as f32,
+        }
+    }
+}
+
+/// A term `Scorer` which multiplies the similarity score of a doc by the
+/// payloads of its positions, like Lucene's `PayloadScoreQuery`.
+///
+/// The payload of each position is decoded by `decoder`, positions without a
+/// payload are skipped, and the weights are folded by `function`. A doc
+/// without any payload has a factor of 1.
+pub struct PayloadScorer<T: PostingIterator> {
+    sim_scorer: Box<dyn SimScorer>,
+    postings_iterator: T,
+    decoder: PayloadDecoder,
+    function: PayloadFunction,
+    // payloads can only be read once per doc, so the factor is cached for `factor_doc`
+    factor: f32,
+    factor_doc: DocId,
+}
+
+impl<T: PostingIterator> PayloadScorer<T> {
+    pub fn new(
+        sim_scorer: Box<dyn SimScorer>,
+        postings_iterator: T,
+        decoder: PayloadDecoder,
+        function: PayloadFunction,
+    ) -> Self {
+        PayloadScorer {
+            sim_scorer,
+            postings_iterator,
+            decoder,
+            function,
+            factor: 1.0,
+            factor_doc: -1,
+        }
+    }
+
+    /// Returns the folded payload weight of the current doc.
+    pub fn payload_factor(&mut self) -> Result<f32> {
+        let doc = self.doc_id();
+        if doc != self.factor_doc {
+            let mut factors = vec![];
+            for _ in 0..self.postings_iterator.freq()? {
+                self.postings_iterator.next_position()?;
+                let payload = self.postings_iterator.payload()?;
+                if !payload.is_empty() {
+                    factors.push((self.decoder)(&payload));
+                }
+            }
+            self.factor = if factors.is_empty() {
+                1.0
+            } else {
+                self.function.fold(&factors)
+            };
+            self.factor_doc = doc;
+        }
+        Ok(self.factor)
+    }
+}
+
+impl<T: PostingIterator> Scorer for PayloadScorer<T> {
+    fn score(&mut self) -> Result<f32> {
+        let doc = self.doc_id();
+        let freq = self.postings_iterator.freq()?;
+        let score = self.sim_scorer.score(doc, freq as f32)?;
+        Ok(score * self.payload_factor()?)
+    }
+
+    fn freq(&self) -> Result<f32> {
+        Ok(self.postings_iterator.freq()? as f32)
+    }
+}
+
+impl<T: PostingIterator> DocIterator for PayloadScorer<T> {
+    fn doc_id(&self) -> DocId {
+        self.postings_iterator.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        self.postings_iterator.next()
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        self.postings_iterator.advance(target)
+    }
+
+    fn cost(&self) -> usize {
+        self.postings_iterator.cost()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::tests::*;
+    use core::search::{Payload, NO_MORE_DOCS};
+    use std::collections::HashMap;
+
+    /// postings with the payloads of some `(doc, position)`
+    struct MockPayloadPostings {
+        postings: MockPostingIterator,
+        payloads: HashMap<(DocId, i32), f32>,
+        position: i32,
+    }
+
+    impl MockPayloadPostings {
+        fn new(postings: Vec<(DocId, Vec<i32>)>, payloads: &[(DocId, i32, f32)]) -> Self {
+            MockPayloadPostings {
+                postings: MockPostingIterator::new(postings),
+                payloads: payloads.iter().map(|&(d, p, w)| ((d, p), w)).collect(),
+                position: -1,
+            }
+        }
+    }
+
+    impl DocIterator for MockPayloadPostings {
+        fn doc_id(&self) -> DocId {
+            self.postings.doc_id()
+        }
+
+        fn next(&mut self) -> Result<DocId> {
+            self.postings.next()
+        }
+
+        fn advance(&mut self, target: DocId) -> Result<DocId> {
+            self.postings.advance(target)
+        }
+
+        fn cost(&self) -> usize {
+            self.postings.cost()
+        }
+    }
+
+    impl PostingIterator for MockPayloadPostings {
+        fn freq(&self) -> Result<i32> {
+            self.postings.freq()
+        }
+
+        fn next_position(&mut self) -> Result<i32> {
+            self.position = self.postings.next_position()?;
+            Ok(self.position)
+        }
+
+        fn start_offset(&self) -> Result<i32> {
+            Ok(-1)
+        }
+
+        fn end_offset(&self) -> Result<i32> {
+            Ok(-1)
+        }
+
+        fn payload(&self) -> Result<Payload> {
+            let key = (self.doc_id(), self.position);
+            Ok(self
+                .payloads
+                .get(&key)
+                .map(|w| w.to_bits().to_be_bytes().to_vec())
+                .unwrap_or_default())
+        }
+    }
+
+    fn decode_f32() -> PayloadDecoder {
+        Box::new(|bytes| {
+            let mut buf = [0u8; 4];
+            buf.copy_from_slice(&bytes[..4]);
+            f32::from_bits(u32::from_be_bytes(buf))
+        })
+    }
+
+    fn create_scorer(function: PayloadFunction) -> PayloadScorer<MockPayloadPostings> {
+        let postings = MockPayloadPostings::new(
+            vec![(1, vec![0, 3, 7]), (4, vec![2]), (6, vec![1, 5])],
+            &[
+                (1, 0, 0.5),
+                (1, 3, 2.0),
+                (1, 7, 1.0),
+                (4, 2, 3.0),
+                (6, 5, 0.25),
+            ],
+        );
+        PayloadScorer::new(Box::new(MockSimScorer), postings, decode_f32(), function)
+    }
+
+    /// (doc, score) of all the docs, the sim score is the freq
+    fn collect(scorer: &mut PayloadScorer<MockPayloadPostings>) -> Vec<(DocId, f32)> {
+        let mut result = vec![];
+        loop {
+            let doc = scorer.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            result.push((doc, scorer.score().unwrap()));
+        }
+        result
+    }
+
+    #[test]
+    fn test_payload_functions() {
+        let cases = [
+            (PayloadFunction::Min, [1.5, 3.0, 0.5]),
+            (PayloadFunction::Max, [6.0, 3.0, 0.5]),
+            (PayloadFunction::Average, [3.5, 3.0, 0.5]),
+        ];
+        for &(function, expected) in &cases {
+            let mut scorer = create_scorer(function);
+            let result = collect(&mut scorer);
+            assert_eq!(result.len(), 3);
+            for (&(_, score), &expected) in result.iter().zip(expected.iter()) {
+                assert!((score - expected).abs() < ::std::f32::EPSILON);
+            }
+        }
+    }
+
+    #[test]
+    fn test_payload_read_once() {
+        let mut scorer = create_scorer(PayloadFunction::Max);
+        assert_eq!(scorer.advance(2).unwrap(), 4);
+        // scoring again doesn't read the positions past the freq
+        assert!((scorer.score().unwrap() - 3.0).abs() < ::std::f32::EPSILON);
+        assert!((scorer.score().unwrap() - 3.0).abs() < ::std::f32::EPSILON);
+        assert!((scorer.payload_factor().unwrap() - 3.0).abs() < ::std::f32::EPSILON);
+
+        // without any payload the factor is 1
+        let postings = MockPayloadPostings::new(vec![(3, vec![1, 2])], &[]);
+        let mut scorer = PayloadScorer::new(
+            Box::new(MockSimScorer),
+            postings,
+            decode_f32(),
+            PayloadFunction::Min,
+        );
+        assert_eq!(scorer.next().unwrap(), 3);
+        assert!((scorer.score().unwrap() - 2.0).abs() < ::std::f32::EPSILON);
+    }
+}
@@ -19,13 +19,13 @@ use std::rc::Rc;
 
 use core::codec::PostingIterator;
 use core::doc::Term;
-use core::search::scorer::{two_phase_next, ConjunctionScorer, Scorer};
+use core::search::scorer::{two_phase_next, ConjunctionDocIterator, Scorer};
 use core::search::similarity::SimScorer;
-use core::search::{DocIterator, NO_MORE_DOCS};
+use core::search::{DocIterator, TwoPhaseIterator, NO_MORE_DOCS};
 use core::util::{BitSet, Bits, DocId, FixedBitSet, ImmutableBitSet};
 use error::Result;
 
-// a fake scorer struct used for `ConjunctionScorer`
+// the shared postings of a term, as a doc iterator to intersect the terms
 pub struct PostingsIterAsScorer<T: PostingIterator> {
     pub iterator: Rc<RefCell<T>>,
 }
@@ -125,7 +125,7 @@ pub struct ExactPhraseScorer<T: PostingIterator> {
     match_cost: f32,
     postings: Vec<PostingsAndPosition<T>>,
     doc_scorer: Box<dyn SimScorer>,
-    conjunction: ConjunctionScorer<PostingsIterAsScorer<T>>,
+    conjunction: ConjunctionDocIterator<PostingsIterAsScorer<T>>,
 }
 
 impl<T: PostingIterator> ExactPhraseScorer<T> {
@@ -147,7 +147,7 @@ impl<T: PostingIterator> ExactPhraseScorer<T> {
             postings_and_positions.push(PostingsAndPosition::new(iterator.clone(), posting.pos));
         }
 
-        let conjunction = ConjunctionScorer::new(iterators);
+        let conjunction = ConjunctionDocIterator::new(iterators);
 
         ExactPhraseScorer {
             freq: 0,
@@ -227,19 +227,6 @@ impl<T: PostingIterator> ExactPhraseScorer<T> {
 
         Ok(self.freq)
     }
-
-    pub fn do_next(&mut self, doc_id: DocId) -> Result<DocId> {
-        let mut doc = doc_id;
-        loop {
-            if doc == NO_MORE_DOCS {
-                return Ok(NO_MORE_DOCS);
-            } else if self.matches()? {
-                return Ok(doc);
-            }
-
-            doc = self.conjunction.next()?;
-        }
-    }
 }
 
 impl<T: PostingIterator> Scorer for ExactPhraseScorer<T> {
@@ -248,6 +235,10 @@ impl<T: PostingIterator> Scorer for ExactPhraseScorer<T> {
         let freq = self.freq as f32;
         self.doc_scorer.score(doc_id, freq)
     }
+
+    fn freq(&self) -> Result<f32> {
+        Ok(self.freq as f32)
+    }
 }
 
 unsafe impl<T: PostingIterator> Send for ExactPhraseScorer<T> {}
@@ -258,13 +249,13 @@ impl<T: PostingIterator> DocIterator for ExactPhraseScorer<T> {
     }
 
     fn next(&mut self) -> Result<DocId> {
-        let doc_id = self.conjunction.next()?;
-        self.do_next(doc_id)
+        self.approximate_next()?;
+        two_phase_next(self)
     }
 
     fn advance(&mut self, target: DocId) -> Result<DocId> {
-        let doc_id = self.conjunction.advance(target)?;
-        self.do_next(doc_id)
+        self.approximate_advance(target)?;
+        two_phase_next(self)
     }
 
     fn cost(&self) -> usize {
@@ -276,20 +267,25 @@ impl<T: PostingIterator> DocIterator for ExactPhraseScorer<T> {
     }
 
     fn match_cost(&self) -> f32 {
-        println!("SELF.MATCH_COST {}", self.match_cost);
         self.match_cost
     }
 
-    /// advance to the next approximate match doc
+    fn support_two_phase(&self) -> bool {
+        true
+    }
+
+    /// advance to the next doc containing all the terms
     fn approximate_next(&mut self) -> Result<DocId> {
-        let doc_id = self.conjunction.next()?;
-        self.do_next(doc_id)
+        self.conjunction.next()
     }
 
-    /// Advances to the first approximate doc beyond the current doc
+    /// advance to the first doc from `target` on containing all the terms
     fn approximate_advance(&mut self, target: DocId) -> Result<DocId> {
-        let doc_id = self.conjunction.advance(target)?;
-        self.do_next(doc_id)
+        self.conjunction.advance(target)
+    }
+
+    fn as_two_phase(&mut self) -> Option<&mut dyn TwoPhaseIterator> {
+        Some(self)
     }
 }
 
@@ -440,7 +436,7 @@ impl<T: PostingIterator> PartialEq for PPElement<T> {
 impl<T: PostingIterator> Eq for PPElement<T> {}
 
 pub struct SloppyPhraseScorer<T: PostingIterator> {
-    conjunction: ConjunctionScorer<PostingsIterAsScorer<T>>,
+    conjunction: ConjunctionDocIterator<PostingsIterAsScorer<T>>,
     // a conjunction doc id set iterator
     phrase_positions: Vec<PhrasePositions<T>>,
     sloppy_freq: f32,
@@ -466,6 +462,8 @@ pub struct SloppyPhraseScorer<T: PostingIterator> {
     num_matches: i32,
     needs_scores: bool,
     match_cost: f32,
+    // score each match by `1 / (1 + distance)` instead of the similarity's slop factor
+    distance_weighted: bool,
 }
 
 impl<T: PostingIterator> SloppyPhraseScorer<T> {
@@ -491,7 +489,7 @@ impl<T: PostingIterator> SloppyPhraseScorer<T> {
                 iterator: iterator.clone(),
             });
         }
-        let conjunction = ConjunctionScorer::new(doc_iterators);
+        let conjunction = ConjunctionDocIterator::new(doc_iterators);
 
         let pq = BinaryHeap::with_capacity(num_postings);
         SloppyPhraseScorer {
@@ -511,6 +509,7 @@ impl<T: PostingIterator> SloppyPhraseScorer<T> {
             num_matches: 0,
             needs_scores,
             match_cost,
+            distance_weighted: false,
         }
     }
 
@@ -518,6 +517,21 @@ impl<T: PostingIterator> SloppyPhraseScorer<T> {
         self.sloppy_freq
     }
 
+    /// When enabled, each match within slop contributes `1.0 / (1.0 + distance)`
+    /// to the sloppy freq instead of `SimScorer::compute_slop_factor`, so closer
+    /// matches always score higher regardless of the similarity in use.
+    pub fn set_distance_weighted(&mut self, distance_weighted: bool) {
+        self.distance_weighted = distance_weighted;
+    }
+
+    fn slop_factor(&self, match_length: i32) -> f32 {
+        if self.distance_weighted {
+            1.0 / (1.0 + match_length as f32)
+        } else {
+            self.doc_scorer.compute_slop_factor(match_length)
+        }
+    }
+
     /// Score a candidate doc for all slop-valid position-combinations (matches)
     /// encountered while traversing/hopping the PhrasePositions.
     /// <br> The score contribution of a match depends on the distance:
@@ -551,7 +565,7 @@ impl<T: PostingIterator> SloppyPhraseScorer<T> {
             if self.phrase_positions[pp_idx].position > next {
                 // done minimizing current match-length
                 if match_length <= self.slop as i32 {
-                    freq += self.doc_scorer.compute_slop_factor(match_length);
+                    freq += self.slop_factor(match_length);
                     self.num_matches += 1;
                     if !self.needs_scores {
                         return Ok(freq);
@@ -568,7 +582,7 @@ impl<T: PostingIterator> SloppyPhraseScorer<T> {
             }
         }
         if match_length <= self.slop {
-            freq += self.doc_scorer.compute_slop_factor(match_length); // score match
+            freq += self.slop_factor(match_length); // score match
             self.num_matches += 1;
         }
         Ok(freq)
@@ -1026,6 +1040,10 @@ impl<T: PostingIterator> Scorer for SloppyPhraseScorer<T> {
         let doc_id = self.doc_id();
         self.doc_scorer.score(doc_id, self.sloppy_freq)
     }
+
+    fn freq(&self) -> Result<f32> {
+        Ok(self.sloppy_freq)
+    }
 }
 
 impl<T: PostingIterator> DocIterator for SloppyPhraseScorer<T> {
@@ -1068,4 +1086,126 @@ impl<T: PostingIterator> DocIterator for SloppyPhraseScorer<T> {
     fn approximate_advance(&mut self, target: i32) -> Result<i32> {
         self.conjunction.advance(target)
     }
+
+    fn as_two_phase(&mut self) -> Option<&mut dyn TwoPhaseIterator> {
+        Some(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::tests::{MemoryPostingsScorer, MockPostingIterator};
+
+    /// scores by the sloppy freq, every match counts the same
+    struct FlatSimScorer;
+
+    impl SimScorer for FlatSimScorer {
+        fn score(&mut self, _doc: DocId, freq: f32) -> Result<f32> {
+            Ok(freq)
+        }
+
+        fn compute_slop_factor(&self, _distance: i32) -> f32 {
+            1.0
+        }
+    }
+
+    fn sloppy_scores(distance_weighted: bool) -> Vec<(DocId, f32)> {
+        let term_a = Term::new("body".to_string(), b"a".to_vec());
+        let term_b = Term::new("body".to_string(), b"b".to_vec());
+        // doc 0: "a b", doc 1: "a _ b", doc 2: "a _ _ b", doc 3: "a _ _ _ b"
+        let a =
+            MockPostingIterator::new(vec![(0, vec![0]), (1, vec![0]), (2, vec![0]), (3, vec![0])]);
+        let b =
+            MockPostingIterator::new(vec![(0, vec![1]), (1, vec![2]), (2, vec![3]), (3, vec![4])]);
+        let postings = vec![
+            PostingsAndFreq::new(a, 0, &term_a),
+            PostingsAndFreq::new(b, 1, &term_b),
+        ];
+        let mut scorer = SloppyPhraseScorer::new(postings, 2, Box::new(FlatSimScorer), true, 1.0);
+        scorer.set_distance_weighted(distance_weighted);
+
+        let mut result = vec![];
+        loop {
+            let doc = scorer.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            result.push((doc, scorer.score().unwrap()));
+        }
+        result
+    }
+
+    #[test]
+    fn test_sloppy_phrase_flat_slop_factor() {
+        let scores = sloppy_scores(false);
+        // doc 3 is out of slop
+        assert_eq!(
+            scores.iter().map(|s| s.0).collect::<Vec<_>>(),
+            vec![0, 1, 2]
+        );
+        for s in &scores {
+            assert!((s.1 - 1.0).abs() < ::std::f32::EPSILON);
+        }
+    }
+
+    #[test]
+    fn test_sloppy_phrase_distance_weighted() {
+        let scores = sloppy_scores(true);
+        assert_eq!(
+            scores.iter().map(|s| s.0).collect::<Vec<_>>(),
+            vec![0, 1, 2]
+        );
+        assert!((scores[0].1 - 1.0).abs() < ::std::f32::EPSILON);
+        assert!((scores[1].1 - 0.5).abs() < ::std::f32::EPSILON);
+        assert!((scores[2].1 - 1.0 / 3.0).abs() < ::std::f32::EPSILON);
+        // the tighter phrase outscores a looser one within the same slop
+        assert!(scores[0].1 > scores[1].1 && scores[1].1 > scores[2].1);
+    }
+
+    #[test]
+    fn test_exact_phrase_on_memory_postings() {
+        let term_a = Term::new("body".to_string(), b"a".to_vec());
+        let term_b = Term::new("body".to_string(), b"b".to_vec());
+        // doc 0: "a b _ _ _ a b", doc 1: "_ _ _ a _ b", doc 2: "_ a b", doc 3: "b"
+        let new_scorer = || {
+            let a = MemoryPostingsScorer::new(vec![
+                (0, 2.0, vec![0, 5]),
+                (1, 1.0, vec![3]),
+                (2, 1.0, vec![1]),
+            ]);
+            let b = MemoryPostingsScorer::new(vec![
+                (0, 2.0, vec![1, 6]),
+                (1, 1.0, vec![5]),
+                (2, 1.0, vec![2]),
+                (3, 1.0, vec![0]),
+            ]);
+            let postings = vec![
+                PostingsAndFreq::new(a, 0, &term_a),
+                PostingsAndFreq::new(b, 1, &term_b),
+            ];
+            ExactPhraseScorer::new(postings, Box::new(FlatSimScorer), true, 1.0)
+        };
+
+        // the approximation is the conjunction of the terms
+        let mut scorer = new_scorer();
+        {
+            let two_phase = scorer.as_two_phase().unwrap();
+            assert_eq!(two_phase.next_approximation().unwrap(), 0);
+            assert!(two_phase.confirm_match().unwrap());
+            assert_eq!(two_phase.next_approximation().unwrap(), 1);
+            assert!(!two_phase.confirm_match().unwrap());
+            assert_eq!(two_phase.advance_approximation(2).unwrap(), 2);
+            assert!(two_phase.confirm_match().unwrap());
+        }
+
+        let mut scorer = new_scorer();
+        assert_eq!(scorer.next().unwrap(), 0);
+        assert_eq!(scorer.freq(), 2);
+        assert!((scorer.score().unwrap() - 2.0).abs() < ::std::f32::EPSILON);
+        // doc 1 has both terms but not as a phrase
+        assert_eq!(scorer.next().unwrap(), 2);
+        assert_eq!(scorer.freq(), 1);
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+    }
 }
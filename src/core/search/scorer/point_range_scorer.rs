@@ -0,0 +1,214 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::search::scorer::Scorer;
+use core::search::{DocIdSet, DocIterator};
+use core::util::{DocId, DocIdSetBuilder, DocIdSetDocIterEnum};
+use error::{ErrorKind::IllegalArgument, Result};
+
+/// A packed structure of 1D `i64` points, eg. `(value, doc)` pairs sorted by
+/// value, so that the points within a range are found by binary searching
+/// its bounds instead of visiting the value of every doc.
+///
+/// A doc may have several points.
+pub struct PackedPoints {
+    values: Vec<i64>,
+    docs: Vec<DocId>,
+}
+
+impl PackedPoints {
+    /// Builds the points from `(value, doc)` pairs sorted by value, returns an
+    /// error if they are not sorted.
+    pub fn from_sorted(points: &[(i64, DocId)]) -> Result<PackedPoints> {
+        if points.windows(2).any(|w| w[0].0 > w[1].0) {
+            bail!(IllegalArgument("points must be sorted by value".into()));
+        }
+        Ok(PackedPoints {
+            values: points.iter().map(|p| p.0).collect(),
+            docs: points.iter().map(|p| p.1).collect(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the docs of the points in `[lower, upper]`, in value order.
+    pub fn docs_in_range(&self, lower: i64, upper: i64) -> &[DocId] {
+        let from = self.values.partition_point(|&v| v < lower);
+        let to = self.values.partition_point(|&v| v <= upper);
+        if from < to {
+            &self.docs[from..to]
+        } else {
+            &[]
+        }
+    }
+}
+
+/// A constant score `Scorer` which matches the docs having a point within
+/// `[lower, upper]` (both inclusive) of a `PackedPoints`.
+pub struct PointRangeScorer {
+    iterator: DocIdSetDocIterEnum,
+    score: f32,
+}
+
+impl PointRangeScorer {
+    /// Returns `None` if no point is within the range.
+    pub fn new(
+        points: &PackedPoints,
+        lower: i64,
+        upper: i64,
+        max_doc: DocId,
+        score: f32,
+    ) -> Result<Option<PointRangeScorer>> {
+        let docs = points.docs_in_range(lower, upper);
+        if docs.is_empty() {
+            return Ok(None);
+        }
+
+        let mut builder = DocIdSetBuilder::with_max_doc(max_doc);
+        builder.grow(docs.len());
+        for &doc in docs {
+            builder.add_doc(doc);
+        }
+        Ok(builder
+            .build()
+            .iterator()?
+            .map(|iterator| PointRangeScorer { iterator, score }))
+    }
+}
+
+impl Scorer for PointRangeScorer {
+    fn score(&mut self) -> Result<f32> {
+        Ok(self.score)
+    }
+
+    fn get_max_score(&mut self, _up_to: DocId) -> Result<f32> {
+        Ok(self.score)
+    }
+}
+
+impl DocIterator for PointRangeScorer {
+    fn doc_id(&self) -> DocId {
+        self.iterator.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        self.iterator.next()
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        self.iterator.advance(target)
+    }
+
+    fn cost(&self) -> usize {
+        self.iterator.cost()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::NO_MORE_DOCS;
+    use std::collections::BTreeSet;
+
+    const MAX_DOC: DocId = 4000;
+
+    /// 10k pseudo random points over 4k docs, some docs have several points
+    fn create_points() -> Vec<(i64, DocId)> {
+        let mut seed = 42u64;
+        let mut random = || {
+            seed = seed
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            seed >> 33
+        };
+        let mut points: Vec<(i64, DocId)> = (0..10_000)
+            .map(|_| {
+                let value = (random() % 100_000) as i64 - 50_000;
+                (value, (random() % MAX_DOC as u64) as DocId)
+            })
+            .collect();
+        points.sort();
+        points
+    }
+
+    fn collect_docs(scorer: &mut PointRangeScorer) -> Vec<DocId> {
+        let mut docs = vec![];
+        loop {
+            let doc = scorer.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            docs.push(doc);
+        }
+        docs
+    }
+
+    #[test]
+    fn test_same_as_brute_force() {
+        let points = create_points();
+        let packed = PackedPoints::from_sorted(&points).unwrap();
+        assert_eq!(packed.len(), 10_000);
+
+        let ranges = [
+            (-50_000, 50_000),
+            (-100, 100),
+            (0, 0),
+            (12_345, 23_456),
+            (-60_000, -49_990),
+            (49_990, 60_000),
+            (points[77].0, points[77].0),
+        ];
+        for &(lower, upper) in &ranges {
+            let expected: BTreeSet<DocId> = points
+                .iter()
+                .filter(|p| p.0 >= lower && p.0 <= upper)
+                .map(|p| p.1)
+                .collect();
+            let scorer = PointRangeScorer::new(&packed, lower, upper, MAX_DOC, 1.5).unwrap();
+            if expected.is_empty() {
+                assert!(scorer.is_none());
+                continue;
+            }
+            let mut scorer = scorer.unwrap();
+            let actual = collect_docs(&mut scorer);
+            assert_eq!(actual, expected.into_iter().collect::<Vec<_>>());
+            assert!((scorer.score().unwrap() - 1.5).abs() < ::std::f32::EPSILON);
+        }
+    }
+
+    #[test]
+    fn test_empty_range() {
+        let packed = PackedPoints::from_sorted(&[(1, 3), (5, 0), (5, 2), (9, 1)]).unwrap();
+        assert_eq!(packed.docs_in_range(5, 5), &[0, 2]);
+        assert!(packed.docs_in_range(2, 4).is_empty());
+        assert!(packed.docs_in_range(9, 1).is_empty());
+        assert!(PointRangeScorer::new(&packed, 10, 20, 4, 1.0)
+            .unwrap()
+            .is_none());
+
+        let mut scorer = PointRangeScorer::new(&packed, 0, 5, 4, 1.0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(scorer.advance(1).unwrap(), 2);
+        assert_eq!(scorer.next().unwrap(), 3);
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+
+        assert!(PackedPoints::from_sorted(&[(2, 0), (1, 1)]).is_err());
+    }
+}
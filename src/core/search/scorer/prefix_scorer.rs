@@ -0,0 +1,203 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::codec::{PostingIterator, PostingIteratorFlags, SeekStatus, TermIterator};
+use core::search::scorer::{ConstantScoreScorer, MultiTermScorer, RewriteMethod, Scorer};
+use core::search::DocIterator;
+use core::util::DocId;
+use error::Result;
+
+/// A constant score `Scorer` which matches the union of the postings of all
+/// the terms starting with a prefix, eg. the counterpart of Lucene's constant
+/// score rewrite of `PrefixQuery`.
+///
+/// At most `max_expansions` terms are expanded, in term order, the remaining
+/// terms are ignored. An empty prefix matches every term.
+pub struct PrefixScorer<P: PostingIterator> {
+    scorer: MultiTermScorer<ConstantScoreScorer<P>>,
+    score: f32,
+}
+
+impl<P: PostingIterator> PrefixScorer<P> {
+    /// Seeks `terms` to `prefix` and collects the docs of the matching terms.
+    /// Returns `None` if no doc matches.
+    pub fn new<T: TermIterator<Postings = P>>(
+        terms: &mut T,
+        prefix: &[u8],
+        max_expansions: usize,
+        max_doc: DocId,
+        score: f32,
+    ) -> Result<Option<PrefixScorer<P>>> {
+        let prefix_terms = PrefixTerms {
+            done: terms.seek_ceil(prefix)? == SeekStatus::End,
+            terms,
+            prefix,
+            score,
+            started: false,
+        };
+        let scorer = MultiTermScorer::new(
+            prefix_terms,
+            RewriteMethod::ConstantScore,
+            max_expansions,
+            max_doc,
+            score,
+        )?;
+        Ok(scorer.map(|scorer| PrefixScorer { scorer, score }))
+    }
+
+    /// Returns the number of terms whose postings are matched.
+    pub fn expanded_terms(&self) -> usize {
+        self.scorer.expanded_terms()
+    }
+}
+
+/// The scorers of the postings of the terms starting with `prefix`, from the
+/// term `terms` is positioned on. The next term is only seeked to once the
+/// postings of the current one are consumed.
+struct PrefixTerms<'a, T: TermIterator> {
+    terms: &'a mut T,
+    prefix: &'a [u8],
+    score: f32,
+    started: bool,
+    done: bool,
+}
+
+impl<'a, T: TermIterator> PrefixTerms<'a, T> {
+    fn next_term(&mut self) -> Result<Option<ConstantScoreScorer<T::Postings>>> {
+        if self.started && self.terms.next()?.is_none() {
+            return Ok(None);
+        }
+        self.started = true;
+        if !self.terms.term()?.starts_with(self.prefix) {
+            return Ok(None);
+        }
+        let postings = self.terms.postings_with_flags(PostingIteratorFlags::NONE)?;
+        let cost = postings.cost();
+        Ok(Some(ConstantScoreScorer::new(self.score, postings, cost)))
+    }
+}
+
+impl<'a, T: TermIterator> Iterator for PrefixTerms<'a, T> {
+    type Item = Result<ConstantScoreScorer<T::Postings>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let term = self.next_term();
+        self.done = !matches!(term, Ok(Some(_)));
+        term.transpose()
+    }
+}
+
+impl<P: PostingIterator> Scorer for PrefixScorer<P> {
+    fn score(&mut self) -> Result<f32> {
+        self.scorer.score()
+    }
+
+    fn get_max_score(&mut self, _up_to: DocId) -> Result<f32> {
+        Ok(self.score)
+    }
+}
+
+impl<P: PostingIterator> DocIterator for PrefixScorer<P> {
+    fn doc_id(&self) -> DocId {
+        self.scorer.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        self.scorer.next()
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        self.scorer.advance(target)
+    }
+
+    fn cost(&self) -> usize {
+        self.scorer.cost()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::tests::*;
+    use core::search::NO_MORE_DOCS;
+
+    fn create_terms() -> MockTermIterator {
+        MockTermIterator::new(vec![
+            ("bar", vec![(0, vec![0]), (7, vec![0])]),
+            ("fo", vec![(1, vec![0])]),
+            ("foo", vec![(2, vec![0]), (5, vec![1])]),
+            ("foobar", vec![(3, vec![0]), (5, vec![0])]),
+            ("food", vec![(4, vec![0])]),
+            ("fop", vec![(6, vec![0])]),
+        ])
+    }
+
+    fn collect_docs(scorer: &mut PrefixScorer<MockPostingIterator>) -> Vec<DocId> {
+        let mut docs = vec![];
+        loop {
+            let doc = scorer.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            assert!((scorer.score().unwrap() - 1.0).abs() < ::std::f32::EPSILON);
+            docs.push(doc);
+        }
+        docs
+    }
+
+    #[test]
+    fn test_prefix() {
+        let mut terms = create_terms();
+        let mut scorer = PrefixScorer::new(&mut terms, b"foo", 10, 8, 1.0)
+            .unwrap()
+            .unwrap();
+        // "foo", "foobar" and "food", doc 5 matches twice but is returned once
+        assert_eq!(scorer.expanded_terms(), 3);
+        assert_eq!(collect_docs(&mut scorer), vec![2, 3, 4, 5]);
+
+        let mut terms = create_terms();
+        let scorer = PrefixScorer::new(&mut terms, b"fooz", 10, 8, 1.0).unwrap();
+        assert!(scorer.is_none());
+        let mut terms = create_terms();
+        let scorer = PrefixScorer::new(&mut terms, b"zzz", 10, 8, 1.0).unwrap();
+        assert!(scorer.is_none());
+    }
+
+    #[test]
+    fn test_max_expansions() {
+        let mut terms = create_terms();
+        let mut scorer = PrefixScorer::new(&mut terms, b"foo", 2, 8, 1.0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(scorer.expanded_terms(), 2);
+        assert_eq!(collect_docs(&mut scorer), vec![2, 3, 5]);
+
+        // empty prefix matches the first terms only
+        let mut terms = create_terms();
+        let mut scorer = PrefixScorer::new(&mut terms, b"", 2, 8, 1.0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(scorer.expanded_terms(), 2);
+        assert_eq!(collect_docs(&mut scorer), vec![0, 1, 7]);
+
+        let mut terms = create_terms();
+        let mut scorer = PrefixScorer::new(&mut terms, b"", 100, 8, 1.0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(scorer.expanded_terms(), 6);
+        assert_eq!(collect_docs(&mut scorer), (0..8).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,157 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::search::scorer::Scorer;
+use core::search::{DocIterator, NO_MORE_DOCS};
+use core::util::DocId;
+use error::Result;
+
+/// A Scorer for queries with a required subscorer and an excluding (prohibited)
+/// `DocIterator`.
+///
+/// This implements the `MUST_NOT` part of a boolean query: documents matched by
+/// `excl` are skipped, all other documents of `req` are returned and scored purely
+/// by `req`.
+pub struct ReqExclScorer<R: Scorer, E: DocIterator> {
+    req: R,
+    excl: E,
+}
+
+impl<R: Scorer, E: DocIterator> ReqExclScorer<R, E> {
+    pub fn new(req: R, excl: E) -> Self {
+        ReqExclScorer { req, excl }
+    }
+
+    /// Returns `doc` if it is not excluded, else keep moving `req` forward
+    /// until a non excluded doc is found. Once `excl` is exhausted every
+    /// remaining doc of `req` is accepted.
+    fn next_non_excluded(&mut self, mut doc: DocId, approximate: bool) -> Result<DocId> {
+        loop {
+            if doc == NO_MORE_DOCS {
+                return Ok(NO_MORE_DOCS);
+            }
+
+            let mut excl_doc = self.excl.doc_id();
+            if excl_doc < doc {
+                excl_doc = self.excl.advance(doc)?;
+            }
+            if excl_doc != doc {
+                return Ok(doc);
+            }
+
+            doc = if approximate {
+                self.req.approximate_next()?
+            } else {
+                self.req.next()?
+            };
+        }
+    }
+}
+
+impl<R: Scorer, E: DocIterator> Scorer for ReqExclScorer<R, E> {
+    fn score(&mut self) -> Result<f32> {
+        self.req.score()
+    }
+}
+
+impl<R: Scorer, E: DocIterator> DocIterator for ReqExclScorer<R, E> {
+    fn doc_id(&self) -> DocId {
+        self.req.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        let doc = self.req.next()?;
+        self.next_non_excluded(doc, false)
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        let doc = self.req.advance(target)?;
+        self.next_non_excluded(doc, false)
+    }
+
+    fn cost(&self) -> usize {
+        self.req.cost()
+    }
+
+    fn matches(&mut self) -> Result<bool> {
+        self.req.matches()
+    }
+
+    fn match_cost(&self) -> f32 {
+        self.req.match_cost()
+    }
+
+    fn support_two_phase(&self) -> bool {
+        self.req.support_two_phase()
+    }
+
+    fn approximate_next(&mut self) -> Result<DocId> {
+        let doc = self.req.approximate_next()?;
+        self.next_non_excluded(doc, true)
+    }
+
+    fn approximate_advance(&mut self, target: DocId) -> Result<DocId> {
+        let doc = self.req.approximate_advance(target)?;
+        self.next_non_excluded(doc, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::tests::*;
+
+    #[test]
+    fn test_next() {
+        let req = create_mock_scorer(vec![1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        let excl = create_mock_doc_iterator(vec![2, 4, 5, 9]);
+        let mut scorer = ReqExclScorer::new(req, excl);
+
+        assert_eq!(scorer.doc_id(), -1);
+
+        let mut docs = vec![];
+        loop {
+            let doc = scorer.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            // score comes from `req` only, the mock scorer scores by doc id
+            assert!((scorer.score().unwrap() - doc as f32).abs() < ::std::f32::EPSILON);
+            docs.push(doc);
+        }
+        assert_eq!(docs, vec![1, 3, 6, 7, 8]);
+    }
+
+    #[test]
+    fn test_advance() {
+        let req = create_mock_scorer(vec![1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        let excl = create_mock_doc_iterator(vec![2, 4, 5, 9]);
+        let mut scorer = ReqExclScorer::new(req, excl);
+
+        assert_eq!(scorer.advance(2).unwrap(), 3);
+        assert_eq!(scorer.advance(4).unwrap(), 6);
+        assert_eq!(scorer.advance(9).unwrap(), NO_MORE_DOCS);
+    }
+
+    #[test]
+    fn test_excl_exhausted_first() {
+        let req = create_mock_scorer(vec![1, 3, 5, 7, 9]);
+        let excl = create_mock_doc_iterator(vec![1, 3]);
+        let mut scorer = ReqExclScorer::new(req, excl);
+
+        assert_eq!(scorer.next().unwrap(), 5);
+        assert_eq!(scorer.next().unwrap(), 7);
+        assert_eq!(scorer.next().unwrap(), 9);
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+    }
+}
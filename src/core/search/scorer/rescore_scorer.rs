@@ -0,0 +1,197 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::search::scorer::Scorer;
+use core::search::{DocIterator, NO_MORE_DOCS};
+use core::util::DocId;
+use error::{ErrorKind::IllegalArgument, Result};
+
+use std::cmp::Ordering;
+
+/// A `Scorer` over the `(doc, score)` hits of a first pass search which
+/// re-ranks the top `window_size` hits with a `secondary` scorer, eg. an
+/// expensive query only run on the best hits of a cheap one.
+///
+/// The hits in the window are scored `first_weight * first + second_weight *
+/// second` where `secondary` matches them and `first_weight * first`
+/// otherwise, like the `Total` mode of `QueryRescorer`. The hits outside of
+/// the window keep their first pass score, and `secondary` is never
+/// positioned on them.
+pub struct RescoreScorer<S: Scorer> {
+    /// the first pass hits sorted by doc
+    docs: Vec<DocId>,
+    scores: Vec<f32>,
+    in_window: Vec<bool>,
+    secondary: S,
+    first_weight: f32,
+    second_weight: f32,
+    /// 0 means unpositioned, otherwise the index of current hit plus one
+    upto: usize,
+}
+
+impl<S: Scorer> RescoreScorer<S> {
+    /// Returns an error if a doc is hit more than once.
+    pub fn new(
+        first_pass: Vec<(DocId, f32)>,
+        window_size: usize,
+        secondary: S,
+        first_weight: f32,
+        second_weight: f32,
+    ) -> Result<Self> {
+        // the window is the best hits, ties broken by doc id
+        let mut ranks: Vec<usize> = (0..first_pass.len()).collect();
+        ranks.sort_by(|&i, &j| {
+            let (d1, s1) = first_pass[i];
+            let (d2, s2) = first_pass[j];
+            s2.partial_cmp(&s1)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| d1.cmp(&d2))
+        });
+        let mut window = vec![false; first_pass.len()];
+        for &i in ranks.iter().take(window_size) {
+            window[i] = true;
+        }
+
+        let mut hits: Vec<_> = first_pass
+            .into_iter()
+            .zip(window)
+            .map(|((doc, score), in_window)| (doc, score, in_window))
+            .collect();
+        hits.sort_by_key(|h| h.0);
+        if let Some(w) = hits.windows(2).find(|w| w[0].0 == w[1].0) {
+            bail!(IllegalArgument(format!(
+                "doc {} is hit more than once",
+                w[0].0
+            )));
+        }
+
+        Ok(RescoreScorer {
+            docs: hits.iter().map(|h| h.0).collect(),
+            scores: hits.iter().map(|h| h.1).collect(),
+            in_window: hits.iter().map(|h| h.2).collect(),
+            secondary,
+            first_weight,
+            second_weight,
+            upto: 0,
+        })
+    }
+
+    /// Returns whether the current doc is in the rescored window.
+    pub fn is_rescored(&self) -> bool {
+        self.upto > 0 && self.upto <= self.docs.len() && self.in_window[self.upto - 1]
+    }
+}
+
+impl<S: Scorer> Scorer for RescoreScorer<S> {
+    fn score(&mut self) -> Result<f32> {
+        debug_assert!(self.upto > 0 && self.upto <= self.docs.len());
+        let first = self.scores[self.upto - 1];
+        if !self.in_window[self.upto - 1] {
+            return Ok(first);
+        }
+
+        let doc = self.docs[self.upto - 1];
+        if self.secondary.doc_id() < doc {
+            self.secondary.advance(doc)?;
+        }
+        let mut score = self.first_weight * first;
+        if self.secondary.doc_id() == doc {
+            score += self.second_weight * self.secondary.score()?;
+        }
+        Ok(score)
+    }
+}
+
+impl<S: Scorer> DocIterator for RescoreScorer<S> {
+    fn doc_id(&self) -> DocId {
+        match self.upto {
+            0 => -1,
+            i if i > self.docs.len() => NO_MORE_DOCS,
+            i => self.docs[i - 1],
+        }
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        if self.upto <= self.docs.len() {
+            self.upto += 1;
+        }
+        Ok(self.doc_id())
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        if self.upto <= self.docs.len() {
+            let from = self.upto;
+            self.upto = from + self.docs[from..].partition_point(|&d| d < target) + 1;
+        }
+        Ok(self.doc_id())
+    }
+
+    fn cost(&self) -> usize {
+        self.docs.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::tests::*;
+
+    /// returns the `(doc, score)` of all the hits, best first
+    fn rank<S: Scorer>(scorer: &mut RescoreScorer<S>) -> Vec<(DocId, f32)> {
+        let mut hits: Vec<(DocId, f32)> = scorer.iter().map(|hit| hit.unwrap()).collect();
+        hits.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then_with(|| a.0.cmp(&b.0)));
+        hits
+    }
+
+    fn first_pass() -> Vec<(DocId, f32)> {
+        vec![(7, 2.0), (1, 5.0), (9, 1.0), (3, 4.0), (5, 3.0)]
+    }
+
+    #[test]
+    fn test_rescore_top_window() {
+        // the mock score is the doc id
+        let secondary = create_mock_scorer(vec![3, 5, 9]);
+        let mut scorer = RescoreScorer::new(first_pass(), 3, secondary, 1.0, 1.0).unwrap();
+        // doc 9 is matched by the secondary scorer but out of the window
+        assert_eq!(
+            rank(&mut scorer),
+            vec![(5, 8.0), (3, 7.0), (1, 5.0), (7, 2.0), (9, 1.0)]
+        );
+
+        // without window, the first pass ranking is kept
+        let secondary = create_mock_scorer(vec![3, 5, 9]);
+        let mut scorer = RescoreScorer::new(first_pass(), 0, secondary, 1.0, 1.0).unwrap();
+        assert_eq!(
+            rank(&mut scorer),
+            vec![(1, 5.0), (3, 4.0), (5, 3.0), (7, 2.0), (9, 1.0)]
+        );
+    }
+
+    #[test]
+    fn test_weights_and_advance() {
+        let secondary = create_mock_scorer(vec![1, 5, 9]);
+        let mut scorer = RescoreScorer::new(first_pass(), 5, secondary, 0.5, 2.0).unwrap();
+        assert_eq!(scorer.advance(4).unwrap(), 5);
+        assert!(scorer.is_rescored());
+        assert!((scorer.score().unwrap() - 11.5).abs() < ::std::f32::EPSILON);
+        assert_eq!(scorer.next().unwrap(), 7);
+        // not matched by the secondary scorer
+        assert!((scorer.score().unwrap() - 1.0).abs() < ::std::f32::EPSILON);
+        assert_eq!(scorer.advance(10).unwrap(), NO_MORE_DOCS);
+        assert!(!scorer.is_rescored());
+        assert_eq!(scorer.cost(), 5);
+
+        let secondary = create_mock_scorer(vec![1]);
+        assert!(RescoreScorer::new(vec![(1, 1.0), (1, 2.0)], 1, secondary, 1.0, 1.0).is_err());
+    }
+}
@@ -0,0 +1,97 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::search::scorer::Scorer;
+use core::search::NO_MORE_DOCS;
+use core::util::DocId;
+use error::Result;
+
+/// An `Iterator` over the `(doc, score)` of the remaining docs of a scorer,
+/// see `Scorer::iter`, eg. for tests and ad-hoc use:
+///
+/// ```rust, ignore
+/// for hit in scorer.iter() {
+///     let (doc, score) = hit?;
+/// }
+/// ```
+///
+/// The scorer is moved by `next` and scored on each doc until
+/// `NO_MORE_DOCS`, so the current doc of a positioned scorer is skipped.
+/// The iteration ends after the first error.
+pub struct ScorerIter<'a, S: Scorer + ?Sized> {
+    scorer: &'a mut S,
+    done: bool,
+}
+
+impl<'a, S: Scorer + ?Sized> ScorerIter<'a, S> {
+    pub fn new(scorer: &'a mut S) -> Self {
+        ScorerIter {
+            scorer,
+            done: false,
+        }
+    }
+
+    fn next_hit(&mut self) -> Result<Option<(DocId, f32)>> {
+        let doc = self.scorer.next()?;
+        if doc == NO_MORE_DOCS {
+            return Ok(None);
+        }
+        Ok(Some((doc, self.scorer.score()?)))
+    }
+}
+
+impl<'a, S: Scorer + ?Sized> Iterator for ScorerIter<'a, S> {
+    type Item = Result<(DocId, f32)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let hit = self.next_hit();
+        self.done = !matches!(hit, Ok(Some(_)));
+        hit.transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::scorer::TimeLimitingScorer;
+    use core::search::tests::*;
+    use core::search::DocIterator;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_collect_hits() {
+        let mut scorer: Box<dyn Scorer> = Box::new(create_mock_scorer(vec![1, 4, 9]));
+        let hits: Vec<(DocId, f32)> = scorer.iter().map(Result::unwrap).collect();
+        assert_eq!(hits, vec![(1, 1.0), (4, 4.0), (9, 9.0)]);
+        assert_eq!(scorer.doc_id(), NO_MORE_DOCS);
+        assert!(scorer.iter().next().is_none());
+
+        // the iteration starts after the current doc, 4 is skipped
+        let mut scorer = create_mock_scorer(vec![1, 4, 9]);
+        scorer.advance(2).unwrap();
+        let docs: Vec<DocId> = ScorerIter::new(&mut scorer).map(|h| h.unwrap().0).collect();
+        assert_eq!(docs, vec![9]);
+    }
+
+    #[test]
+    fn test_stop_on_error() {
+        let deadline = Instant::now() - Duration::from_millis(1);
+        let mut scorer = TimeLimitingScorer::new(create_mock_scorer(vec![1, 2]), deadline, 1);
+        let mut iter = scorer.iter();
+        assert!(iter.next().unwrap().is_err());
+        assert!(iter.next().is_none());
+    }
+}
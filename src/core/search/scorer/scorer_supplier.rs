@@ -0,0 +1,189 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::search::scorer::{BoostingScorer, ConjunctionScorer, Scorer};
+use core::search::DocIterator;
+use error::Result;
+
+/// A supplier of a `Scorer` which can estimate the cost of the scorer before
+/// it is built, so that query planning can decide how to combine clauses, eg.
+/// which clause leads a conjunction, without paying for building all of them.
+pub trait ScorerSupplier: Send {
+    /// Returns an estimate of the cost of the scorer, eg. the number of docs
+    /// the scorer may match, see `DocIterator::cost`.
+    fn cost_estimate(&self) -> usize;
+
+    /// Builds the scorer, consuming this supplier.
+    fn build(self: Box<Self>) -> Result<Box<dyn Scorer>>;
+}
+
+/// An already built scorer supplies itself, its estimate is the real cost.
+impl ScorerSupplier for Box<dyn Scorer> {
+    fn cost_estimate(&self) -> usize {
+        self.cost()
+    }
+
+    fn build(self: Box<Self>) -> Result<Box<dyn Scorer>> {
+        Ok(*self)
+    }
+}
+
+/// A `ScorerSupplier` which builds the scorer with `build` on demand.
+pub struct LazyScorerSupplier<F: FnOnce() -> Result<Box<dyn Scorer>> + Send> {
+    cost: usize,
+    build: F,
+}
+
+impl<F: FnOnce() -> Result<Box<dyn Scorer>> + Send> LazyScorerSupplier<F> {
+    pub fn new(cost: usize, build: F) -> Self {
+        LazyScorerSupplier { cost, build }
+    }
+}
+
+impl<F: FnOnce() -> Result<Box<dyn Scorer>> + Send> ScorerSupplier for LazyScorerSupplier<F> {
+    fn cost_estimate(&self) -> usize {
+        self.cost
+    }
+
+    fn build(self: Box<Self>) -> Result<Box<dyn Scorer>> {
+        (self.build)()
+    }
+}
+
+/// Supplies a `BoostingScorer`, the negative clause only affects scores so
+/// the cost is estimated from the positive clause.
+pub struct BoostingScorerSupplier {
+    positive: Box<dyn ScorerSupplier>,
+    negative: Box<dyn ScorerSupplier>,
+    negative_boost: f32,
+}
+
+impl BoostingScorerSupplier {
+    pub fn new(
+        positive: Box<dyn ScorerSupplier>,
+        negative: Box<dyn ScorerSupplier>,
+        negative_boost: f32,
+    ) -> Self {
+        BoostingScorerSupplier {
+            positive,
+            negative,
+            negative_boost,
+        }
+    }
+}
+
+impl ScorerSupplier for BoostingScorerSupplier {
+    fn cost_estimate(&self) -> usize {
+        self.positive.cost_estimate()
+    }
+
+    fn build(self: Box<Self>) -> Result<Box<dyn Scorer>> {
+        let positive = self.positive.build()?;
+        let negative = self.negative.build()?;
+        Ok(Box::new(BoostingScorer::new(
+            positive,
+            negative,
+            self.negative_boost,
+        )))
+    }
+}
+
+/// Orders `suppliers` by their estimated cost, cheapest first, without
+/// building any of them. The first one is the best clause to lead a
+/// conjunction.
+pub fn order_by_cost(suppliers: &mut [Box<dyn ScorerSupplier>]) {
+    suppliers.sort_by_key(|s| s.cost_estimate());
+}
+
+/// Builds the conjunction of all the `suppliers`, which are built in the
+/// order of their estimated cost. A single supplier is returned as is.
+pub fn build_conjunction(mut suppliers: Vec<Box<dyn ScorerSupplier>>) -> Result<Box<dyn Scorer>> {
+    debug_assert!(!suppliers.is_empty());
+    order_by_cost(&mut suppliers);
+
+    let mut scorers = Vec::with_capacity(suppliers.len());
+    for supplier in suppliers {
+        scorers.push(supplier.build()?);
+    }
+    if scorers.len() == 1 {
+        Ok(scorers.remove(0))
+    } else {
+        Ok(Box::new(ConjunctionScorer::new(scorers)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::tests::*;
+    use core::search::NO_MORE_DOCS;
+    use std::sync::{Arc, Mutex};
+
+    fn lazy_supplier(
+        name: &'static str,
+        cost: usize,
+        docs: Vec<i32>,
+        built: &Arc<Mutex<Vec<&'static str>>>,
+    ) -> Box<dyn ScorerSupplier> {
+        let built = Arc::clone(built);
+        Box::new(LazyScorerSupplier::new(cost, move || {
+            built.lock().unwrap().push(name);
+            Ok(Box::new(create_mock_scorer(docs)) as Box<dyn Scorer>)
+        }))
+    }
+
+    #[test]
+    fn test_order_by_cost() {
+        let built = Arc::new(Mutex::new(vec![]));
+        let mut suppliers = vec![
+            lazy_supplier("common", 100, vec![1, 2, 3, 4, 5, 6], &built),
+            lazy_supplier("rare", 2, vec![2, 6], &built),
+            lazy_supplier("medium", 10, vec![2, 4, 6], &built),
+        ];
+        order_by_cost(&mut suppliers);
+        let costs: Vec<usize> = suppliers.iter().map(|s| s.cost_estimate()).collect();
+        assert_eq!(costs, vec![2, 10, 100]);
+        // nothing is built for planning
+        assert!(built.lock().unwrap().is_empty());
+
+        let mut scorer = build_conjunction(suppliers).unwrap();
+        // the cheapest clause is built first and leads
+        assert_eq!(*built.lock().unwrap(), vec!["rare", "medium", "common"]);
+        assert_eq!(scorer.next().unwrap(), 2);
+        assert_eq!(scorer.next().unwrap(), 6);
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+    }
+
+    #[test]
+    fn test_boosting_supplier() {
+        let built = Arc::new(Mutex::new(vec![]));
+        let supplier = BoostingScorerSupplier::new(
+            lazy_supplier("positive", 4, vec![1, 2, 3, 4], &built),
+            lazy_supplier("negative", 1000, vec![2, 4], &built),
+            0.5,
+        );
+        assert_eq!(supplier.cost_estimate(), 4);
+
+        let mut scorer = Box::new(supplier).build().unwrap();
+        assert_eq!(scorer.next().unwrap(), 1);
+        assert!((scorer.score().unwrap() - 1.0).abs() < ::std::f32::EPSILON);
+        assert_eq!(scorer.next().unwrap(), 2);
+        assert!((scorer.score().unwrap() - 1.0).abs() < ::std::f32::EPSILON);
+
+        // a built scorer supplies itself
+        let scorer: Box<dyn Scorer> = Box::new(create_mock_scorer(vec![1, 3]));
+        let supplier: Box<dyn ScorerSupplier> = Box::new(scorer);
+        assert_eq!(supplier.cost_estimate(), 2);
+        assert_eq!(supplier.build().unwrap().next().unwrap(), 1);
+    }
+}
@@ -0,0 +1,323 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::codec::PostingIterator;
+use core::search::query::spans::{
+    NearSpansOrdered, NearSpansUnordered, Spans, SpansEnum, NO_MORE_POSITIONS,
+};
+use core::search::scorer::{Scorer, SpanScorer};
+use core::search::similarity::SimScorer;
+use core::search::{DocIterator, NO_MORE_DOCS};
+use core::util::DocId;
+use error::{ErrorKind::IllegalArgument, Result};
+
+/// A `SpanScorer` which matches documents where all the sub spans occur
+/// within `slop` positions of each other.
+///
+/// When `in_order` is true the sub spans must appear in the given order and
+/// may not overlap, each sub span is moved to its first position after the end
+/// of the previous one, so matches are found greedily from each start position
+/// and are not necessarily minimal. When `in_order` is false the sub spans may
+/// appear in any order and may overlap each other.
+///
+/// Matches of the current document are collected once the document is
+/// positioned, the sloppy freq is the sum of `compute_slop_factor(width)` of
+/// all the matches.
+pub struct SpanNearScorer<P: PostingIterator> {
+    spans: SpansEnum<P>,
+    doc_scorer: Box<dyn SimScorer>,
+    matches: SpanMatches,
+    freq: f32,
+}
+
+impl<P: PostingIterator> SpanNearScorer<P> {
+    pub fn new(
+        sub_spans: Vec<SpansEnum<P>>,
+        slop: i32,
+        in_order: bool,
+        doc_scorer: Box<dyn SimScorer>,
+    ) -> Result<Self> {
+        if sub_spans.len() < 2 {
+            bail!(IllegalArgument(
+                "span near scorer requires at least two sub spans".into()
+            ));
+        }
+        if slop < 0 {
+            bail!(IllegalArgument(format!("slop must be >= 0, got {}", slop)));
+        }
+        let spans = if in_order {
+            SpansEnum::NearOrdered(NearSpansOrdered::new(slop, sub_spans)?)
+        } else {
+            SpansEnum::NearUnordered(NearSpansUnordered::new(slop, sub_spans)?)
+        };
+        Ok(SpanNearScorer {
+            spans,
+            doc_scorer,
+            matches: SpanMatches::default(),
+            freq: 0f32,
+        })
+    }
+
+    fn collect_matches(&mut self, doc: DocId) -> Result<DocId> {
+        self.matches.clear();
+        self.freq = 0f32;
+        if doc == NO_MORE_DOCS {
+            return Ok(doc);
+        }
+
+        self.spans.do_start_current_doc()?;
+        let mut start_pos = self.spans.next_start_position()?;
+        while start_pos != NO_MORE_POSITIONS {
+            self.matches.push(start_pos, self.spans.end_position());
+            self.freq += self.doc_scorer.compute_slop_factor(self.spans.width());
+            self.spans.do_current_spans()?;
+            start_pos = self.spans.next_start_position()?;
+        }
+        Ok(doc)
+    }
+}
+
+impl<P: PostingIterator> Scorer for SpanNearScorer<P> {
+    fn score(&mut self) -> Result<f32> {
+        let doc = self.doc_id();
+        self.doc_scorer.score(doc, self.freq)
+    }
+
+    fn freq(&self) -> Result<f32> {
+        Ok(self.freq)
+    }
+}
+
+impl<P: PostingIterator> SpanScorer for SpanNearScorer<P> {
+    fn next_start_position(&mut self) -> Result<i32> {
+        Ok(self.matches.next_start_position())
+    }
+
+    fn start_position(&self) -> i32 {
+        self.matches.start_position()
+    }
+
+    fn end_position(&self) -> i32 {
+        self.matches.end_position()
+    }
+}
+
+impl<P: PostingIterator> DocIterator for SpanNearScorer<P> {
+    fn doc_id(&self) -> DocId {
+        self.spans.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        let doc = self.spans.next()?;
+        self.collect_matches(doc)
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        let doc = self.spans.advance(target)?;
+        self.collect_matches(doc)
+    }
+
+    fn cost(&self) -> usize {
+        self.spans.cost()
+    }
+
+    fn matches(&mut self) -> Result<bool> {
+        if self.spans.matches()? {
+            let doc = self.doc_id();
+            self.collect_matches(doc)?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn match_cost(&self) -> f32 {
+        self.spans.match_cost()
+    }
+
+    fn support_two_phase(&self) -> bool {
+        self.spans.support_two_phase()
+    }
+
+    fn approximate_next(&mut self) -> Result<DocId> {
+        self.spans.approximate_next()
+    }
+
+    fn approximate_advance(&mut self, target: DocId) -> Result<DocId> {
+        self.spans.approximate_advance(target)
+    }
+}
+
+/// The `(start, end)` positions of the matches of a `SpanScorer` in the
+/// current doc, collected once the doc is positioned, with a cursor to
+/// implement `SpanScorer` on top of them.
+#[derive(Default)]
+pub struct SpanMatches {
+    matches: Vec<(i32, i32)>,
+    /// 0 means unpositioned, otherwise the index of current match plus one
+    idx: usize,
+}
+
+impl SpanMatches {
+    /// Removes all the matches and unpositions the cursor, eg. for a new doc.
+    pub fn clear(&mut self) {
+        self.matches.clear();
+        self.idx = 0;
+    }
+
+    pub fn push(&mut self, start: i32, end: i32) {
+        self.matches.push((start, end));
+    }
+
+    pub fn len(&self) -> usize {
+        self.matches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.matches.is_empty()
+    }
+
+    pub fn next_start_position(&mut self) -> i32 {
+        if self.idx <= self.matches.len() {
+            self.idx += 1;
+        }
+        self.start_position()
+    }
+
+    pub fn start_position(&self) -> i32 {
+        match self.idx {
+            0 => -1,
+            i if i > self.matches.len() => NO_MORE_POSITIONS,
+            i => self.matches[i - 1].0,
+        }
+    }
+
+    pub fn end_position(&self) -> i32 {
+        match self.idx {
+            0 => -1,
+            i if i > self.matches.len() => NO_MORE_POSITIONS,
+            i => self.matches[i - 1].1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::doc::Term;
+    use core::search::query::spans::TermSpans;
+    use core::search::tests::*;
+
+    fn term_spans(text: &str, postings: Vec<(DocId, Vec<i32>)>) -> SpansEnum<MockPostingIterator> {
+        let term = Term::new("body".to_string(), text.as_bytes().to_vec());
+        SpansEnum::Term(TermSpans::new(
+            MockPostingIterator::new(postings),
+            term,
+            1.0,
+        ))
+    }
+
+    fn create_scorer(slop: i32, in_order: bool) -> SpanNearScorer<MockPostingIterator> {
+        // doc 0: "a _ b", doc 1: "a b", doc 2: "b a",
+        // doc 3: "a _ _ _ _ b", doc 4: "a _ a b"
+        let a = term_spans(
+            "a",
+            vec![
+                (0, vec![0]),
+                (1, vec![0]),
+                (2, vec![1]),
+                (3, vec![0]),
+                (4, vec![0, 2]),
+            ],
+        );
+        let b = term_spans(
+            "b",
+            vec![
+                (0, vec![2]),
+                (1, vec![1]),
+                (2, vec![0]),
+                (3, vec![5]),
+                (4, vec![3]),
+            ],
+        );
+        SpanNearScorer::new(vec![a, b], slop, in_order, Box::new(MockSimScorer)).unwrap()
+    }
+
+    fn collect_docs(scorer: &mut SpanNearScorer<MockPostingIterator>) -> Vec<DocId> {
+        let mut docs = vec![];
+        loop {
+            let doc = scorer.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            docs.push(doc);
+        }
+        docs
+    }
+
+    fn collect_positions(scorer: &mut SpanNearScorer<MockPostingIterator>) -> Vec<(i32, i32)> {
+        let mut positions = vec![];
+        while scorer.next_start_position().unwrap() != NO_MORE_POSITIONS {
+            positions.push((scorer.start_position(), scorer.end_position()));
+        }
+        positions
+    }
+
+    #[test]
+    fn test_ordered() {
+        assert_eq!(collect_docs(&mut create_scorer(0, true)), vec![1, 4]);
+        assert_eq!(collect_docs(&mut create_scorer(1, true)), vec![0, 1, 4]);
+        assert_eq!(collect_docs(&mut create_scorer(4, true)), vec![0, 1, 3, 4]);
+    }
+
+    #[test]
+    fn test_unordered() {
+        assert_eq!(collect_docs(&mut create_scorer(0, false)), vec![1, 2, 4]);
+        assert_eq!(collect_docs(&mut create_scorer(1, false)), vec![0, 1, 2, 4]);
+        assert_eq!(
+            collect_docs(&mut create_scorer(4, false)),
+            vec![0, 1, 2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn test_span_positions() {
+        let mut scorer = create_scorer(1, true);
+        assert_eq!(scorer.next().unwrap(), 0);
+        assert_eq!(scorer.start_position(), -1);
+        assert_eq!(collect_positions(&mut scorer), vec![(0, 3)]);
+        assert_eq!(scorer.start_position(), NO_MORE_POSITIONS);
+        // MockSimScorer scores by the sloppy freq, eg. 1 / (width + 1)
+        assert!((scorer.score().unwrap() - 0.5).abs() < ::std::f32::EPSILON);
+
+        assert_eq!(scorer.advance(2).unwrap(), 4);
+        assert_eq!(collect_positions(&mut scorer), vec![(2, 4)]);
+        assert!((scorer.freq().unwrap() - 1.0).abs() < ::std::f32::EPSILON);
+    }
+
+    #[test]
+    fn test_overlapping_matches() {
+        // with slop 2 both "a" in doc 4 start a match ending at the same "b"
+        let mut scorer = create_scorer(2, true);
+        assert_eq!(scorer.advance(4).unwrap(), 4);
+        assert_eq!(collect_positions(&mut scorer), vec![(0, 4), (2, 4)]);
+        let expected = 1.0 / 3.0 + 1.0;
+        assert!((scorer.score().unwrap() - expected).abs() < ::std::f32::EPSILON);
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        let a = term_spans("a", vec![(0, vec![0])]);
+        assert!(SpanNearScorer::new(vec![a], 0, true, Box::new(MockSimScorer)).is_err());
+    }
+}
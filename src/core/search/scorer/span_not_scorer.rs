@@ -0,0 +1,260 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::search::query::spans::NO_MORE_POSITIONS;
+use core::search::scorer::{Scorer, SpanMatches, SpanScorer};
+use core::search::similarity::SimScorer;
+use core::search::{DocIterator, NO_MORE_DOCS};
+use core::util::DocId;
+use error::{ErrorKind::IllegalArgument, Result};
+
+/// A `SpanScorer` which matches the spans of `include` that are not near a
+/// span of `exclude`, like Lucene's `SpanNotQuery`.
+///
+/// An include span `[start, end)` is removed if an exclude span of the same
+/// doc overlaps the window `[start - pre, end + post)`, eg. with `pre` and
+/// `post` both 0 only overlapping exclude spans remove it, with `post` 1 an
+/// exclude span starting right at `end` removes it too. Docs where all the
+/// include spans are removed don't match, docs without exclude spans keep
+/// all their include spans.
+///
+/// The freq of a doc is the number of include spans left, scored by
+/// `doc_scorer`.
+pub struct SpanNotScorer<I: SpanScorer, E: SpanScorer> {
+    include: I,
+    exclude: E,
+    pre: i32,
+    post: i32,
+    doc_scorer: Box<dyn SimScorer>,
+    /// the include spans left in the current doc
+    matches: SpanMatches,
+}
+
+impl<I: SpanScorer, E: SpanScorer> SpanNotScorer<I, E> {
+    pub fn new(
+        include: I,
+        exclude: E,
+        pre: i32,
+        post: i32,
+        doc_scorer: Box<dyn SimScorer>,
+    ) -> Result<Self> {
+        if pre < 0 || post < 0 {
+            bail!(IllegalArgument(format!(
+                "pre and post must be >= 0, got {} and {}",
+                pre, post
+            )));
+        }
+        Ok(SpanNotScorer {
+            include,
+            exclude,
+            pre,
+            post,
+            doc_scorer,
+            matches: SpanMatches::default(),
+        })
+    }
+
+    /// Moves `include` to the first doc from `doc` on with an include span
+    /// left and collects those spans.
+    fn next_match(&mut self, mut doc: DocId) -> Result<DocId> {
+        while doc != NO_MORE_DOCS {
+            self.collect_matches(doc)?;
+            if !self.matches.is_empty() {
+                return Ok(doc);
+            }
+            doc = self.include.next()?;
+        }
+        self.matches.clear();
+        Ok(NO_MORE_DOCS)
+    }
+
+    fn collect_matches(&mut self, doc: DocId) -> Result<()> {
+        self.matches.clear();
+
+        let mut excluded = vec![];
+        if self.exclude.doc_id() < doc {
+            self.exclude.advance(doc)?;
+        }
+        if self.exclude.doc_id() == doc {
+            while self.exclude.next_start_position()? != NO_MORE_POSITIONS {
+                excluded.push((self.exclude.start_position(), self.exclude.end_position()));
+            }
+        }
+
+        while self.include.next_start_position()? != NO_MORE_POSITIONS {
+            let start = self.include.start_position();
+            let end = self.include.end_position();
+            let window_start = start - self.pre;
+            let window_end = end + self.post;
+            let is_excluded = excluded
+                .iter()
+                .any(|&(s, e)| s < window_end && e > window_start);
+            if !is_excluded {
+                self.matches.push(start, end);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<I: SpanScorer, E: SpanScorer> Scorer for SpanNotScorer<I, E> {
+    fn score(&mut self) -> Result<f32> {
+        let doc = self.doc_id();
+        let freq = self.matches.len() as f32;
+        self.doc_scorer.score(doc, freq)
+    }
+
+    fn freq(&self) -> Result<f32> {
+        Ok(self.matches.len() as f32)
+    }
+}
+
+impl<I: SpanScorer, E: SpanScorer> SpanScorer for SpanNotScorer<I, E> {
+    fn next_start_position(&mut self) -> Result<i32> {
+        Ok(self.matches.next_start_position())
+    }
+
+    fn start_position(&self) -> i32 {
+        self.matches.start_position()
+    }
+
+    fn end_position(&self) -> i32 {
+        self.matches.end_position()
+    }
+}
+
+impl<I: SpanScorer, E: SpanScorer> DocIterator for SpanNotScorer<I, E> {
+    fn doc_id(&self) -> DocId {
+        self.include.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        let doc = self.include.next()?;
+        self.next_match(doc)
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        let doc = self.include.advance(target)?;
+        self.next_match(doc)
+    }
+
+    fn cost(&self) -> usize {
+        self.include.cost()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::doc::Term;
+    use core::search::query::spans::TermSpans;
+    use core::search::scorer::SpanTermScorer;
+    use core::search::tests::*;
+
+    type TermScorer = SpanTermScorer<MockPostingIterator>;
+
+    fn term_scorer(text: &str, postings: Vec<(DocId, Vec<i32>)>) -> TermScorer {
+        let term = Term::new("body".to_string(), text.as_bytes().to_vec());
+        let spans = TermSpans::new(MockPostingIterator::new(postings), term, 1.0);
+        SpanTermScorer::new(spans, Box::new(MockSimScorer))
+    }
+
+    fn collect<I: SpanScorer, E: SpanScorer>(
+        scorer: &mut SpanNotScorer<I, E>,
+    ) -> Vec<(DocId, Vec<i32>)> {
+        let mut result = vec![];
+        loop {
+            let doc = scorer.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            let mut starts = vec![];
+            while scorer.next_start_position().unwrap() != NO_MORE_POSITIONS {
+                starts.push(scorer.start_position());
+            }
+            result.push((doc, starts));
+        }
+        result
+    }
+
+    /// "apple" not preceded or followed by the stop term "the"
+    fn create_scorer(pre: i32, post: i32) -> SpanNotScorer<TermScorer, TermScorer> {
+        // doc 0: "the apple", doc 1: "apple the", doc 2: "apple pie the apple",
+        // doc 3: "the _ apple", doc 4: "apple"
+        let apple = term_scorer(
+            "apple",
+            vec![
+                (0, vec![1]),
+                (1, vec![0]),
+                (2, vec![0, 3]),
+                (3, vec![2]),
+                (4, vec![0]),
+            ],
+        );
+        let the = term_scorer(
+            "the",
+            vec![(0, vec![0]), (1, vec![1]), (2, vec![2]), (3, vec![0])],
+        );
+        SpanNotScorer::new(apple, the, pre, post, Box::new(MockSimScorer)).unwrap()
+    }
+
+    #[test]
+    fn test_adjacent_stop_term() {
+        // nothing overlaps, all the spans are kept
+        let mut scorer = create_scorer(0, 0);
+        assert_eq!(
+            collect(&mut scorer),
+            vec![
+                (0, vec![1]),
+                (1, vec![0]),
+                (2, vec![0, 3]),
+                (3, vec![2]),
+                (4, vec![0])
+            ]
+        );
+
+        // "the" right before "apple"
+        let mut scorer = create_scorer(1, 0);
+        assert_eq!(
+            collect(&mut scorer),
+            vec![(1, vec![0]), (2, vec![0]), (3, vec![2]), (4, vec![0])]
+        );
+
+        // "the" right after "apple"
+        let mut scorer = create_scorer(0, 1);
+        assert_eq!(
+            collect(&mut scorer),
+            vec![(0, vec![1]), (2, vec![0, 3]), (3, vec![2]), (4, vec![0])]
+        );
+
+        // the window reaches two positions before and one after
+        let mut scorer = create_scorer(2, 1);
+        assert_eq!(collect(&mut scorer), vec![(2, vec![0]), (4, vec![0])]);
+    }
+
+    #[test]
+    fn test_empty_exclude() {
+        let apple = term_scorer("apple", vec![(2, vec![0, 4]), (5, vec![1])]);
+        let none = term_scorer("the", vec![]);
+        let mut scorer = SpanNotScorer::new(apple, none, 3, 3, Box::new(MockSimScorer)).unwrap();
+        assert_eq!(scorer.advance(1).unwrap(), 2);
+        assert!((scorer.freq().unwrap() - 2.0).abs() < ::std::f32::EPSILON);
+        assert!((scorer.score().unwrap() - 2.0).abs() < ::std::f32::EPSILON);
+        assert_eq!(scorer.next().unwrap(), 5);
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+
+        let apple = term_scorer("apple", vec![(0, vec![0])]);
+        let the = term_scorer("the", vec![]);
+        assert!(SpanNotScorer::new(apple, the, -1, 0, Box::new(MockSimScorer)).is_err());
+    }
+}
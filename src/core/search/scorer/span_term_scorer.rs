@@ -0,0 +1,111 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::codec::PostingIterator;
+use core::search::query::spans::{Spans, TermSpans};
+use core::search::scorer::{Scorer, SpanScorer};
+use core::search::similarity::SimScorer;
+use core::search::DocIterator;
+use core::util::DocId;
+use error::Result;
+
+/// A `SpanScorer` over the spans of a single term, each position `pos` of the
+/// term is a span `[pos, pos + 1)`.
+///
+/// The freq of a doc is the number of positions of the term, scored by
+/// `doc_scorer`.
+pub struct SpanTermScorer<P: PostingIterator> {
+    spans: TermSpans<P>,
+    doc_scorer: Box<dyn SimScorer>,
+}
+
+impl<P: PostingIterator> SpanTermScorer<P> {
+    pub fn new(spans: TermSpans<P>, doc_scorer: Box<dyn SimScorer>) -> Self {
+        SpanTermScorer { spans, doc_scorer }
+    }
+}
+
+impl<P: PostingIterator> Scorer for SpanTermScorer<P> {
+    fn score(&mut self) -> Result<f32> {
+        let doc = self.doc_id();
+        let freq = self.spans.freq() as f32;
+        self.doc_scorer.score(doc, freq)
+    }
+
+    fn freq(&self) -> Result<f32> {
+        Ok(self.spans.freq() as f32)
+    }
+}
+
+impl<P: PostingIterator> SpanScorer for SpanTermScorer<P> {
+    fn next_start_position(&mut self) -> Result<i32> {
+        self.spans.next_start_position()
+    }
+
+    fn start_position(&self) -> i32 {
+        self.spans.start_position()
+    }
+
+    fn end_position(&self) -> i32 {
+        self.spans.end_position()
+    }
+}
+
+impl<P: PostingIterator> DocIterator for SpanTermScorer<P> {
+    fn doc_id(&self) -> DocId {
+        self.spans.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        self.spans.next()
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        self.spans.advance(target)
+    }
+
+    fn cost(&self) -> usize {
+        self.spans.cost()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::doc::Term;
+    use core::search::query::spans::NO_MORE_POSITIONS;
+    use core::search::tests::*;
+    use core::search::NO_MORE_DOCS;
+
+    #[test]
+    fn test_term_spans() {
+        let term = Term::new("body".to_string(), b"apple".to_vec());
+        let postings = MockPostingIterator::new(vec![(1, vec![0, 3]), (4, vec![2])]);
+        let mut scorer =
+            SpanTermScorer::new(TermSpans::new(postings, term, 1.0), Box::new(MockSimScorer));
+
+        assert_eq!(scorer.next().unwrap(), 1);
+        assert!((scorer.score().unwrap() - 2.0).abs() < ::std::f32::EPSILON);
+        assert_eq!(scorer.start_position(), -1);
+        assert_eq!(scorer.next_start_position().unwrap(), 0);
+        assert_eq!(scorer.end_position(), 1);
+        assert_eq!(scorer.next_start_position().unwrap(), 3);
+        assert_eq!(scorer.end_position(), 4);
+        assert_eq!(scorer.next_start_position().unwrap(), NO_MORE_POSITIONS);
+
+        assert_eq!(scorer.advance(2).unwrap(), 4);
+        assert!((scorer.freq().unwrap() - 1.0).abs() < ::std::f32::EPSILON);
+        assert_eq!(scorer.next_start_position().unwrap(), 2);
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+    }
+}
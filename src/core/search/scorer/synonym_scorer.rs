@@ -0,0 +1,187 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::search::scorer::Scorer;
+use core::search::similarity::SimScorer;
+use core::search::{DocIterator, NO_MORE_DOCS};
+use core::util::DocId;
+use error::Result;
+
+/// A `Scorer` which scores several synonymous terms as if they were a single
+/// term.
+///
+/// The matched docs are the union of the docs of all the sub scorers, the
+/// frequency of a doc is the sum of the frequencies of the sub scorers on it,
+/// and the score is computed by `sim_scorer` from the summed frequency. So a
+/// doc matching two synonyms counts as one term with a higher frequency, not
+/// as two matches.
+///
+/// Each sub scorer may be given a weight its frequency is multiplied by, see
+/// `CombinedFieldsScorer`, the weights default to 1.
+pub struct SynonymScorer {
+    scorers: Vec<Box<dyn Scorer>>,
+    weights: Vec<f32>,
+    sim_scorer: Box<dyn SimScorer>,
+    doc: DocId,
+    cost: usize,
+}
+
+impl SynonymScorer {
+    pub fn new(scorers: Vec<Box<dyn Scorer>>, sim_scorer: Box<dyn SimScorer>) -> SynonymScorer {
+        let weights = vec![1.0; scorers.len()];
+        Self::with_weights(scorers, weights, sim_scorer)
+    }
+
+    /// `weights[i]` is the weight of the frequency of `scorers[i]`.
+    pub fn with_weights(
+        scorers: Vec<Box<dyn Scorer>>,
+        weights: Vec<f32>,
+        sim_scorer: Box<dyn SimScorer>,
+    ) -> SynonymScorer {
+        debug_assert!(!scorers.is_empty());
+        debug_assert_eq!(scorers.len(), weights.len());
+        let cost = scorers.iter().map(|s| s.cost()).sum();
+        SynonymScorer {
+            scorers,
+            weights,
+            sim_scorer,
+            doc: -1,
+            cost,
+        }
+    }
+
+    fn min_doc(&self) -> DocId {
+        self.scorers
+            .iter()
+            .map(|s| s.doc_id())
+            .min()
+            .unwrap_or(NO_MORE_DOCS)
+    }
+}
+
+impl Scorer for SynonymScorer {
+    fn score(&mut self) -> Result<f32> {
+        let freq = self.freq()?;
+        self.sim_scorer.score(self.doc, freq)
+    }
+
+    fn freq(&self) -> Result<f32> {
+        let mut freq = 0f32;
+        for (s, weight) in self.scorers.iter().zip(&self.weights) {
+            if s.doc_id() == self.doc {
+                freq += weight * s.freq()?;
+            }
+        }
+        Ok(freq)
+    }
+}
+
+impl DocIterator for SynonymScorer {
+    fn doc_id(&self) -> DocId {
+        self.doc
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        if self.doc == NO_MORE_DOCS {
+            return Ok(NO_MORE_DOCS);
+        }
+        for s in &mut self.scorers {
+            if s.doc_id() == self.doc {
+                s.next()?;
+            }
+        }
+        self.doc = self.min_doc();
+        Ok(self.doc)
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        for s in &mut self.scorers {
+            if s.doc_id() < target {
+                s.advance(target)?;
+            }
+        }
+        self.doc = self.min_doc();
+        Ok(self.doc)
+    }
+
+    fn cost(&self) -> usize {
+        self.cost
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::scorer::TermScorer;
+    use core::search::tests::*;
+
+    /// a saturating similarity, eg. `freq / (freq + 1)`
+    struct SaturatingSimScorer;
+
+    impl SimScorer for SaturatingSimScorer {
+        fn score(&mut self, _doc: DocId, freq: f32) -> Result<f32> {
+            Ok(freq / (freq + 1.0))
+        }
+
+        fn compute_slop_factor(&self, _distance: i32) -> f32 {
+            1.0
+        }
+    }
+
+    fn term_scorer(postings: Vec<(DocId, Vec<i32>)>) -> Box<dyn Scorer> {
+        Box::new(TermScorer::new(
+            Box::new(SaturatingSimScorer),
+            MockPostingIterator::new(postings),
+        ))
+    }
+
+    fn create_scorer() -> SynonymScorer {
+        // "car": docs 1 (freq 2) and 3, "automobile": docs 1 and 5
+        let car = term_scorer(vec![(1, vec![0, 4]), (3, vec![2])]);
+        let automobile = term_scorer(vec![(1, vec![7]), (5, vec![1, 3, 6])]);
+        SynonymScorer::new(vec![car, automobile], Box::new(SaturatingSimScorer))
+    }
+
+    #[test]
+    fn test_union_and_combined_freq() {
+        let mut scorer = create_scorer();
+        let mut hits = vec![];
+        loop {
+            let doc = scorer.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            hits.push((doc, scorer.freq().unwrap(), scorer.score().unwrap()));
+        }
+
+        assert_eq!(
+            hits.iter().map(|h| (h.0, h.1)).collect::<Vec<_>>(),
+            vec![(1, 3.0), (3, 1.0), (5, 3.0)]
+        );
+        // doc 1 is scored as a single term of freq 3, not as two matches
+        assert!((hits[0].2 - 0.75).abs() < ::std::f32::EPSILON);
+        assert!((hits[0].2 - (2.0 / 3.0 + 0.5)).abs() > 0.1);
+        assert!((hits[0].2 - hits[2].2).abs() < ::std::f32::EPSILON);
+        assert!((hits[1].2 - 0.5).abs() < ::std::f32::EPSILON);
+    }
+
+    #[test]
+    fn test_advance() {
+        let mut scorer = create_scorer();
+        assert_eq!(scorer.advance(2).unwrap(), 3);
+        assert!((scorer.freq().unwrap() - 1.0).abs() < ::std::f32::EPSILON);
+        assert_eq!(scorer.advance(4).unwrap(), 5);
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+    }
+}
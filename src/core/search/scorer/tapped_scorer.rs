@@ -0,0 +1,173 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::search::scorer::{FeatureResult, ScoreMode, Scorer};
+use core::search::{DocIterator, Explanation, TwoPhaseIterator};
+use core::util::{DocId, IndexedContext};
+use error::Result;
+
+/// A `Scorer` which wraps another scorer and reports every computed score to
+/// `callback` as `(doc, score)`, everything else is forwarded untouched.
+///
+/// This is a side-channel for debugging and metrics, eg. to histogram the
+/// scores of a query without modifying its logic. The callback is invoked on
+/// every `score()` call, wrap this with a `CachingScorer` if the score of a
+/// single hit may be requested multiple times.
+pub struct TappedScorer<S: Scorer, F: FnMut(DocId, f32) + Send> {
+    scorer: S,
+    callback: F,
+}
+
+impl<S: Scorer, F: FnMut(DocId, f32) + Send> TappedScorer<S, F> {
+    pub fn new(scorer: S, callback: F) -> Self {
+        TappedScorer { scorer, callback }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.scorer
+    }
+}
+
+impl<S: Scorer, F: FnMut(DocId, f32) + Send> Scorer for TappedScorer<S, F> {
+    fn score(&mut self) -> Result<f32> {
+        let score = self.scorer.score()?;
+        (self.callback)(self.scorer.doc_id(), score);
+        Ok(score)
+    }
+
+    fn score_context(&mut self) -> Result<IndexedContext> {
+        self.scorer.score_context()
+    }
+
+    fn score_feature(&mut self) -> Result<Vec<FeatureResult>> {
+        self.scorer.score_feature()
+    }
+
+    fn freq(&self) -> Result<f32> {
+        self.scorer.freq()
+    }
+
+    fn set_score_mode(&mut self, mode: ScoreMode) {
+        self.scorer.set_score_mode(mode)
+    }
+
+    fn advance_shallow(&mut self, target: DocId) -> Result<DocId> {
+        self.scorer.advance_shallow(target)
+    }
+
+    fn get_max_score(&mut self, up_to: DocId) -> Result<f32> {
+        self.scorer.get_max_score(up_to)
+    }
+
+    fn set_min_competitive_score(&mut self, min_score: f32) {
+        self.scorer.set_min_competitive_score(min_score)
+    }
+
+    fn explain(&mut self, doc: DocId) -> Result<Explanation> {
+        self.scorer.explain(doc)
+    }
+}
+
+impl<S: Scorer, F: FnMut(DocId, f32) + Send> DocIterator for TappedScorer<S, F> {
+    fn doc_id(&self) -> DocId {
+        self.scorer.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        self.scorer.next()
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        self.scorer.advance(target)
+    }
+
+    fn cost(&self) -> usize {
+        self.scorer.cost()
+    }
+
+    fn matches(&mut self) -> Result<bool> {
+        self.scorer.matches()
+    }
+
+    fn match_cost(&self) -> f32 {
+        self.scorer.match_cost()
+    }
+
+    fn support_two_phase(&self) -> bool {
+        self.scorer.support_two_phase()
+    }
+
+    fn approximate_next(&mut self) -> Result<DocId> {
+        self.scorer.approximate_next()
+    }
+
+    fn approximate_advance(&mut self, target: DocId) -> Result<DocId> {
+        self.scorer.approximate_advance(target)
+    }
+
+    fn as_two_phase(&mut self) -> Option<&mut dyn TwoPhaseIterator> {
+        if self.scorer.support_two_phase() {
+            Some(self)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::tests::*;
+    use core::search::NO_MORE_DOCS;
+
+    #[test]
+    fn test_callback_sees_each_doc() {
+        let mut observed = vec![];
+        {
+            let inner = create_mock_scorer(vec![1, 3, 5, 7, 9]);
+            let mut scorer = TappedScorer::new(inner, |doc, score| observed.push((doc, score)));
+
+            assert_eq!(scorer.next().unwrap(), 1);
+            assert!((scorer.score().unwrap() - 1.0).abs() < ::std::f32::EPSILON);
+            assert_eq!(scorer.advance(4).unwrap(), 5);
+            scorer.score().unwrap();
+            // docs that are not scored are not reported
+            assert_eq!(scorer.next().unwrap(), 7);
+            loop {
+                let doc = scorer.next().unwrap();
+                if doc == NO_MORE_DOCS {
+                    break;
+                }
+                scorer.score().unwrap();
+            }
+        }
+        // the mock scorer scores by doc id
+        assert_eq!(observed, vec![(1, 1.0), (5, 5.0), (9, 9.0)]);
+    }
+
+    #[test]
+    fn test_forward_two_phase() {
+        let child = MockTwoPhaseScorer::new(vec![1, 3, 5], vec![3]);
+        let mut scorer = TappedScorer::new(child, |_, _| {});
+        {
+            let two_phase = scorer.as_two_phase().unwrap();
+            assert_eq!(two_phase.next_approximation().unwrap(), 1);
+            assert!(!two_phase.confirm_match().unwrap());
+            assert_eq!(two_phase.next_approximation().unwrap(), 3);
+            assert!(two_phase.confirm_match().unwrap());
+        }
+
+        let mut scorer = TappedScorer::new(create_mock_scorer(vec![1]), |_, _| {});
+        assert!(scorer.as_two_phase().is_none());
+    }
+}
@@ -12,15 +12,18 @@
 // limitations under the License.
 
 use core::codec::PostingIterator;
-use core::search::scorer::Scorer;
+use core::search::scorer::{PositionsScorer, Scorer};
 use core::search::similarity::SimScorer;
 use core::search::DocIterator;
 use core::util::DocId;
-use error::Result;
+use error::{ErrorKind::IllegalState, Result};
 
 pub struct TermScorer<T: PostingIterator> {
     sim_scorer: Box<dyn SimScorer>,
     postings_iterator: T,
+    // positions can only be read once per doc, so they are cached for `positions_doc`
+    positions: Vec<u32>,
+    positions_doc: DocId,
 }
 
 impl<T: PostingIterator> TermScorer<T> {
@@ -28,10 +31,12 @@ impl<T: PostingIterator> TermScorer<T> {
         TermScorer {
             sim_scorer,
             postings_iterator,
+            positions: Vec::new(),
+            positions_doc: -1,
         }
     }
 
-    fn freq(&self) -> i32 {
+    fn term_freq(&self) -> i32 {
         if let Ok(f) = self.postings_iterator.freq() {
             f
         } else {
@@ -43,9 +48,34 @@ impl<T: PostingIterator> TermScorer<T> {
 impl<T: PostingIterator> Scorer for TermScorer<T> {
     fn score(&mut self) -> Result<f32> {
         let doc_id = self.doc_id();
-        let freq = self.freq();
+        let freq = self.term_freq();
         Ok(self.sim_scorer.score(doc_id, freq as f32)?)
     }
+
+    fn freq(&self) -> Result<f32> {
+        Ok(self.term_freq() as f32)
+    }
+}
+
+impl<T: PostingIterator> PositionsScorer for TermScorer<T> {
+    fn positions(&mut self) -> Result<Vec<u32>> {
+        let doc = self.doc_id();
+        if doc != self.positions_doc {
+            self.positions.clear();
+            for _ in 0..self.postings_iterator.freq()? {
+                let pos = self.postings_iterator.next_position()?;
+                if pos < 0 {
+                    bail!(IllegalState(format!(
+                        "positions are not available for doc {}",
+                        doc
+                    )));
+                }
+                self.positions.push(pos as u32);
+            }
+            self.positions_doc = doc;
+        }
+        Ok(self.positions.clone())
+    }
 }
 
 impl<T: PostingIterator> DocIterator for TermScorer<T> {
@@ -65,3 +95,56 @@ impl<T: PostingIterator> DocIterator for TermScorer<T> {
         self.postings_iterator.cost()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::scorer::BoostingScorer;
+    use core::search::tests::*;
+    use core::search::NO_MORE_DOCS;
+
+    #[test]
+    fn test_freq() {
+        let postings =
+            MockPostingIterator::new(vec![(1, vec![0, 3, 7]), (4, vec![2]), (6, vec![1, 5])]);
+        let mut scorer = TermScorer::new(Box::new(MockSimScorer), postings);
+
+        assert_eq!(scorer.next().unwrap(), 1);
+        assert!((scorer.freq().unwrap() - 3.0).abs() < ::std::f32::EPSILON);
+        assert!((scorer.score().unwrap() - 3.0).abs() < ::std::f32::EPSILON);
+
+        assert_eq!(scorer.next().unwrap(), 4);
+        assert!((scorer.freq().unwrap() - 1.0).abs() < ::std::f32::EPSILON);
+
+        assert_eq!(scorer.advance(5).unwrap(), 6);
+        assert!((scorer.freq().unwrap() - 2.0).abs() < ::std::f32::EPSILON);
+
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+    }
+
+    #[test]
+    fn test_boosting_scorer_forward_freq() {
+        let postings = MockPostingIterator::new(vec![(2, vec![1, 4])]);
+        let positive: Box<dyn Scorer> =
+            Box::new(TermScorer::new(Box::new(MockSimScorer), postings));
+        let negative: Box<dyn Scorer> = Box::new(create_mock_scorer(vec![2]));
+        let mut scorer = BoostingScorer::new(positive, negative, 0.5);
+
+        assert_eq!(scorer.next().unwrap(), 2);
+        assert!((scorer.freq().unwrap() - 2.0).abs() < ::std::f32::EPSILON);
+    }
+
+    #[test]
+    fn test_positions() {
+        let postings = MockPostingIterator::new(vec![(1, vec![0, 3, 7]), (4, vec![2])]);
+        let mut scorer = TermScorer::new(Box::new(MockSimScorer), postings);
+
+        assert_eq!(scorer.next().unwrap(), 1);
+        assert_eq!(scorer.positions().unwrap(), vec![0, 3, 7]);
+        // may be called again on the same doc
+        assert_eq!(scorer.positions().unwrap(), vec![0, 3, 7]);
+
+        assert_eq!(scorer.next().unwrap(), 4);
+        assert_eq!(scorer.positions().unwrap(), vec![2]);
+    }
+}
@@ -0,0 +1,193 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::search;
+use core::search::scorer::{FeatureResult, ScoreMode, Scorer};
+use core::search::{DocIterator, Explanation};
+use core::util::DocId;
+use error::{ErrorKind, Result};
+
+use std::time::{Duration, Instant};
+
+/// A `Scorer` which aborts the iteration of `child` once a deadline is
+/// passed, eg. to enforce a time budget per query.
+///
+/// The clock is read on the first `next`/`advance` call and then once every
+/// `check_interval` calls, so that the cost of reading it is amortized. Once
+/// the deadline is passed, `next` and `advance` fail with
+/// `search::ErrorKind::TimeExceeded`, otherwise everything is forwarded to
+/// `child`.
+pub struct TimeLimitingScorer<S: Scorer> {
+    child: S,
+    deadline: Instant,
+    check_interval: usize,
+    // number of `next`/`advance` calls since the last check
+    calls: usize,
+}
+
+impl<S: Scorer> TimeLimitingScorer<S> {
+    pub fn new(child: S, deadline: Instant, check_interval: usize) -> Self {
+        debug_assert!(check_interval > 0);
+        TimeLimitingScorer {
+            child,
+            deadline,
+            check_interval: check_interval.max(1),
+            calls: 0,
+        }
+    }
+
+    /// Creates a scorer whose deadline is `timeout` from now.
+    pub fn with_timeout(child: S, timeout: Duration, check_interval: usize) -> Self {
+        Self::new(child, Instant::now() + timeout, check_interval)
+    }
+
+    fn check_deadline(&mut self) -> Result<()> {
+        if self.calls == 0 && Instant::now() >= self.deadline {
+            bail!(ErrorKind::Search(search::ErrorKind::TimeExceeded));
+        }
+        self.calls += 1;
+        if self.calls == self.check_interval {
+            self.calls = 0;
+        }
+        Ok(())
+    }
+}
+
+impl<S: Scorer> Scorer for TimeLimitingScorer<S> {
+    fn score(&mut self) -> Result<f32> {
+        self.child.score()
+    }
+
+    fn score_feature(&mut self) -> Result<Vec<FeatureResult>> {
+        self.child.score_feature()
+    }
+
+    fn freq(&self) -> Result<f32> {
+        self.child.freq()
+    }
+
+    fn set_score_mode(&mut self, mode: ScoreMode) {
+        self.child.set_score_mode(mode)
+    }
+
+    fn advance_shallow(&mut self, target: DocId) -> Result<DocId> {
+        self.child.advance_shallow(target)
+    }
+
+    fn get_max_score(&mut self, up_to: DocId) -> Result<f32> {
+        self.child.get_max_score(up_to)
+    }
+
+    fn set_min_competitive_score(&mut self, min_score: f32) {
+        self.child.set_min_competitive_score(min_score)
+    }
+
+    fn explain(&mut self, doc: DocId) -> Result<Explanation> {
+        self.child.explain(doc)
+    }
+}
+
+impl<S: Scorer> DocIterator for TimeLimitingScorer<S> {
+    fn doc_id(&self) -> DocId {
+        self.child.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        self.check_deadline()?;
+        self.child.next()
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        self.check_deadline()?;
+        self.child.advance(target)
+    }
+
+    fn cost(&self) -> usize {
+        self.child.cost()
+    }
+
+    fn matches(&mut self) -> Result<bool> {
+        self.child.matches()
+    }
+
+    fn match_cost(&self) -> f32 {
+        self.child.match_cost()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::tests::*;
+    use core::search::NO_MORE_DOCS;
+    use error::Error;
+
+    fn is_time_exceeded(e: &Error) -> bool {
+        match e.kind() {
+            ErrorKind::Search(search::ErrorKind::TimeExceeded) => true,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn test_forward_before_deadline() {
+        let child = create_mock_scorer(vec![1, 3, 7]);
+        let mut scorer = TimeLimitingScorer::with_timeout(child, Duration::from_secs(3600), 2);
+        assert_eq!(scorer.next().unwrap(), 1);
+        assert!((scorer.score().unwrap() - 1.0).abs() < ::std::f32::EPSILON);
+        assert_eq!(scorer.advance(5).unwrap(), 7);
+        assert_eq!(scorer.doc_id(), 7);
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+        assert_eq!(scorer.cost(), 3);
+    }
+
+    #[test]
+    fn test_deadline_in_past() {
+        let docs: Vec<DocId> = (0..10_000).collect();
+        let deadline = Instant::now() - Duration::from_millis(10);
+
+        // the first call already checks the clock
+        let mut scorer = TimeLimitingScorer::new(create_mock_scorer(docs.clone()), deadline, 1024);
+        let err = scorer.next().unwrap_err();
+        assert!(is_time_exceeded(&err));
+        assert_eq!(scorer.doc_id(), -1);
+
+        let err = scorer.advance(10).unwrap_err();
+        assert!(is_time_exceeded(&err));
+    }
+
+    #[test]
+    fn test_check_interval() {
+        let docs: Vec<DocId> = (0..100).collect();
+        let child = create_mock_scorer(docs);
+        let mut scorer = TimeLimitingScorer::with_timeout(child, Duration::from_secs(3600), 10);
+        for doc in 0..5 {
+            assert_eq!(scorer.next().unwrap(), doc);
+        }
+
+        // the calls between two checks don't read the clock, so they don't
+        // notice the deadline is passed
+        scorer.deadline = Instant::now() - Duration::from_millis(10);
+        let mut calls = 0;
+        let err = loop {
+            match scorer.next() {
+                Ok(_) => calls += 1,
+                Err(e) => break e,
+            }
+        };
+        assert!(is_time_exceeded(&err));
+        // aborted at the next check, eg. the 11th call
+        assert_eq!(calls, 5);
+        assert_eq!(scorer.doc_id(), 9);
+    }
+}
@@ -16,8 +16,6 @@ use std::fmt;
 use std::ops::Deref;
 use std::sync::Arc;
 
-use crossbeam::channel::{unbounded, Receiver, Sender};
-
 use core::codec::postings::FieldsProducer;
 use core::codec::{Codec, Terms};
 use core::codec::{TermIterator, TermState};
@@ -26,7 +24,9 @@ use core::index::reader::{IndexReader, LeafReaderContext, LeafReaderContextPtr,
 use core::search::cache::{
     LRUQueryCache, QueryCache, QueryCachingPolicy, UsageTrackingQueryCachingPolicy,
 };
-use core::search::collector::{self, Collector, ParallelLeafCollector, SearchCollector};
+use core::search::collector::{
+    self, Collector, ParallelLeafCollector, SearchCollector, TotalHitCountCollector,
+};
 use core::search::explanation::Explanation;
 use core::search::query::{ConstantScoreQuery, MatchAllDocsQuery, Query, TermQuery, Weight};
 use core::search::scorer::{BulkScorer, Scorer};
@@ -771,104 +771,6 @@ where
     }
 }
 
-struct TotalHitCountCollector {
-    total_hits: i32,
-    channel: Option<(Sender<i32>, Receiver<i32>)>,
-}
-
-impl TotalHitCountCollector {
-    pub fn new() -> Self {
-        TotalHitCountCollector {
-            total_hits: 0,
-            channel: None,
-        }
-    }
-
-    pub fn total_hits(&self) -> i32 {
-        self.total_hits
-    }
-}
-
-impl SearchCollector for TotalHitCountCollector {
-    type LC = TotalHitsCountLeafCollector;
-    fn set_next_reader<C: Codec>(&mut self, _reader: &LeafReaderContext<'_, C>) -> Result<()> {
-        Ok(())
-    }
-
-    fn support_parallel(&self) -> bool {
-        true
-    }
-
-    fn init_parallel(&mut self) {
-        if self.channel.is_none() {
-            self.channel = Some(unbounded());
-        }
-    }
-
-    fn leaf_collector<C: Codec>(
-        &self,
-        _reader: &LeafReaderContext<'_, C>,
-    ) -> Result<TotalHitsCountLeafCollector> {
-        Ok(TotalHitsCountLeafCollector {
-            count: 0,
-            sender: self.channel.as_ref().unwrap().0.clone(),
-        })
-    }
-
-    fn finish_parallel(&mut self) -> Result<()> {
-        let channel = self.channel.take();
-        // iff all the `weight.create_scorer(leaf_reader)` return None, the channel won't
-        // inited and thus stay None
-        if let Some((sender, receiver)) = channel {
-            drop(sender);
-            while let Ok(v) = receiver.recv() {
-                self.total_hits += v;
-            }
-        }
-
-        Ok(())
-    }
-}
-
-impl Collector for TotalHitCountCollector {
-    fn needs_scores(&self) -> bool {
-        false
-    }
-
-    fn collect<S: Scorer + ?Sized>(&mut self, _doc: i32, _scorer: &mut S) -> Result<()> {
-        self.total_hits += 1;
-        Ok(())
-    }
-}
-
-struct TotalHitsCountLeafCollector {
-    count: i32,
-    sender: Sender<i32>,
-}
-
-impl Collector for TotalHitsCountLeafCollector {
-    fn needs_scores(&self) -> bool {
-        false
-    }
-
-    fn collect<S: Scorer + ?Sized>(&mut self, _doc: i32, _scorer: &mut S) -> Result<()> {
-        self.count += 1;
-        Ok(())
-    }
-}
-
-impl ParallelLeafCollector for TotalHitsCountLeafCollector {
-    fn finish_leaf(&mut self) -> Result<()> {
-        self.sender.send(self.count).map_err(|e| {
-            ErrorKind::IllegalState(format!(
-                "channel unexpected closed before search complete with err: {:?}",
-                e
-            ))
-            .into()
-        })
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -338,6 +338,47 @@ impl SortedNumericSelector {
         };
         Ok(res)
     }
+
+    /// Reduces the numeric values of a multi-valued doc, eg. a
+    /// `VariantValue::Vec`, to the single value used as its sort key, a single
+    /// numeric value is its own key.
+    ///
+    /// Integer values are compared exactly and mixed with floating point
+    /// values as `f64`, NaN sorts after all other values. Returns `None` if
+    /// there is no value or if any value is not numeric.
+    pub fn select(
+        values: &VariantValue,
+        selector: SortedNumericSelectorType,
+    ) -> Option<VariantValue> {
+        let values = match values {
+            VariantValue::Vec(v) => v,
+            v if v.get_number().is_some() => return Some(v.clone()),
+            _ => return None,
+        };
+        if values.iter().any(|v| v.get_number().is_none()) {
+            return None;
+        }
+
+        let integral = |v: &VariantValue| match *v {
+            VariantValue::Short(s) => Some(i64::from(s)),
+            VariantValue::Int(i) => Some(i64::from(i)),
+            VariantValue::Long(l) => Some(l),
+            _ => None,
+        };
+        let cmp = |a: &&VariantValue, b: &&VariantValue| match (integral(a), integral(b)) {
+            (Some(x), Some(y)) => x.cmp(&y),
+            _ => {
+                let (x, y) = (a.get_number().unwrap(), b.get_number().unwrap());
+                x.partial_cmp(&y)
+                    .unwrap_or_else(|| x.is_nan().cmp(&y.is_nan()))
+            }
+        };
+        let selected = match selector {
+            SortedNumericSelectorType::Min => values.iter().min_by(cmp),
+            SortedNumericSelectorType::Max => values.iter().max_by(cmp),
+        };
+        selected.cloned()
+    }
 }
 
 struct SortableFloatNumericDocValues {
@@ -470,4 +511,45 @@ mod tests {
         assert_eq!(SortFieldType::Doc, sort_field.field_type());
         assert_eq!(true, sort_field.is_reverse());
     }
+
+    #[test]
+    fn test_sorted_numeric_select() {
+        let values = VariantValue::Vec(vec![
+            VariantValue::Long(7),
+            VariantValue::Int(-3),
+            VariantValue::Double(2.5),
+            VariantValue::Short(12),
+        ]);
+        assert_eq!(
+            SortedNumericSelector::select(&values, SortedNumericSelectorType::Min),
+            Some(VariantValue::Int(-3))
+        );
+        assert_eq!(
+            SortedNumericSelector::select(&values, SortedNumericSelectorType::Max),
+            Some(VariantValue::Short(12))
+        );
+
+        // large longs are compared exactly, NaN is the max
+        let values = VariantValue::Vec(vec![
+            VariantValue::Long(i64::max_value()),
+            VariantValue::Long(i64::max_value() - 1),
+            VariantValue::Double(::std::f64::NAN),
+        ]);
+        assert_eq!(
+            SortedNumericSelector::select(&values, SortedNumericSelectorType::Min),
+            Some(VariantValue::Long(i64::max_value() - 1))
+        );
+        let max = SortedNumericSelector::select(&values, SortedNumericSelectorType::Max);
+        assert!(max.unwrap().get_double().unwrap().is_nan());
+
+        let single = VariantValue::Float(1.5);
+        assert_eq!(
+            SortedNumericSelector::select(&single, SortedNumericSelectorType::Max),
+            Some(single)
+        );
+        let empty = VariantValue::Vec(vec![]);
+        assert!(SortedNumericSelector::select(&empty, SortedNumericSelectorType::Min).is_none());
+        let mixed = VariantValue::Vec(vec![VariantValue::Int(1), VariantValue::from("a")]);
+        assert!(SortedNumericSelector::select(&mixed, SortedNumericSelectorType::Min).is_none());
+    }
 }
@@ -98,17 +98,17 @@ impl DocIdSetBuilder {
     /// `DocIterator`, you should rather use `RoaringDocIdSet.Builder`.
     pub fn add(&mut self, iter: &mut dyn DocIterator) -> Result<()> {
         if let Some(ref mut bit_set) = self.bit_set {
-            bit_set.or(iter)?;
-        } else {
-            let cost = min(i32::max_value() as usize, iter.cost());
-            self.grow(cost);
-            for _ in 0..cost {
-                let doc = iter.next()?;
-                if doc == NO_MORE_DOCS {
-                    return Ok(());
-                }
-                self.add_doc(doc);
+            // `or` exhausts `iter`, it must not be advanced any further
+            return bit_set.or(iter);
+        }
+        let cost = min(i32::max_value() as usize, iter.cost());
+        self.grow(cost);
+        for _ in 0..cost {
+            let doc = iter.next()?;
+            if doc == NO_MORE_DOCS {
+                return Ok(());
             }
+            self.add_doc(doc);
         }
         loop {
             let doc = iter.next()?;
@@ -334,3 +334,64 @@ impl Buffer {
         Buffer { array, length }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::search::tests::MockDocIterator;
+    use core::search::DocIdSet;
+
+    /// fails if it's advanced once exhausted, like iterators computing
+    /// `doc + 1` would overflow
+    struct ExhaustedOnceIterator(MockDocIterator);
+
+    impl DocIterator for ExhaustedOnceIterator {
+        fn doc_id(&self) -> DocId {
+            self.0.doc_id()
+        }
+
+        fn next(&mut self) -> Result<DocId> {
+            assert_ne!(self.0.doc_id(), NO_MORE_DOCS, "advanced once exhausted");
+            self.0.next()
+        }
+
+        fn advance(&mut self, target: DocId) -> Result<DocId> {
+            assert_ne!(self.0.doc_id(), NO_MORE_DOCS, "advanced once exhausted");
+            self.0.advance(target)
+        }
+
+        fn cost(&self) -> usize {
+            self.0.cost()
+        }
+    }
+
+    #[test]
+    fn test_add_to_bit_set() {
+        // the threshold is 1024 >> 7 = 8 docs, the first iterator upgrades
+        // the builder to a bit set
+        let mut builder = DocIdSetBuilder::with_max_doc(1024);
+        let first: Vec<DocId> = (0..20).map(|i| i * 10).collect();
+        builder
+            .add(&mut ExhaustedOnceIterator(MockDocIterator::new(first)))
+            .unwrap();
+        builder
+            .add(&mut ExhaustedOnceIterator(MockDocIterator::new(vec![
+                5, 15, 1000,
+            ])))
+            .unwrap();
+
+        let mut iter = builder.build().iterator().unwrap().unwrap();
+        let mut docs = vec![];
+        loop {
+            let doc = iter.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            docs.push(doc);
+        }
+        let mut expected: Vec<DocId> = (0..20).map(|i| i * 10).collect();
+        expected.extend_from_slice(&[5, 15, 1000]);
+        expected.sort();
+        assert_eq!(docs, expected);
+    }
+}
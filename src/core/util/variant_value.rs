@@ -11,14 +11,24 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use fasthash::xx;
 use serde;
 use serde::ser::{SerializeMap, SerializeSeq};
+use serde_json::{self, Number, Value};
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
 use std::fmt;
 use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
+use std::sync::Arc;
 
 use core::util::numeric::Numeric;
+use error::{
+    Error,
+    ErrorKind::{IllegalArgument, UnexpectedEOF},
+    Result,
+};
 
 #[derive(Debug, Clone, Deserialize)]
 pub enum VariantValue {
@@ -33,15 +43,156 @@ pub enum VariantValue {
     Binary(Vec<u8>),
     Vec(Vec<VariantValue>),
     Map(HashMap<String, VariantValue>),
+    /// A string shared with other values, see `intern_strings`.
+    #[serde(skip_deserializing)]
+    VStringShared(Arc<str>),
+    /// A map iterated in key order, see `to_sorted`. It is a map for all the
+    /// methods walking nested values, but `get_map` only returns a `Map`.
+    SortedMap(BTreeMap<String, VariantValue>),
+}
+
+/// The kind of a numeric `VariantValue`, eg. the target of `widen`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NumericKind {
+    Short,
+    Int,
+    Long,
+    Float,
+    Double,
+}
+
+/// The kind of a `VariantValue`, eg. the type of a field in a schema.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VariantKind {
+    Bool,
+    Char,
+    Short,
+    Int,
+    Long,
+    Float,
+    Double,
+    VString,
+    Binary,
+    Vec,
+    Map,
 }
 
 impl VariantValue {
+    // Constructors of the scalar variants, eg. `VariantValue::long(1)`, which
+    // can be used in const contexts and as `fn` values, eg. `map(VariantValue::long)`.
+
+    #[inline]
+    pub const fn bool(v: bool) -> VariantValue {
+        VariantValue::Bool(v)
+    }
+
+    #[inline]
+    pub const fn char(v: char) -> VariantValue {
+        VariantValue::Char(v)
+    }
+
+    #[inline]
+    pub const fn short(v: i16) -> VariantValue {
+        VariantValue::Short(v)
+    }
+
+    #[inline]
+    pub const fn int(v: i32) -> VariantValue {
+        VariantValue::Int(v)
+    }
+
+    #[inline]
+    pub const fn long(v: i64) -> VariantValue {
+        VariantValue::Long(v)
+    }
+
+    #[inline]
+    pub const fn float(v: f32) -> VariantValue {
+        VariantValue::Float(v)
+    }
+
+    #[inline]
+    pub const fn double(v: f64) -> VariantValue {
+        VariantValue::Double(v)
+    }
+
+    /// Takes ownership of `v`, unlike `From<&str>` which copies it.
+    #[inline]
+    pub const fn vstring(v: String) -> VariantValue {
+        VariantValue::VString(v)
+    }
+
+    /// Takes ownership of `v`, unlike `From<&[u8]>` which copies it.
+    #[inline]
+    pub const fn binary(v: Vec<u8>) -> VariantValue {
+        VariantValue::Binary(v)
+    }
+
+    pub fn kind(&self) -> VariantKind {
+        match self {
+            VariantValue::Bool(_) => VariantKind::Bool,
+            VariantValue::Char(_) => VariantKind::Char,
+            VariantValue::Short(_) => VariantKind::Short,
+            VariantValue::Int(_) => VariantKind::Int,
+            VariantValue::Long(_) => VariantKind::Long,
+            VariantValue::Float(_) => VariantKind::Float,
+            VariantValue::Double(_) => VariantKind::Double,
+            VariantValue::VString(_) | VariantValue::VStringShared(_) => VariantKind::VString,
+            VariantValue::Binary(_) => VariantKind::Binary,
+            VariantValue::Vec(_) => VariantKind::Vec,
+            VariantValue::Map(_) | VariantValue::SortedMap(_) => VariantKind::Map,
+        }
+    }
+
+    /// Returns the width of a numeric value, the same variants as
+    /// `get_numeric`, `None` for the other variants.
+    pub fn numeric_kind(&self) -> Option<NumericKind> {
+        match self {
+            VariantValue::Short(_) => Some(NumericKind::Short),
+            VariantValue::Int(_) => Some(NumericKind::Int),
+            VariantValue::Long(_) => Some(NumericKind::Long),
+            VariantValue::Float(_) => Some(NumericKind::Float),
+            VariantValue::Double(_) => Some(NumericKind::Double),
+            _ => None,
+        }
+    }
+
     pub fn get_bool(&self) -> Option<bool> {
         match self {
             VariantValue::Bool(b) => Some(*b),
             _ => None,
         }
     }
+
+    /// Same as `get_bool`, but also accepts the integral values 0 and 1 and
+    /// the strings "true"/"false", "yes"/"no" and "1"/"0" ignoring case, eg.
+    /// booleans ingested from text, surrounding whitespace is ignored. Other
+    /// values return `None`.
+    pub fn get_bool_lenient(&self) -> Option<bool> {
+        if let Some(s) = self.get_string() {
+            let s = s.trim();
+            return if s.eq_ignore_ascii_case("true") || s.eq_ignore_ascii_case("yes") || s == "1" {
+                Some(true)
+            } else if s.eq_ignore_ascii_case("false") || s.eq_ignore_ascii_case("no") || s == "0" {
+                Some(false)
+            } else {
+                None
+            };
+        }
+        let number = match *self {
+            VariantValue::Bool(b) => return Some(b),
+            VariantValue::Short(s) => i64::from(s),
+            VariantValue::Int(i) => i64::from(i),
+            VariantValue::Long(l) => l,
+            _ => return None,
+        };
+        match number {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+
     pub fn get_char(&self) -> Option<char> {
         match self {
             VariantValue::Char(c) => Some(*c),
@@ -76,6 +227,85 @@ impl VariantValue {
             _ => None,
         }
     }
+    /// Returns any numeric variant coerced to `f64`, `Bool` and non-numeric
+    /// variants return `None`.
+    ///
+    /// This is meant for aggregation math where the exact type doesn't matter.
+    /// Note that `Long` values beyond 2^53 can't be represented exactly by `f64`
+    /// and will lose precision.
+    pub fn get_number(&self) -> Option<f64> {
+        self.get_number_with_bool(false)
+    }
+
+    /// Same as `get_number`, except that `Bool` is coerced to `1.0`/`0.0` if
+    /// `bool_as_number` is true.
+    pub fn get_number_with_bool(&self, bool_as_number: bool) -> Option<f64> {
+        match *self {
+            VariantValue::Bool(b) if bool_as_number => Some(if b { 1.0 } else { 0.0 }),
+            VariantValue::Short(s) => Some(f64::from(s)),
+            VariantValue::Int(i) => Some(f64::from(i)),
+            VariantValue::Long(l) => Some(l as f64),
+            VariantValue::Float(f) => Some(f64::from(f)),
+            VariantValue::Double(d) => Some(d),
+            _ => None,
+        }
+    }
+
+    /// Same as `get_number`, but also returns whether precision was lost, eg.
+    /// for `Long` values beyond 2^53 which `f64` can't represent exactly.
+    /// The conversions of the other numeric variants are always exact.
+    pub fn to_f64_lossy(&self) -> Option<(f64, bool)> {
+        match *self {
+            VariantValue::Long(l) => {
+                let d = l as f64;
+                // compare in i128 as `d` may be 2^63, beyond `i64::MAX`
+                Some((d, d as i128 != i128::from(l)))
+            }
+            _ => self.get_number().map(|d| (d, false)),
+        }
+    }
+
+    /// Returns any numeric variant converted to `i32`, values out of range are
+    /// clamped to `i32::MIN` or `i32::MAX` and floating point values are
+    /// truncated toward zero. `NaN` and non-numeric variants return `None`.
+    ///
+    /// This is explicitly lossy, `get_int` is the checked version.
+    pub fn get_i32_saturating(&self) -> Option<i32> {
+        self.saturating_integer(i64::from(i32::MIN), i64::from(i32::MAX))
+            .map(|v| v as i32)
+    }
+
+    /// Same as `get_i32_saturating`, but clamps to the bounds of `i16`.
+    pub fn get_i16_saturating(&self) -> Option<i16> {
+        self.saturating_integer(i64::from(i16::MIN), i64::from(i16::MAX))
+            .map(|v| v as i16)
+    }
+
+    /// Same as `get_i32_saturating`, but clamps to the bounds of `u8`, eg.
+    /// negative values become 0.
+    pub fn get_u8_saturating(&self) -> Option<u8> {
+        self.saturating_integer(i64::from(u8::MIN), i64::from(u8::MAX))
+            .map(|v| v as u8)
+    }
+
+    fn saturating_integer(&self, min: i64, max: i64) -> Option<i64> {
+        let value = match *self {
+            VariantValue::Short(s) => i64::from(s),
+            VariantValue::Int(i) => i64::from(i),
+            VariantValue::Long(l) => l,
+            VariantValue::Float(_) | VariantValue::Double(_) => {
+                let d = self.get_number()?;
+                if d.is_nan() {
+                    return None;
+                }
+                // the bounds are exact in `f64`
+                return Some(d.max(min as f64).min(max as f64) as i64);
+            }
+            _ => return None,
+        };
+        Some(value.max(min).min(max))
+    }
+
     pub fn get_float(&self) -> Option<f32> {
         match self {
             VariantValue::Float(f) => Some(*f),
@@ -91,6 +321,7 @@ impl VariantValue {
     pub fn get_string(&self) -> Option<&str> {
         match self {
             VariantValue::VString(s) => Some(s.as_str()),
+            VariantValue::VStringShared(s) => Some(s),
             _ => None,
         }
     }
@@ -104,6 +335,7 @@ impl VariantValue {
     pub fn get_utf8_string(&self) -> Option<String> {
         match self {
             VariantValue::VString(s) => Some(s.clone()),
+            VariantValue::VStringShared(s) => Some(s.to_string()),
             VariantValue::Binary(b) => {
                 if let Ok(s) = String::from_utf8(b.clone()) {
                     Some(s)
@@ -135,342 +367,4867 @@ impl VariantValue {
         }
     }
 
+    /// Returns the elements of a `Vec` as `i64` if all of them are integral
+    /// variants, eg. `Short`, `Int` or `Long`, otherwise returns `None`.
+    pub fn get_i64_vec(&self) -> Option<Vec<i64>> {
+        self.get_vec()?
+            .iter()
+            .map(|v| match *v {
+                VariantValue::Short(s) => Some(i64::from(s)),
+                VariantValue::Int(i) => Some(i64::from(i)),
+                VariantValue::Long(l) => Some(l),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the elements of a `Vec` as `f64` if all of them are numeric,
+    /// otherwise returns `None`. The elements are coerced like `get_number`.
+    pub fn get_f64_vec(&self) -> Option<Vec<f64>> {
+        self.get_vec()?
+            .iter()
+            .map(VariantValue::get_number)
+            .collect()
+    }
+
+    /// Returns the elements of a `Vec` as borrowed strings if all of them are
+    /// strings, eg. the values of a repeated keyword field, otherwise returns
+    /// `None`. Unlike `get_utf8_string` nothing is copied and `Binary`
+    /// elements are not decoded.
+    pub fn get_str_array(&self) -> Option<Vec<&str>> {
+        self.get_vec()?
+            .iter()
+            .map(VariantValue::get_string)
+            .collect()
+    }
+
     pub fn get_map(&self) -> Option<&HashMap<String, VariantValue>> {
         match self {
             VariantValue::Map(m) => Some(m),
             _ => None,
         }
     }
-}
-
-impl Eq for VariantValue {}
 
-impl fmt::Display for VariantValue {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            VariantValue::Bool(b) => write!(f, "{}", b),
-            VariantValue::Char(c) => write!(f, "{}", c),
-            VariantValue::Short(s) => write!(f, "{}s", s),
-            VariantValue::Int(ival) => write!(f, "{}", ival),
-            VariantValue::Long(lval) => write!(f, "{}", lval),
-            VariantValue::Float(fval) => write!(f, "{:.3}", fval),
-            VariantValue::Double(d) => write!(f, "{:.6}", d),
-            VariantValue::VString(ref s) => write!(f, "{}", s),
-            VariantValue::Binary(ref _b) => write!(f, "Binary(unprintable)"),
-            VariantValue::Vec(ref v) => write!(f, "{:?}", v),
-            VariantValue::Map(ref m) => write!(f, "{:?}", m),
+    pub fn get_map_mut(&mut self) -> Option<&mut HashMap<String, VariantValue>> {
+        match self {
+            VariantValue::Map(m) => Some(m),
+            _ => None,
         }
     }
-}
 
-impl serde::Serialize for VariantValue {
-    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        match *self {
-            VariantValue::Bool(b) => serializer.serialize_bool(b),
-            VariantValue::Char(c) => serializer.serialize_char(c),
-            VariantValue::Short(s) => serializer.serialize_i16(s),
-            VariantValue::Int(ival) => serializer.serialize_i32(ival),
-            VariantValue::Long(lval) => serializer.serialize_i64(lval),
-            VariantValue::Float(fval) => serializer.serialize_f32(fval),
-            VariantValue::Double(d) => serializer.serialize_f64(d),
-            VariantValue::VString(ref s) => serializer.serialize_str(s.as_str()),
-            VariantValue::Binary(ref b) => serializer.serialize_bytes(b),
-            VariantValue::Vec(ref vec) => {
-                let mut seq = serializer.serialize_seq(Some(vec.len())).unwrap();
-                for v in vec {
-                    seq.serialize_element(v)?;
-                }
+    pub fn get_sorted_map(&self) -> Option<&BTreeMap<String, VariantValue>> {
+        match self {
+            VariantValue::SortedMap(m) => Some(m),
+            _ => None,
+        }
+    }
 
-                seq.end()
+    /// Returns a copy of this value where all the maps, including nested
+    /// ones, are `SortedMap`s, eg. so that snapshot tests walking a document
+    /// always see its keys in the same order.
+    pub fn to_sorted(&self) -> VariantValue {
+        match self {
+            VariantValue::Map(m) => {
+                VariantValue::SortedMap(m.iter().map(|(k, v)| (k.clone(), v.to_sorted())).collect())
             }
-            VariantValue::Map(ref m) => {
-                let mut map = serializer.serialize_map(Some(m.len())).unwrap();
-                for (k, v) in m {
-                    map.serialize_entry(&k.to_string(), &v)?;
-                }
-                map.end()
+            VariantValue::SortedMap(m) => {
+                VariantValue::SortedMap(m.iter().map(|(k, v)| (k.clone(), v.to_sorted())).collect())
+            }
+            VariantValue::Vec(v) => {
+                VariantValue::Vec(v.iter().map(VariantValue::to_sorted).collect())
             }
+            v => v.clone(),
         }
     }
-}
 
-impl Hash for VariantValue {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        match *self {
-            VariantValue::Bool(ref b) => b.hash(state),
-            VariantValue::Char(ref c) => c.hash(state),
-            VariantValue::Short(ref s) => s.hash(state),
-            VariantValue::Int(ref i) => i.hash(state),
-            VariantValue::Long(ref l) => l.hash(state),
-            VariantValue::Float(ref f) => f.to_bits().hash(state),
-            VariantValue::Double(ref d) => d.to_bits().hash(state),
-            VariantValue::VString(ref s) => s.hash(state),
-            VariantValue::Binary(ref v) => v.hash(state),
-            _ => (),
+    // the entries of a `Map` or a `SortedMap` in key order
+    fn map_entries(&self) -> Option<Vec<(&String, &VariantValue)>> {
+        match self {
+            VariantValue::Map(m) => Some(sorted_entries(m)),
+            VariantValue::SortedMap(m) => Some(m.iter().collect()),
+            _ => None,
         }
     }
-}
 
-impl PartialEq for VariantValue {
-    fn eq(&self, other: &VariantValue) -> bool {
-        match *self {
-            VariantValue::Bool(ref b) => {
-                if let VariantValue::Bool(ref o) = *other {
-                    b.eq(o)
-                } else {
-                    false
-                }
-            }
-            VariantValue::Char(ref c) => {
-                if let VariantValue::Char(ref o) = *other {
-                    c.eq(o)
-                } else {
-                    false
-                }
-            }
-            VariantValue::Short(ref s) => {
-                if let VariantValue::Short(ref o) = *other {
-                    s.eq(o)
-                } else {
-                    false
-                }
-            }
-            VariantValue::Int(ref i) => {
-                if let VariantValue::Int(ref o) = *other {
-                    i.eq(o)
-                } else {
-                    false
-                }
-            }
-            VariantValue::Long(ref l) => {
-                if let VariantValue::Long(ref o) = *other {
-                    l.eq(o)
-                } else {
-                    false
-                }
+    // the value of `key` in a `Map` or a `SortedMap`
+    fn map_get(&self, key: &str) -> Option<&VariantValue> {
+        match self {
+            VariantValue::Map(m) => m.get(key),
+            VariantValue::SortedMap(m) => m.get(key),
+            _ => None,
+        }
+    }
+
+    /// Inserts `val` under `key` if this is a `Map` or a `SortedMap`,
+    /// returning the previous value of `key`. This is a no-op returning
+    /// `None` for other variants.
+    pub fn insert<K: Into<String>>(&mut self, key: K, val: VariantValue) -> Option<VariantValue> {
+        match self {
+            VariantValue::Map(m) => m.insert(key.into(), val),
+            VariantValue::SortedMap(m) => m.insert(key.into(), val),
+            _ => None,
+        }
+    }
+
+    /// Appends all the values of `iter` in place if this is a `Vec`.
+    ///
+    /// This is the counterpart of `Extend`, which can't report that `self`
+    /// is another variant, in that case an error is returned and `iter` is
+    /// not consumed.
+    pub fn extend_vec<I: IntoIterator<Item = VariantValue>>(&mut self, iter: I) -> Result<()> {
+        match self {
+            VariantValue::Vec(v) => {
+                v.extend(iter);
+                Ok(())
             }
-            VariantValue::Float(ref f) => {
-                if let VariantValue::Float(ref o) = *other {
-                    f.eq(o)
-                } else {
-                    false
-                }
+            _ => bail!(IllegalArgument(
+                "only a vec value can be extended by values".into()
+            )),
+        }
+    }
+
+    /// Inserts all the entries of `iter` in place if this is a `Map` or a
+    /// `SortedMap`, existing keys are overwritten. Returns an error for other
+    /// variants.
+    pub fn extend_map<I: IntoIterator<Item = (String, VariantValue)>>(
+        &mut self,
+        iter: I,
+    ) -> Result<()> {
+        match self {
+            VariantValue::Map(m) => {
+                m.extend(iter);
+                Ok(())
             }
-            VariantValue::Double(ref d) => {
-                if let VariantValue::Double(ref o) = *other {
-                    d.eq(o)
-                } else {
-                    false
-                }
+            VariantValue::SortedMap(m) => {
+                m.extend(iter);
+                Ok(())
             }
-            VariantValue::VString(ref s) => {
-                if let VariantValue::VString(ref o) = *other {
-                    s.eq(o)
-                } else {
-                    false
+            _ => bail!(IllegalArgument(
+                "only a map value can be extended by entries".into()
+            )),
+        }
+    }
+
+    /// Keeps only the elements for which `f` returns true if this is a `Vec`,
+    /// in place and in order. Other variants are left unchanged.
+    pub fn retain<F: FnMut(&VariantValue) -> bool>(&mut self, f: F) {
+        if let VariantValue::Vec(v) = self {
+            v.retain(f);
+        }
+    }
+
+    /// Maps the elements of a `Vec` with `f`, dropping the ones it returns
+    /// `None` for, and returns the new `Vec`. Other variants are returned as
+    /// is and `f` is not called.
+    pub fn filter_map<F: FnMut(VariantValue) -> Option<VariantValue>>(self, f: F) -> VariantValue {
+        match self {
+            VariantValue::Vec(v) => VariantValue::Vec(v.into_iter().filter_map(f).collect()),
+            other => other,
+        }
+    }
+
+    /// Returns a mutable reference to the value of `key` if this is a `Map`
+    /// or a `SortedMap`, inserting the result of `f` first if `key` is
+    /// absent, like `HashMap::entry(key).or_insert_with(f)`. Returns `None`
+    /// for other variants, in which case `f` is not called.
+    pub fn get_or_insert_with<F: FnOnce() -> VariantValue>(
+        &mut self,
+        key: &str,
+        f: F,
+    ) -> Option<&mut VariantValue> {
+        match self {
+            VariantValue::Map(map) => {
+                if !map.contains_key(key) {
+                    map.insert(key.to_string(), f());
                 }
+                map.get_mut(key)
             }
-            VariantValue::Binary(ref v) => {
-                if let VariantValue::Binary(ref o) = *other {
-                    v.eq(o)
-                } else {
-                    false
+            VariantValue::SortedMap(map) => {
+                if !map.contains_key(key) {
+                    map.insert(key.to_string(), f());
                 }
+                map.get_mut(key)
             }
-            _ => unreachable!(),
+            _ => None,
         }
     }
-}
 
-impl Ord for VariantValue {
-    fn cmp(&self, other: &Self) -> Ordering {
-        match (self, other) {
-            (&VariantValue::Bool(b1), &VariantValue::Bool(b2)) => b1.cmp(&b2),
-            (&VariantValue::Char(c1), &VariantValue::Char(c2)) => c1.cmp(&c2),
-            (&VariantValue::Short(v1), &VariantValue::Short(v2)) => v1.cmp(&v2),
-            (&VariantValue::Int(v1), &VariantValue::Int(v2)) => v1.cmp(&v2),
-            (&VariantValue::Long(v1), &VariantValue::Long(v2)) => v1.cmp(&v2),
-            (&VariantValue::Float(v1), &VariantValue::Float(v2)) => v1.partial_cmp(&v2).unwrap(),
-            (&VariantValue::Double(v1), &VariantValue::Double(v2)) => v1.partial_cmp(&v2).unwrap(),
-            (&VariantValue::VString(ref s1), &VariantValue::VString(ref s2)) => s1.cmp(&s2),
-            (&VariantValue::Binary(ref b1), &VariantValue::Binary(ref b2)) => b1.cmp(&b2),
-            (_, _) => panic!("Non-comparable"),
-        }
+    /// Returns whether this is a `Map` or a `SortedMap` containing `key`.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.map_get(key).is_some()
     }
-}
 
-impl PartialOrd for VariantValue {
-    fn partial_cmp(&self, other: &VariantValue) -> Option<Ordering> {
-        Some(self.cmp(other))
+    /// Looks up a nested value by a dotted path, eg. `fld_object.obj_fld_array.1`.
+    /// Each segment is used as the key of a `Map` or as the index of a `Vec`.
+    /// Dots and backslashes that are part of a key can be escaped with a
+    /// backslash, eg. `a\.b` looks up the key `a.b`.
+    ///
+    /// Returns `None` if any segment is missing, out of range, or addresses
+    /// into a scalar value. An empty path returns `self`.
+    pub fn get_path(&self, path: &str) -> Option<&VariantValue> {
+        if path.is_empty() {
+            return Some(self);
+        }
+        self.get_segments(&VariantValue::split_path(path))
     }
-}
 
-impl From<bool> for VariantValue {
-    fn from(val: bool) -> Self {
-        VariantValue::Bool(val)
+    fn get_segments(&self, segments: &[String]) -> Option<&VariantValue> {
+        let mut current = self;
+        for segment in segments {
+            current = match current {
+                VariantValue::Map(_) | VariantValue::SortedMap(_) => current.map_get(segment)?,
+                VariantValue::Vec(v) => v.get(segment.parse::<usize>().ok()?)?,
+                _ => return None,
+            };
+        }
+        Some(current)
     }
-}
 
-/// Implement the From<char> trait for VariantValue
-impl From<char> for VariantValue {
-    fn from(val: char) -> Self {
-        VariantValue::Char(val)
+    /// Returns a comparator of the values at the path `key` of two values, as
+    /// looked up by `get_path`, eg. to sort a `Vec` of maps by a sub-field
+    /// with `sort_by`. Values missing the key are ordered first.
+    ///
+    /// Numeric values are compared by their value whatever their variants,
+    /// eg. `Long(2) < Double(2.5)`, with NaN last. Other values are compared
+    /// by `Ord`, which panics if they are not comparable, eg. a string
+    /// against a number.
+    pub fn compare_by_key(key: &str) -> impl Fn(&VariantValue, &VariantValue) -> Ordering {
+        let segments = if key.is_empty() {
+            vec![]
+        } else {
+            VariantValue::split_path(key)
+        };
+        move |v1, v2| match (v1.get_segments(&segments), v2.get_segments(&segments)) {
+            (Some(v1), Some(v2)) => v1.compare_numeric_first(v2),
+            (v1, v2) => v1.is_some().cmp(&v2.is_some()),
+        }
     }
-}
 
-/// Implement the From<i16> trait for VariantValue
-impl From<i16> for VariantValue {
-    fn from(val: i16) -> Self {
-        VariantValue::Short(val)
+    fn compare_numeric_first(&self, other: &VariantValue) -> Ordering {
+        // the same variants are compared exactly, eg. `Long`s beyond 2^53
+        if ::std::mem::discriminant(self) == ::std::mem::discriminant(other) {
+            return self.cmp(other);
+        }
+        match (self.get_number(), other.get_number()) {
+            (Some(n1), Some(n2)) => n1
+                .partial_cmp(&n2)
+                .unwrap_or_else(|| n1.is_nan().cmp(&n2.is_nan())),
+            _ => self.cmp(other),
+        }
     }
-}
 
-impl From<i32> for VariantValue {
-    fn from(val: i32) -> Self {
-        VariantValue::Int(val)
+    fn split_path(path: &str) -> Vec<String> {
+        let mut segments = vec![];
+        let mut segment = String::new();
+        let mut chars = path.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => {
+                    if let Some(escaped) = chars.next() {
+                        segment.push(escaped);
+                    } else {
+                        segment.push(c);
+                    }
+                }
+                '.' => segments.push(::std::mem::take(&mut segment)),
+                _ => segment.push(c),
+            }
+        }
+        segments.push(segment);
+        segments
     }
-}
 
-impl From<i64> for VariantValue {
-    fn from(val: i64) -> Self {
-        VariantValue::Long(val)
+    /// Looks up a nested value by a JSON Pointer as defined by RFC 6901, eg.
+    /// `/fld_array/1/ary_fld_string`. A `/` and a `~` that are part of a key
+    /// are escaped as `~1` and `~0`, eg. `/a~1b` looks up the key `a/b`.
+    ///
+    /// Returns `None` if the pointer is invalid, eg. doesn't start with `/` or
+    /// holds an unknown escape, if an array index has leading zeros or is
+    /// `-`, or if the value is missing. An empty pointer returns `self`.
+    pub fn get_pointer(&self, ptr: &str) -> Option<&VariantValue> {
+        if ptr.is_empty() {
+            return Some(self);
+        }
+        if !ptr.starts_with('/') {
+            return None;
+        }
+        let mut current = self;
+        for token in ptr[1..].split('/') {
+            let token = VariantValue::unescape_pointer_token(token)?;
+            current = match current {
+                VariantValue::Map(_) | VariantValue::SortedMap(_) => current.map_get(&token)?,
+                VariantValue::Vec(v) => {
+                    let valid_index = token.bytes().all(|b| b.is_ascii_digit())
+                        && (token == "0" || !token.starts_with('0'));
+                    if !valid_index {
+                        return None;
+                    }
+                    v.get(token.parse::<usize>().ok()?)?
+                }
+                _ => return None,
+            };
+        }
+        Some(current)
     }
-}
 
-impl From<f32> for VariantValue {
-    fn from(val: f32) -> Self {
-        VariantValue::Float(val)
+    fn unescape_pointer_token(token: &str) -> Option<String> {
+        let mut unescaped = String::with_capacity(token.len());
+        let mut chars = token.chars();
+        while let Some(c) = chars.next() {
+            if c == '~' {
+                match chars.next() {
+                    Some('0') => unescaped.push('~'),
+                    Some('1') => unescaped.push('/'),
+                    _ => return None,
+                }
+            } else {
+                unescaped.push(c);
+            }
+        }
+        Some(unescaped)
     }
-}
 
-impl From<f64> for VariantValue {
-    fn from(val: f64) -> Self {
-        VariantValue::Double(val)
+    /// Returns a copy of this value with all integer variants promoted to `Long`
+    /// and all floating point variants promoted to `Double`, non-numeric values
+    /// are returned unchanged. Equal numbers of different widths compare and
+    /// hash equal after normalization, which is useful for dedup.
+    pub fn canonical_numeric(&self) -> VariantValue {
+        match *self {
+            VariantValue::Short(s) => VariantValue::Long(i64::from(s)),
+            VariantValue::Int(i) => VariantValue::Long(i64::from(i)),
+            VariantValue::Float(f) => VariantValue::Double(f64::from(f)),
+            _ => self.clone(),
+        }
     }
-}
 
-impl<'a> From<&'a str> for VariantValue {
-    fn from(val: &'a str) -> Self {
-        VariantValue::VString(String::from(val))
+    /// Converts this value to the numeric kind `to` if it can be done without
+    /// losing precision, eg. `Short` -> `Int` -> `Long`, `Float` -> `Double`
+    /// and `Short` or `Int` to a floating point wide enough for every value.
+    /// Returns `None` if the conversion would narrow, eg. `Long` -> `Int` or
+    /// `Long` -> `Double`, or if this value is not numeric.
+    pub fn widen(self, to: NumericKind) -> Option<VariantValue> {
+        match (self, to) {
+            (v @ VariantValue::Short(_), NumericKind::Short) => Some(v),
+            (VariantValue::Short(s), NumericKind::Int) => Some(VariantValue::Int(i32::from(s))),
+            (VariantValue::Short(s), NumericKind::Long) => Some(VariantValue::Long(i64::from(s))),
+            (VariantValue::Short(s), NumericKind::Float) => Some(VariantValue::Float(f32::from(s))),
+            (VariantValue::Short(s), NumericKind::Double) => {
+                Some(VariantValue::Double(f64::from(s)))
+            }
+            (v @ VariantValue::Int(_), NumericKind::Int) => Some(v),
+            (VariantValue::Int(i), NumericKind::Long) => Some(VariantValue::Long(i64::from(i))),
+            (VariantValue::Int(i), NumericKind::Double) => Some(VariantValue::Double(f64::from(i))),
+            (v @ VariantValue::Long(_), NumericKind::Long) => Some(v),
+            (v @ VariantValue::Float(_), NumericKind::Float) => Some(v),
+            (VariantValue::Float(f), NumericKind::Double) => {
+                Some(VariantValue::Double(f64::from(f)))
+            }
+            (v @ VariantValue::Double(_), NumericKind::Double) => Some(v),
+            _ => None,
+        }
     }
-}
 
-impl<'a> From<&'a [u8]> for VariantValue {
-    fn from(val: &'a [u8]) -> Self {
-        VariantValue::Binary(val.to_vec())
-    }
+    /// Clamps this numeric value in place into `[min, max]`, eg. to normalize
+    /// a field into a range before scoring. The bounds are widened to the
+    /// kind of this value, so an `Int` bound can clamp a `Long` but a `Long`
+    /// bound can't clamp an `Int`.
+    ///
+    /// Returns an error if this value is not numeric, if a bound is not a
+    /// numeric that widens to its kind or is NaN, or if `min > max`. A NaN
+    /// value is left unchanged.
+    pub fn clamp_numeric(&mut self, min: &VariantValue, max: &VariantValue) -> Result<()> {
+        let kind = match self.numeric_kind() {
+            Some(kind) => kind,
+            None => bail!(IllegalArgument(format!(
+                "can't clamp non numeric value {}",
+                self
+            ))),
+        };
+        let widen_bound = |bound: &VariantValue| -> Result<VariantValue> {
+            match bound.clone().widen(kind) {
+                Some(ref b) if b.get_number().map_or(false, f64::is_nan) => {
+                    bail!(IllegalArgument("clamp bound can't be NaN".into()))
+                }
+                Some(b) => Ok(b),
+                None => bail!(IllegalArgument(format!(
+                    "clamp bound {} is not compatible with {:?}",
+                    bound, kind
+                ))),
+            }
+        };
+        let min = widen_bound(min)?;
+        let max = widen_bound(max)?;
+        if min > max {
+            bail!(IllegalArgument(format!(
+                "clamp min {} is greater than max {}",
+                min, max
+            )));
+        }
+
+        if self.get_number().map_or(false, f64::is_nan) {
+            return Ok(());
+        }
+        if *self < min {
+            *self = min;
+        } else if *self > max {
+            *self = max;
+        }
+        Ok(())
+    }
+
+    /// Replaces the contents of all the `VString` values, including nested
+    /// ones, with strings shared through `pool`, eg. so that the many equal
+    /// category names of ingested docs are allocated once. The keys of maps
+    /// are not interned.
+    ///
+    /// Shared strings are equal to, hash and compare like owned strings with
+    /// the same contents.
+    pub fn intern_strings(&mut self, pool: &mut StringInterner) {
+        match self {
+            VariantValue::VString(s) => *self = VariantValue::VStringShared(pool.intern(s)),
+            VariantValue::VStringShared(s) => *s = pool.intern(s),
+            VariantValue::Vec(v) => v.iter_mut().for_each(|v| v.intern_strings(pool)),
+            VariantValue::Map(m) => m.values_mut().for_each(|v| v.intern_strings(pool)),
+            VariantValue::SortedMap(m) => m.values_mut().for_each(|v| v.intern_strings(pool)),
+            _ => {}
+        }
+    }
+
+    /// Turns a shared string into an owned `VString`, eg. before indexing it
+    /// as a field, other values are returned as is.
+    pub fn unshare(self) -> VariantValue {
+        match self {
+            VariantValue::VStringShared(s) => VariantValue::VString(s.to_string()),
+            v => v,
+        }
+    }
+
+    /// Converts this value to `kind`, eg. the type of the target field when
+    /// ingesting JSON, which can't tell a `Short` from a `Long`:
+    ///
+    /// - numbers are converted to any numeric kind, integer kinds must hold
+    /// the value exactly, eg. `Double(3.0)` to `Int(3)` but not `Double(3.5)`
+    /// - strings are parsed to `Bool`, `Char` and numeric kinds, and numbers,
+    /// `Bool` and `Char` are formatted to strings
+    /// - strings and `Binary` are converted to each other, if valid UTF-8
+    ///
+    /// A value already of `kind` is returned as is, any other coercion fails.
+    pub fn coerce(self, kind: VariantKind) -> Result<VariantValue> {
+        let value = match (self.unshare(), kind) {
+            (VariantValue::VString(s), VariantKind::Binary) => {
+                return Ok(VariantValue::Binary(s.into_bytes()));
+            }
+            (VariantValue::Binary(b), VariantKind::VString) => {
+                return String::from_utf8(b)
+                    .map(VariantValue::VString)
+                    .map_err(|_| {
+                        IllegalArgument("can't coerce non UTF-8 binary to VString".into()).into()
+                    });
+            }
+            (v, _) => v,
+        };
+        if value.kind() == kind {
+            return Ok(value);
+        }
+        let coerced = match (&value, kind) {
+            (VariantValue::VString(s), VariantKind::Bool) => s.parse().ok().map(VariantValue::Bool),
+            (VariantValue::VString(s), VariantKind::Char) => {
+                let mut chars = s.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Some(VariantValue::Char(c)),
+                    _ => None,
+                }
+            }
+            (VariantValue::VString(s), VariantKind::Short) => {
+                s.parse().ok().map(VariantValue::Short)
+            }
+            (VariantValue::VString(s), VariantKind::Int) => s.parse().ok().map(VariantValue::Int),
+            (VariantValue::VString(s), VariantKind::Long) => s.parse().ok().map(VariantValue::Long),
+            (VariantValue::VString(s), VariantKind::Float) => {
+                s.parse().ok().map(VariantValue::Float)
+            }
+            (VariantValue::VString(s), VariantKind::Double) => {
+                s.parse().ok().map(VariantValue::Double)
+            }
+            (VariantValue::Bool(b), VariantKind::VString) => {
+                Some(VariantValue::VString(b.to_string()))
+            }
+            (VariantValue::Char(c), VariantKind::VString) => {
+                Some(VariantValue::VString(c.to_string()))
+            }
+            (VariantValue::Short(v), VariantKind::VString) => {
+                Some(VariantValue::VString(v.to_string()))
+            }
+            (VariantValue::Int(v), VariantKind::VString) => {
+                Some(VariantValue::VString(v.to_string()))
+            }
+            (VariantValue::Long(v), VariantKind::VString) => {
+                Some(VariantValue::VString(v.to_string()))
+            }
+            (VariantValue::Float(v), VariantKind::VString) => {
+                Some(VariantValue::VString(v.to_string()))
+            }
+            (VariantValue::Double(v), VariantKind::VString) => {
+                Some(VariantValue::VString(v.to_string()))
+            }
+            (_, VariantKind::Float) => value.get_number().map(|n| VariantValue::Float(n as f32)),
+            (_, VariantKind::Double) => value.get_number().map(VariantValue::Double),
+            (_, VariantKind::Short) => value
+                .exact_i64()
+                .and_then(|l| i16::try_from(l).ok())
+                .map(VariantValue::Short),
+            (_, VariantKind::Int) => value
+                .exact_i64()
+                .and_then(|l| i32::try_from(l).ok())
+                .map(VariantValue::Int),
+            (_, VariantKind::Long) => value.exact_i64().map(VariantValue::Long),
+            _ => None,
+        };
+        match coerced {
+            Some(v) => Ok(v),
+            None => bail!(IllegalArgument(format!(
+                "can't coerce {} to {:?}",
+                value.coerce_description(),
+                kind
+            ))),
+        }
+    }
+
+    // the integer held by a numeric value, if it is integral and fits in i64
+    fn exact_i64(&self) -> Option<i64> {
+        match *self {
+            VariantValue::Short(s) => Some(i64::from(s)),
+            VariantValue::Int(i) => Some(i64::from(i)),
+            VariantValue::Long(l) => Some(l),
+            VariantValue::Float(_) | VariantValue::Double(_) => {
+                let d = self.get_number()?;
+                // 2^63 itself is out of range
+                if d.fract() == 0.0
+                    && (-9.223_372_036_854_776e18..9.223_372_036_854_776e18).contains(&d)
+                {
+                    Some(d as i64)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn coerce_description(&self) -> String {
+        match self {
+            VariantValue::Vec(_)
+            | VariantValue::Map(_)
+            | VariantValue::SortedMap(_)
+            | VariantValue::Binary(_) => {
+                format!("{:?}", self.kind())
+            }
+            _ => format!("{:?}", self),
+        }
+    }
+
+    /// Returns a timestamp as epoch millis, either a `Long` as is or a `VString`
+    /// in the UTC ISO-8601 form `YYYY-MM-DDTHH:MM:SSZ`, optionally with up to
+    /// three digits of fractional seconds, eg. `2019-03-01T08:30:00.250Z`.
+    ///
+    /// Returns `None` for other variants and for strings which are not a valid
+    /// date and time in exactly this form.
+    pub fn as_epoch_millis(&self) -> Option<i64> {
+        match self {
+            VariantValue::Long(l) => Some(*l),
+            VariantValue::VString(_) | VariantValue::VStringShared(_) => {
+                parse_iso8601_millis(self.get_string().unwrap())
+            }
+            _ => None,
+        }
+    }
+
+    /// Parses a string with optional surrounding whitespace into the narrowest
+    /// numeric variant holding it exactly, eg. ingested numbers sent as
+    /// strings: `Int` if it is an integer which fits, else `Long`, else
+    /// `Double`, eg. `" 42 "` is `Int(42)` and `"3.14"` is `Double(3.14)`.
+    ///
+    /// Numeric values are returned as is. Returns `None` for strings which are
+    /// not a finite number, eg. `"NaN"`, and for the other variants.
+    pub fn parse_numeric(&self) -> Option<VariantValue> {
+        if self.numeric_kind().is_some() {
+            return Some(self.clone());
+        }
+        let s = self.get_string()?.trim();
+        if let Ok(l) = s.parse::<i64>() {
+            return Some(match i32::try_from(l) {
+                Ok(i) => VariantValue::Int(i),
+                Err(_) => VariantValue::Long(l),
+            });
+        }
+        match s.parse::<f64>() {
+            Ok(d) if d.is_finite() => Some(VariantValue::Double(d)),
+            _ => None,
+        }
+    }
+
+    /// Replaces any NaN `Float` or `Double`, including the ones nested in `Vec`
+    /// and `Map`, with the canonical `NAN` bit pattern.
+    pub fn normalize_nan(&mut self) {
+        match self {
+            VariantValue::Float(f) if f.is_nan() => *f = f32::NAN,
+            VariantValue::Double(d) if d.is_nan() => *d = f64::NAN,
+            VariantValue::Vec(v) => v.iter_mut().for_each(VariantValue::normalize_nan),
+            VariantValue::Map(m) => m.values_mut().for_each(VariantValue::normalize_nan),
+            VariantValue::SortedMap(m) => m.values_mut().for_each(VariantValue::normalize_nan),
+            _ => {}
+        }
+    }
+
+    /// Truncates any string or `Binary`, including the ones nested in `Vec`
+    /// and `Map`, to at most `max_bytes` bytes, eg. to cap the size of the
+    /// fields of an ingested document. Strings are cut on a char boundary, so
+    /// they may end up shorter than `max_bytes`.
+    ///
+    /// Returns whether anything was truncated.
+    pub fn truncate_to(&mut self, max_bytes: usize) -> bool {
+        fn char_boundary(s: &str, max_bytes: usize) -> usize {
+            let mut end = max_bytes;
+            while !s.is_char_boundary(end) {
+                end -= 1;
+            }
+            end
+        }
+
+        match self {
+            VariantValue::VString(s) if s.len() > max_bytes => {
+                let end = char_boundary(s, max_bytes);
+                s.truncate(end);
+                true
+            }
+            VariantValue::VStringShared(s) if s.len() > max_bytes => {
+                let end = char_boundary(s, max_bytes);
+                *s = Arc::from(&s[..end]);
+                true
+            }
+            VariantValue::Binary(b) if b.len() > max_bytes => {
+                b.truncate(max_bytes);
+                true
+            }
+            VariantValue::Vec(v) => v
+                .iter_mut()
+                .fold(false, |truncated, v| v.truncate_to(max_bytes) || truncated),
+            VariantValue::Map(m) => m
+                .values_mut()
+                .fold(false, |truncated, v| v.truncate_to(max_bytes) || truncated),
+            VariantValue::SortedMap(m) => m
+                .values_mut()
+                .fold(false, |truncated, v| v.truncate_to(max_bytes) || truncated),
+            _ => false,
+        }
+    }
+
+    /// Applies `f` in place to every scalar leaf of nested `Map`/`Vec` values,
+    /// eg. to lowercase or trim all the strings of a document. A scalar value
+    /// is its own single leaf.
+    pub fn map_values<F: FnMut(&mut VariantValue)>(&mut self, mut f: F) {
+        self.map_values_with(&mut f);
+    }
+
+    fn map_values_with<F: FnMut(&mut VariantValue)>(&mut self, f: &mut F) {
+        match self {
+            VariantValue::Vec(v) => v.iter_mut().for_each(|v| v.map_values_with(f)),
+            VariantValue::Map(m) => m.values_mut().for_each(|v| v.map_values_with(f)),
+            VariantValue::SortedMap(m) => m.values_mut().for_each(|v| v.map_values_with(f)),
+            _ => f(self),
+        }
+    }
+
+    /// Compares two floating point values, eg. `Float` or `Double` in any
+    /// combination, and returns whether they differ by at most `epsilon`.
+    /// Equal infinities are considered equal and `NaN` never is. Returns `None`
+    /// if either value is not a floating point variant.
+    ///
+    /// Unlike `PartialEq` which stays exact, this is meant for tests and dedup
+    /// where rounding errors of computed scores should be ignored.
+    pub fn approx_eq(&self, other: &VariantValue, epsilon: f64) -> Option<bool> {
+        let float_value = |v: &VariantValue| match *v {
+            VariantValue::Float(f) => Some(f64::from(f)),
+            VariantValue::Double(d) => Some(d),
+            _ => None,
+        };
+        match (float_value(self), float_value(other)) {
+            (Some(a), Some(b)) => Some(a == b || (a - b).abs() <= epsilon),
+            _ => None,
+        }
+    }
+
+    /// Compares the structure and values of two values like `PartialEq`, but
+    /// the width of numbers is ignored: `Short`, `Int` and `Long` are equal if
+    /// they hold the same integer, and `Float` and `Double` are equal within
+    /// a relative epsilon of `1e-6`, eg. `Float(0.1)` equals `Double(0.1)`.
+    /// Integers never equal floating point values. `Vec` and `Map` are
+    /// compared recursively.
+    ///
+    /// This is meant for tests comparing ingested docs with expected ones.
+    pub fn deep_eq_ignoring_numeric_width(&self, other: &VariantValue) -> bool {
+        let integer_value = |v: &VariantValue| match *v {
+            VariantValue::Short(s) => Some(i64::from(s)),
+            VariantValue::Int(i) => Some(i64::from(i)),
+            VariantValue::Long(l) => Some(l),
+            _ => None,
+        };
+        if let (Some(a), Some(b)) = (integer_value(self), integer_value(other)) {
+            return a == b;
+        }
+        match (self, other) {
+            (VariantValue::Float(_), _) | (VariantValue::Double(_), _) => {
+                let a = self.get_number().unwrap();
+                other.approx_eq(self, 1e-6 * a.abs().max(1.0)) == Some(true)
+            }
+            (VariantValue::Vec(v1), VariantValue::Vec(v2)) => {
+                v1.len() == v2.len()
+                    && v1
+                        .iter()
+                        .zip(v2)
+                        .all(|(a, b)| a.deep_eq_ignoring_numeric_width(b))
+            }
+            (VariantValue::Map(m1), VariantValue::Map(m2)) => {
+                m1.len() == m2.len()
+                    && m1.iter().all(|(k, v1)| {
+                        m2.get(k)
+                            .map_or(false, |v2| v1.deep_eq_ignoring_numeric_width(v2))
+                    })
+            }
+            // how a map is backed doesn't matter
+            (VariantValue::SortedMap(_), _) | (_, VariantValue::SortedMap(_)) => {
+                match (self.map_entries(), other.map_entries()) {
+                    (Some(e1), Some(e2)) => {
+                        e1.len() == e2.len()
+                            && e1.iter().zip(&e2).all(|((k1, v1), (k2, v2))| {
+                                k1 == k2 && v1.deep_eq_ignoring_numeric_width(v2)
+                            })
+                    }
+                    _ => false,
+                }
+            }
+            (VariantValue::Vec(_), _)
+            | (VariantValue::Map(_), _)
+            | (_, VariantValue::Vec(_))
+            | (_, VariantValue::Map(_)) => false,
+            _ => self == other,
+        }
+    }
+
+    /// Compares `VString` and `Binary` values lexicographically by their bytes,
+    /// so that a string can be compared against a binary holding UTF-8 bytes.
+    /// Returns `None` if either value is neither a string nor a binary.
+    pub fn compare_as_bytes(&self, other: &VariantValue) -> Option<Ordering> {
+        match (
+            self.string_or_binary_bytes(),
+            other.string_or_binary_bytes(),
+        ) {
+            (Some(b1), Some(b2)) => Some(b1.cmp(b2)),
+            _ => None,
+        }
+    }
+
+    fn string_or_binary_bytes(&self) -> Option<&[u8]> {
+        match self {
+            VariantValue::VString(s) => Some(s.as_bytes()),
+            VariantValue::VStringShared(s) => Some(s.as_bytes()),
+            VariantValue::Binary(b) => Some(b.as_slice()),
+            _ => None,
+        }
+    }
+
+    /// Parses a JSON string into a `VariantValue`, see `TryFrom<Value>` for
+    /// how JSON values are mapped.
+    pub fn from_json_str(json: &str) -> Result<VariantValue> {
+        let value: Value = serde_json::from_str(json)?;
+        VariantValue::try_from(value)
+    }
+
+    /// Applies a JSON Merge Patch as defined by RFC 7386, eg. for partial
+    /// updates: an object patch is merged into this value key by key,
+    /// recursively, where a `null` deletes the key, and any other patch
+    /// replaces this value. A non-map value patched by an object becomes a
+    /// map first.
+    ///
+    /// The patch is taken as a JSON `Value` as there is no null variant to
+    /// express deletions with. For the same reason a `null` patch, or a
+    /// `null` within an array of the patch, fails. This value is left
+    /// partially patched on failure.
+    pub fn apply_merge_patch(&mut self, patch: &Value) -> Result<()> {
+        let obj = match patch {
+            Value::Null => bail!(IllegalArgument(
+                "a null merge patch would delete the value".into()
+            )),
+            Value::Object(obj) => obj,
+            _ => {
+                *self = VariantValue::try_from(patch)?;
+                return Ok(());
+            }
+        };
+        if let VariantValue::SortedMap(map) = self {
+            // the maps created by the patch are sorted as well
+            for (key, value) in obj {
+                if value.is_null() {
+                    map.remove(key);
+                    continue;
+                }
+                let entry = map
+                    .entry(key.clone())
+                    .or_insert_with(|| VariantValue::SortedMap(BTreeMap::new()));
+                if value.is_object() && entry.map_entries().is_none() {
+                    *entry = VariantValue::SortedMap(BTreeMap::new());
+                }
+                entry.apply_merge_patch(value)?;
+            }
+            return Ok(());
+        }
+        if self.get_map().is_none() {
+            *self = VariantValue::Map(HashMap::with_capacity(obj.len()));
+        }
+        let map = self.get_map_mut().unwrap();
+        for (key, value) in obj {
+            if value.is_null() {
+                map.remove(key);
+            } else {
+                map.entry(key.clone())
+                    .or_default()
+                    .apply_merge_patch(value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Serializes this value into a JSON string.
+    pub fn to_json_str(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Serializes this value into a canonical JSON string, eg. for content
+    /// hashing, unlike the lossy `Display`: map keys are sorted and floating
+    /// point values are written in their shortest form which parses back to
+    /// the exact same value, a `Float` back to the same `f32`.
+    ///
+    /// Returns an error if a `Float` or `Double` is NaN or infinite, which
+    /// JSON can't represent.
+    pub fn to_canonical_json(&self) -> Result<String> {
+        for (path, leaf) in self.iter_leaves() {
+            let finite = match *leaf {
+                VariantValue::Float(f) => f.is_finite(),
+                VariantValue::Double(d) => d.is_finite(),
+                _ => true,
+            };
+            if !finite {
+                bail!(IllegalArgument(format!(
+                    "{} at '{}' can't be written as JSON",
+                    leaf, path
+                )));
+            }
+        }
+        // serde_json writes the keys in the order of `Serialize`, which sorts
+        // them, and formats floats with the shortest round tripping digits
+        self.to_json_str()
+    }
+
+    /// Walks this value with `visitor`, see `VariantVisitor`.
+    pub fn accept(&self, visitor: &mut dyn VariantVisitor) {
+        match self {
+            VariantValue::Vec(v) => {
+                visitor.enter_seq(v.len());
+                for e in v {
+                    e.accept(visitor);
+                }
+                visitor.leave_seq();
+            }
+            VariantValue::Map(m) => {
+                visitor.enter_map(m.len());
+                for (k, v) in m {
+                    visitor.visit_key(k);
+                    v.accept(visitor);
+                }
+                visitor.leave_map();
+            }
+            VariantValue::SortedMap(m) => {
+                visitor.enter_map(m.len());
+                for (k, v) in m {
+                    visitor.visit_key(k);
+                    v.accept(visitor);
+                }
+                visitor.leave_map();
+            }
+            _ => visitor.visit_scalar(self),
+        }
+    }
+
+    /// Converts this value into a JSON value which keeps the exact variant of
+    /// every value, eg. `Short(30)` becomes `{"$type":"short","$val":30}`, so
+    /// that it can be restored losslessly by `from_tagged_json`.
+    ///
+    /// `Binary` values are stored as a hex string, `Vec`, `Map` and `SortedMap`
+    /// values hold their tagged elements, and non-finite floats are stored as the strings
+    /// `"NaN"`, `"inf"` and `"-inf"` since JSON numbers can't represent them.
+    pub fn to_tagged_json(&self) -> Value {
+        let (tag, val) = match self {
+            VariantValue::Bool(b) => ("bool", Value::Bool(*b)),
+            VariantValue::Char(c) => ("char", Value::String(c.to_string())),
+            VariantValue::Short(s) => ("short", Value::from(*s)),
+            VariantValue::Int(i) => ("int", Value::from(*i)),
+            VariantValue::Long(l) => ("long", Value::from(*l)),
+            VariantValue::Float(f) => ("float", VariantValue::tagged_float(f64::from(*f))),
+            VariantValue::Double(d) => ("double", VariantValue::tagged_float(*d)),
+            VariantValue::VString(s) => ("string", Value::String(s.clone())),
+            VariantValue::VStringShared(s) => ("shared_string", Value::String(s.to_string())),
+            VariantValue::Binary(b) => {
+                let hex: String = b.iter().map(|byte| format!("{:02x}", byte)).collect();
+                ("binary", Value::String(hex))
+            }
+            VariantValue::Vec(v) => (
+                "vec",
+                Value::Array(v.iter().map(VariantValue::to_tagged_json).collect()),
+            ),
+            VariantValue::Map(_) => ("map", self.tagged_entries()),
+            VariantValue::SortedMap(_) => ("sorted_map", self.tagged_entries()),
+        };
+        let mut obj = serde_json::Map::with_capacity(2);
+        obj.insert("$type".to_string(), Value::String(tag.to_string()));
+        obj.insert("$val".to_string(), val);
+        Value::Object(obj)
+    }
+
+    fn tagged_entries(&self) -> Value {
+        Value::Object(
+            self.map_entries()
+                .unwrap()
+                .into_iter()
+                .map(|(k, v)| (k.clone(), v.to_tagged_json()))
+                .collect(),
+        )
+    }
+
+    /// Restores a value produced by `to_tagged_json`.
+    pub fn from_tagged_json(value: &Value) -> Result<VariantValue> {
+        let invalid = || IllegalArgument(format!("invalid tagged value: {}", value));
+        let obj = value.as_object().ok_or_else(invalid)?;
+        let tag = obj
+            .get("$type")
+            .and_then(Value::as_str)
+            .ok_or_else(invalid)?;
+        let val = obj.get("$val").ok_or_else(invalid)?;
+
+        let res = match tag {
+            "bool" => val.as_bool().map(VariantValue::Bool),
+            "char" => val.as_str().and_then(|s| {
+                let mut chars = s.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Some(VariantValue::Char(c)),
+                    _ => None,
+                }
+            }),
+            "short" => val
+                .as_i64()
+                .and_then(|v| i16::try_from(v).ok())
+                .map(VariantValue::Short),
+            "int" => val
+                .as_i64()
+                .and_then(|v| i32::try_from(v).ok())
+                .map(VariantValue::Int),
+            "long" => val.as_i64().map(VariantValue::Long),
+            "float" => VariantValue::parse_tagged_float(val).map(|f| VariantValue::Float(f as f32)),
+            "double" => VariantValue::parse_tagged_float(val).map(VariantValue::Double),
+            "string" => val.as_str().map(VariantValue::from),
+            "shared_string" => val
+                .as_str()
+                .map(|s| VariantValue::VStringShared(Arc::from(s))),
+            "binary" => val
+                .as_str()
+                .and_then(VariantValue::parse_hex)
+                .map(VariantValue::Binary),
+            "vec" => match val {
+                Value::Array(arr) => {
+                    let mut vec = Vec::with_capacity(arr.len());
+                    for v in arr {
+                        vec.push(VariantValue::from_tagged_json(v)?);
+                    }
+                    Some(VariantValue::Vec(vec))
+                }
+                _ => None,
+            },
+            "map" => match val {
+                Value::Object(o) => {
+                    let mut map = HashMap::with_capacity(o.len());
+                    for (k, v) in o {
+                        map.insert(k.clone(), VariantValue::from_tagged_json(v)?);
+                    }
+                    Some(VariantValue::Map(map))
+                }
+                _ => None,
+            },
+            "sorted_map" => match val {
+                Value::Object(o) => {
+                    let mut map = BTreeMap::new();
+                    for (k, v) in o {
+                        map.insert(k.clone(), VariantValue::from_tagged_json(v)?);
+                    }
+                    Some(VariantValue::SortedMap(map))
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        res.ok_or_else(|| invalid().into())
+    }
+
+    fn tagged_float(v: f64) -> Value {
+        if v.is_nan() {
+            Value::from("NaN")
+        } else if v == f64::INFINITY {
+            Value::from("inf")
+        } else if v == f64::NEG_INFINITY {
+            Value::from("-inf")
+        } else {
+            Value::from(v)
+        }
+    }
+
+    fn parse_tagged_float(val: &Value) -> Option<f64> {
+        match val {
+            Value::Number(n) => n.as_f64(),
+            Value::String(s) => match s.as_str() {
+                "NaN" => Some(f64::NAN),
+                "inf" => Some(f64::INFINITY),
+                "-inf" => Some(f64::NEG_INFINITY),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+        if hex.len() % 2 != 0 || !hex.is_ascii() {
+            return None;
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+            .collect()
+    }
+
+    /// Encodes this value into a compact self describing binary format, each
+    /// value is a type tag byte followed by its payload.
+    ///
+    /// All the fixed width numbers are big endian, `Char` is stored as its
+    /// `u32` code point. `VString` and `Binary` payloads, as well as the number
+    /// of elements of `Vec` and `Map`, are prefixed by their length as a `u32`.
+    /// `Map` entries are encoded in sorted key order, each key is a length
+    /// prefixed UTF-8 string followed by its value.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.write_bytes(&mut buf);
+        buf
+    }
+
+    fn write_bytes(&self, buf: &mut Vec<u8>) {
+        match self {
+            VariantValue::Bool(b) => {
+                buf.push(TAG_BOOL);
+                buf.push(*b as u8);
+            }
+            VariantValue::Char(c) => {
+                buf.push(TAG_CHAR);
+                buf.extend_from_slice(&(*c as u32).to_be_bytes());
+            }
+            VariantValue::Short(v) => {
+                buf.push(TAG_SHORT);
+                buf.extend_from_slice(&v.to_be_bytes());
+            }
+            VariantValue::Int(v) => {
+                buf.push(TAG_INT);
+                buf.extend_from_slice(&v.to_be_bytes());
+            }
+            VariantValue::Long(v) => {
+                buf.push(TAG_LONG);
+                buf.extend_from_slice(&v.to_be_bytes());
+            }
+            VariantValue::Float(v) => {
+                buf.push(TAG_FLOAT);
+                buf.extend_from_slice(&v.to_bits().to_be_bytes());
+            }
+            VariantValue::Double(v) => {
+                buf.push(TAG_DOUBLE);
+                buf.extend_from_slice(&v.to_bits().to_be_bytes());
+            }
+            VariantValue::VString(_) | VariantValue::VStringShared(_) => {
+                buf.push(TAG_STRING);
+                write_len_prefixed(buf, self.get_string().unwrap().as_bytes());
+            }
+            VariantValue::Binary(b) => {
+                buf.push(TAG_BINARY);
+                write_len_prefixed(buf, b);
+            }
+            VariantValue::Vec(v) => {
+                buf.push(TAG_VEC);
+                buf.extend_from_slice(&(v.len() as u32).to_be_bytes());
+                for e in v {
+                    e.write_bytes(buf);
+                }
+            }
+            VariantValue::Map(_) | VariantValue::SortedMap(_) => {
+                let entries = self.map_entries().unwrap();
+                buf.push(TAG_MAP);
+                buf.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+                for (k, v) in entries {
+                    write_len_prefixed(buf, k.as_bytes());
+                    v.write_bytes(buf);
+                }
+            }
+        }
+    }
+
+    /// Returns a 64 bits hash of the content of this value, eg. to dedup
+    /// documents, which unlike `Hash` doesn't depend on a random seed nor on
+    /// the iteration order of maps, so equal values hash the same across
+    /// processes.
+    ///
+    /// This is the xxHash64 of the encoding of `to_bytes`, so the variants
+    /// are part of the hash, eg. `Int(1)` and `Long(1)` hash differently, as
+    /// do `0.0` and `-0.0`.
+    pub fn content_hash(&self) -> u64 {
+        xx::hash64(self.to_bytes())
+    }
+
+    /// Decodes a value encoded by `to_bytes` from the start of `bytes`, and
+    /// returns it with the number of bytes consumed, so that a sequence of
+    /// values can be decoded one after another.
+    ///
+    /// Returns an error if `bytes` is truncated, holds an unknown type tag,
+    /// a string or char that is not valid UTF-8, or containers nested more
+    /// than `MAX_DECODE_DEPTH` (128) levels deep, which untrusted input
+    /// could use to overflow the stack.
+    pub fn from_bytes(bytes: &[u8]) -> Result<(VariantValue, usize)> {
+        let mut pos = 0;
+        let value = VariantValue::read_bytes(bytes, &mut pos, MAX_DECODE_DEPTH)?;
+        Ok((value, pos))
+    }
+
+    /// Same as `from_bytes`, but `bytes` must hold exactly one value,
+    /// trailing bytes are an error.
+    pub fn from_bytes_exact(bytes: &[u8]) -> Result<VariantValue> {
+        let (value, len) = VariantValue::from_bytes(bytes)?;
+        if len != bytes.len() {
+            bail!(IllegalArgument(format!(
+                "{} trailing bytes after the encoded value",
+                bytes.len() - len
+            )));
+        }
+        Ok(value)
+    }
+
+    /// Decodes the values encoded by `to_bytes` and concatenated in `bytes`,
+    /// one after another until the end of `bytes`.
+    ///
+    /// The first value which fails to decode, eg. a truncated trailing value,
+    /// yields an error and ends the iteration.
+    pub fn from_bytes_stream(bytes: &[u8]) -> impl Iterator<Item = Result<VariantValue>> + '_ {
+        let mut pos = 0;
+        let mut failed = false;
+        ::std::iter::from_fn(move || {
+            if failed || pos >= bytes.len() {
+                return None;
+            }
+            let value = VariantValue::read_bytes(bytes, &mut pos, MAX_DECODE_DEPTH);
+            failed = value.is_err();
+            Some(value)
+        })
+    }
+
+    // `depth` is the number of container levels still allowed
+    fn read_bytes(bytes: &[u8], pos: &mut usize, depth: usize) -> Result<VariantValue> {
+        let tag = take_bytes(bytes, pos, 1)?[0];
+        if (tag == TAG_VEC || tag == TAG_MAP) && depth == 0 {
+            bail!(IllegalArgument(format!(
+                "containers are nested more than {} levels deep",
+                MAX_DECODE_DEPTH
+            )));
+        }
+        let value = match tag {
+            TAG_BOOL => match take_bytes(bytes, pos, 1)?[0] {
+                0 => VariantValue::Bool(false),
+                1 => VariantValue::Bool(true),
+                b => bail!(IllegalArgument(format!("invalid bool byte {}", b))),
+            },
+            TAG_CHAR => {
+                let code = read_u32(bytes, pos)?;
+                match ::std::char::from_u32(code) {
+                    Some(c) => VariantValue::Char(c),
+                    None => bail!(IllegalArgument(format!("invalid char {:#x}", code))),
+                }
+            }
+            TAG_SHORT => {
+                let mut buf = [0u8; 2];
+                buf.copy_from_slice(take_bytes(bytes, pos, 2)?);
+                VariantValue::Short(i16::from_be_bytes(buf))
+            }
+            TAG_INT => VariantValue::Int(read_u32(bytes, pos)? as i32),
+            TAG_LONG => VariantValue::Long(read_u64(bytes, pos)? as i64),
+            TAG_FLOAT => VariantValue::Float(f32::from_bits(read_u32(bytes, pos)?)),
+            TAG_DOUBLE => VariantValue::Double(f64::from_bits(read_u64(bytes, pos)?)),
+            TAG_STRING => {
+                let len = read_u32(bytes, pos)? as usize;
+                let s = ::std::str::from_utf8(take_bytes(bytes, pos, len)?)?;
+                VariantValue::VString(s.to_string())
+            }
+            TAG_BINARY => {
+                let len = read_u32(bytes, pos)? as usize;
+                VariantValue::Binary(take_bytes(bytes, pos, len)?.to_vec())
+            }
+            TAG_VEC => {
+                let len = read_u32(bytes, pos)? as usize;
+                // every element takes at least 2 bytes, don't trust `len` blindly
+                let mut vec = Vec::with_capacity(len.min((bytes.len() - *pos) / 2));
+                for _ in 0..len {
+                    vec.push(VariantValue::read_bytes(bytes, pos, depth - 1)?);
+                }
+                VariantValue::Vec(vec)
+            }
+            TAG_MAP => {
+                let len = read_u32(bytes, pos)? as usize;
+                let mut map = HashMap::with_capacity(len.min((bytes.len() - *pos) / 6));
+                for _ in 0..len {
+                    let key_len = read_u32(bytes, pos)? as usize;
+                    let key = ::std::str::from_utf8(take_bytes(bytes, pos, key_len)?)?;
+                    let key = key.to_string();
+                    map.insert(key, VariantValue::read_bytes(bytes, pos, depth - 1)?);
+                }
+                VariantValue::Map(map)
+            }
+            t => bail!(IllegalArgument(format!("unknown type tag {}", t))),
+        };
+        Ok(value)
+    }
+
+    /// Encodes this value as CBOR (RFC 8949), eg. to exchange values with
+    /// services that don't speak JSON.
+    ///
+    /// Unlike JSON, `Binary` is encoded as a byte string so it round trips.
+    /// `Short`, `Int` and `Long` are encoded as integers of the shortest
+    /// width and `Char` as a one char text string, so they are decoded back
+    /// as `Long` and `VString`. `Float` and `Double` keep their width, `Map`
+    /// entries are encoded in sorted key order.
+    ///
+    /// The codec is written by hand rather than on top of `serde_cbor`: the
+    /// latter is unmaintained and would be a new dependency, and the derived
+    /// `Deserialize` of `VariantValue` expects externally tagged variants,
+    /// so it couldn't decode plain CBOR items from other services anyway.
+    /// Writing the items directly also keeps the encoding above stable.
+    pub fn to_cbor(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.write_cbor(&mut buf);
+        buf
+    }
+
+    fn write_cbor(&self, buf: &mut Vec<u8>) {
+        match self {
+            VariantValue::Bool(b) => buf.push(if *b { CBOR_TRUE } else { CBOR_FALSE }),
+            VariantValue::Char(c) => {
+                let mut utf8 = [0u8; 4];
+                write_cbor_bytes(buf, CBOR_TEXT, c.encode_utf8(&mut utf8).as_bytes());
+            }
+            VariantValue::Short(v) => write_cbor_int(buf, i64::from(*v)),
+            VariantValue::Int(v) => write_cbor_int(buf, i64::from(*v)),
+            VariantValue::Long(v) => write_cbor_int(buf, *v),
+            VariantValue::Float(v) => {
+                buf.push(CBOR_FLOAT32);
+                buf.extend_from_slice(&v.to_bits().to_be_bytes());
+            }
+            VariantValue::Double(v) => {
+                buf.push(CBOR_FLOAT64);
+                buf.extend_from_slice(&v.to_bits().to_be_bytes());
+            }
+            VariantValue::VString(_) | VariantValue::VStringShared(_) => {
+                write_cbor_bytes(buf, CBOR_TEXT, self.get_string().unwrap().as_bytes());
+            }
+            VariantValue::Binary(b) => write_cbor_bytes(buf, CBOR_BYTES, b),
+            VariantValue::Vec(v) => {
+                write_cbor_head(buf, CBOR_ARRAY, v.len() as u64);
+                for e in v {
+                    e.write_cbor(buf);
+                }
+            }
+            VariantValue::Map(_) | VariantValue::SortedMap(_) => {
+                let entries = self.map_entries().unwrap();
+                write_cbor_head(buf, CBOR_MAP, entries.len() as u64);
+                for (k, v) in entries {
+                    write_cbor_bytes(buf, CBOR_TEXT, k.as_bytes());
+                    v.write_cbor(buf);
+                }
+            }
+        }
+    }
+
+    /// Decodes a CBOR data item, eg. encoded by `to_cbor`, `bytes` must hold
+    /// exactly one item.
+    ///
+    /// Integers are decoded as `Long`, half and single precision floats as
+    /// `Float` and double precision ones as `Double`. Returns an error on
+    /// what has no `VariantValue` counterpart: integers out of the range of
+    /// `i64`, `null` and other simple values, tags, indefinite lengths and
+    /// map keys which are not text strings, as well as on arrays and maps
+    /// nested more than `MAX_DECODE_DEPTH` (128) levels deep.
+    pub fn from_cbor(bytes: &[u8]) -> Result<VariantValue> {
+        let mut pos = 0;
+        let value = VariantValue::read_cbor(bytes, &mut pos, MAX_DECODE_DEPTH)?;
+        if pos != bytes.len() {
+            bail!(IllegalArgument(format!(
+                "{} trailing bytes after the CBOR item",
+                bytes.len() - pos
+            )));
+        }
+        Ok(value)
+    }
+
+    // `depth` is the number of container levels still allowed
+    fn read_cbor(bytes: &[u8], pos: &mut usize, depth: usize) -> Result<VariantValue> {
+        let initial = take_bytes(bytes, pos, 1)?[0];
+        let major = initial & 0xe0;
+        let info = initial & 0x1f;
+        if major == CBOR_SIMPLE {
+            let value = match info {
+                20 => VariantValue::Bool(false),
+                21 => VariantValue::Bool(true),
+                25 => {
+                    let mut buf = [0u8; 2];
+                    buf.copy_from_slice(take_bytes(bytes, pos, 2)?);
+                    VariantValue::Float(half_to_f32(u16::from_be_bytes(buf)))
+                }
+                26 => VariantValue::Float(f32::from_bits(read_u32(bytes, pos)?)),
+                27 => VariantValue::Double(f64::from_bits(read_u64(bytes, pos)?)),
+                _ => bail!(IllegalArgument(format!(
+                    "unsupported CBOR simple value {:#x}",
+                    initial
+                ))),
+            };
+            return Ok(value);
+        }
+
+        if (major == CBOR_ARRAY || major == CBOR_MAP) && depth == 0 {
+            bail!(IllegalArgument(format!(
+                "CBOR items are nested more than {} levels deep",
+                MAX_DECODE_DEPTH
+            )));
+        }
+        let arg = read_cbor_arg(bytes, pos, info)?;
+        let value = match major {
+            CBOR_UINT | CBOR_NEGINT => {
+                if arg > i64::max_value() as u64 {
+                    bail!(IllegalArgument(format!(
+                        "CBOR integer {}{} is out of range of i64",
+                        if major == CBOR_NEGINT { "-1 - " } else { "" },
+                        arg
+                    )));
+                }
+                if major == CBOR_UINT {
+                    VariantValue::Long(arg as i64)
+                } else {
+                    VariantValue::Long(!(arg as i64))
+                }
+            }
+            CBOR_BYTES => VariantValue::Binary(take_cbor_bytes(bytes, pos, arg)?.to_vec()),
+            CBOR_TEXT => {
+                let s = ::std::str::from_utf8(take_cbor_bytes(bytes, pos, arg)?)?;
+                VariantValue::VString(s.to_string())
+            }
+            CBOR_ARRAY => {
+                // every element takes at least 1 byte, don't trust `arg` blindly
+                let len = arg.min((bytes.len() - *pos) as u64) as usize;
+                let mut vec = Vec::with_capacity(len);
+                for _ in 0..arg {
+                    vec.push(VariantValue::read_cbor(bytes, pos, depth - 1)?);
+                }
+                VariantValue::Vec(vec)
+            }
+            CBOR_MAP => {
+                let len = arg.min((bytes.len() - *pos) as u64 / 2) as usize;
+                let mut map = HashMap::with_capacity(len);
+                for _ in 0..arg {
+                    let key = match VariantValue::read_cbor(bytes, pos, depth - 1)? {
+                        VariantValue::VString(s) => s,
+                        k => bail!(IllegalArgument(format!(
+                            "CBOR map key {} is not a text string",
+                            k
+                        ))),
+                    };
+                    map.insert(key, VariantValue::read_cbor(bytes, pos, depth - 1)?);
+                }
+                VariantValue::Map(map)
+            }
+            _ => bail!(IllegalArgument("CBOR tags are not supported".into())),
+        };
+        Ok(value)
+    }
+
+    fn from_json_number(n: &Number) -> Result<VariantValue> {
+        if let Some(l) = n.as_i64() {
+            Ok(VariantValue::Long(l))
+        } else if n.is_u64() {
+            bail!(IllegalArgument(format!("{} is out of range of i64", n)))
+        } else if let Some(d) = n.as_f64() {
+            Ok(VariantValue::Double(d))
+        } else {
+            bail!(IllegalArgument(format!("invalid number {}", n)))
+        }
+    }
+
+    /// Flattens nested `Map`/`Vec` values into a single level map, keyed by the
+    /// path of each scalar leaf joined with `sep`, eg. `fld_object.obj_fld_long`
+    /// or `fld_array.0.ary_fld_string`. `Vec` elements use its index as the path
+    /// segment, and empty containers produce no entry.
+    ///
+    /// A scalar value is flattened to a single entry with an empty key.
+    pub fn flatten(&self, sep: char) -> HashMap<String, VariantValue> {
+        let mut result = HashMap::new();
+        let mut path = String::new();
+        self.flatten_into(&mut path, sep, &mut result);
+        result
+    }
+
+    /// Returns an iterator over the scalar leaves of nested `Map`/`Vec` values
+    /// with their dotted paths, eg. the same entries as `flatten('.')` except
+    /// that the leaves are borrowed rather than cloned.
+    ///
+    /// `Vec` elements are visited in order, `Map` entries in no particular
+    /// order and `SortedMap` ones in key order. A scalar value yields itself
+    /// with an empty path.
+    pub fn iter_leaves(&self) -> impl Iterator<Item = (String, &VariantValue)> + '_ {
+        let mut stack = vec![(String::new(), self)];
+        ::std::iter::from_fn(move || {
+            while let Some((path, value)) = stack.pop() {
+                match value {
+                    VariantValue::Map(m) => {
+                        stack.extend(m.iter().map(|(k, v)| (join_path(&path, k), v)));
+                    }
+                    VariantValue::SortedMap(m) => {
+                        let children = m.iter().rev();
+                        stack.extend(children.map(|(k, v)| (join_path(&path, k), v)));
+                    }
+                    VariantValue::Vec(vec) => {
+                        let children = vec.iter().enumerate().rev();
+                        stack.extend(children.map(|(i, v)| (join_path(&path, &i.to_string()), v)));
+                    }
+                    _ => return Some((path, value)),
+                }
+            }
+            None
+        })
+    }
+
+    /// Diffs this document against `other`, eg. for change data capture,
+    /// reporting the fields added, removed and changed by `other` with their
+    /// dotted paths, eg. `fld_object.obj_fld_long`.
+    ///
+    /// Nested maps are diffed recursively, any other value, `Vec` included,
+    /// is compared as a whole, so a changed element or a map replaced by a
+    /// scalar shows up as a single change. If either value is not a `Map`
+    /// they are compared as a whole as well, with an empty path. Numbers of
+    /// different widths are different, eg. `Int(1)` and `Long(1)`. The paths
+    /// of each list are sorted.
+    pub fn diff(&self, other: &VariantValue) -> VariantDiff {
+        let mut diff = VariantDiff::default();
+        self.diff_into("", other, &mut diff);
+        diff.added.sort_by(|a, b| a.0.cmp(&b.0));
+        diff.removed.sort_by(|a, b| a.0.cmp(&b.0));
+        diff.changed.sort_by(|a, b| a.0.cmp(&b.0));
+        diff
+    }
+
+    fn diff_into(&self, path: &str, other: &VariantValue, diff: &mut VariantDiff) {
+        match (self.map_entries(), other.map_entries()) {
+            (Some(old), Some(new)) => {
+                for (k, old_value) in old {
+                    let path = join_path(path, k);
+                    match other.map_get(k) {
+                        Some(new_value) => old_value.diff_into(&path, new_value, diff),
+                        None => diff.removed.push((path, old_value.clone())),
+                    }
+                }
+                for (k, new_value) in new {
+                    if !self.contains_key(k) {
+                        diff.added.push((join_path(path, k), new_value.clone()));
+                    }
+                }
+            }
+            _ => {
+                if self != other {
+                    diff.changed
+                        .push((path.to_string(), self.clone(), other.clone()));
+                }
+            }
+        }
+    }
+
+    fn flatten_into(
+        &self,
+        path: &mut String,
+        sep: char,
+        result: &mut HashMap<String, VariantValue>,
+    ) {
+        match self {
+            VariantValue::Map(_) | VariantValue::SortedMap(_) => {
+                for (k, v) in self.map_entries().unwrap() {
+                    let len = path.len();
+                    if len > 0 {
+                        path.push(sep);
+                    }
+                    path.push_str(k);
+                    v.flatten_into(path, sep, result);
+                    path.truncate(len);
+                }
+            }
+            VariantValue::Vec(vec) => {
+                for (i, v) in vec.iter().enumerate() {
+                    let len = path.len();
+                    if len > 0 {
+                        path.push(sep);
+                    }
+                    path.push_str(&i.to_string());
+                    v.flatten_into(path, sep, result);
+                    path.truncate(len);
+                }
+            }
+            _ => {
+                result.insert(path.clone(), self.clone());
+            }
+        }
+    }
+}
+
+impl Eq for VariantValue {}
+
+// parses `YYYY-MM-DDTHH:MM:SS[.sss]Z` to epoch millis
+fn parse_iso8601_millis(s: &str) -> Option<i64> {
+    let bytes = s.as_bytes();
+    if bytes.len() < 20 || bytes[bytes.len() - 1] != b'Z' {
+        return None;
+    }
+    let number = |from: usize, len: usize| -> Option<i64> {
+        let digits = bytes.get(from..from + len)?;
+        if !digits.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        Some(digits.iter().fold(0, |n, d| n * 10 + i64::from(d - b'0')))
+    };
+    let separators = [(4, b'-'), (7, b'-'), (10, b'T'), (13, b':'), (16, b':')];
+    if separators.iter().any(|&(i, c)| bytes[i] != c) {
+        return None;
+    }
+    let (year, month, day) = (number(0, 4)?, number(5, 2)?, number(8, 2)?);
+    let (hour, minute, second) = (number(11, 2)?, number(14, 2)?, number(17, 2)?);
+    let millis = match bytes.len() - 20 {
+        0 => 0,
+        len @ 2..=4 if bytes[19] == b'.' => number(20, len - 1)? * 10i64.pow(4 - len as u32),
+        _ => return None,
+    };
+
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return None,
+    };
+    if day < 1 || day > days_in_month || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    // days since the epoch of the civil date, see Howard Hinnant's
+    // `days_from_civil`, with years starting in March
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = (if y >= 0 { y } else { y - 399 }) / 400;
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+
+    Some(((days * 24 + hour) * 60 + minute) * 60_000 + second * 1000 + millis)
+}
+
+fn join_path(path: &str, segment: &str) -> String {
+    if path.is_empty() {
+        segment.to_string()
+    } else {
+        format!("{}.{}", path, segment)
+    }
+}
+
+/// Visits a `VariantValue` tree in depth first order without allocating, see
+/// `VariantValue::accept`.
+///
+/// For a `Vec`, `enter_seq` is called with its length before its elements and
+/// `leave_seq` after them. For a `Map`, `enter_map` is called with its length,
+/// then `visit_key` followed by the visit of the value for each entry, in the
+/// map's iteration order, and `leave_map` at last. All the other variants are
+/// passed to `visit_scalar`.
+///
+/// Only the entries of a `SortedMap` are visited in key order, as sorting the
+/// keys of a `Map` like `Serialize` does would allocate. Visit the result of
+/// `VariantValue::to_sorted` for a deterministic traversal.
+pub trait VariantVisitor {
+    fn visit_scalar(&mut self, value: &VariantValue);
+
+    fn enter_seq(&mut self, _len: usize) {}
+
+    fn leave_seq(&mut self) {}
+
+    fn enter_map(&mut self, _len: usize) {}
+
+    fn visit_key(&mut self, _key: &str) {}
+
+    fn leave_map(&mut self) {}
+}
+
+/// max number of bytes printed by `Display` for `VariantValue::Binary`, longer
+/// payloads are truncated with an ellipsis.
+const BINARY_DISPLAY_MAX_BYTES: usize = 32;
+
+impl fmt::Display for VariantValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VariantValue::Bool(b) => write!(f, "{}", b),
+            VariantValue::Char(c) => write!(f, "{}", c),
+            VariantValue::Short(s) => write!(f, "{}s", s),
+            VariantValue::Int(ival) => write!(f, "{}", ival),
+            VariantValue::Long(lval) => write!(f, "{}", lval),
+            VariantValue::Float(fval) => write!(f, "{:.3}", fval),
+            VariantValue::Double(d) => write!(f, "{:.6}", d),
+            VariantValue::VString(ref s) => write!(f, "{}", s),
+            VariantValue::VStringShared(ref s) => write!(f, "{}", s),
+            VariantValue::Binary(ref b) => {
+                write!(f, "Binary(0x")?;
+                for byte in b.iter().take(BINARY_DISPLAY_MAX_BYTES) {
+                    write!(f, "{:02x}", byte)?;
+                }
+                if b.len() > BINARY_DISPLAY_MAX_BYTES {
+                    write!(f, "...")?;
+                }
+                write!(f, ")")
+            }
+            VariantValue::Vec(ref v) => write!(f, "{:?}", v),
+            VariantValue::Map(ref m) => write!(f, "{:?}", m),
+            VariantValue::SortedMap(ref m) => write!(f, "{:?}", m),
+        }
+    }
+}
+
+// type tags of the binary format of `VariantValue::to_bytes`
+const TAG_BOOL: u8 = 0;
+const TAG_CHAR: u8 = 1;
+const TAG_SHORT: u8 = 2;
+const TAG_INT: u8 = 3;
+const TAG_LONG: u8 = 4;
+const TAG_FLOAT: u8 = 5;
+const TAG_DOUBLE: u8 = 6;
+const TAG_STRING: u8 = 7;
+const TAG_BINARY: u8 = 8;
+const TAG_VEC: u8 = 9;
+const TAG_MAP: u8 = 10;
+
+// the max number of nested container levels the decoders accept
+const MAX_DECODE_DEPTH: usize = 128;
+
+fn write_len_prefixed(buf: &mut Vec<u8>, data: &[u8]) {
+    buf.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    buf.extend_from_slice(data);
+}
+
+/// Returns the next `len` bytes at `pos` and moves `pos` after them.
+fn take_bytes<'a>(bytes: &'a [u8], pos: &mut usize, len: usize) -> Result<&'a [u8]> {
+    if bytes.len() - *pos < len {
+        bail!(UnexpectedEOF(format!(
+            "need {} bytes at offset {}, but only {} left",
+            len,
+            *pos,
+            bytes.len() - *pos
+        )));
+    }
+    let res = &bytes[*pos..*pos + len];
+    *pos += len;
+    Ok(res)
+}
+
+fn read_u32(bytes: &[u8], pos: &mut usize) -> Result<u32> {
+    let mut buf = [0u8; 4];
+    buf.copy_from_slice(take_bytes(bytes, pos, 4)?);
+    Ok(u32::from_be_bytes(buf))
+}
+
+fn read_u64(bytes: &[u8], pos: &mut usize) -> Result<u64> {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(take_bytes(bytes, pos, 8)?);
+    Ok(u64::from_be_bytes(buf))
+}
+
+// the major types of CBOR, in the high 3 bits of the initial byte of an item
+const CBOR_UINT: u8 = 0x00;
+const CBOR_NEGINT: u8 = 0x20;
+const CBOR_BYTES: u8 = 0x40;
+const CBOR_TEXT: u8 = 0x60;
+const CBOR_ARRAY: u8 = 0x80;
+const CBOR_MAP: u8 = 0xa0;
+const CBOR_SIMPLE: u8 = 0xe0;
+
+const CBOR_FALSE: u8 = 0xf4;
+const CBOR_TRUE: u8 = 0xf5;
+const CBOR_FLOAT32: u8 = 0xfa;
+const CBOR_FLOAT64: u8 = 0xfb;
+
+/// Writes the initial byte of an item of type `major` followed by `arg` in
+/// its shortest form, eg. a length or an unsigned integer.
+fn write_cbor_head(buf: &mut Vec<u8>, major: u8, arg: u64) {
+    if arg < 24 {
+        buf.push(major | arg as u8);
+    } else if arg <= u64::from(u8::max_value()) {
+        buf.push(major | 24);
+        buf.push(arg as u8);
+    } else if arg <= u64::from(u16::max_value()) {
+        buf.push(major | 25);
+        buf.extend_from_slice(&(arg as u16).to_be_bytes());
+    } else if arg <= u64::from(u32::max_value()) {
+        buf.push(major | 26);
+        buf.extend_from_slice(&(arg as u32).to_be_bytes());
+    } else {
+        buf.push(major | 27);
+        buf.extend_from_slice(&arg.to_be_bytes());
+    }
+}
+
+fn write_cbor_int(buf: &mut Vec<u8>, v: i64) {
+    if v >= 0 {
+        write_cbor_head(buf, CBOR_UINT, v as u64);
+    } else {
+        // negative integers are encoded as `-1 - v`
+        write_cbor_head(buf, CBOR_NEGINT, !v as u64);
+    }
+}
+
+fn write_cbor_bytes(buf: &mut Vec<u8>, major: u8, data: &[u8]) {
+    write_cbor_head(buf, major, data.len() as u64);
+    buf.extend_from_slice(data);
+}
+
+/// Reads the argument of an item whose initial byte has the additional
+/// info `info`.
+fn read_cbor_arg(bytes: &[u8], pos: &mut usize, info: u8) -> Result<u64> {
+    let arg = match info {
+        0..=23 => u64::from(info),
+        24 => u64::from(take_bytes(bytes, pos, 1)?[0]),
+        25 => {
+            let mut buf = [0u8; 2];
+            buf.copy_from_slice(take_bytes(bytes, pos, 2)?);
+            u64::from(u16::from_be_bytes(buf))
+        }
+        26 => u64::from(read_u32(bytes, pos)?),
+        27 => read_u64(bytes, pos)?,
+        31 => bail!(IllegalArgument(
+            "CBOR indefinite lengths are not supported".into()
+        )),
+        _ => bail!(IllegalArgument(format!(
+            "reserved CBOR additional info {}",
+            info
+        ))),
+    };
+    Ok(arg)
+}
+
+fn take_cbor_bytes<'a>(bytes: &'a [u8], pos: &mut usize, len: u64) -> Result<&'a [u8]> {
+    // lengths past the end fail in `take_bytes` instead of overflowing `usize`
+    let len = len.min((bytes.len() - *pos) as u64 + 1) as usize;
+    take_bytes(bytes, pos, len)
+}
+
+fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exp = i32::from((half >> 10) & 0x1f);
+    let mantissa = f32::from(half & 0x3ff);
+    let abs = match exp {
+        0 => mantissa * 2f32.powi(-24),
+        31 if mantissa == 0.0 => f32::INFINITY,
+        31 => f32::NAN,
+        e => (1024.0 + mantissa) * 2f32.powi(e - 25),
+    };
+    sign * abs
+}
+
+fn sorted_entries(m: &HashMap<String, VariantValue>) -> Vec<(&String, &VariantValue)> {
+    let mut entries: Vec<_> = m.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries
+}
+
+impl serde::Serialize for VariantValue {
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match *self {
+            VariantValue::Bool(b) => serializer.serialize_bool(b),
+            VariantValue::Char(c) => serializer.serialize_char(c),
+            VariantValue::Short(s) => serializer.serialize_i16(s),
+            VariantValue::Int(ival) => serializer.serialize_i32(ival),
+            VariantValue::Long(lval) => serializer.serialize_i64(lval),
+            VariantValue::Float(fval) => serializer.serialize_f32(fval),
+            VariantValue::Double(d) => serializer.serialize_f64(d),
+            VariantValue::VString(ref s) => serializer.serialize_str(s.as_str()),
+            VariantValue::VStringShared(ref s) => serializer.serialize_str(s),
+            VariantValue::Binary(ref b) => serializer.serialize_bytes(b),
+            VariantValue::Vec(ref vec) => {
+                let mut seq = serializer.serialize_seq(Some(vec.len())).unwrap();
+                for v in vec {
+                    seq.serialize_element(v)?;
+                }
+
+                seq.end()
+            }
+            VariantValue::Map(_) | VariantValue::SortedMap(_) => {
+                // keys are emitted in sorted order to keep the output deterministic
+                let entries = self.map_entries().unwrap();
+                let mut map = serializer.serialize_map(Some(entries.len())).unwrap();
+                for (k, v) in entries {
+                    map.serialize_entry(k, v)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+impl Hash for VariantValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match *self {
+            VariantValue::Bool(ref b) => b.hash(state),
+            VariantValue::Char(ref c) => c.hash(state),
+            VariantValue::Short(ref s) => s.hash(state),
+            VariantValue::Int(ref i) => i.hash(state),
+            VariantValue::Long(ref l) => l.hash(state),
+            // all NaNs hash the same regardless of their payload
+            VariantValue::Float(f) if f.is_nan() => f32::NAN.to_bits().hash(state),
+            VariantValue::Double(d) if d.is_nan() => f64::NAN.to_bits().hash(state),
+            // `-0.0` is equal to `0.0`, adding `0.0` turns it into `0.0`
+            VariantValue::Float(f) => (f + 0.0).to_bits().hash(state),
+            VariantValue::Double(d) => (d + 0.0).to_bits().hash(state),
+            // shared strings hash like owned ones as they are equal
+            VariantValue::VString(ref s) => s.as_str().hash(state),
+            VariantValue::VStringShared(ref s) => (**s).hash(state),
+            VariantValue::Binary(ref v) => v.hash(state),
+            VariantValue::Vec(ref v) => v.hash(state),
+            // entries are hashed in key order, as equal maps may iterate
+            // in different orders
+            VariantValue::Map(_) | VariantValue::SortedMap(_) => {
+                let entries = self.map_entries().unwrap();
+                entries.len().hash(state);
+                for (k, v) in entries {
+                    k.hash(state);
+                    v.hash(state);
+                }
+            }
+        }
+    }
+}
+
+/// `VString` and `VStringShared` are equal if their contents are, all NaNs
+/// are equal, and containers are equal if their elements are equal.
+impl PartialEq for VariantValue {
+    fn eq(&self, other: &VariantValue) -> bool {
+        match (self, other) {
+            (VariantValue::Bool(b1), VariantValue::Bool(b2)) => b1 == b2,
+            (VariantValue::Char(c1), VariantValue::Char(c2)) => c1 == c2,
+            (VariantValue::Short(v1), VariantValue::Short(v2)) => v1 == v2,
+            (VariantValue::Int(v1), VariantValue::Int(v2)) => v1 == v2,
+            (VariantValue::Long(v1), VariantValue::Long(v2)) => v1 == v2,
+            (VariantValue::Float(v1), VariantValue::Float(v2)) => {
+                v1 == v2 || (v1.is_nan() && v2.is_nan())
+            }
+            (VariantValue::Double(v1), VariantValue::Double(v2)) => {
+                v1 == v2 || (v1.is_nan() && v2.is_nan())
+            }
+            (VariantValue::VString(_), _) | (VariantValue::VStringShared(_), _) => {
+                other.get_string().is_some() && self.get_string() == other.get_string()
+            }
+            (VariantValue::Binary(b1), VariantValue::Binary(b2)) => b1 == b2,
+            (VariantValue::Vec(v1), VariantValue::Vec(v2)) => v1 == v2,
+            (VariantValue::Map(m1), VariantValue::Map(m2)) => m1 == m2,
+            (VariantValue::SortedMap(m1), VariantValue::SortedMap(m2)) => m1 == m2,
+            // how a map is backed doesn't matter
+            (VariantValue::Map(_), VariantValue::SortedMap(_))
+            | (VariantValue::SortedMap(_), VariantValue::Map(_)) => {
+                self.map_entries() == other.map_entries()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Values of the same variant are compared by their value, eg. `Bool(false) < Bool(true)`.
+///
+/// Across variants only `Bool` against a numeric variant is supported: `Bool`
+/// is ordered as a distinct kind below all numbers, it is *not* promoted to
+/// `0`/`1`, so `Bool(true) < Int(0)`. Comparing other mixed variants panics.
+///
+/// `Float` and `Double` NaN values are ordered after all other values of the
+/// same variant, and all NaNs are equal to each other. `VString` and
+/// `VStringShared` are compared by their contents. `Vec`s are compared
+/// element by element, and maps, whether `Map` or `SortedMap`, entry by
+/// entry in key order.
+impl Ord for VariantValue {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (VariantValue::Bool(_), v) if v.get_number().is_some() => Ordering::Less,
+            (v, VariantValue::Bool(_)) if v.get_number().is_some() => Ordering::Greater,
+            (&VariantValue::Bool(b1), &VariantValue::Bool(b2)) => b1.cmp(&b2),
+            (&VariantValue::Char(c1), &VariantValue::Char(c2)) => c1.cmp(&c2),
+            (&VariantValue::Short(v1), &VariantValue::Short(v2)) => v1.cmp(&v2),
+            (&VariantValue::Int(v1), &VariantValue::Int(v2)) => v1.cmp(&v2),
+            (&VariantValue::Long(v1), &VariantValue::Long(v2)) => v1.cmp(&v2),
+            // NaN is ordered after all other values, including infinity
+            (&VariantValue::Float(v1), &VariantValue::Float(v2)) => v1
+                .partial_cmp(&v2)
+                .unwrap_or_else(|| v1.is_nan().cmp(&v2.is_nan())),
+            (&VariantValue::Double(v1), &VariantValue::Double(v2)) => v1
+                .partial_cmp(&v2)
+                .unwrap_or_else(|| v1.is_nan().cmp(&v2.is_nan())),
+            (VariantValue::VString(_), _) | (VariantValue::VStringShared(_), _)
+                if other.get_string().is_some() =>
+            {
+                self.get_string().cmp(&other.get_string())
+            }
+            (&VariantValue::Binary(ref b1), &VariantValue::Binary(ref b2)) => b1.cmp(&b2),
+            (&VariantValue::Vec(ref v1), &VariantValue::Vec(ref v2)) => v1.cmp(v2),
+            (VariantValue::Map(_), _) | (VariantValue::SortedMap(_), _)
+                if other.map_entries().is_some() =>
+            {
+                self.map_entries().cmp(&other.map_entries())
+            }
+            (_, _) => panic!("Non-comparable"),
+        }
+    }
+}
+
+impl PartialOrd for VariantValue {
+    fn partial_cmp(&self, other: &VariantValue) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The default is an empty `Map`, since there is no null variant, so that
+/// nested documents can be built with `entry(..).or_default()`.
+impl Default for VariantValue {
+    fn default() -> Self {
+        VariantValue::Map(HashMap::new())
+    }
+}
+
+impl From<bool> for VariantValue {
+    #[inline]
+    fn from(val: bool) -> Self {
+        VariantValue::Bool(val)
+    }
+}
+
+/// Implement the From<char> trait for VariantValue
+impl From<char> for VariantValue {
+    #[inline]
+    fn from(val: char) -> Self {
+        VariantValue::Char(val)
+    }
+}
+
+/// Implement the From<i16> trait for VariantValue
+impl From<i16> for VariantValue {
+    #[inline]
+    fn from(val: i16) -> Self {
+        VariantValue::Short(val)
+    }
+}
+
+impl From<i32> for VariantValue {
+    #[inline]
+    fn from(val: i32) -> Self {
+        VariantValue::Int(val)
+    }
+}
+
+impl From<i64> for VariantValue {
+    #[inline]
+    fn from(val: i64) -> Self {
+        VariantValue::Long(val)
+    }
+}
+
+impl From<f32> for VariantValue {
+    #[inline]
+    fn from(val: f32) -> Self {
+        VariantValue::Float(val)
+    }
+}
+
+impl From<f64> for VariantValue {
+    #[inline]
+    fn from(val: f64) -> Self {
+        VariantValue::Double(val)
+    }
+}
+
+impl<'a> From<&'a str> for VariantValue {
+    #[inline]
+    fn from(val: &'a str) -> Self {
+        VariantValue::VString(String::from(val))
+    }
+}
+
+impl<'a> From<&'a [u8]> for VariantValue {
+    #[inline]
+    fn from(val: &'a [u8]) -> Self {
+        VariantValue::Binary(val.to_vec())
+    }
+}
+
+impl From<Numeric> for VariantValue {
+    #[inline]
+    fn from(val: Numeric) -> Self {
+        debug_assert!(!val.is_null());
+        match val {
+            Numeric::Byte(b) => VariantValue::Char(b as u8 as char),
+            Numeric::Short(s) => VariantValue::Short(s),
+            Numeric::Int(i) => VariantValue::Int(i),
+            Numeric::Long(v) => VariantValue::Long(v),
+            Numeric::Float(v) => VariantValue::Float(v),
+            Numeric::Double(v) => VariantValue::Double(v),
+            Numeric::Null => unreachable!(),
+        }
+    }
+}
+
+impl FromIterator<VariantValue> for VariantValue {
+    fn from_iter<I: IntoIterator<Item = VariantValue>>(iter: I) -> Self {
+        VariantValue::Vec(iter.into_iter().collect())
+    }
+}
+
+impl FromIterator<(String, VariantValue)> for VariantValue {
+    fn from_iter<I: IntoIterator<Item = (String, VariantValue)>>(iter: I) -> Self {
+        VariantValue::Map(iter.into_iter().collect())
+    }
+}
+
+/// Converts a JSON value, integers are mapped to `Long` and other numbers to
+/// `Double`, since JSON carries no width info. `null` and integers beyond
+/// `i64` range are not supported.
+impl<'a> TryFrom<&'a Value> for VariantValue {
+    type Error = Error;
+
+    fn try_from(value: &'a Value) -> Result<Self> {
+        match value {
+            Value::Null => bail!(IllegalArgument(
+                "null can't be converted to VariantValue".into()
+            )),
+            Value::Bool(b) => Ok(VariantValue::Bool(*b)),
+            Value::Number(n) => VariantValue::from_json_number(n),
+            Value::String(s) => Ok(VariantValue::VString(s.clone())),
+            Value::Array(arr) => {
+                let mut vec = Vec::with_capacity(arr.len());
+                for v in arr {
+                    vec.push(VariantValue::try_from(v)?);
+                }
+                Ok(VariantValue::Vec(vec))
+            }
+            Value::Object(obj) => {
+                let mut map = HashMap::with_capacity(obj.len());
+                for (k, v) in obj {
+                    map.insert(k.clone(), VariantValue::try_from(v)?);
+                }
+                Ok(VariantValue::Map(map))
+            }
+        }
+    }
+}
+
+/// Same as `TryFrom<&Value>`, but moves the strings, arrays and object keys
+/// out of `value` instead of cloning them.
+impl TryFrom<Value> for VariantValue {
+    type Error = Error;
+
+    fn try_from(value: Value) -> Result<Self> {
+        match value {
+            Value::Null => bail!(IllegalArgument(
+                "null can't be converted to VariantValue".into()
+            )),
+            Value::Bool(b) => Ok(VariantValue::Bool(b)),
+            Value::Number(n) => VariantValue::from_json_number(&n),
+            Value::String(s) => Ok(VariantValue::VString(s)),
+            Value::Array(arr) => {
+                let mut vec = Vec::with_capacity(arr.len());
+                for v in arr {
+                    vec.push(VariantValue::try_from(v)?);
+                }
+                Ok(VariantValue::Vec(vec))
+            }
+            Value::Object(obj) => {
+                let mut map = HashMap::with_capacity(obj.len());
+                for (k, v) in obj {
+                    map.insert(k, VariantValue::try_from(v)?);
+                }
+                Ok(VariantValue::Map(map))
+            }
+        }
+    }
+}
+
+/// A view of a JSON value which borrows its strings and keys instead of
+/// copying them, eg. for validation passes over large documents, see
+/// `TryFrom<&Value>` for the conversion rules. `to_variant` builds the owned
+/// `VariantValue` when needed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VariantValueRef<'a> {
+    Bool(bool),
+    Long(i64),
+    Double(f64),
+    Str(&'a str),
+    Vec(Vec<VariantValueRef<'a>>),
+    Map(HashMap<&'a str, VariantValueRef<'a>>),
+}
+
+impl<'a> VariantValueRef<'a> {
+    pub fn get_string(&self) -> Option<&'a str> {
+        match *self {
+            VariantValueRef::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of `key` if this is a map.
+    pub fn get(&self, key: &str) -> Option<&VariantValueRef<'a>> {
+        match self {
+            VariantValueRef::Map(m) => m.get(key),
+            _ => None,
+        }
+    }
+
+    /// Copies this value into an owned `VariantValue`.
+    pub fn to_variant(&self) -> VariantValue {
+        match self {
+            VariantValueRef::Bool(b) => VariantValue::Bool(*b),
+            VariantValueRef::Long(l) => VariantValue::Long(*l),
+            VariantValueRef::Double(d) => VariantValue::Double(*d),
+            VariantValueRef::Str(s) => VariantValue::VString(s.to_string()),
+            VariantValueRef::Vec(v) => {
+                VariantValue::Vec(v.iter().map(|v| v.to_variant()).collect())
+            }
+            VariantValueRef::Map(m) => VariantValue::Map(
+                m.iter()
+                    .map(|(k, v)| (k.to_string(), v.to_variant()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a Value> for VariantValueRef<'a> {
+    type Error = Error;
+
+    fn try_from(value: &'a Value) -> Result<Self> {
+        match value {
+            Value::Null => bail!(IllegalArgument(
+                "null can't be converted to VariantValue".into()
+            )),
+            Value::Bool(b) => Ok(VariantValueRef::Bool(*b)),
+            Value::Number(n) => match VariantValue::from_json_number(n)? {
+                VariantValue::Long(l) => Ok(VariantValueRef::Long(l)),
+                VariantValue::Double(d) => Ok(VariantValueRef::Double(d)),
+                _ => unreachable!(),
+            },
+            Value::String(s) => Ok(VariantValueRef::Str(s)),
+            Value::Array(arr) => {
+                let mut vec = Vec::with_capacity(arr.len());
+                for v in arr {
+                    vec.push(VariantValueRef::try_from(v)?);
+                }
+                Ok(VariantValueRef::Vec(vec))
+            }
+            Value::Object(obj) => {
+                let mut map = HashMap::with_capacity(obj.len());
+                for (k, v) in obj {
+                    map.insert(k.as_str(), VariantValueRef::try_from(v)?);
+                }
+                Ok(VariantValueRef::Map(map))
+            }
+        }
+    }
+}
+
+/// A slice holding exactly one value encoded by `to_bytes`, so that it can
+/// be decoded through `TryFrom`, eg. by generic deserialization code, as
+/// `TryFrom<&[u8]>` already wraps the slice as `Binary` through `From`.
+#[derive(Clone, Copy, Debug)]
+pub struct EncodedVariantValue<'a>(pub &'a [u8]);
+
+impl<'a> TryFrom<EncodedVariantValue<'a>> for VariantValue {
+    type Error = Error;
+
+    fn try_from(bytes: EncodedVariantValue<'a>) -> Result<Self> {
+        VariantValue::from_bytes_exact(bytes.0)
+    }
+}
+
+/// Strongly typed conversion out of a `VariantValue`, eg. for mapping field
+/// values to the type declared by the schema.
+///
+/// Integral and floating point values are widened if that is lossless, eg.
+/// `i64` accepts `Short`, `Int` and `Long`, while `f64` accepts `Float` and
+/// `Double` as well as `Short` and `Int`. Any other variant is a type mismatch.
+pub trait FromVariant: Sized {
+    fn from_variant(value: &VariantValue) -> Result<Self>;
+}
+
+fn type_mismatch<T>(expected: &str, value: &VariantValue) -> Result<T> {
+    let actual = match value {
+        VariantValue::Vec(_) => "vec".to_string(),
+        VariantValue::Map(_) | VariantValue::SortedMap(_) => "map".to_string(),
+        v => format!("{:?}", v),
+    };
+    bail!(IllegalArgument(format!(
+        "type mismatch: expected {}, got {}",
+        expected, actual
+    )))
+}
+
+impl FromVariant for bool {
+    fn from_variant(value: &VariantValue) -> Result<Self> {
+        match value.get_bool() {
+            Some(b) => Ok(b),
+            None => type_mismatch("bool", value),
+        }
+    }
+}
+
+impl FromVariant for i32 {
+    fn from_variant(value: &VariantValue) -> Result<Self> {
+        match *value {
+            VariantValue::Short(s) => Ok(i32::from(s)),
+            VariantValue::Int(i) => Ok(i),
+            _ => type_mismatch("i32", value),
+        }
+    }
+}
+
+impl FromVariant for i64 {
+    fn from_variant(value: &VariantValue) -> Result<Self> {
+        match *value {
+            VariantValue::Short(s) => Ok(i64::from(s)),
+            VariantValue::Int(i) => Ok(i64::from(i)),
+            VariantValue::Long(l) => Ok(l),
+            _ => type_mismatch("i64", value),
+        }
+    }
+}
+
+impl FromVariant for f64 {
+    fn from_variant(value: &VariantValue) -> Result<Self> {
+        match *value {
+            VariantValue::Short(s) => Ok(f64::from(s)),
+            VariantValue::Int(i) => Ok(f64::from(i)),
+            VariantValue::Float(f) => Ok(f64::from(f)),
+            VariantValue::Double(d) => Ok(d),
+            _ => type_mismatch("f64", value),
+        }
+    }
+}
+
+impl FromVariant for String {
+    fn from_variant(value: &VariantValue) -> Result<Self> {
+        match value.get_string() {
+            Some(s) => Ok(s.to_string()),
+            None => type_mismatch("string", value),
+        }
+    }
+}
+
+impl FromVariant for Vec<u8> {
+    fn from_variant(value: &VariantValue) -> Result<Self> {
+        match value.get_binary() {
+            Some(b) => Ok(b.to_vec()),
+            None => type_mismatch("binary", value),
+        }
+    }
+}
+
+/// A pool of shared strings, eg. for `VariantValue::intern_strings`, which
+/// hands out the same allocation for equal strings.
+#[derive(Default)]
+pub struct StringInterner {
+    strings: HashSet<Arc<str>>,
+}
+
+impl StringInterner {
+    pub fn new() -> StringInterner {
+        StringInterner::default()
+    }
+
+    /// Returns the shared string equal to `s`, adding it to the pool if it
+    /// isn't there yet.
+    pub fn intern(&mut self, s: &str) -> Arc<str> {
+        if let Some(shared) = self.strings.get(s) {
+            return Arc::clone(shared);
+        }
+        let shared: Arc<str> = Arc::from(s);
+        self.strings.insert(Arc::clone(&shared));
+        shared
+    }
+
+    /// Returns the number of distinct strings in the pool.
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+/// The fields added, removed and changed between two documents, see
+/// `VariantValue::diff`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VariantDiff {
+    /// `(path, new value)` of the fields only in the new document
+    pub added: Vec<(String, VariantValue)>,
+    /// `(path, old value)` of the fields only in the old document
+    pub removed: Vec<(String, VariantValue)>,
+    /// `(path, old value, new value)` of the fields with different values
+    pub changed: Vec<(String, VariantValue, VariantValue)>,
+}
+
+impl VariantDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+enum BuilderFrame {
+    // the key is `None` for the root and for the elements of an array
+    Object(Option<String>, HashMap<String, VariantValue>),
+    Array(Option<String>, Vec<VariantValue>),
+}
+
+/// A builder of `Map` documents, eg. for tests and ingestion, so that deep
+/// documents can be built fluently instead of from nested `HashMap` and
+/// `Vec` literals:
+///
+/// ```rust, ignore
+/// let doc = VariantValueBuilder::new()
+///     .field("id", 1i64)
+///     .begin_array("tags")
+///     .push("a")
+///     .push_object()
+///     .field("name", "b")
+///     .end_object()
+///     .end_array()
+///     .build();
+/// ```
+///
+/// Fields are added to the innermost open object and values are pushed to
+/// the innermost open array. Misuse, eg. adding a field to an array, closing
+/// the wrong kind of container or building with containers still open, is a
+/// programming error and panics.
+pub struct VariantValueBuilder {
+    stack: Vec<BuilderFrame>,
+}
+
+impl Default for VariantValueBuilder {
+    fn default() -> Self {
+        VariantValueBuilder::new()
+    }
+}
+
+impl VariantValueBuilder {
+    pub fn new() -> VariantValueBuilder {
+        VariantValueBuilder {
+            stack: vec![BuilderFrame::Object(None, HashMap::new())],
+        }
+    }
+
+    /// Sets the field `key` of the current object.
+    pub fn field<K: Into<String>, V: Into<VariantValue>>(mut self, key: K, value: V) -> Self {
+        match self.stack.last_mut() {
+            Some(BuilderFrame::Object(_, map)) => {
+                map.insert(key.into(), value.into());
+            }
+            _ => panic!("field() called inside an array"),
+        }
+        self
+    }
+
+    /// Pushes `value` to the current array.
+    pub fn push<V: Into<VariantValue>>(mut self, value: V) -> Self {
+        match self.stack.last_mut() {
+            Some(BuilderFrame::Array(_, values)) => values.push(value.into()),
+            _ => panic!("push() called outside of an array"),
+        }
+        self
+    }
+
+    /// Opens an array as the field `key` of the current object.
+    pub fn begin_array<K: Into<String>>(mut self, key: K) -> Self {
+        self.assert_in_object("begin_array");
+        self.stack
+            .push(BuilderFrame::Array(Some(key.into()), vec![]));
+        self
+    }
+
+    /// Opens an object as the field `key` of the current object.
+    pub fn begin_object<K: Into<String>>(mut self, key: K) -> Self {
+        self.assert_in_object("begin_object");
+        self.stack
+            .push(BuilderFrame::Object(Some(key.into()), HashMap::new()));
+        self
+    }
+
+    /// Opens an array as the next element of the current array.
+    pub fn push_array(mut self) -> Self {
+        self.assert_in_array("push_array");
+        self.stack.push(BuilderFrame::Array(None, vec![]));
+        self
+    }
+
+    /// Opens an object as the next element of the current array.
+    pub fn push_object(mut self) -> Self {
+        self.assert_in_array("push_object");
+        self.stack.push(BuilderFrame::Object(None, HashMap::new()));
+        self
+    }
+
+    /// Closes the current array.
+    pub fn end_array(mut self) -> Self {
+        match self.stack.pop() {
+            Some(BuilderFrame::Array(key, values)) => self.attach(key, VariantValue::Vec(values)),
+            _ => panic!("end_array() called outside of an array"),
+        }
+        self
+    }
+
+    /// Closes the current object.
+    pub fn end_object(mut self) -> Self {
+        if self.stack.len() == 1 {
+            panic!("end_object() called on the root object");
+        }
+        match self.stack.pop() {
+            Some(BuilderFrame::Object(key, map)) => self.attach(key, VariantValue::Map(map)),
+            _ => panic!("end_object() called outside of an object"),
+        }
+        self
+    }
+
+    /// Returns the document as a `Map`, all the containers must be closed.
+    pub fn build(mut self) -> VariantValue {
+        if self.stack.len() != 1 {
+            panic!(
+                "build() called with {} open containers",
+                self.stack.len() - 1
+            );
+        }
+        match self.stack.pop() {
+            Some(BuilderFrame::Object(_, map)) => VariantValue::Map(map),
+            _ => unreachable!(),
+        }
+    }
+
+    fn attach(&mut self, key: Option<String>, value: VariantValue) {
+        match (self.stack.last_mut(), key) {
+            (Some(BuilderFrame::Object(_, map)), Some(key)) => {
+                map.insert(key, value);
+            }
+            (Some(BuilderFrame::Array(_, values)), None) => values.push(value),
+            _ => unreachable!(),
+        }
+    }
+
+    fn assert_in_object(&self, method: &str) {
+        if let Some(BuilderFrame::Array(..)) = self.stack.last() {
+            panic!("{}() called inside an array", method);
+        }
+    }
+
+    fn assert_in_array(&self, method: &str) {
+        if let Some(BuilderFrame::Object(..)) = self.stack.last() {
+            panic!("{}() called outside of an array", method);
+        }
+    }
 }
 
-impl From<Numeric> for VariantValue {
-    fn from(val: Numeric) -> Self {
-        debug_assert!(!val.is_null());
-        match val {
-            Numeric::Byte(b) => VariantValue::Char(b as u8 as char),
-            Numeric::Short(s) => VariantValue::Short(s),
-            Numeric::Int(i) => VariantValue::Int(i),
-            Numeric::Long(v) => VariantValue::Long(v),
-            Numeric::Float(v) => VariantValue::Float(v),
-            Numeric::Double(v) => VariantValue::Double(v),
-            Numeric::Null => unreachable!(),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+    use std::mem;
+
+    /// nested document shared by the path related tests:
+    ///
+    /// ```json
+    /// {
+    ///     "fld_bool": true,
+    ///     "fld_long": 1234,
+    ///     "fld_string": "hello",
+    ///     "fld_object": {
+    ///         "obj_fld_long": 42,
+    ///         "obj_fld_string": "Inner",
+    ///         "obj_fld_array": [1, 2, 3]
+    ///     },
+    ///     "fld_array": [
+    ///         {"ary_fld_string": "B", "ary_fld_double": 2.5},
+    ///         {"ary_fld_string": "a", "ary_fld_double": 1.5}
+    ///     ]
+    /// }
+    /// ```
+    fn nested_sample() -> VariantValue {
+        let mut obj = HashMap::new();
+        obj.insert("obj_fld_long".to_string(), VariantValue::Long(42));
+        obj.insert("obj_fld_string".to_string(), VariantValue::from("Inner"));
+        obj.insert(
+            "obj_fld_array".to_string(),
+            VariantValue::Vec(vec![
+                VariantValue::Long(1),
+                VariantValue::Long(2),
+                VariantValue::Long(3),
+            ]),
+        );
+
+        let mut elem0 = HashMap::new();
+        elem0.insert("ary_fld_string".to_string(), VariantValue::from("B"));
+        elem0.insert("ary_fld_double".to_string(), VariantValue::Double(2.5));
+        let mut elem1 = HashMap::new();
+        elem1.insert("ary_fld_string".to_string(), VariantValue::from("a"));
+        elem1.insert("ary_fld_double".to_string(), VariantValue::Double(1.5));
+
+        let mut doc = HashMap::new();
+        doc.insert("fld_bool".to_string(), VariantValue::Bool(true));
+        doc.insert("fld_long".to_string(), VariantValue::Long(1234));
+        doc.insert("fld_string".to_string(), VariantValue::from("hello"));
+        doc.insert("fld_object".to_string(), VariantValue::Map(obj));
+        doc.insert(
+            "fld_array".to_string(),
+            VariantValue::Vec(vec![VariantValue::Map(elem0), VariantValue::Map(elem1)]),
+        );
+        VariantValue::Map(doc)
+    }
+
+    #[test]
+    fn variant_bool_test() {
+        let b = VariantValue::Bool(true);
+        let expr = format!("{}", b);
+        assert_eq!(expr, "true");
+    }
+
+    #[test]
+    fn variant_char_test() {
+        let c = VariantValue::Char('Z');
+        let expr = format!("{}", c);
+        assert_eq!(expr, "Z");
+    }
+
+    #[test]
+    fn variant_short_test() {
+        let s = VariantValue::Short(30);
+        let expr = format!("{}", s);
+        assert_eq!(expr, "30s");
+    }
+
+    #[test]
+    fn variant_int_test() {
+        let ival = VariantValue::Int(287);
+        let expr = format!("{}", ival);
+        assert_eq!(expr, "287");
+    }
+
+    #[test]
+    fn variant_long_test() {
+        let ival = VariantValue::Long(28_754_383);
+        let expr = format!("{}", ival);
+        assert_eq!(expr, "28754383");
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)]
+    fn variant_float_test() {
+        let fval = VariantValue::Float(3.141_593);
+        let expr = format!("{}f", fval);
+        assert_eq!(expr, "3.142f");
+
+        {
+            let fval2 = VariantValue::Float(3.141_593);
+            assert_eq!(fval.cmp(&fval2), Ordering::Equal);
+        }
+
+        {
+            let fval2 = VariantValue::Float(2.141_593);
+            assert_eq!(fval.cmp(&fval2), Ordering::Greater);
+        }
+
+        {
+            let fval2 = VariantValue::Float(4.141_593);
+            assert_eq!(fval.cmp(&fval2), Ordering::Less);
+        }
+
+        {
+            let fval2 = VariantValue::Float(4.141_593);
+            assert_eq!(fval.partial_cmp(&fval2), Some(Ordering::Less));
+        }
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)]
+    fn variant_double_test() {
+        let dval = VariantValue::Double(3.141_592_653_5);
+        let expr = format!("{}", dval);
+        assert_eq!(expr, "3.141593");
+    }
+
+    #[test]
+    fn variant_string_test() {
+        let strval = VariantValue::VString(String::from("hello world"));
+        let expr = format!("{}", strval);
+        assert_eq!(expr, "hello world");
+    }
+
+    #[test]
+    fn variant_binary_test() {
+        let bval = VariantValue::Binary(vec![65u8, 66u8, 67u8]);
+        let expr = format!("{}", bval);
+        assert_eq!(expr, "Binary(0x414243)");
+
+        if let VariantValue::Binary(ref bvec) = bval {
+            for (i, val) in bvec.iter().enumerate() {
+                assert_eq!(*val, b'A' + i as u8);
+            }
+        }
+    }
+
+    #[test]
+    fn variant_binary_display_truncate_test() {
+        let bval = VariantValue::Binary(vec![]);
+        assert_eq!(format!("{}", bval), "Binary(0x)");
+
+        let bval = VariantValue::Binary(vec![0xabu8; BINARY_DISPLAY_MAX_BYTES]);
+        let expr = format!("{}", bval);
+        assert_eq!(
+            expr,
+            format!("Binary(0x{})", "ab".repeat(BINARY_DISPLAY_MAX_BYTES))
+        );
+
+        let bval = VariantValue::Binary(vec![0x0fu8; 1024]);
+        let expr = format!("{}", bval);
+        assert_eq!(
+            expr,
+            format!("Binary(0x{}...)", "0f".repeat(BINARY_DISPLAY_MAX_BYTES))
+        );
+    }
+
+    #[test]
+    fn variant_get_number_test() {
+        assert_eq!(VariantValue::Short(3).get_number(), Some(3.0));
+        assert_eq!(VariantValue::Int(-7).get_number(), Some(-7.0));
+        assert_eq!(
+            VariantValue::Long(1 << 40).get_number(),
+            Some((1u64 << 40) as f64)
+        );
+        assert_eq!(VariantValue::Float(0.5).get_number(), Some(0.5));
+        assert_eq!(VariantValue::Double(2.25).get_number(), Some(2.25));
+        assert_eq!(VariantValue::VString("1".into()).get_number(), None);
+
+        assert_eq!(VariantValue::Bool(true).get_number(), None);
+        assert_eq!(
+            VariantValue::Bool(true).get_number_with_bool(true),
+            Some(1.0)
+        );
+        assert_eq!(
+            VariantValue::Bool(false).get_number_with_bool(true),
+            Some(0.0)
+        );
+        assert_eq!(VariantValue::Int(2).get_number_with_bool(true), Some(2.0));
+    }
+
+    #[test]
+    fn variant_get_number_precision_test() {
+        let exact = 1i64 << 53;
+        assert_eq!(
+            VariantValue::Long(exact).get_number().unwrap() as i64,
+            exact
+        );
+        assert_eq!(
+            VariantValue::Long(exact - 1).get_number().unwrap() as i64,
+            exact - 1
+        );
+
+        // 2^53 + 1 is the first integer that can't be represented by f64
+        let lossy = VariantValue::Long(exact + 1).get_number().unwrap();
+        assert_ne!(lossy as i64, exact + 1);
+        assert_eq!(lossy as i64, exact);
+    }
+
+    #[test]
+    fn variant_flatten_test() {
+        let flat = nested_sample().flatten('.');
+        assert_eq!(flat.len(), 12);
+        assert_eq!(flat["fld_bool"], VariantValue::Bool(true));
+        assert_eq!(flat["fld_object.obj_fld_long"], VariantValue::Long(42));
+        assert_eq!(flat["fld_object.obj_fld_array.2"], VariantValue::Long(3));
+        assert_eq!(flat["fld_array.0.ary_fld_string"], VariantValue::from("B"));
+        assert_eq!(
+            flat["fld_array.1.ary_fld_double"],
+            VariantValue::Double(1.5)
+        );
+        assert!(!flat.contains_key("fld_object"));
+        assert!(!flat.contains_key("fld_array.0"));
+
+        let flat = nested_sample().flatten('/');
+        assert_eq!(flat["fld_array/1/ary_fld_string"], VariantValue::from("a"));
+
+        let flat = VariantValue::Int(1).flatten('.');
+        assert_eq!(flat.len(), 1);
+        assert_eq!(flat[""], VariantValue::Int(1));
+
+        assert!(VariantValue::Vec(vec![]).flatten('.').is_empty());
+    }
+
+    #[test]
+    fn variant_iter_leaves_test() {
+        let sample = nested_sample();
+        let leaves: HashMap<String, &VariantValue> = sample.iter_leaves().collect();
+        assert_eq!(leaves.len(), 12);
+        // same paths and values as `flatten`
+        for (path, value) in sample.flatten('.') {
+            assert_eq!(leaves[&path], &value);
+        }
+
+        let array = sample.get_path("fld_object.obj_fld_array").unwrap();
+        let paths: Vec<String> = array.iter_leaves().map(|(p, _)| p).collect();
+        assert_eq!(paths, vec!["0", "1", "2"]);
+
+        let scalar = VariantValue::Int(1);
+        let leaves: Vec<_> = scalar.iter_leaves().collect();
+        assert_eq!(leaves, vec![(String::new(), &VariantValue::Int(1))]);
+        assert_eq!(VariantValue::Map(HashMap::new()).iter_leaves().count(), 0);
+    }
+
+    #[test]
+    fn variant_json_str_test() {
+        let json =
+            r#"{"name": "rucene", "stars": 42, "ratio": 0.5, "ok": true, "tags": ["a", "b"]}"#;
+        let v = VariantValue::from_json_str(json).unwrap();
+
+        let m = v.get_map().unwrap();
+        assert_eq!(m["name"], VariantValue::from("rucene"));
+        assert_eq!(m["stars"], VariantValue::Long(42));
+        assert_eq!(m["ratio"], VariantValue::Double(0.5));
+        assert_eq!(m["ok"], VariantValue::Bool(true));
+        assert_eq!(m["tags"].get_vec().unwrap()[1], VariantValue::from("b"));
+
+        let out = v.to_json_str().unwrap();
+        let expected: Value = serde_json::from_str(json).unwrap();
+        let actual: Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(actual, expected);
+
+        assert_eq!(VariantValue::Int(7).to_json_str().unwrap(), "7");
+        assert!(VariantValue::from_json_str("null").is_err());
+        assert!(VariantValue::from_json_str("18446744073709551615").is_err());
+        assert!(VariantValue::from_json_str("{").is_err());
+    }
+
+    #[test]
+    fn variant_try_from_owned_value_test() {
+        let json = r#"{
+            "name": "rucene",
+            "stars": 42,
+            "ratio": 0.5,
+            "nested": {"tags": ["a", "b"], "ok": false, "bin": [1, 2, 3]}
+        }"#;
+        let value: Value = serde_json::from_str(json).unwrap();
+
+        let borrowed = VariantValue::try_from(&value).unwrap();
+        let owned = VariantValue::try_from(value).unwrap();
+        assert_eq!(
+            serde_json::to_value(&owned).unwrap(),
+            serde_json::to_value(&borrowed).unwrap()
+        );
+        let nested = owned.get_map().unwrap()["nested"].get_map().unwrap();
+        assert_eq!(
+            nested["tags"].get_vec().unwrap()[0],
+            VariantValue::from("a")
+        );
+        assert_eq!(nested["ok"], VariantValue::Bool(false));
+
+        assert!(VariantValue::try_from(Value::Null).is_err());
+        assert!(VariantValue::try_from(serde_json::json!([1, null])).is_err());
+        assert!(VariantValue::try_from(serde_json::json!(u64::max_value())).is_err());
+    }
+
+    #[test]
+    fn variant_get_path_test() {
+        let v = nested_sample();
+
+        assert_eq!(v.get_path("fld_long"), Some(&VariantValue::Long(1234)));
+        assert_eq!(
+            v.get_path("fld_object.obj_fld_string"),
+            Some(&VariantValue::from("Inner"))
+        );
+        assert_eq!(
+            v.get_path("fld_object.obj_fld_array.1"),
+            Some(&VariantValue::Long(2))
+        );
+        assert_eq!(
+            v.get_path("fld_array.0.ary_fld_string"),
+            Some(&VariantValue::from("B"))
+        );
+        assert!(v.get_path("fld_object").unwrap().get_map().is_some());
+        assert!(v.get_path("").unwrap().get_map().is_some());
+
+        // missing key, out of range index, non numeric index and scalar traversal
+        assert!(v.get_path("fld_missing").is_none());
+        assert!(v.get_path("fld_object.obj_fld_array.3").is_none());
+        assert!(v.get_path("fld_array.first").is_none());
+        assert!(v.get_path("fld_long.0").is_none());
+        assert!(v.get_path("fld_object.").is_none());
+
+        // escaped dots and backslashes in keys
+        let mut inner = HashMap::new();
+        inner.insert("c".to_string(), VariantValue::Int(1));
+        let mut m = HashMap::new();
+        m.insert("a.b".to_string(), VariantValue::Map(inner));
+        m.insert("x\\y".to_string(), VariantValue::Int(2));
+        let v = VariantValue::Map(m);
+        assert_eq!(v.get_path("a\\.b.c"), Some(&VariantValue::Int(1)));
+        assert_eq!(v.get_path("x\\\\y"), Some(&VariantValue::Int(2)));
+        assert!(v.get_path("a.b.c").is_none());
+    }
+
+    #[test]
+    fn variant_tagged_json_test() {
+        let scalars = vec![
+            VariantValue::Bool(true),
+            VariantValue::Char('字'),
+            VariantValue::Short(30),
+            VariantValue::Short(i16::min_value()),
+            VariantValue::Int(i32::max_value()),
+            VariantValue::Long(i64::min_value()),
+            VariantValue::Float(0.1),
+            VariantValue::Float(f32::MAX),
+            VariantValue::Float(f32::NEG_INFINITY),
+            VariantValue::Double(0.1),
+            VariantValue::Double(f64::MIN_POSITIVE),
+            VariantValue::Double(f64::INFINITY),
+            VariantValue::VString("hello".to_string()),
+            VariantValue::VStringShared(Arc::from("shared")),
+            VariantValue::Binary(vec![0x00, 0xab, 0xff]),
+            VariantValue::Binary(vec![]),
+        ];
+        for v in &scalars {
+            let tagged = v.to_tagged_json();
+            // goes through a string, as snapshots do
+            let json: Value = serde_json::from_str(&tagged.to_string()).unwrap();
+            let restored = VariantValue::from_tagged_json(&json).unwrap();
+            assert_eq!(&restored, v);
+            // the strings are equal by contents, the variant is kept too
+            assert_eq!(mem::discriminant(&restored), mem::discriminant(v));
+        }
+
+        assert_eq!(
+            VariantValue::Short(30).to_tagged_json().to_string(),
+            r#"{"$type":"short","$val":30}"#
+        );
+
+        let nan = VariantValue::from_tagged_json(&VariantValue::Double(f64::NAN).to_tagged_json());
+        assert!(nan.unwrap().get_double().unwrap().is_nan());
+
+        // containers keep the types of their elements
+        let v = nested_sample();
+        let restored = VariantValue::from_tagged_json(&v.to_tagged_json()).unwrap();
+        assert_eq!(restored.flatten('.'), v.flatten('.'));
+
+        // and so do sorted maps
+        let sorted = v.to_sorted();
+        let restored = VariantValue::from_tagged_json(&sorted.to_tagged_json()).unwrap();
+        assert_eq!(restored, sorted);
+        assert_eq!(mem::discriminant(&restored), mem::discriminant(&sorted));
+        let inner = restored.get_path("fld_object").unwrap();
+        assert_eq!(
+            mem::discriminant(inner),
+            mem::discriminant(sorted.get_path("fld_object").unwrap())
+        );
+        let map = VariantValue::from_tagged_json(&v.to_tagged_json()).unwrap();
+        assert_eq!(mem::discriminant(&map), mem::discriminant(&v));
+
+        let invalid = vec![
+            r#"30"#,
+            r#"{"$type":"short"}"#,
+            r#"{"$type":"short","$val":40000}"#,
+            r#"{"$type":"char","$val":"ab"}"#,
+            r#"{"$type":"binary","$val":"abc"}"#,
+            r#"{"$type":"unknown","$val":1}"#,
+        ];
+        for json in invalid {
+            let value: Value = serde_json::from_str(json).unwrap();
+            assert!(VariantValue::from_tagged_json(&value).is_err());
+        }
+    }
+
+    #[test]
+    fn variant_approx_eq_test() {
+        let a = VariantValue::Double(1.0);
+        assert_eq!(a.approx_eq(&VariantValue::Double(1.0), 0.0), Some(true));
+        assert_eq!(a.approx_eq(&VariantValue::Double(1.25), 0.25), Some(true));
+        assert_eq!(a.approx_eq(&VariantValue::Double(0.75), 0.25), Some(true));
+        assert_eq!(
+            a.approx_eq(&VariantValue::Double(1.2500001), 0.25),
+            Some(false)
+        );
+        assert_eq!(
+            a.approx_eq(&VariantValue::Double(0.7499999), 0.25),
+            Some(false)
+        );
+
+        // mixed widths, 0.1f32 is not exactly 0.1f64
+        let f = VariantValue::Float(0.1);
+        let d = VariantValue::Double(0.1);
+        assert_ne!(f, d);
+        assert_eq!(f.approx_eq(&d, 0.0), Some(false));
+        assert_eq!(f.approx_eq(&d, 1e-8), Some(true));
+        assert_eq!(d.approx_eq(&f, 1e-8), Some(true));
+
+        let inf = VariantValue::Double(f64::INFINITY);
+        assert_eq!(
+            inf.approx_eq(&VariantValue::Float(f32::INFINITY), 0.0),
+            Some(true)
+        );
+        assert_eq!(
+            inf.approx_eq(&VariantValue::Double(f64::MAX), 1.0),
+            Some(false)
+        );
+        let nan = VariantValue::Double(f64::NAN);
+        assert_eq!(nan.approx_eq(&nan, f64::INFINITY), Some(false));
+
+        assert_eq!(a.approx_eq(&VariantValue::Long(1), 0.5), None);
+        assert_eq!(VariantValue::Int(1).approx_eq(&a, 0.5), None);
+        assert_eq!(VariantValue::from("1.0").approx_eq(&a, 0.5), None);
+    }
+
+    /// rebuilds the JSON string, tracking whether a separator is needed at each level
+    #[derive(Default)]
+    struct JsonVisitor {
+        out: String,
+        // for each open container, whether it has no element written yet
+        first: Vec<bool>,
+    }
+
+    impl JsonVisitor {
+        fn before_value(&mut self) {
+            if let Some(first) = self.first.last_mut() {
+                if !*first && !self.out.ends_with(':') {
+                    self.out.push(',');
+                }
+                *first = false;
+            }
+        }
+    }
+
+    impl VariantVisitor for JsonVisitor {
+        fn visit_scalar(&mut self, value: &VariantValue) {
+            self.before_value();
+            self.out.push_str(&serde_json::to_string(value).unwrap());
+        }
+
+        fn enter_seq(&mut self, _len: usize) {
+            self.before_value();
+            self.out.push('[');
+            self.first.push(true);
+        }
+
+        fn leave_seq(&mut self) {
+            self.first.pop();
+            self.out.push(']');
+        }
+
+        fn enter_map(&mut self, _len: usize) {
+            self.before_value();
+            self.out.push('{');
+            self.first.push(true);
+        }
+
+        fn visit_key(&mut self, key: &str) {
+            self.before_value();
+            self.out.push_str(&serde_json::to_string(key).unwrap());
+            self.out.push(':');
+        }
+
+        fn leave_map(&mut self) {
+            self.first.pop();
+            self.out.push('}');
+        }
+    }
+
+    #[test]
+    fn variant_visitor_test() {
+        let mut v = nested_sample();
+        if let VariantValue::Map(ref mut m) = v {
+            m.insert("fld_empty_array".to_string(), VariantValue::Vec(vec![]));
+            m.insert(
+                "fld_empty_object".to_string(),
+                VariantValue::Map(HashMap::new()),
+            );
+        }
+
+        // the keys of a `Map` are visited in its iteration order
+        let mut visitor = JsonVisitor::default();
+        v.accept(&mut visitor);
+        let visited: Value = serde_json::from_str(&visitor.out).unwrap();
+        assert_eq!(visited, serde_json::to_value(&v).unwrap());
+        // and in key order for a `SortedMap`
+        let mut visitor = JsonVisitor::default();
+        v.to_sorted().accept(&mut visitor);
+        assert_eq!(visitor.out, v.to_json_str().unwrap());
+
+        let mut visitor = JsonVisitor::default();
+        VariantValue::Int(7).accept(&mut visitor);
+        assert_eq!(visitor.out, "7");
+    }
+
+    #[test]
+    fn variant_ord_bool_below_numbers_test() {
+        assert!(VariantValue::Bool(false) < VariantValue::Bool(true));
+        // bools are not promoted to 0/1
+        assert!(VariantValue::Bool(true) < VariantValue::Int(0));
+        assert!(VariantValue::Bool(true) < VariantValue::Int(-1));
+        assert!(VariantValue::Double(f64::MIN) > VariantValue::Bool(true));
+        assert_ne!(VariantValue::Bool(true), VariantValue::Int(1));
+
+        let mut values = vec![
+            VariantValue::Int(3),
+            VariantValue::Bool(true),
+            VariantValue::Int(-5),
+            VariantValue::Bool(false),
+            VariantValue::Int(0),
+            VariantValue::Bool(true),
+        ];
+        values.sort();
+        assert_eq!(
+            values,
+            vec![
+                VariantValue::Bool(false),
+                VariantValue::Bool(true),
+                VariantValue::Bool(true),
+                VariantValue::Int(-5),
+                VariantValue::Int(0),
+                VariantValue::Int(3),
+            ]
+        );
+    }
+
+    #[test]
+    fn variant_map_mut_test() {
+        let mut doc = VariantValue::Map(HashMap::new());
+        assert!(!doc.contains_key("title"));
+
+        assert!(doc.insert("title", VariantValue::from("rucene")).is_none());
+        assert!(doc
+            .insert("stars".to_string(), VariantValue::Int(1))
+            .is_none());
+        assert_eq!(
+            doc.insert("stars", VariantValue::Int(2)),
+            Some(VariantValue::Int(1))
+        );
+        assert!(doc.contains_key("title"));
+        assert!(doc.contains_key("stars"));
+        assert_eq!(doc.get_map().unwrap()["stars"], VariantValue::Int(2));
+
+        doc.get_map_mut().unwrap().remove("title");
+        assert!(!doc.contains_key("title"));
+
+        // nested maps are reachable through get_map_mut
+        doc.insert("inner", VariantValue::Map(HashMap::new()));
+        doc.get_map_mut()
+            .unwrap()
+            .get_mut("inner")
+            .unwrap()
+            .insert("x", VariantValue::Long(7));
+        assert_eq!(doc.get_path("inner.x"), Some(&VariantValue::Long(7)));
+
+        // other variants are left untouched
+        let mut v = VariantValue::Int(1);
+        assert!(v.get_map_mut().is_none());
+        assert!(v.insert("a", VariantValue::Int(2)).is_none());
+        assert!(!v.contains_key("a"));
+        assert_eq!(v, VariantValue::Int(1));
+    }
+
+    #[test]
+    fn variant_sorted_map_serialization_test() {
+        let expected = r#"{"fld_array":[{"ary_fld_double":2.5,"ary_fld_string":"B"},{"ary_fld_double":1.5,"ary_fld_string":"a"}],"fld_bool":true,"fld_long":1234,"fld_object":{"obj_fld_array":[1,2,3],"obj_fld_long":42,"obj_fld_string":"Inner"},"fld_string":"hello"}"#;
+        assert_eq!(nested_sample().to_json_str().unwrap(), expected);
+
+        // maps with the same entries inserted in different orders, and so
+        // likely with different iteration orders, serialize the same
+        let keys: Vec<String> = (0..32).map(|i| format!("key_{:02}", i)).collect();
+        let forward: VariantValue = keys
+            .iter()
+            .map(|k| (k.clone(), VariantValue::from(k.as_str())))
+            .collect();
+        let backward: VariantValue = keys
+            .iter()
+            .rev()
+            .map(|k| (k.clone(), VariantValue::from(k.as_str())))
+            .collect();
+        let json = forward.to_json_str().unwrap();
+        assert_eq!(json, backward.to_json_str().unwrap());
+        assert!(json.starts_with(r#"{"key_00":"key_00","key_01":"key_01","#));
+    }
+
+    #[test]
+    fn variant_numeric_vec_test() {
+        let longs: VariantValue = vec![1i64, -2, 1 << 40]
+            .into_iter()
+            .map(VariantValue::Long)
+            .collect();
+        assert_eq!(longs.get_i64_vec(), Some(vec![1, -2, 1 << 40]));
+        assert_eq!(
+            longs.get_f64_vec(),
+            Some(vec![1.0, -2.0, (1i64 << 40) as f64])
+        );
+
+        // integral widths are widened
+        let ints = VariantValue::Vec(vec![VariantValue::Short(3), VariantValue::Int(4)]);
+        assert_eq!(ints.get_i64_vec(), Some(vec![3, 4]));
+
+        let mixed = VariantValue::Vec(vec![VariantValue::Long(1), VariantValue::Double(2.5)]);
+        assert!(mixed.get_i64_vec().is_none());
+        assert_eq!(mixed.get_f64_vec(), Some(vec![1.0, 2.5]));
+
+        let with_string = VariantValue::Vec(vec![VariantValue::Long(1), VariantValue::from("2")]);
+        assert!(with_string.get_i64_vec().is_none());
+        assert!(with_string.get_f64_vec().is_none());
+
+        let with_bool = VariantValue::Vec(vec![VariantValue::Bool(true)]);
+        assert!(with_bool.get_f64_vec().is_none());
+
+        assert_eq!(VariantValue::Vec(vec![]).get_i64_vec(), Some(vec![]));
+        assert!(VariantValue::Long(1).get_i64_vec().is_none());
+        assert!(VariantValue::Long(1).get_f64_vec().is_none());
+    }
+
+    #[test]
+    fn variant_str_array_test() {
+        let keywords: VariantValue = vec!["red", "green", "blue"]
+            .into_iter()
+            .map(VariantValue::from)
+            .collect();
+        assert_eq!(keywords.get_str_array(), Some(vec!["red", "green", "blue"]));
+
+        // shared strings are borrowed too
+        let mut pool = StringInterner::new();
+        let mut shared = keywords.clone();
+        shared.intern_strings(&mut pool);
+        assert_eq!(shared.get_str_array(), Some(vec!["red", "green", "blue"]));
+
+        let mixed = VariantValue::Vec(vec![VariantValue::from("red"), VariantValue::Long(1)]);
+        assert!(mixed.get_str_array().is_none());
+        let with_binary = VariantValue::Vec(vec![
+            VariantValue::from("red"),
+            VariantValue::Binary(b"green".to_vec()),
+        ]);
+        assert!(with_binary.get_str_array().is_none());
+
+        assert_eq!(VariantValue::Vec(vec![]).get_str_array(), Some(vec![]));
+        assert!(VariantValue::from("red").get_str_array().is_none());
+    }
+
+    #[test]
+    fn variant_normalize_nan_test() {
+        use std::collections::hash_map::DefaultHasher;
+
+        fn hash_of(v: &VariantValue) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            v.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let payload_nan = f64::from_bits(f64::NAN.to_bits() | 1);
+        assert!(payload_nan.is_nan());
+        assert_ne!(payload_nan.to_bits(), f64::NAN.to_bits());
+        assert_eq!(
+            hash_of(&VariantValue::Double(payload_nan)),
+            hash_of(&VariantValue::Double(f64::NAN))
+        );
+
+        let mut v = VariantValue::Vec(vec![
+            VariantValue::Double(payload_nan),
+            VariantValue::Float(f32::from_bits(f32::NAN.to_bits() | 1)),
+        ]);
+        v.normalize_nan();
+        let values = v.get_vec().unwrap();
+        assert_eq!(
+            values[0].get_double().unwrap().to_bits(),
+            f64::NAN.to_bits()
+        );
+        assert_eq!(values[1].get_float().unwrap().to_bits(), f32::NAN.to_bits());
+
+        // NaN sorts after everything, including infinity, instead of panicking
+        let mut values = vec![
+            VariantValue::Double(f64::NAN),
+            VariantValue::Double(1.5),
+            VariantValue::Double(f64::INFINITY),
+            VariantValue::Double(payload_nan),
+            VariantValue::Double(-3.0),
+        ];
+        values.sort();
+        let sorted: Vec<f64> = values.iter().map(|v| v.get_double().unwrap()).collect();
+        assert_eq!(&sorted[..3], &[-3.0, 1.5, f64::INFINITY]);
+        assert!(sorted[3].is_nan() && sorted[4].is_nan());
+        assert_eq!(
+            VariantValue::Float(f32::NAN).cmp(&VariantValue::Float(f32::NAN)),
+            Ordering::Equal
+        );
+        assert_eq!(
+            VariantValue::Float(f32::NAN).cmp(&VariantValue::Float(f32::MAX)),
+            Ordering::Greater
+        );
+
+        // `PartialEq` and `Hash` agree with `Ord`, whatever the NaN payload
+        assert_eq!(
+            VariantValue::Double(payload_nan),
+            VariantValue::Double(f64::NAN)
+        );
+        assert_eq!(VariantValue::Float(f32::NAN), VariantValue::Float(f32::NAN));
+        assert_ne!(VariantValue::Double(f64::NAN), VariantValue::Double(1.0));
+        let mut set = HashSet::new();
+        set.insert(VariantValue::Double(f64::NAN));
+        assert!(set.contains(&VariantValue::Double(payload_nan)));
+        set.insert(VariantValue::Double(0.0));
+        assert!(set.contains(&VariantValue::Double(-0.0)));
+        let mut values = vec![
+            VariantValue::Double(f64::NAN),
+            VariantValue::Double(1.5),
+            VariantValue::Double(payload_nan),
+        ];
+        values.sort();
+        values.dedup();
+        assert_eq!(values.len(), 2);
+    }
+
+    #[test]
+    fn variant_extend_test() {
+        let mut v = VariantValue::Vec(vec![VariantValue::Int(1)]);
+        v.extend_vec((2..4).map(VariantValue::Int)).unwrap();
+        assert_eq!(v.get_vec().unwrap().len(), 3);
+        assert_eq!(v.get_vec().unwrap()[2], VariantValue::Int(3));
+
+        let mut m: VariantValue = vec![("a".to_string(), VariantValue::Int(1))]
+            .into_iter()
+            .collect();
+        m.extend_map(vec![
+            ("a".to_string(), VariantValue::Int(10)),
+            ("b".to_string(), VariantValue::from("x")),
+        ])
+        .unwrap();
+        assert_eq!(m.get_map().unwrap().len(), 2);
+        assert_eq!(m.get_path("a"), Some(&VariantValue::Int(10)));
+        assert_eq!(m.get_path("b"), Some(&VariantValue::from("x")));
+
+        // mismatched variants are rejected and left unchanged
+        assert!(v
+            .extend_map(vec![("c".to_string(), VariantValue::Int(1))])
+            .is_err());
+        assert!(m.extend_vec(vec![VariantValue::Int(1)]).is_err());
+        assert!(VariantValue::Long(1).extend_vec(vec![]).is_err());
+        assert_eq!(v.get_vec().unwrap().len(), 3);
+        assert_eq!(m.get_map().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn variant_from_variant_test() {
+        assert!(bool::from_variant(&VariantValue::Bool(true)).unwrap());
+        assert!(bool::from_variant(&VariantValue::Int(1)).is_err());
+
+        assert_eq!(i32::from_variant(&VariantValue::Short(-3)).unwrap(), -3);
+        assert_eq!(i32::from_variant(&VariantValue::Int(7)).unwrap(), 7);
+        assert!(i32::from_variant(&VariantValue::Long(7)).is_err());
+
+        assert_eq!(i64::from_variant(&VariantValue::Int(7)).unwrap(), 7);
+        assert_eq!(
+            i64::from_variant(&VariantValue::Long(1 << 40)).unwrap(),
+            1 << 40
+        );
+        assert!(i64::from_variant(&VariantValue::Double(1.0)).is_err());
+
+        assert!((f64::from_variant(&VariantValue::Float(1.5)).unwrap() - 1.5).abs() < 1e-9);
+        assert!((f64::from_variant(&VariantValue::Int(2)).unwrap() - 2.0).abs() < 1e-9);
+        assert!(f64::from_variant(&VariantValue::Long(2)).is_err());
+        assert!(f64::from_variant(&VariantValue::Bool(true)).is_err());
+
+        assert_eq!(
+            String::from_variant(&VariantValue::from("abc")).unwrap(),
+            "abc"
+        );
+        assert!(String::from_variant(&VariantValue::from(&b"abc"[..])).is_err());
+
+        assert_eq!(
+            Vec::<u8>::from_variant(&VariantValue::from(&b"abc"[..])).unwrap(),
+            b"abc".to_vec()
+        );
+        assert!(Vec::<u8>::from_variant(&VariantValue::from("abc")).is_err());
+
+        let err = i64::from_variant(&nested_sample()).unwrap_err();
+        assert!(err.to_string().contains("expected i64, got map"));
+    }
+
+    #[test]
+    fn variant_bytes_codec_test() {
+        let values = vec![
+            VariantValue::Bool(true),
+            VariantValue::Bool(false),
+            VariantValue::Char('\u{1F600}'),
+            VariantValue::Short(-30),
+            VariantValue::Int(i32::min_value()),
+            VariantValue::Long(i64::max_value()),
+            VariantValue::Float(-1.25),
+            VariantValue::Double(::std::f64::consts::PI),
+            VariantValue::from("héllo"),
+            VariantValue::from(""),
+            VariantValue::from(&[0u8, 255, 7][..]),
+            VariantValue::Vec(vec![]),
+            nested_sample(),
+        ];
+        for v in &values {
+            let bytes = v.to_bytes();
+            let (decoded, len) = VariantValue::from_bytes(&bytes).unwrap();
+            assert_eq!(len, bytes.len());
+            assert_eq!(decoded, *v);
         }
+
+        // fixed widths are big endian
+        assert_eq!(VariantValue::Int(1).to_bytes(), vec![TAG_INT, 0, 0, 0, 1]);
+        // NaN payloads are kept bit for bit
+        let nan = f64::from_bits(f64::NAN.to_bits() | 1);
+        let (decoded, _) = VariantValue::from_bytes(&VariantValue::Double(nan).to_bytes()).unwrap();
+        assert_eq!(decoded.get_double().unwrap().to_bits(), nan.to_bits());
+
+        // values can be decoded back to back
+        let mut bytes = VariantValue::Short(1).to_bytes();
+        bytes.extend(VariantValue::from("a").to_bytes());
+        let (first, len) = VariantValue::from_bytes(&bytes).unwrap();
+        assert_eq!(first, VariantValue::Short(1));
+        let (second, _) = VariantValue::from_bytes(&bytes[len..]).unwrap();
+        assert_eq!(second, VariantValue::from("a"));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn variant_bytes_codec_invalid_test() {
+        let bytes = nested_sample().to_bytes();
+        for len in 0..bytes.len() {
+            assert!(VariantValue::from_bytes(&bytes[..len]).is_err());
+        }
+        // unknown tag
+        assert!(VariantValue::from_bytes(&[42]).is_err());
+        // invalid bool
+        assert!(VariantValue::from_bytes(&[TAG_BOOL, 2]).is_err());
+        // surrogate code point
+        assert!(VariantValue::from_bytes(&[TAG_CHAR, 0, 0, 0xd8, 0]).is_err());
+        // invalid UTF-8
+        assert!(VariantValue::from_bytes(&[TAG_STRING, 0, 0, 0, 1, 0xff]).is_err());
+        // a huge length prefix fails without allocating for it
+        assert!(VariantValue::from_bytes(&[TAG_VEC, 0xff, 0xff, 0xff, 0xff]).is_err());
+        // too deeply nested vecs fail instead of overflowing the stack
+        let nested = |depth: usize| {
+            let mut bytes = [TAG_VEC, 0, 0, 0, 1].repeat(depth);
+            bytes.extend(VariantValue::Bool(true).to_bytes());
+            bytes
+        };
+        assert!(VariantValue::from_bytes_exact(&nested(MAX_DECODE_DEPTH)).is_ok());
+        assert!(VariantValue::from_bytes(&nested(MAX_DECODE_DEPTH + 1)).is_err());
+        assert!(VariantValue::from_bytes(&nested(100_000)).is_err());
+    }
 
     #[test]
-    fn variant_bool_test() {
-        let b = VariantValue::Bool(true);
-        let expr = format!("{}", b);
-        assert_eq!(expr, "true");
+    fn variant_try_from_bytes_test() {
+        let sample = nested_sample();
+        let mut bytes = sample.to_bytes();
+        let decoded = VariantValue::from_bytes_exact(&bytes).unwrap();
+        assert_eq!(decoded, sample);
+        let int_bytes = VariantValue::Int(3).to_bytes();
+        let decoded: VariantValue = EncodedVariantValue(&int_bytes).try_into().unwrap();
+        assert_eq!(decoded, VariantValue::Int(3));
+
+        // trailing bytes, eg. a second value
+        bytes.extend(VariantValue::Bool(true).to_bytes());
+        assert!(VariantValue::from_bytes_exact(&bytes).is_err());
+        assert!(VariantValue::try_from(EncodedVariantValue(&bytes)).is_err());
+        bytes.truncate(bytes.len() - 1);
+        assert!(VariantValue::from_bytes_exact(&bytes).is_err());
+        // truncated
+        assert!(VariantValue::from_bytes_exact(&bytes[..3]).is_err());
+        assert!(VariantValue::try_from(EncodedVariantValue(&[])).is_err());
     }
 
     #[test]
-    fn variant_char_test() {
-        let c = VariantValue::Char('Z');
-        let expr = format!("{}", c);
-        assert_eq!(expr, "Z");
+    fn variant_bytes_stream_test() {
+        let values = vec![
+            VariantValue::Long(-7),
+            VariantValue::from("stream"),
+            nested_sample(),
+        ];
+        let mut bytes = vec![];
+        for v in &values {
+            bytes.extend(v.to_bytes());
+        }
+
+        let decoded: Vec<VariantValue> = VariantValue::from_bytes_stream(&bytes)
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(decoded, values);
+
+        // the offsets returned by `from_bytes` continue the stream
+        let (_, len) = VariantValue::from_bytes(&bytes).unwrap();
+        let (second, _) = VariantValue::from_bytes(&bytes[len..]).unwrap();
+        assert_eq!(second, VariantValue::from("stream"));
+
+        // trailing garbage fails once after the complete values
+        bytes.extend(&[TAG_LONG, 0, 1]);
+        let mut stream = VariantValue::from_bytes_stream(&bytes);
+        for _ in 0..3 {
+            assert!(stream.next().unwrap().is_ok());
+        }
+        assert!(stream.next().unwrap().is_err());
+        assert!(stream.next().is_none());
+
+        assert!(VariantValue::from_bytes_stream(&[]).next().is_none());
     }
 
     #[test]
-    fn variant_short_test() {
-        let s = VariantValue::Short(30);
-        let expr = format!("{}", s);
-        assert_eq!(expr, "30s");
+    fn variant_get_or_insert_with_test() {
+        let mut counts = VariantValue::Map(HashMap::new());
+        for term in &["a", "b", "a", "c", "a", "b"] {
+            let count = counts
+                .get_or_insert_with(term, || VariantValue::Long(0))
+                .unwrap();
+            *count = VariantValue::Long(count.get_long().unwrap() + 1);
+        }
+        assert_eq!(counts.get_path("a"), Some(&VariantValue::Long(3)));
+        assert_eq!(counts.get_path("b"), Some(&VariantValue::Long(2)));
+        assert_eq!(counts.get_path("c"), Some(&VariantValue::Long(1)));
+
+        // nested accumulators
+        let mut aggs = VariantValue::Map(HashMap::new());
+        aggs.get_or_insert_with("by_field", || VariantValue::Map(HashMap::new()))
+            .unwrap()
+            .get_or_insert_with("title", || VariantValue::Vec(vec![]))
+            .unwrap()
+            .extend_vec(vec![VariantValue::from("x")])
+            .unwrap();
+        assert_eq!(
+            aggs.get_path("by_field.title.0"),
+            Some(&VariantValue::from("x"))
+        );
+
+        // existing values are kept and `f` is not called
+        let existing = counts
+            .get_or_insert_with("a", || panic!("must not be called"))
+            .unwrap();
+        assert_eq!(*existing, VariantValue::Long(3));
+
+        let mut v = VariantValue::Int(1);
+        assert!(v
+            .get_or_insert_with("a", || panic!("must not be called"))
+            .is_none());
     }
 
     #[test]
-    fn variant_int_test() {
-        let ival = VariantValue::Int(287);
-        let expr = format!("{}", ival);
-        assert_eq!(expr, "287");
+    fn variant_default_test() {
+        let v = VariantValue::default();
+        assert!(v.get_map().unwrap().is_empty());
+
+        let mut docs: HashMap<String, VariantValue> = HashMap::new();
+        docs.entry("doc1".to_string())
+            .or_default()
+            .insert("title", VariantValue::from("rucene"));
+        assert_eq!(
+            docs["doc1"].get_path("title"),
+            Some(&VariantValue::from("rucene"))
+        );
     }
 
     #[test]
-    fn variant_long_test() {
-        let ival = VariantValue::Long(28_754_383);
-        let expr = format!("{}", ival);
-        assert_eq!(expr, "28754383");
+    fn variant_widen_test() {
+        assert_eq!(
+            VariantValue::Short(-3).widen(NumericKind::Int),
+            Some(VariantValue::Int(-3))
+        );
+        assert_eq!(
+            VariantValue::Short(7).widen(NumericKind::Long),
+            Some(VariantValue::Long(7))
+        );
+        assert_eq!(
+            VariantValue::Short(7).widen(NumericKind::Float),
+            Some(VariantValue::Float(7.0))
+        );
+        assert_eq!(
+            VariantValue::Int(i32::max_value()).widen(NumericKind::Long),
+            Some(VariantValue::Long(i64::from(i32::max_value())))
+        );
+        assert_eq!(
+            VariantValue::Int(i32::min_value()).widen(NumericKind::Double),
+            Some(VariantValue::Double(f64::from(i32::min_value())))
+        );
+        assert_eq!(
+            VariantValue::Float(1.5).widen(NumericKind::Double),
+            Some(VariantValue::Double(1.5))
+        );
+        assert_eq!(
+            VariantValue::Long(5).widen(NumericKind::Long),
+            Some(VariantValue::Long(5))
+        );
+
+        // narrowing or lossy conversions are rejected
+        assert_eq!(VariantValue::Long(5).widen(NumericKind::Int), None);
+        assert_eq!(VariantValue::Int(5).widen(NumericKind::Short), None);
+        assert_eq!(VariantValue::Double(1.5).widen(NumericKind::Float), None);
+        assert_eq!(VariantValue::Long(5).widen(NumericKind::Double), None);
+        assert_eq!(VariantValue::Int(5).widen(NumericKind::Float), None);
+        assert_eq!(VariantValue::Float(1.5).widen(NumericKind::Long), None);
+        assert_eq!(VariantValue::Bool(true).widen(NumericKind::Int), None);
+        assert_eq!(VariantValue::from("5").widen(NumericKind::Long), None);
     }
 
     #[test]
-    #[allow(clippy::approx_constant)]
-    fn variant_float_test() {
-        let fval = VariantValue::Float(3.141_593);
-        let expr = format!("{}f", fval);
-        assert_eq!(expr, "3.142f");
+    fn variant_retain_filter_map_test() {
+        let ints = || VariantValue::Vec((1..=6).map(VariantValue::Int).collect());
+        let is_odd = |v: &VariantValue| v.get_int().map_or(true, |i| i % 2 != 0);
 
-        {
-            let fval2 = VariantValue::Float(3.141_593);
-            assert_eq!(fval.cmp(&fval2), Ordering::Equal);
+        let mut values = ints();
+        values.retain(is_odd);
+        assert_eq!(
+            values.get_vec().unwrap(),
+            &vec![
+                VariantValue::Int(1),
+                VariantValue::Int(3),
+                VariantValue::Int(5),
+            ]
+        );
+
+        // odd values are widened to `Long`, even ones are dropped
+        let mapped = ints().filter_map(|v| match v {
+            VariantValue::Int(i) if i % 2 != 0 => Some(VariantValue::Long(i64::from(i))),
+            _ => None,
+        });
+        assert_eq!(
+            mapped.get_vec().unwrap(),
+            &vec![
+                VariantValue::Long(1),
+                VariantValue::Long(3),
+                VariantValue::Long(5),
+            ]
+        );
+
+        // no-op for other variants
+        let mut scalar = VariantValue::Int(2);
+        scalar.retain(|_| false);
+        assert_eq!(scalar, VariantValue::Int(2));
+        let map = nested_sample().filter_map(|_| None);
+        assert_eq!(
+            map.get_map().unwrap().len(),
+            nested_sample().get_map().unwrap().len()
+        );
+    }
+
+    #[test]
+    fn variant_coerce_test() {
+        let coerce = |v: VariantValue, kind| v.coerce(kind).unwrap();
+        // string -> numbers
+        assert_eq!(
+            coerce(VariantValue::from("123"), VariantKind::Int),
+            VariantValue::Int(123)
+        );
+        assert_eq!(
+            coerce(VariantValue::from("-7"), VariantKind::Short),
+            VariantValue::Short(-7)
+        );
+        assert_eq!(
+            coerce(VariantValue::from("2.5"), VariantKind::Double),
+            VariantValue::Double(2.5)
+        );
+        assert_eq!(
+            coerce(VariantValue::from("true"), VariantKind::Bool),
+            VariantValue::Bool(true)
+        );
+        assert_eq!(
+            coerce(VariantValue::from("x"), VariantKind::Char),
+            VariantValue::Char('x')
+        );
+        // numbers -> string
+        assert_eq!(
+            coerce(VariantValue::Double(2.5), VariantKind::VString),
+            VariantValue::from("2.5")
+        );
+        assert_eq!(
+            coerce(VariantValue::Double(-3.0), VariantKind::VString),
+            VariantValue::from("-3")
+        );
+        assert_eq!(
+            coerce(VariantValue::Short(4), VariantKind::VString),
+            VariantValue::from("4")
+        );
+        // JSON integers are `Long`, the schema decides the width
+        assert_eq!(
+            coerce(VariantValue::Long(42), VariantKind::Short),
+            VariantValue::Short(42)
+        );
+        assert_eq!(
+            coerce(VariantValue::Double(3.0), VariantKind::Int),
+            VariantValue::Int(3)
+        );
+        assert_eq!(
+            coerce(VariantValue::Int(3), VariantKind::Double),
+            VariantValue::Double(3.0)
+        );
+        assert_eq!(
+            coerce(VariantValue::Long(9), VariantKind::Long),
+            VariantValue::Long(9)
+        );
+        assert_eq!(
+            coerce(VariantValue::from("ab"), VariantKind::Binary),
+            VariantValue::from(&b"ab"[..])
+        );
+
+        let err = |v: VariantValue, kind| v.coerce(kind).is_err();
+        assert!(err(VariantValue::from("12a"), VariantKind::Int));
+        assert!(err(VariantValue::from("70000"), VariantKind::Short));
+        assert!(err(VariantValue::Long(1 << 40), VariantKind::Int));
+        assert!(err(VariantValue::Double(3.5), VariantKind::Long));
+        assert!(err(
+            VariantValue::Double(::std::f64::NAN),
+            VariantKind::Long
+        ));
+        assert!(err(VariantValue::Double(1e19), VariantKind::Long));
+        assert!(err(VariantValue::from("ab"), VariantKind::Char));
+        assert!(err(VariantValue::Bool(true), VariantKind::Int));
+        assert!(err(VariantValue::from(&[0xffu8][..]), VariantKind::VString));
+        assert!(err(nested_sample(), VariantKind::VString));
+        let msg = VariantValue::from("abc")
+            .coerce(VariantKind::Int)
+            .unwrap_err()
+            .to_string();
+        assert!(msg.contains("VString(\"abc\")") && msg.contains("Int"));
+    }
+
+    #[test]
+    fn variant_as_epoch_millis_test() {
+        assert_eq!(
+            VariantValue::Long(1_551_429_000_250).as_epoch_millis(),
+            Some(1_551_429_000_250)
+        );
+        let millis = |s: &str| VariantValue::from(s).as_epoch_millis();
+        assert_eq!(millis("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(millis("2019-03-01T08:30:00Z"), Some(1_551_429_000_000));
+        assert_eq!(millis("2019-03-01T08:30:00.250Z"), Some(1_551_429_000_250));
+        assert_eq!(millis("2019-03-01T08:30:00.5Z"), Some(1_551_429_000_500));
+        assert_eq!(millis("2000-02-29T23:59:59Z"), Some(951_868_799_000));
+        assert_eq!(millis("1969-12-31T23:59:59Z"), Some(-1000));
+        assert_eq!(millis("1900-01-01T00:00:00Z"), Some(-2_208_988_800_000));
+
+        assert_eq!(millis("2019-02-29T00:00:00Z"), None);
+        assert_eq!(millis("1900-02-29T00:00:00Z"), None);
+        assert_eq!(millis("2019-13-01T00:00:00Z"), None);
+        assert_eq!(millis("2019-03-01T24:00:00Z"), None);
+        assert_eq!(millis("2019-03-01 08:30:00Z"), None);
+        assert_eq!(millis("2019-03-01T08:30:00"), None);
+        assert_eq!(millis("2019-03-01T08:30:00+01:00"), None);
+        assert_eq!(millis("2019-03-01T08:30:00.1234Z"), None);
+        assert_eq!(millis("2019-03-01T08:30:0xZ"), None);
+        assert_eq!(millis("2019-03-01T08:30:00.Z"), None);
+        assert_eq!(millis("²019-03-01T08:30:00Z"), None);
+        assert_eq!(VariantValue::Int(5).as_epoch_millis(), None);
+        assert_eq!(VariantValue::Double(5.0).as_epoch_millis(), None);
+    }
+
+    #[test]
+    fn variant_map_values_test() {
+        let mut doc = nested_sample();
+        let mut leaves = 0;
+        doc.map_values(|v| {
+            leaves += 1;
+            if let VariantValue::VString(s) = v {
+                *s = s.to_lowercase();
+            }
+        });
+        assert_eq!(leaves, 12);
+        assert_eq!(
+            doc.get_path("fld_object.obj_fld_string"),
+            Some(&VariantValue::from("inner"))
+        );
+        assert_eq!(
+            doc.get_path("fld_array.0.ary_fld_string"),
+            Some(&VariantValue::from("b"))
+        );
+        // other leaves are unchanged
+        assert_eq!(
+            doc.get_path("fld_object.obj_fld_long"),
+            Some(&VariantValue::Long(42))
+        );
+
+        let mut scalar = VariantValue::Int(1);
+        scalar.map_values(|v| *v = VariantValue::Long(2));
+        assert_eq!(scalar, VariantValue::Long(2));
+    }
+
+    #[test]
+    fn variant_get_bool_lenient_test() {
+        assert_eq!(VariantValue::Bool(true).get_bool_lenient(), Some(true));
+        assert_eq!(VariantValue::Bool(false).get_bool_lenient(), Some(false));
+
+        assert_eq!(VariantValue::Short(1).get_bool_lenient(), Some(true));
+        assert_eq!(VariantValue::Int(0).get_bool_lenient(), Some(false));
+        assert_eq!(VariantValue::Long(1).get_bool_lenient(), Some(true));
+        assert_eq!(VariantValue::Int(2).get_bool_lenient(), None);
+        assert_eq!(VariantValue::Long(-1).get_bool_lenient(), None);
+        assert_eq!(VariantValue::Double(1.0).get_bool_lenient(), None);
+
+        for s in &["true", "TRUE", "True", "yes", "YES", "1", " yes "] {
+            assert_eq!(VariantValue::from(*s).get_bool_lenient(), Some(true));
+        }
+        for s in &["false", "FALSE", "no", "No", "0"] {
+            assert_eq!(VariantValue::from(*s).get_bool_lenient(), Some(false));
+        }
+        for s in &["", "y", "on", "2", "truee"] {
+            assert_eq!(VariantValue::from(*s).get_bool_lenient(), None);
         }
 
-        {
-            let fval2 = VariantValue::Float(2.141_593);
-            assert_eq!(fval.cmp(&fval2), Ordering::Greater);
+        assert_eq!(VariantValue::Char('1').get_bool_lenient(), None);
+        assert_eq!(VariantValue::Vec(vec![]).get_bool_lenient(), None);
+    }
+
+    #[test]
+    fn variant_deep_eq_ignoring_numeric_width_test() {
+        let ingested = VariantValue::from_json_str(
+            r#"{"id": 7, "price": 0.1, "tags": ["a", 2], "inner": {"count": 3, "ratio": 2.5}}"#,
+        )
+        .unwrap();
+
+        let mut inner = HashMap::new();
+        inner.insert("count".to_string(), VariantValue::Short(3));
+        inner.insert("ratio".to_string(), VariantValue::Float(2.5));
+        let mut expected = HashMap::new();
+        expected.insert("id".to_string(), VariantValue::Int(7));
+        expected.insert("price".to_string(), VariantValue::Float(0.1));
+        expected.insert(
+            "tags".to_string(),
+            VariantValue::Vec(vec![VariantValue::from("a"), VariantValue::Int(2)]),
+        );
+        expected.insert("inner".to_string(), VariantValue::Map(inner));
+        let mut expected = VariantValue::Map(expected);
+
+        assert!(ingested.deep_eq_ignoring_numeric_width(&expected));
+        assert!(expected.deep_eq_ignoring_numeric_width(&ingested));
+
+        // a different value deep inside
+        fn set_count(doc: &mut VariantValue, count: VariantValue) {
+            let inner = doc.get_map_mut().unwrap().get_mut("inner").unwrap();
+            inner
+                .get_map_mut()
+                .unwrap()
+                .insert("count".to_string(), count);
         }
+        set_count(&mut expected, VariantValue::Long(4));
+        assert!(!ingested.deep_eq_ignoring_numeric_width(&expected));
+        set_count(&mut expected, VariantValue::Long(3));
+        assert!(ingested.deep_eq_ignoring_numeric_width(&expected));
 
-        {
-            let fval2 = VariantValue::Float(4.141_593);
-            assert_eq!(fval.cmp(&fval2), Ordering::Less);
+        // a missing key
+        expected.get_map_mut().unwrap().remove("price");
+        assert!(!ingested.deep_eq_ignoring_numeric_width(&expected));
+
+        assert!(VariantValue::Int(5).deep_eq_ignoring_numeric_width(&VariantValue::Long(5)));
+        assert!(!VariantValue::Int(5).deep_eq_ignoring_numeric_width(&VariantValue::Double(5.0)));
+        assert!(!VariantValue::Double(5.0).deep_eq_ignoring_numeric_width(&VariantValue::Int(5)));
+        assert!(
+            VariantValue::Double(1e10).deep_eq_ignoring_numeric_width(&VariantValue::Float(1e10))
+        );
+        assert!(
+            !VariantValue::Double(1.0).deep_eq_ignoring_numeric_width(&VariantValue::Double(1.01))
+        );
+        assert!(!VariantValue::from("5").deep_eq_ignoring_numeric_width(&VariantValue::Int(5)));
+        assert!(!VariantValue::Vec(vec![]).deep_eq_ignoring_numeric_width(&VariantValue::Int(5)));
+        assert!(!VariantValue::from("a").deep_eq_ignoring_numeric_width(&VariantValue::Vec(vec![])));
+    }
+
+    #[test]
+    fn variant_get_pointer_test() {
+        let doc = nested_sample();
+        assert_eq!(
+            doc.get_pointer("/fld_array/1/ary_fld_string"),
+            doc.get_path("fld_array.1.ary_fld_string")
+        );
+        assert!(doc.get_pointer("/fld_array/1/ary_fld_string").is_some());
+        assert_eq!(
+            doc.get_pointer("/fld_object/obj_fld_long"),
+            Some(&VariantValue::Long(42))
+        );
+        assert!(doc.get_pointer("").unwrap().get_map().is_some());
+
+        // missing, out of range and invalid pointers
+        assert_eq!(doc.get_pointer("/missing"), None);
+        assert_eq!(doc.get_pointer("/fld_array/99"), None);
+        assert_eq!(doc.get_pointer("/fld_array/01"), None);
+        assert_eq!(doc.get_pointer("/fld_array/-"), None);
+        assert_eq!(doc.get_pointer("/fld_object/obj_fld_long/0"), None);
+        assert_eq!(doc.get_pointer("fld_object"), None);
+
+        let doc = VariantValue::from_json_str(
+            r#"{"a/b": {"m~n": 1}, "": {"": 2}, "c.d": [true], "e~1": 3}"#,
+        )
+        .unwrap();
+        assert_eq!(doc.get_pointer("/a~1b/m~0n"), Some(&VariantValue::Long(1)));
+        // `~01` unescapes to `~1`, not to `/`
+        assert_eq!(doc.get_pointer("/e~01"), Some(&VariantValue::Long(3)));
+        assert_eq!(doc.get_pointer("//"), Some(&VariantValue::Long(2)));
+        assert_eq!(doc.get_pointer("/c.d/0"), Some(&VariantValue::Bool(true)));
+        assert_eq!(doc.get_pointer("/a~2b"), None);
+        assert_eq!(doc.get_pointer("/a~"), None);
+    }
+
+    #[test]
+    fn variant_builder_test() {
+        let doc = VariantValueBuilder::new()
+            .field("fld_bool", true)
+            .field("fld_long", 1234i64)
+            .field("fld_string", "hello")
+            .begin_object("fld_object")
+            .field("obj_fld_long", 42i64)
+            .field("obj_fld_string", "Inner")
+            .begin_array("obj_fld_array")
+            .push(1i64)
+            .push(2i64)
+            .push(3i64)
+            .end_array()
+            .end_object()
+            .begin_array("fld_array")
+            .push_object()
+            .field("ary_fld_string", "B")
+            .field("ary_fld_double", 2.5)
+            .end_object()
+            .push_object()
+            .field("ary_fld_string", "a")
+            .field("ary_fld_double", 1.5)
+            .end_object()
+            .end_array()
+            .build();
+        assert_eq!(doc, nested_sample());
+        // the widths are kept as given
+        assert_eq!(
+            doc.get_path("fld_object.obj_fld_array.2"),
+            Some(&VariantValue::Long(3))
+        );
+
+        let doc = VariantValueBuilder::new()
+            .begin_array("matrix")
+            .push_array()
+            .push(1)
+            .end_array()
+            .push_array()
+            .end_array()
+            .end_array()
+            .begin_object("empty")
+            .end_object()
+            .build();
+        assert_eq!(doc.get_path("matrix.0.0"), Some(&VariantValue::Int(1)));
+        assert_eq!(
+            doc.get_path("matrix.1").unwrap().get_vec().unwrap().len(),
+            0
+        );
+        assert!(doc.get_path("empty").unwrap().get_map().unwrap().is_empty());
+
+        assert_eq!(
+            VariantValueBuilder::new().build().get_map().unwrap().len(),
+            0
+        );
+    }
+
+    #[test]
+    fn variant_saturating_test() {
+        assert_eq!(
+            VariantValue::Long(i64::max_value()).get_i32_saturating(),
+            Some(i32::max_value())
+        );
+        assert_eq!(
+            VariantValue::Long(i64::min_value()).get_i32_saturating(),
+            Some(i32::min_value())
+        );
+        assert_eq!(VariantValue::Long(-7).get_i32_saturating(), Some(-7));
+        assert_eq!(VariantValue::Short(3).get_i32_saturating(), Some(3));
+        assert_eq!(
+            VariantValue::Double(1e20).get_i32_saturating(),
+            Some(i32::max_value())
+        );
+        assert_eq!(VariantValue::Float(-2.9).get_i32_saturating(), Some(-2));
+        assert_eq!(
+            VariantValue::Double(::std::f64::NEG_INFINITY).get_i32_saturating(),
+            Some(i32::min_value())
+        );
+        assert_eq!(
+            VariantValue::Double(::std::f64::NAN).get_i32_saturating(),
+            None
+        );
+
+        assert_eq!(
+            VariantValue::Int(40_000).get_i16_saturating(),
+            Some(i16::max_value())
+        );
+        assert_eq!(
+            VariantValue::Int(-40_000).get_i16_saturating(),
+            Some(i16::min_value())
+        );
+        assert_eq!(VariantValue::Double(12.7).get_i16_saturating(), Some(12));
+
+        assert_eq!(VariantValue::Int(300).get_u8_saturating(), Some(255));
+        assert_eq!(VariantValue::Long(-1).get_u8_saturating(), Some(0));
+        assert_eq!(VariantValue::Short(200).get_u8_saturating(), Some(200));
+        assert_eq!(VariantValue::Float(-0.5).get_u8_saturating(), Some(0));
+
+        assert_eq!(VariantValue::Bool(true).get_i32_saturating(), None);
+        assert_eq!(VariantValue::from("5").get_u8_saturating(), None);
+        // the checked getter still refuses the other widths
+        assert_eq!(VariantValue::Long(5).get_int(), None);
+    }
+
+    #[test]
+    fn variant_to_f64_lossy_test() {
+        let max_exact = 1i64 << 53;
+        assert_eq!(
+            VariantValue::Long(max_exact).to_f64_lossy(),
+            Some((max_exact as f64, false))
+        );
+        assert_eq!(
+            VariantValue::Long(-max_exact).to_f64_lossy(),
+            Some((-max_exact as f64, false))
+        );
+        // 2^53 + 1 rounds to 2^53
+        assert_eq!(
+            VariantValue::Long(max_exact + 1).to_f64_lossy(),
+            Some((max_exact as f64, true))
+        );
+        assert_eq!(
+            VariantValue::Long(-max_exact - 1).to_f64_lossy().unwrap().1,
+            true
+        );
+        // even values beyond 2^53 are exact up to 2^54
+        assert_eq!(
+            VariantValue::Long(max_exact + 2).to_f64_lossy().unwrap().1,
+            false
+        );
+        assert_eq!(
+            VariantValue::Long(i64::max_value())
+                .to_f64_lossy()
+                .unwrap()
+                .1,
+            true
+        );
+        assert_eq!(
+            VariantValue::Long(i64::min_value())
+                .to_f64_lossy()
+                .unwrap()
+                .1,
+            false
+        );
+
+        assert_eq!(
+            VariantValue::Int(i32::max_value())
+                .to_f64_lossy()
+                .unwrap()
+                .1,
+            false
+        );
+        assert_eq!(VariantValue::Float(0.1).to_f64_lossy().unwrap().1, false);
+        assert_eq!(
+            VariantValue::Double(1e300).to_f64_lossy(),
+            Some((1e300, false))
+        );
+        assert_eq!(VariantValue::Bool(true).to_f64_lossy(), None);
+        assert_eq!(VariantValue::from("1").to_f64_lossy(), None);
+    }
+
+    #[test]
+    fn variant_intern_strings_test() {
+        use std::collections::hash_map::DefaultHasher;
+
+        let mut doc = VariantValueBuilder::new()
+            .field("category", "books")
+            .field("id", 7)
+            .begin_array("items")
+            .push_object()
+            .field("category", "books")
+            .end_object()
+            .push("music")
+            .end_array()
+            .build();
+        let expected = doc.clone();
+
+        let mut pool = StringInterner::new();
+        doc.intern_strings(&mut pool);
+        assert_eq!(pool.len(), 2);
+
+        let shared = |v: &VariantValue| match v {
+            VariantValue::VStringShared(s) => Arc::clone(s),
+            v => panic!("not shared: {:?}", v),
+        };
+        let s1 = shared(doc.get_path("category").unwrap());
+        let s2 = shared(doc.get_path("items.0.category").unwrap());
+        // the two equal strings share one allocation
+        assert!(Arc::ptr_eq(&s1, &s2));
+        assert_eq!(&*s1, "books");
+        assert!(!Arc::ptr_eq(&s1, &shared(doc.get_path("items.1").unwrap())));
+
+        // shared strings behave like owned ones
+        assert_eq!(doc, expected);
+        let owned = VariantValue::from("books");
+        let interned = doc.get_path("category").unwrap();
+        assert_eq!(interned, &owned);
+        assert_eq!(&owned, interned);
+        assert_eq!(interned.cmp(&VariantValue::from("art")), Ordering::Greater);
+        assert_eq!(interned.get_string(), Some("books"));
+        assert_eq!(format!("{}", interned), "books");
+        let mut h1 = DefaultHasher::new();
+        interned.hash(&mut h1);
+        let mut h2 = DefaultHasher::new();
+        owned.hash(&mut h2);
+        assert_eq!(h1.finish(), h2.finish());
+        let json = VariantValue::from_json_str(&doc.to_json_str().unwrap()).unwrap();
+        assert!(json.deep_eq_ignoring_numeric_width(&expected));
+        assert_eq!(
+            interned.clone().coerce(VariantKind::Binary).unwrap(),
+            VariantValue::Binary(b"books".to_vec())
+        );
+
+        // interning again keeps the pool as is
+        doc.intern_strings(&mut pool);
+        assert_eq!(pool.len(), 2);
+        assert!(Arc::ptr_eq(&s1, &shared(doc.get_path("category").unwrap())));
+    }
+
+    #[test]
+    fn variant_apply_merge_patch_test() {
+        // the example of RFC 7386
+        let mut doc = VariantValue::from_json_str(
+            r#"{"title": "Goodbye!", "author": {"givenName": "John", "familyName": "Doe"},
+                "tags": ["example", "sample"], "content": "This will be unchanged"}"#,
+        )
+        .unwrap();
+        let patch: Value = serde_json::from_str(
+            r#"{"title": "Hello!", "phoneNumber": "+01-123-456-7890",
+                "author": {"familyName": null}, "tags": ["example"]}"#,
+        )
+        .unwrap();
+        doc.apply_merge_patch(&patch).unwrap();
+
+        let expected = VariantValue::from_json_str(
+            r#"{"title": "Hello!", "author": {"givenName": "John"}, "tags": ["example"],
+                "content": "This will be unchanged", "phoneNumber": "+01-123-456-7890"}"#,
+        )
+        .unwrap();
+        assert!(doc.deep_eq_ignoring_numeric_width(&expected));
+        assert!(!doc.contains_key("familyName"));
+        assert!(doc.get_path("author.familyName").is_none());
+
+        let cases = [
+            (r#"{"a": "b"}"#, r#"{"a": "c"}"#, r#"{"a": "c"}"#),
+            (r#"{"a": "b"}"#, r#"{"b": "c"}"#, r#"{"a": "b", "b": "c"}"#),
+            (r#"{"a": "b", "b": "c"}"#, r#"{"a": null}"#, r#"{"b": "c"}"#),
+            (r#"{"a": ["b"]}"#, r#"{"a": "c"}"#, r#"{"a": "c"}"#),
+            (r#"{"a": "c"}"#, r#"{"a": ["b"]}"#, r#"{"a": ["b"]}"#),
+            (
+                r#"{"a": {"b": "c"}}"#,
+                r#"{"a": {"b": "d", "c": null}}"#,
+                r#"{"a": {"b": "d"}}"#,
+            ),
+            (r#"{"a": [{"b": "c"}]}"#, r#"{"a": [1]}"#, r#"{"a": [1]}"#),
+            (r#"["a", "b"]"#, r#"["c", "d"]"#, r#"["c", "d"]"#),
+            (r#"{"a": "b"}"#, r#"["c"]"#, r#"["c"]"#),
+            (r#"{"e": null}"#, r#"{"a": 1}"#, r#"{"a": 1}"#),
+            (r#"[1, 2]"#, r#"{"a": "b", "c": null}"#, r#"{"a": "b"}"#),
+            (
+                r#"{}"#,
+                r#"{"a": {"bb": {"ccc": null}}}"#,
+                r#"{"a": {"bb": {}}}"#,
+            ),
+        ];
+        for &(target, patch, expected) in &cases {
+            // JSON nulls can't be ingested, they are dropped from the targets
+            let target = target.replace(r#""e": null"#, "");
+            let mut doc = VariantValue::from_json_str(&target).unwrap();
+            doc.apply_merge_patch(&serde_json::from_str(patch).unwrap())
+                .unwrap();
+            let expected = VariantValue::from_json_str(expected).unwrap();
+            assert!(doc.deep_eq_ignoring_numeric_width(&expected), "{}", patch);
         }
 
+        let mut doc = VariantValue::from_json_str(r#"{"a": 1}"#).unwrap();
+        assert!(doc.apply_merge_patch(&Value::Null).is_err());
+        assert!(doc
+            .apply_merge_patch(&serde_json::from_str(r#"{"a": [null]}"#).unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn variant_diff_test() {
+        let old = nested_sample();
+        assert!(old.diff(&old.clone()).is_empty());
+
+        let mut new = old.clone();
+        new.insert("fld_new", VariantValue::from("added"));
+        new.get_map_mut().unwrap().remove("fld_bool");
+        new.insert("fld_long", VariantValue::Long(4321));
         {
-            let fval2 = VariantValue::Float(4.141_593);
-            assert_eq!(fval.partial_cmp(&fval2), Some(Ordering::Less));
+            let object = new.get_map_mut().unwrap().get_mut("fld_object").unwrap();
+            object.insert("obj_fld_new", VariantValue::Int(1));
         }
+        let diff = old.diff(&new);
+        assert_eq!(diff.added.len(), 2);
+        assert_eq!(
+            diff.added[0],
+            ("fld_new".to_string(), VariantValue::from("added"))
+        );
+        assert_eq!(
+            diff.added[1],
+            ("fld_object.obj_fld_new".to_string(), VariantValue::Int(1))
+        );
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(
+            diff.removed[0],
+            ("fld_bool".to_string(), VariantValue::Bool(true))
+        );
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(
+            diff.changed[0],
+            (
+                "fld_long".to_string(),
+                VariantValue::Long(1234),
+                VariantValue::Long(4321)
+            )
+        );
+
+        // back to the old document, the other way around
+        let diff = new.diff(&old);
+        assert_eq!(diff.removed.len(), 2);
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.changed[0].1, VariantValue::Long(4321));
+
+        // vecs and type changes are replaced as a whole
+        let old = VariantValue::from_json_str(r#"{"tags": ["a", "b"], "n": {"x": 1}}"#).unwrap();
+        let new = VariantValue::from_json_str(r#"{"tags": ["a", "c"], "n": 1}"#).unwrap();
+        assert_eq!(
+            old.diff(&new),
+            VariantDiff {
+                changed: vec![
+                    (
+                        "n".to_string(),
+                        old.get_path("n").unwrap().clone(),
+                        VariantValue::Long(1)
+                    ),
+                    (
+                        "tags".to_string(),
+                        old.get_path("tags").unwrap().clone(),
+                        new.get_path("tags").unwrap().clone()
+                    ),
+                ],
+                ..VariantDiff::default()
+            }
+        );
+
+        let diff = VariantValue::Int(1).diff(&VariantValue::Long(1));
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].0, "");
     }
 
     #[test]
-    #[allow(clippy::approx_constant)]
-    fn variant_double_test() {
-        let dval = VariantValue::Double(3.141_592_653_5);
-        let expr = format!("{}", dval);
-        assert_eq!(expr, "3.141593");
+    fn variant_truncate_to_test() {
+        let mut value = VariantValue::from("hello");
+        assert!(!value.truncate_to(5));
+        assert!(value.truncate_to(3));
+        assert_eq!(value, VariantValue::from("hel"));
+
+        // "é" takes 2 bytes and "€" 3, the cut never splits them
+        let mut value = VariantValue::from("aé€b");
+        assert!(value.truncate_to(5));
+        assert_eq!(value, VariantValue::from("aé"));
+        let mut value = VariantValue::from("aé€b");
+        assert!(value.truncate_to(2));
+        assert_eq!(value, VariantValue::from("a"));
+        let mut value = VariantValue::from("€");
+        assert!(value.truncate_to(2));
+        assert_eq!(value, VariantValue::from(""));
+        let mut value = VariantValue::VStringShared(Arc::from("aé€b"));
+        assert!(value.truncate_to(6));
+        assert_eq!(value.get_string(), Some("aé€"));
+
+        let mut value = VariantValue::Binary(vec![0xe2, 0x82, 0xac, 1]);
+        assert!(value.truncate_to(2));
+        assert_eq!(value, VariantValue::Binary(vec![0xe2, 0x82]));
+
+        let mut doc = nested_sample();
+        assert!(!doc.truncate_to(1024));
+        assert!(doc.truncate_to(2));
+        for (path, leaf) in doc.iter_leaves() {
+            if let Some(s) = leaf.get_string() {
+                assert!(s.len() <= 2, "{}", path);
+            }
+        }
+        let mut value = VariantValue::Long(123_456);
+        assert!(!value.truncate_to(0));
     }
 
     #[test]
-    fn variant_string_test() {
-        let strval = VariantValue::VString(String::from("hello world"));
-        let expr = format!("{}", strval);
-        assert_eq!(expr, "hello world");
+    fn variant_to_canonical_json_test() {
+        let third = 1.0 / 3.0;
+        let sum = 0.1 + 0.2;
+        let value = VariantValue::Vec(vec![
+            VariantValue::Double(third),
+            VariantValue::Double(sum),
+            VariantValue::Double(1e300),
+            VariantValue::Float(0.1),
+        ]);
+        assert_eq!(format!("{}", VariantValue::Double(third)), "0.333333");
+
+        let json = value.to_canonical_json().unwrap();
+        assert_eq!(json, "[0.3333333333333333,0.30000000000000004,1e+300,0.1]");
+        let parsed = VariantValue::from_json_str(&json).unwrap();
+        let parsed = parsed.get_vec().unwrap();
+        assert_eq!(parsed[0].get_double().unwrap().to_bits(), third.to_bits());
+        assert_eq!(parsed[1].get_double().unwrap().to_bits(), sum.to_bits());
+        assert_eq!(parsed[2], VariantValue::Double(1e300));
+        assert_eq!(parsed[3].get_double().unwrap() as f32, 0.1f32);
+
+        let mut doc = VariantValue::Map(HashMap::new());
+        for key in &["zeta", "alpha", "mid"] {
+            doc.insert(*key, VariantValue::Int(1));
+        }
+        assert_eq!(
+            doc.to_canonical_json().unwrap(),
+            r#"{"alpha":1,"mid":1,"zeta":1}"#
+        );
+
+        doc.insert("nan", VariantValue::Float(f32::NAN));
+        assert!(doc.to_canonical_json().is_err());
+        assert!(VariantValue::Double(f64::INFINITY)
+            .to_canonical_json()
+            .is_err());
     }
 
     #[test]
-    fn variant_binary_test() {
-        let bval = VariantValue::Binary(vec![65u8, 66u8, 67u8]);
-        let expr = format!("{}", bval);
-        assert_eq!(expr, "Binary(unprintable)");
+    fn variant_value_ref_test() {
+        let json: Value = serde_json::from_str(
+            r#"{"title": "borrowed", "tags": ["a", "b"], "n": 3, "x": 0.5, "ok": true}"#,
+        )
+        .unwrap();
+        let value = VariantValueRef::try_from(&json).unwrap();
 
-        if let VariantValue::Binary(ref bvec) = bval {
-            for (i, val) in bvec.iter().enumerate() {
-                assert_eq!(*val, b'A' + i as u8);
+        // the strings point into `json`, they are not copied
+        let title = value.get("title").unwrap().get_string().unwrap();
+        assert_eq!(title, "borrowed");
+        assert_eq!(title.as_ptr(), json["title"].as_str().unwrap().as_ptr());
+        if let Some(VariantValueRef::Vec(tags)) = value.get("tags") {
+            for (tag, source) in tags.iter().zip(json["tags"].as_array().unwrap()) {
+                assert_eq!(
+                    tag.get_string().unwrap().as_ptr(),
+                    source.as_str().unwrap().as_ptr()
+                );
             }
+        } else {
+            panic!("tags is not a vec");
+        }
+        assert_eq!(value.get("n"), Some(&VariantValueRef::Long(3)));
+        assert_eq!(value.get("x"), Some(&VariantValueRef::Double(0.5)));
+        assert_eq!(value.get("ok"), Some(&VariantValueRef::Bool(true)));
+        assert!(value.get("missing").is_none());
+
+        let owned = value.to_variant();
+        let expected = VariantValue::try_from(&json).unwrap();
+        assert_eq!(owned, expected);
+
+        let json: Value = serde_json::from_str(r#"{"a": [1, null]}"#).unwrap();
+        assert!(VariantValueRef::try_from(&json).is_err());
+    }
+
+    #[test]
+    fn variant_content_hash_test() {
+        // same entries inserted in another order and into a bigger table
+        let mut a = VariantValue::Map(HashMap::new());
+        let mut b = VariantValue::Map(HashMap::with_capacity(64));
+        let entries: Vec<(String, VariantValue)> = (0..20)
+            .map(|i| (format!("fld_{}", i), VariantValue::Int(i)))
+            .collect();
+        for (k, v) in &entries {
+            a.insert(k.clone(), v.clone());
+        }
+        for (k, v) in entries.iter().rev() {
+            b.insert(k.clone(), v.clone());
+        }
+        a.insert("nested", nested_sample());
+        b.insert("nested", nested_sample());
+        assert_eq!(a.content_hash(), b.content_hash());
+
+        let mut interned = b.clone();
+        interned.intern_strings(&mut StringInterner::new());
+        assert_eq!(a.content_hash(), interned.content_hash());
+
+        b.insert("fld_0", VariantValue::Int(-1));
+        assert_ne!(a.content_hash(), b.content_hash());
+        assert_ne!(
+            VariantValue::Int(1).content_hash(),
+            VariantValue::Long(1).content_hash()
+        );
+        assert_ne!(
+            VariantValue::from("ab").content_hash(),
+            VariantValue::Vec(vec![VariantValue::from("a"), VariantValue::from("b")])
+                .content_hash()
+        );
+    }
+
+    #[test]
+    fn variant_cbor_round_trip_test() {
+        let mut doc = match nested_sample() {
+            VariantValue::Map(m) => m,
+            _ => unreachable!(),
+        };
+        doc.insert(
+            "fld_binary".to_string(),
+            VariantValue::Binary(vec![0, 159, 255]),
+        );
+        doc.insert("fld_float".to_string(), VariantValue::Float(0.1));
+        doc.insert("fld_min".to_string(), VariantValue::Long(i64::min_value()));
+        doc.insert("fld_max".to_string(), VariantValue::Long(i64::max_value()));
+        let value = VariantValue::Map(doc);
+        let decoded = VariantValue::from_cbor(&value.to_cbor()).unwrap();
+        assert_eq!(decoded, value);
+
+        // binary values are byte strings, not arrays
+        let binary = VariantValue::Binary(vec![1, 2, 3]);
+        assert_eq!(binary.to_cbor(), vec![0x43, 1, 2, 3]);
+        assert_eq!(VariantValue::from_cbor(&binary.to_cbor()).unwrap(), binary);
+
+        assert_eq!(VariantValue::Long(-500).to_cbor(), vec![0x39, 0x01, 0xf3]);
+        assert_eq!(
+            VariantValue::Int(1_000_000).to_cbor(),
+            vec![0x1a, 0x00, 0x0f, 0x42, 0x40]
+        );
+        let mut map = HashMap::new();
+        map.insert(
+            "a".to_string(),
+            VariantValue::Vec(vec![VariantValue::Short(1), VariantValue::Bool(false)]),
+        );
+        assert_eq!(
+            VariantValue::Map(map).to_cbor(),
+            vec![0xa1, 0x61, b'a', 0x82, 0x01, 0xf4]
+        );
+
+        // narrow integers and chars come back as `Long` and `VString`
+        assert_eq!(
+            VariantValue::from_cbor(&VariantValue::Short(-7).to_cbor()).unwrap(),
+            VariantValue::Long(-7)
+        );
+        assert_eq!(
+            VariantValue::from_cbor(&VariantValue::Char('é').to_cbor()).unwrap(),
+            VariantValue::from("é")
+        );
+    }
+
+    #[test]
+    fn variant_from_cbor_test() {
+        // half precision floats
+        assert_eq!(
+            VariantValue::from_cbor(&[0xf9, 0x3c, 0x00]).unwrap(),
+            VariantValue::Float(1.0)
+        );
+        assert_eq!(
+            VariantValue::from_cbor(&[0xf9, 0xc4, 0x00]).unwrap(),
+            VariantValue::Float(-4.0)
+        );
+        assert_eq!(
+            VariantValue::from_cbor(&[0xf9, 0x7c, 0x00]).unwrap(),
+            VariantValue::Float(::std::f32::INFINITY)
+        );
+        // a non shortest length is fine
+        assert_eq!(
+            VariantValue::from_cbor(&[0x78, 0x02, b'h', b'i']).unwrap(),
+            VariantValue::from("hi")
+        );
+
+        // null, indefinite length, tag, out of range, non text key,
+        // truncated and trailing bytes
+        let invalid: [&[u8]; 7] = [
+            &[0xf6],
+            &[0x5f, 0x41, 0x00, 0xff],
+            &[0xc1, 0x01],
+            &[0x1b, 0x80, 0, 0, 0, 0, 0, 0, 0],
+            &[0xa1, 0x01, 0x02],
+            &[0x43, 0x01],
+            &[0x01, 0x02],
+        ];
+        for bytes in invalid.iter() {
+            assert!(VariantValue::from_cbor(bytes).is_err());
         }
+
+        // too deeply nested arrays and maps fail instead of overflowing the stack
+        let mut nested = vec![0x81; MAX_DECODE_DEPTH];
+        nested.push(0x01);
+        let leaf = vec!["0"; MAX_DECODE_DEPTH].join(".");
+        assert_eq!(
+            VariantValue::from_cbor(&nested).unwrap().get_path(&leaf),
+            Some(&VariantValue::Long(1))
+        );
+        nested.insert(0, 0x81);
+        assert!(VariantValue::from_cbor(&nested).is_err());
+        assert!(VariantValue::from_cbor(&[0x81; 100_000]).is_err());
+        let mut nested = [0xa1, 0x61, b'a'].repeat(MAX_DECODE_DEPTH + 1);
+        nested.push(0x01);
+        assert!(VariantValue::from_cbor(&nested).is_err());
+    }
+
+    #[test]
+    fn variant_numeric_kind_test() {
+        assert_eq!(
+            VariantValue::Short(1).numeric_kind(),
+            Some(NumericKind::Short)
+        );
+        assert_eq!(VariantValue::Int(1).numeric_kind(), Some(NumericKind::Int));
+        assert_eq!(
+            VariantValue::Long(1).numeric_kind(),
+            Some(NumericKind::Long)
+        );
+        assert_eq!(
+            VariantValue::Float(1.0).numeric_kind(),
+            Some(NumericKind::Float)
+        );
+        assert_eq!(
+            VariantValue::Double(1.0).numeric_kind(),
+            Some(NumericKind::Double)
+        );
+
+        assert_eq!(VariantValue::Bool(true).numeric_kind(), None);
+        assert_eq!(VariantValue::Char('1').numeric_kind(), None);
+        assert_eq!(VariantValue::from("1").numeric_kind(), None);
+        assert_eq!(nested_sample().numeric_kind(), None);
+
+        // the kind a value is widened to
+        let value = VariantValue::Int(3).widen(NumericKind::Long).unwrap();
+        assert_eq!(value.numeric_kind(), Some(NumericKind::Long));
+    }
+
+    #[test]
+    fn variant_constructors_test() {
+        const ZERO: VariantValue = VariantValue::long(0);
+        assert_eq!(ZERO, VariantValue::Long(0));
+
+        assert_eq!(VariantValue::bool(true), VariantValue::Bool(true));
+        assert_eq!(VariantValue::char('x'), VariantValue::Char('x'));
+        assert_eq!(VariantValue::short(-3), VariantValue::Short(-3));
+        assert_eq!(VariantValue::int(7), VariantValue::Int(7));
+        assert_eq!(VariantValue::float(1.5), VariantValue::Float(1.5));
+        assert_eq!(VariantValue::double(2.5), VariantValue::Double(2.5));
+        assert_eq!(
+            VariantValue::binary(vec![1, 2]),
+            VariantValue::from(&[1u8, 2][..])
+        );
+
+        // the string is moved, not copied
+        let s = "hello".to_string();
+        let ptr = s.as_ptr();
+        let value = VariantValue::vstring(s);
+        assert_eq!(value, VariantValue::from("hello"));
+        assert_eq!(value.get_string().unwrap().as_ptr(), ptr);
+
+        let longs: Vec<VariantValue> = (0..3).map(VariantValue::long).collect();
+        assert_eq!(longs[2], VariantValue::from(2i64));
+    }
+
+    #[test]
+    fn variant_compare_by_key_test() {
+        let doc = nested_sample();
+        let mut array = match doc.get_path("fld_array") {
+            Some(VariantValue::Vec(v)) => v.clone(),
+            _ => unreachable!(),
+        };
+        array.sort_by(VariantValue::compare_by_key("ary_fld_double"));
+        let strings: Vec<_> = array
+            .iter()
+            .map(|e| e.get_path("ary_fld_string").unwrap().get_string().unwrap())
+            .collect();
+        assert_eq!(strings, vec!["a", "B"]);
+
+        // missing keys first, then by string, and by a nested path
+        let mut no_double = HashMap::new();
+        no_double.insert("ary_fld_string".to_string(), VariantValue::from("c"));
+        array.push(VariantValue::Map(no_double));
+        array.sort_by(VariantValue::compare_by_key("ary_fld_double"));
+        let first = array[0].get_path("ary_fld_string").unwrap();
+        assert_eq!(first.get_string(), Some("c"));
+        array.sort_by(|a, b| VariantValue::compare_by_key("ary_fld_string")(b, a));
+        assert_eq!(
+            array[0].get_path("ary_fld_string").unwrap().get_string(),
+            Some("c")
+        );
+
+        let mut docs = vec![doc.clone(), VariantValue::Map(HashMap::new())];
+        docs.sort_by(VariantValue::compare_by_key("fld_object.obj_fld_long"));
+        assert!(docs[0].get_map().unwrap().is_empty());
+
+        // numbers of mixed widths are compared by value
+        let mut values: Vec<VariantValue> = vec![
+            VariantValue::Double(::std::f64::NAN),
+            VariantValue::Long(3),
+            VariantValue::Double(2.5),
+            VariantValue::Int(-1),
+            VariantValue::Float(3.5),
+            VariantValue::Long(i64::max_value()),
+            VariantValue::Long(i64::max_value() - 1),
+        ]
+        .into_iter()
+        .map(|v| VariantValue::from_iter(vec![("n".to_string(), v)]))
+        .collect();
+        values.sort_by(VariantValue::compare_by_key("n"));
+        let sorted: Vec<&VariantValue> = values.iter().map(|v| v.get_path("n").unwrap()).collect();
+        assert_eq!(
+            sorted,
+            vec![
+                &VariantValue::Int(-1),
+                &VariantValue::Double(2.5),
+                &VariantValue::Long(3),
+                &VariantValue::Float(3.5),
+                &VariantValue::Long(i64::max_value() - 1),
+                &VariantValue::Long(i64::max_value()),
+                &VariantValue::Double(::std::f64::NAN),
+            ]
+        );
+        // the empty key compares the values themselves
+        assert_eq!(
+            VariantValue::compare_by_key("")(&VariantValue::Int(2), &VariantValue::Long(1)),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn variant_clamp_numeric_test() {
+        let min = VariantValue::Int(0);
+        let max = VariantValue::Int(100);
+        let mut value = VariantValue::Int(150);
+        value.clamp_numeric(&min, &max).unwrap();
+        assert_eq!(value, VariantValue::Int(100));
+        let mut value = VariantValue::Int(-3);
+        value.clamp_numeric(&min, &max).unwrap();
+        assert_eq!(value, VariantValue::Int(0));
+        let mut value = VariantValue::Int(42);
+        value.clamp_numeric(&min, &max).unwrap();
+        assert_eq!(value, VariantValue::Int(42));
+
+        // the bounds are widened to the kind of the value
+        let mut value = VariantValue::Long(1 << 40);
+        value.clamp_numeric(&min, &max).unwrap();
+        assert_eq!(value, VariantValue::Long(100));
+        let mut value = VariantValue::Double(-0.5);
+        value
+            .clamp_numeric(&VariantValue::Float(0.0), &VariantValue::Float(1.0))
+            .unwrap();
+        assert!(value.get_double().unwrap().abs() < ::std::f64::EPSILON);
+        let mut value = VariantValue::Double(::std::f64::NAN);
+        value.clamp_numeric(&min, &max).unwrap();
+        assert!(value.get_double().unwrap().is_nan());
+
+        let mut value = VariantValue::from("150");
+        assert!(value.clamp_numeric(&min, &max).is_err());
+        assert_eq!(value, VariantValue::from("150"));
+        let mut value = VariantValue::Bool(true);
+        assert!(value.clamp_numeric(&min, &max).is_err());
+
+        // bounds which don't widen to the value kind or are out of order
+        let mut value = VariantValue::Int(150);
+        assert!(value
+            .clamp_numeric(&VariantValue::Long(0), &VariantValue::Long(100))
+            .is_err());
+        assert!(value
+            .clamp_numeric(&min, &VariantValue::from("100"))
+            .is_err());
+        assert!(value.clamp_numeric(&max, &min).is_err());
+        let mut value = VariantValue::Float(1.5);
+        assert!(value.clamp_numeric(&min, &max).is_err());
+        assert!(value
+            .clamp_numeric(
+                &VariantValue::Float(::std::f32::NAN),
+                &VariantValue::Float(1.0)
+            )
+            .is_err());
+        assert_eq!(value, VariantValue::Float(1.5));
+    }
+
+    #[test]
+    fn variant_to_sorted_test() {
+        use std::collections::hash_map::DefaultHasher;
+
+        let doc = nested_sample();
+        let sorted = doc.to_sorted();
+        assert!(sorted.get_map().is_none());
+        let keys: Vec<&str> = sorted
+            .get_sorted_map()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                "fld_array",
+                "fld_bool",
+                "fld_long",
+                "fld_object",
+                "fld_string"
+            ]
+        );
+
+        // nested maps are sorted too, in objects and in arrays
+        let leaves: Vec<String> = sorted.iter_leaves().map(|(path, _)| path).collect();
+        assert_eq!(
+            leaves,
+            vec![
+                "fld_array.0.ary_fld_double",
+                "fld_array.0.ary_fld_string",
+                "fld_array.1.ary_fld_double",
+                "fld_array.1.ary_fld_string",
+                "fld_bool",
+                "fld_long",
+                "fld_object.obj_fld_array.0",
+                "fld_object.obj_fld_array.1",
+                "fld_object.obj_fld_array.2",
+                "fld_object.obj_fld_long",
+                "fld_object.obj_fld_string",
+                "fld_string",
+            ]
+        );
+        assert!(sorted
+            .get_path("fld_object")
+            .unwrap()
+            .get_sorted_map()
+            .is_some());
+        assert_eq!(
+            sorted.get_path("fld_array.1.ary_fld_string"),
+            Some(&VariantValue::from("a"))
+        );
+
+        // the content is the same, only the backing of the maps differs
+        assert_eq!(sorted, doc);
+        assert_eq!(doc, sorted);
+        assert_eq!(sorted.cmp(&doc), Ordering::Equal);
+        let hash = |v: &VariantValue| {
+            let mut hasher = DefaultHasher::new();
+            v.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(&sorted), hash(&doc));
+        assert!(doc.diff(&sorted).is_empty());
+        assert_eq!(sorted.to_bytes(), doc.to_bytes());
+        assert_eq!(sorted.to_json_str().unwrap(), doc.to_json_str().unwrap());
+
+        let mut other = sorted.clone();
+        other.insert("fld_bool", VariantValue::Bool(false));
+        assert_ne!(other, doc);
+        assert_eq!(other.cmp(&sorted), Ordering::Less);
+
+        // sorted maps can be updated in place
+        let mut sorted = sorted;
+        *sorted
+            .get_or_insert_with("fld_count", || VariantValue::Int(0))
+            .unwrap() = VariantValue::Int(1);
+        assert_eq!(sorted.get_path("fld_count"), Some(&VariantValue::Int(1)));
+        sorted
+            .apply_merge_patch(
+                &serde_json::from_str(r#"{"fld_new": {"b": 1}, "fld_bool": {"a": 1}}"#).unwrap(),
+            )
+            .unwrap();
+        assert!(sorted
+            .get_path("fld_new")
+            .unwrap()
+            .get_sorted_map()
+            .is_some());
+        assert!(sorted
+            .get_path("fld_bool")
+            .unwrap()
+            .get_sorted_map()
+            .is_some());
+        assert_eq!(sorted.get_path("fld_bool.a"), Some(&VariantValue::Long(1)));
+    }
+
+    #[test]
+    fn variant_parse_numeric_test() {
+        let parse = |s: &str| VariantValue::from(s).parse_numeric();
+        assert_eq!(parse("42"), Some(VariantValue::Int(42)));
+        assert_eq!(parse(" -7\n"), Some(VariantValue::Int(-7)));
+        assert_eq!(parse("+5"), Some(VariantValue::Int(5)));
+        assert_eq!(parse("3000000000"), Some(VariantValue::Long(3_000_000_000)));
+        assert_eq!(parse(" 3.14 "), Some(VariantValue::Double(3.14)));
+        assert_eq!(parse("1e3"), Some(VariantValue::Double(1000.0)));
+        // too large for a `Long`
+        assert_eq!(
+            parse("100000000000000000000"),
+            Some(VariantValue::Double(1e20))
+        );
+
+        assert!(parse("").is_none());
+        assert!(parse("forty two").is_none());
+        assert!(parse("42abc").is_none());
+        assert!(parse("4 2").is_none());
+        assert!(parse("NaN").is_none());
+        assert!(parse("inf").is_none());
+
+        // shared strings are parsed too, numbers pass through
+        let mut shared = VariantValue::from(" 12 ");
+        shared.intern_strings(&mut StringInterner::new());
+        assert_eq!(shared.parse_numeric(), Some(VariantValue::Int(12)));
+        assert_eq!(
+            VariantValue::Short(3).parse_numeric(),
+            Some(VariantValue::Short(3))
+        );
+        assert_eq!(
+            VariantValue::Float(1.5).parse_numeric(),
+            Some(VariantValue::Float(1.5))
+        );
+        assert!(VariantValue::Bool(true).parse_numeric().is_none());
+        assert!(VariantValue::Char('4').parse_numeric().is_none());
+        assert!(VariantValue::Binary(b"42".to_vec())
+            .parse_numeric()
+            .is_none());
+    }
+
+    #[test]
+    fn variant_container_eq_test() {
+        use std::collections::hash_map::DefaultHasher;
+
+        let hash = |v: &VariantValue| {
+            let mut hasher = DefaultHasher::new();
+            v.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        let doc = nested_sample();
+        assert_eq!(doc, doc.clone());
+        assert_eq!(hash(&doc), hash(&doc.clone()));
+        let mut other = doc.clone();
+        other.insert("fld_long", VariantValue::Long(1));
+        assert_ne!(doc, other);
+        assert_ne!(hash(&doc), hash(&other));
+
+        // maps are equal whatever their insertion order
+        let m1 = VariantValue::from_json_str(r#"{"a": 1, "b": [1, "x"], "c": {"d": 2}}"#).unwrap();
+        let m2 = VariantValue::from_json_str(r#"{"c": {"d": 2}, "b": [1, "x"], "a": 1}"#).unwrap();
+        assert_eq!(m1, m2);
+        assert_eq!(hash(&m1), hash(&m2));
+
+        // JSON numbers are ingested as `Long`
+        let vec = VariantValue::Vec(vec![VariantValue::Long(1), VariantValue::from("x")]);
+        assert_eq!(m1.get_path("b"), Some(&vec));
+        assert_ne!(vec, VariantValue::Vec(vec![VariantValue::Long(1)]));
+        assert_ne!(
+            vec,
+            VariantValue::Vec(vec![VariantValue::Int(1), VariantValue::from("x")])
+        );
+        assert_ne!(vec, VariantValue::Long(1));
+        assert_ne!(VariantValue::Vec(vec![]), VariantValue::Map(HashMap::new()));
+
+        let keys: HashSet<VariantValue> = vec![m1, m2, vec.clone(), vec].into_iter().collect();
+        assert_eq!(keys.len(), 2);
+    }
+
+    #[test]
+    fn variant_from_iter_test() {
+        let v: VariantValue = (1..4).map(VariantValue::Int).collect();
+        assert_eq!(
+            v.get_vec().unwrap(),
+            &vec![
+                VariantValue::Int(1),
+                VariantValue::Int(2),
+                VariantValue::Int(3)
+            ]
+        );
+
+        let v: VariantValue = Vec::<VariantValue>::new().into_iter().collect();
+        assert!(v.get_vec().unwrap().is_empty());
+
+        let v: VariantValue = vec![("a", 1i64), ("b", 2i64)]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), VariantValue::Long(v)))
+            .collect();
+        let m = v.get_map().unwrap();
+        assert_eq!(m.len(), 2);
+        assert_eq!(m["a"], VariantValue::Long(1));
+        assert_eq!(m["b"], VariantValue::Long(2));
+    }
+
+    #[test]
+    fn variant_compare_as_bytes_test() {
+        let s = VariantValue::from("abc");
+        let b = VariantValue::Binary(b"abd".to_vec());
+        assert_eq!(s.compare_as_bytes(&b), Some(Ordering::Less));
+        assert_eq!(b.compare_as_bytes(&s), Some(Ordering::Greater));
+        assert_eq!(
+            s.compare_as_bytes(&VariantValue::Binary(b"abc".to_vec())),
+            Some(Ordering::Equal)
+        );
+        assert_eq!(
+            s.compare_as_bytes(&VariantValue::from("ab")),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(s.compare_as_bytes(&VariantValue::Int(1)), None);
+        assert_eq!(VariantValue::Long(1).compare_as_bytes(&b), None);
+    }
+
+    #[test]
+    fn variant_canonical_numeric_test() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::collections::HashSet;
+
+        let hash = |v: &VariantValue| {
+            let mut hasher = DefaultHasher::new();
+            v.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        let int = VariantValue::Int(5).canonical_numeric();
+        let long = VariantValue::Long(5).canonical_numeric();
+        let short = VariantValue::Short(5).canonical_numeric();
+        assert_eq!(int, long);
+        assert_eq!(short, long);
+        assert_eq!(int, VariantValue::Long(5));
+        assert_eq!(hash(&int), hash(&long));
+
+        let float = VariantValue::Float(0.5).canonical_numeric();
+        let double = VariantValue::Double(0.5).canonical_numeric();
+        assert_eq!(float, double);
+        assert_eq!(hash(&float), hash(&double));
+
+        assert_eq!(
+            VariantValue::from("5").canonical_numeric(),
+            VariantValue::from("5")
+        );
+        assert_eq!(
+            VariantValue::Bool(true).canonical_numeric(),
+            VariantValue::Bool(true)
+        );
+
+        let keys: HashSet<VariantValue> = vec![
+            VariantValue::Int(5),
+            VariantValue::Long(5),
+            VariantValue::Short(5),
+            VariantValue::Long(6),
+        ]
+        .iter()
+        .map(VariantValue::canonical_numeric)
+        .collect();
+        assert_eq!(keys.len(), 2);
     }
 }